@@ -0,0 +1,287 @@
+//! Request and response bodies of the Dumont REST API.
+//!
+//! These are shared between `dumont-web-server` and `dumont-client`, so both
+//! sides agree on the wire format. Enable the `openapi` feature to derive
+//! `utoipa::ToSchema` for them.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::Deref;
+
+/// Header carrying the write marker. Responses to requests that wrote have it, and a
+/// request that sends the latest one back reads what was written, even when the
+/// server reads from a lagging replica.
+pub const LAST_WRITE_HEADER: &str = "dumont-last-write";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct GenericLabels {
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl From<BTreeMap<String, String>> for GenericLabels {
+    fn from(source: BTreeMap<String, String>) -> Self {
+        Self { labels: source }
+    }
+}
+
+impl From<Vec<(&str, &str)>> for GenericLabels {
+    fn from(source: Vec<(&str, &str)>) -> Self {
+        let mut labels: BTreeMap<String, String> = Default::default();
+        for (key, value) in source {
+            labels.insert(key.to_owned(), value.to_owned());
+        }
+
+        labels.into()
+    }
+}
+
+impl FromIterator<(String, String)> for GenericLabels {
+    fn from_iter<T: IntoIterator<Item = (String, String)>>(iter: T) -> Self {
+        Self {
+            labels: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Deref for GenericLabels {
+    type Target = BTreeMap<String, String>;
+    fn deref(&self) -> &Self::Target {
+        &self.labels
+    }
+}
+
+pub type GetOrganization = CreateOrganization;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreateOrganization {
+    pub org: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreateRepository {
+    pub repo: String,
+    #[serde(flatten, default)]
+    pub labels: GenericLabels,
+}
+
+#[test]
+fn validate_create_repo_deserialize() {
+    use json::object;
+
+    let _foo: CreateRepository = serde_json::from_str(&json::stringify(object! {
+        "repo":  "example",
+    }))
+    .unwrap();
+
+    let _foo: CreateRepository = serde_json::from_str(&json::stringify(object! {
+        "repo":  "example",
+        "labels": {
+            "foo": "bar"
+        }
+    }))
+    .unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct GetRepository {
+    pub org: String,
+    pub repo: String,
+    #[serde(flatten)]
+    pub labels: GenericLabels,
+    /// Names of the policies applied to the labels. Only set in the responses of
+    /// creates and updates.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub applied_policies: Vec<String>,
+}
+
+#[test]
+fn validate_get_repo_deserialize() {
+    use json::object;
+
+    let _foo: GetRepository = serde_json::from_str(&json::stringify(object! {
+        "org": "foo",
+        "repo":  "example",
+    }))
+    .unwrap();
+
+    let _foo: GetRepository = serde_json::from_str(&json::stringify(object! {
+        "org": "foo",
+        "repo":  "example",
+        "labels": {
+            "foo": "bar"
+        }
+    }))
+    .unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UpdateRepository {
+    #[serde(flatten, default)]
+    pub labels: GenericLabels,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreateVersion {
+    pub version: String,
+    #[serde(default, flatten)]
+    pub labels: GenericLabels,
+}
+
+#[test]
+fn validate_create_version_deserialize() {
+    use json::object;
+
+    let _foo: CreateVersion = serde_json::from_str(&json::stringify(object! {
+        "version":  "abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyz",
+        "labels": {
+            "foo": "bar"
+        }
+    }))
+    .unwrap();
+
+    let _foo: CreateVersion = serde_json::from_str(&json::stringify(object! {
+        "version":  "abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyz",
+    }))
+    .unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UpdateVersion {
+    #[serde(flatten)]
+    pub labels: GenericLabels,
+}
+
+#[test]
+fn validate_update_version_deserialize() {
+    use json::object;
+
+    let _foo: UpdateVersion = serde_json::from_str(&json::stringify(object! {
+        "labels": {
+            "foo": "bar"
+        }
+    }))
+    .unwrap();
+
+    let _foo: UpdateVersion = serde_json::from_str(&json::stringify(object! {})).unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct GetVersion {
+    pub version: String,
+    #[serde(flatten)]
+    pub labels: GenericLabels,
+    /// Names of the policies applied to the labels. Only set in the responses of
+    /// creates and updates.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub applied_policies: Vec<String>,
+}
+
+#[test]
+fn validate_get_version_deserialize() {
+    use json::object;
+
+    let _foo: GetVersion = serde_json::from_str(&json::stringify(object! {
+        "version":  "abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyz",
+        "labels": {
+            "foo": "bar"
+        }
+    }))
+    .unwrap();
+
+    let _foo: GetVersion = serde_json::from_str(&json::stringify(object! {
+        "version":  "abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyz",
+    }))
+    .unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DeleteStatus {
+    pub deleted: bool,
+}
+
+impl From<bool> for DeleteStatus {
+    fn from(source: bool) -> Self {
+        Self { deleted: source }
+    }
+}
+
+/// Result of evaluating every stored repo and version against the current policies.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ComplianceReport {
+    pub repos_checked: u64,
+    pub versions_checked: u64,
+    pub violations: Vec<ComplianceViolation>,
+}
+
+/// Labels of a repo, or of a version when `version` is set, that the current
+/// policies would reject.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ComplianceViolation {
+    pub org: String,
+    pub repo: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub message: String,
+}
+
+/// One reason the policies rejected a request, returned with every other reason
+/// under `status.violations`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PolicyViolation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// What went wrong, such as `label_not_defined` or `label_not_in_set`.
+    pub kind: String,
+    /// The value that broke the policy, when there is one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    pub message: String,
+}
+
+/// Versions expired by the retention rules of the current policies.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct GcReport {
+    /// When set, the expired versions were listed but not deleted.
+    pub dry_run: bool,
+    pub versions_checked: u64,
+    pub expired: Vec<ExpiredVersion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ExpiredVersion {
+    pub org: String,
+    pub repo: String,
+    pub version: String,
+    /// The policy with the retention rule that expired the version.
+    pub policy: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PaginationState {
+    #[serde(rename = "more")]
+    pub has_more: bool,
+    pub total: u64,
+}
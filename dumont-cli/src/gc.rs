@@ -0,0 +1,21 @@
+use crate::output::{render, OutputFormat};
+use clap::Args;
+use dumont_client::DumontClient;
+use std::io::Write;
+
+#[derive(Args, Debug)]
+pub struct GcArgs {
+    /// List the versions the retention rules expired, without deleting them
+    #[clap(long = "dry-run")]
+    pub dry_run: bool,
+}
+
+pub async fn run(
+    client: &DumontClient,
+    args: GcArgs,
+    format: OutputFormat,
+    out: &mut dyn Write,
+) -> Result<(), anyhow::Error> {
+    let report = client.collect_garbage(args.dry_run).await?;
+    render(format, &report, out)
+}
@@ -0,0 +1,189 @@
+use crate::output::{render, Label, OutputFormat};
+use anyhow::bail;
+use clap::Subcommand;
+use dumont_client::models::*;
+use dumont_client::DumontClient;
+use std::io::Write;
+
+/// A repository (`org/repo`) or version (`org/repo/version`) to edit labels on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabelTarget {
+    Repo {
+        org: String,
+        repo: String,
+    },
+    Version {
+        org: String,
+        repo: String,
+        version: String,
+    },
+}
+
+fn parse_target(source: &str) -> Result<LabelTarget, String> {
+    let parts: Vec<&str> = source.split('/').collect();
+    if parts.iter().any(|part| part.is_empty()) {
+        return Err(format!("'{}' has an empty path segment", source));
+    }
+
+    match parts.as_slice() {
+        [org, repo] => Ok(LabelTarget::Repo {
+            org: org.to_string(),
+            repo: repo.to_string(),
+        }),
+        [org, repo, version] => Ok(LabelTarget::Version {
+            org: org.to_string(),
+            repo: repo.to_string(),
+            version: version.to_string(),
+        }),
+        _ => Err(format!("'{}' is not org/repo or org/repo/version", source)),
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum LabelCommand {
+    /// List the labels on a repository or version
+    List {
+        /// `org/repo` or `org/repo/version`
+        #[clap(value_parser = parse_target)]
+        target: LabelTarget,
+    },
+    /// Show a single label
+    Get {
+        /// `org/repo` or `org/repo/version`
+        #[clap(value_parser = parse_target)]
+        target: LabelTarget,
+        key: String,
+    },
+    /// Add a label that isn't set yet
+    Create {
+        /// `org/repo` or `org/repo/version`
+        #[clap(value_parser = parse_target)]
+        target: LabelTarget,
+        key: String,
+        value: String,
+    },
+    /// Change the value of a label that is already set
+    Update {
+        /// `org/repo` or `org/repo/version`
+        #[clap(value_parser = parse_target)]
+        target: LabelTarget,
+        key: String,
+        value: String,
+    },
+    /// Remove a label
+    Delete {
+        /// `org/repo` or `org/repo/version`
+        #[clap(value_parser = parse_target)]
+        target: LabelTarget,
+        key: String,
+    },
+}
+
+async fn get_labels(
+    client: &DumontClient,
+    target: &LabelTarget,
+) -> Result<GenericLabels, anyhow::Error> {
+    let labels = match target {
+        LabelTarget::Repo { org, repo } => client.get_repo(org, repo).await?.labels,
+        LabelTarget::Version { org, repo, version } => {
+            client.get_version(org, repo, version).await?.labels
+        }
+    };
+
+    Ok(labels)
+}
+
+async fn put_labels(
+    client: &DumontClient,
+    target: &LabelTarget,
+    labels: GenericLabels,
+) -> Result<GenericLabels, anyhow::Error> {
+    let labels = match target {
+        LabelTarget::Repo { org, repo } => {
+            let body = UpdateRepository { labels };
+            client.update_repo(org, repo, &body).await?.labels
+        }
+        LabelTarget::Version { org, repo, version } => {
+            let body = UpdateVersion { labels };
+            client
+                .update_version(org, repo, version, &body)
+                .await?
+                .labels
+        }
+    };
+
+    Ok(labels)
+}
+
+pub async fn run(
+    client: &DumontClient,
+    command: LabelCommand,
+    format: OutputFormat,
+    out: &mut dyn Write,
+) -> Result<(), anyhow::Error> {
+    match command {
+        LabelCommand::List { target } => render(format, &get_labels(client, &target).await?, out),
+        LabelCommand::Get { target, key } => {
+            let labels = get_labels(client, &target).await?;
+            match labels.get(&key) {
+                Some(value) => render(
+                    format,
+                    &Label {
+                        key,
+                        value: value.clone(),
+                    },
+                    out,
+                ),
+                None => bail!("Label {} is not set", key),
+            }
+        }
+        LabelCommand::Create { target, key, value } => {
+            let mut labels = get_labels(client, &target).await?;
+            if labels.contains_key(&key) {
+                bail!(
+                    "Label {} is already set, use `label update` to change it",
+                    key
+                );
+            }
+            labels.labels.insert(key, value);
+            render(format, &put_labels(client, &target, labels).await?, out)
+        }
+        LabelCommand::Update { target, key, value } => {
+            let mut labels = get_labels(client, &target).await?;
+            if !labels.contains_key(&key) {
+                bail!("Label {} is not set, use `label create` to add it", key);
+            }
+            labels.labels.insert(key, value);
+            render(format, &put_labels(client, &target, labels).await?, out)
+        }
+        LabelCommand::Delete { target, key } => {
+            let mut labels = get_labels(client, &target).await?;
+            if labels.labels.remove(&key).is_none() {
+                bail!("Label {} is not set", key);
+            }
+            render(format, &put_labels(client, &target, labels).await?, out)
+        }
+    }
+}
+
+#[test]
+fn validate_parse_target() {
+    assert_eq!(
+        parse_target("example/clu"),
+        Ok(LabelTarget::Repo {
+            org: "example".to_owned(),
+            repo: "clu".to_owned()
+        })
+    );
+    assert_eq!(
+        parse_target("example/clu/1.6.9"),
+        Ok(LabelTarget::Version {
+            org: "example".to_owned(),
+            repo: "clu".to_owned(),
+            version: "1.6.9".to_owned()
+        })
+    );
+    assert!(parse_target("example").is_err());
+    assert!(parse_target("example//1.6.9").is_err());
+    assert!(parse_target("a/b/c/d").is_err());
+}
@@ -0,0 +1,145 @@
+//! Command line client for Dumont, built on `dumont-client`.
+
+mod gc;
+mod label;
+mod org;
+mod output;
+mod policy;
+mod repo;
+mod version;
+
+use clap::{Parser, Subcommand};
+use dumont_client::{ClientError, DumontClient};
+use std::io::Write;
+
+pub use output::OutputFormat;
+
+#[derive(Parser, Debug)]
+#[clap(name = "dumont", author, about, version)]
+pub struct Opts {
+    #[clap(subcommand)]
+    pub sub_command: MainOperation,
+
+    /// URL of the Dumont server
+    #[clap(
+        long = "server-url",
+        env = "DUMONT_URL",
+        default_value("http://127.0.0.1:3030"),
+        global = true
+    )]
+    pub server_url: String,
+
+    /// URL of the Dumont server's admin address, used by `gc`
+    #[clap(
+        long = "admin-url",
+        env = "DUMONT_ADMIN_URL",
+        default_value("http://127.0.0.1:3031"),
+        global = true
+    )]
+    pub admin_url: String,
+
+    /// Token sent as `Authorization: Bearer <token>`
+    #[clap(
+        long = "token",
+        env = "DUMONT_TOKEN",
+        hide_env_values = true,
+        global = true
+    )]
+    pub token: Option<String>,
+
+    /// How results are printed
+    #[clap(
+        long = "output",
+        short = 'o',
+        value_enum,
+        default_value_t = OutputFormat::Table,
+        global = true
+    )]
+    pub output: OutputFormat,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum MainOperation {
+    /// Manage organizations
+    #[clap(subcommand)]
+    Org(org::OrgCommand),
+
+    /// Manage repositories
+    #[clap(subcommand)]
+    Repo(repo::RepoCommand),
+
+    /// Manage versions
+    #[clap(subcommand)]
+    Version(version::VersionCommand),
+
+    /// Manage single labels on a repository or version
+    #[clap(subcommand)]
+    Label(label::LabelCommand),
+
+    /// Check stored data against the server's policies
+    #[clap(subcommand)]
+    Policy(policy::PolicyCommand),
+
+    /// Delete the versions expired by the server's retention rules
+    Gc(gc::GcArgs),
+}
+
+pub async fn run(opts: Opts, out: &mut dyn Write) -> Result<(), anyhow::Error> {
+    let server_url = match opts.sub_command {
+        MainOperation::Gc(_) => opts.admin_url,
+        _ => opts.server_url,
+    };
+    let mut builder = DumontClient::builder(server_url);
+    if let Some(token) = opts.token {
+        builder = builder.bearer_token(token);
+    }
+    let client = builder.build()?;
+
+    let result = match opts.sub_command {
+        MainOperation::Org(command) => org::run(&client, command, opts.output, out).await,
+        MainOperation::Repo(command) => repo::run(&client, command, opts.output, out).await,
+        MainOperation::Version(command) => version::run(&client, command, opts.output, out).await,
+        MainOperation::Label(command) => label::run(&client, command, opts.output, out).await,
+        MainOperation::Policy(command) => policy::run(&client, command, opts.output, out).await,
+        MainOperation::Gc(args) => gc::run(&client, args, opts.output, out).await,
+    };
+    result.map_err(list_violations)
+}
+
+/// Lists the policy violations a request was rejected for one per line, rather than
+/// joined together like the other messages of the server.
+fn list_violations(error: anyhow::Error) -> anyhow::Error {
+    let violations = match error.downcast_ref::<ClientError>() {
+        Some(client_error) if !client_error.violations().is_empty() => client_error.violations(),
+        _ => return error,
+    };
+
+    let mut message = "Rejected by the policies:".to_owned();
+    for violation in violations {
+        message.push_str("\n  - ");
+        message.push_str(&violation.message);
+    }
+    anyhow::anyhow!(message)
+}
+
+/// Parses `key=value` arguments given to `--label`.
+pub(crate) fn parse_label(source: &str) -> Result<(String, String), String> {
+    match source.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_owned(), value.to_owned())),
+        _ => Err(format!("'{}' is not in the form key=value", source)),
+    }
+}
+
+#[test]
+fn validate_parse_label() {
+    assert_eq!(
+        parse_label("owner=team-a=b"),
+        Ok(("owner".to_owned(), "team-a=b".to_owned()))
+    );
+    assert_eq!(
+        parse_label("owner="),
+        Ok(("owner".to_owned(), "".to_owned()))
+    );
+    assert!(parse_label("owner").is_err());
+    assert!(parse_label("=team-a").is_err());
+}
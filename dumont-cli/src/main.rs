@@ -0,0 +1,8 @@
+use clap::Parser;
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<(), anyhow::Error> {
+    let opts = dumont_cli::Opts::parse();
+    let mut stdout = std::io::stdout();
+    dumont_cli::run(opts, &mut stdout).await
+}
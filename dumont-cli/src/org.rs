@@ -0,0 +1,38 @@
+use crate::output::{render, OutputFormat};
+use clap::Subcommand;
+use dumont_client::models::*;
+use dumont_client::DumontClient;
+use futures_util::TryStreamExt;
+use std::io::Write;
+
+#[derive(Subcommand, Debug)]
+pub enum OrgCommand {
+    /// List every organization
+    List,
+    /// Show a single organization
+    Get { org: String },
+    /// Create an organization
+    Create { org: String },
+    /// Delete an organization
+    Delete { org: String },
+}
+
+pub async fn run(
+    client: &DumontClient,
+    command: OrgCommand,
+    format: OutputFormat,
+    out: &mut dyn Write,
+) -> Result<(), anyhow::Error> {
+    match command {
+        OrgCommand::List => {
+            let orgs: Vec<GetOrganization> = client.list_orgs().try_collect().await?;
+            render(format, &orgs, out)
+        }
+        OrgCommand::Get { org } => render(format, &client.get_org(&org).await?, out),
+        OrgCommand::Create { org } => render(format, &client.create_org(&org).await?, out),
+        OrgCommand::Delete { org } => {
+            let status: DeleteStatus = client.delete_org(&org).await?.into();
+            render(format, &status, out)
+        }
+    }
+}
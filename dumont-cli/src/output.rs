@@ -0,0 +1,219 @@
+use clap::ValueEnum;
+use dumont_client::models::*;
+use serde::Serialize;
+use std::io::Write;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Yaml,
+}
+
+/// A result that can be printed as JSON, YAML, or a table of `HEADERS` columns.
+pub trait Render: Serialize {
+    const HEADERS: &'static [&'static str];
+
+    fn rows(&self) -> Vec<Vec<String>>;
+}
+
+#[derive(Debug, Serialize)]
+pub struct Label {
+    pub key: String,
+    pub value: String,
+}
+
+pub fn render<T: Render>(
+    format: OutputFormat,
+    value: &T,
+    out: &mut dyn Write,
+) -> Result<(), anyhow::Error> {
+    match format {
+        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(value)?)?,
+        OutputFormat::Yaml => write!(out, "{}", serde_yaml::to_string(value)?)?,
+        OutputFormat::Table => write!(out, "{}", table(T::HEADERS, value.rows()))?,
+    }
+
+    Ok(())
+}
+
+fn table(headers: &[&str], rows: Vec<Vec<String>>) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = std::cmp::max(*width, cell.len());
+        }
+    }
+
+    let headers = headers.iter().map(|header| header.to_string()).collect();
+    let mut result = String::new();
+    for row in std::iter::once(headers).chain(rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        result.push_str(line.join("  ").trim_end());
+        result.push('\n');
+    }
+
+    result
+}
+
+fn format_labels(labels: &GenericLabels) -> String {
+    labels
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl<T: Render> Render for Vec<T> {
+    const HEADERS: &'static [&'static str] = T::HEADERS;
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.iter().flat_map(|item| item.rows()).collect()
+    }
+}
+
+impl Render for GetOrganization {
+    const HEADERS: &'static [&'static str] = &["ORG"];
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.org.clone()]]
+    }
+}
+
+impl Render for GetRepository {
+    const HEADERS: &'static [&'static str] = &["ORG", "REPO", "LABELS"];
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![
+            self.org.clone(),
+            self.repo.clone(),
+            format_labels(&self.labels),
+        ]]
+    }
+}
+
+impl Render for GetVersion {
+    const HEADERS: &'static [&'static str] = &["VERSION", "LABELS"];
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.version.clone(), format_labels(&self.labels)]]
+    }
+}
+
+impl Render for GenericLabels {
+    const HEADERS: &'static [&'static str] = &["KEY", "VALUE"];
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.iter()
+            .map(|(key, value)| vec![key.clone(), value.clone()])
+            .collect()
+    }
+}
+
+impl Render for Label {
+    const HEADERS: &'static [&'static str] = &["KEY", "VALUE"];
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.key.clone(), self.value.clone()]]
+    }
+}
+
+impl Render for DeleteStatus {
+    const HEADERS: &'static [&'static str] = &["DELETED"];
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.deleted.to_string()]]
+    }
+}
+
+impl Render for ComplianceReport {
+    const HEADERS: &'static [&'static str] =
+        &["ORG", "REPO", "VERSION", "POLICY", "LABEL", "MESSAGE"];
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.violations
+            .iter()
+            .map(|violation| {
+                vec![
+                    violation.org.clone(),
+                    violation.repo.clone(),
+                    violation.version.clone().unwrap_or_default(),
+                    violation.policy.clone().unwrap_or_default(),
+                    violation.label.clone().unwrap_or_default(),
+                    violation.message.clone(),
+                ]
+            })
+            .collect()
+    }
+}
+
+impl Render for GcReport {
+    const HEADERS: &'static [&'static str] = &["ORG", "REPO", "VERSION", "POLICY", "CREATED_AT"];
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.expired
+            .iter()
+            .map(|expired| {
+                vec![
+                    expired.org.clone(),
+                    expired.repo.clone(),
+                    expired.version.clone(),
+                    expired.policy.clone(),
+                    expired.created_at.clone(),
+                ]
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn validate_table_output() {
+    let repos = vec![
+        GetRepository {
+            org: "example".to_owned(),
+            repo: "clu".to_owned(),
+            labels: vec![("owner", "kevin"), ("scm", "git")].into(),
+            applied_policies: Vec::new(),
+        },
+        GetRepository {
+            org: "example".to_owned(),
+            repo: "tron-legacy".to_owned(),
+            labels: Default::default(),
+            applied_policies: Vec::new(),
+        },
+    ];
+
+    let mut out = Vec::new();
+    render(OutputFormat::Table, &repos, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "ORG      REPO         LABELS\n\
+         example  clu          owner=kevin,scm=git\n\
+         example  tron-legacy\n"
+    );
+}
+
+#[test]
+fn validate_structured_output() {
+    let version = GetVersion {
+        version: "1.6.9".to_owned(),
+        labels: vec![("deployed", "true")].into(),
+        applied_policies: Vec::new(),
+    };
+
+    let mut out = Vec::new();
+    render(OutputFormat::Json, &version, &mut out).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(json["labels"]["deployed"], "true");
+
+    let mut out = Vec::new();
+    render(OutputFormat::Yaml, &version, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "version: 1.6.9\nlabels:\n  deployed: 'true'\n"
+    );
+}
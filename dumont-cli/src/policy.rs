@@ -0,0 +1,35 @@
+use crate::output::{render, OutputFormat};
+use clap::Subcommand;
+use dumont_client::DumontClient;
+use std::io::Write;
+
+#[derive(Subcommand, Debug)]
+pub enum PolicyCommand {
+    /// Report every repo and version the current policies would reject. Exits
+    /// with an error when any are found.
+    Check,
+}
+
+pub async fn run(
+    client: &DumontClient,
+    command: PolicyCommand,
+    format: OutputFormat,
+    out: &mut dyn Write,
+) -> Result<(), anyhow::Error> {
+    match command {
+        PolicyCommand::Check => {
+            let report = client.compliance_report().await?;
+            render(format, &report, out)?;
+            if report.violations.is_empty() {
+                return Ok(());
+            }
+
+            anyhow::bail!(
+                "{} policy violations found in {} repos and {} versions",
+                report.violations.len(),
+                report.repos_checked,
+                report.versions_checked
+            )
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use crate::output::{render, OutputFormat};
+use clap::Subcommand;
+use dumont_client::models::*;
+use dumont_client::DumontClient;
+use futures_util::TryStreamExt;
+use std::io::Write;
+
+#[derive(Subcommand, Debug)]
+pub enum RepoCommand {
+    /// List every repository in an organization
+    List { org: String },
+    /// Show a single repository
+    Get { org: String, repo: String },
+    /// Create a repository
+    Create {
+        org: String,
+        repo: String,
+        /// Label to set, as key=value. May be repeated.
+        #[clap(long = "label", short = 'l', value_parser = crate::parse_label)]
+        labels: Vec<(String, String)>,
+    },
+    /// Replace all labels on a repository
+    Update {
+        org: String,
+        repo: String,
+        /// Label to set, as key=value. May be repeated.
+        #[clap(long = "label", short = 'l', value_parser = crate::parse_label)]
+        labels: Vec<(String, String)>,
+    },
+    /// Delete a repository
+    Delete { org: String, repo: String },
+}
+
+pub async fn run(
+    client: &DumontClient,
+    command: RepoCommand,
+    format: OutputFormat,
+    out: &mut dyn Write,
+) -> Result<(), anyhow::Error> {
+    match command {
+        RepoCommand::List { org } => {
+            let repos: Vec<GetRepository> = client.list_repos(&org).try_collect().await?;
+            render(format, &repos, out)
+        }
+        RepoCommand::Get { org, repo } => render(format, &client.get_repo(&org, &repo).await?, out),
+        RepoCommand::Create { org, repo, labels } => {
+            let body = CreateRepository {
+                repo,
+                labels: labels.into_iter().collect(),
+            };
+            render(format, &client.create_repo(&org, &body).await?, out)
+        }
+        RepoCommand::Update { org, repo, labels } => {
+            let body = UpdateRepository {
+                labels: labels.into_iter().collect(),
+            };
+            render(format, &client.update_repo(&org, &repo, &body).await?, out)
+        }
+        RepoCommand::Delete { org, repo } => {
+            let status: DeleteStatus = client.delete_repo(&org, &repo).await?.into();
+            render(format, &status, out)
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::output::{render, OutputFormat};
+use clap::Subcommand;
+use dumont_client::models::*;
+use dumont_client::DumontClient;
+use futures_util::TryStreamExt;
+use std::io::Write;
+
+#[derive(Subcommand, Debug)]
+pub enum VersionCommand {
+    /// List every version of a repository
+    List { org: String, repo: String },
+    /// Show a single version
+    Get {
+        org: String,
+        repo: String,
+        version: String,
+    },
+    /// Create a version
+    Create {
+        org: String,
+        repo: String,
+        version: String,
+        /// Label to set, as key=value. May be repeated.
+        #[clap(long = "label", short = 'l', value_parser = crate::parse_label)]
+        labels: Vec<(String, String)>,
+    },
+    /// Replace all labels on a version
+    Update {
+        org: String,
+        repo: String,
+        version: String,
+        /// Label to set, as key=value. May be repeated.
+        #[clap(long = "label", short = 'l', value_parser = crate::parse_label)]
+        labels: Vec<(String, String)>,
+    },
+    /// Delete a version
+    Delete {
+        org: String,
+        repo: String,
+        version: String,
+    },
+    /// Set a single label on a version, keeping the others, e.g. `stage prod`
+    Promote {
+        org: String,
+        repo: String,
+        version: String,
+        label: String,
+        value: String,
+    },
+}
+
+pub async fn run(
+    client: &DumontClient,
+    command: VersionCommand,
+    format: OutputFormat,
+    out: &mut dyn Write,
+) -> Result<(), anyhow::Error> {
+    match command {
+        VersionCommand::List { org, repo } => {
+            let versions: Vec<GetVersion> = client.list_versions(&org, &repo).try_collect().await?;
+            render(format, &versions, out)
+        }
+        VersionCommand::Get { org, repo, version } => render(
+            format,
+            &client.get_version(&org, &repo, &version).await?,
+            out,
+        ),
+        VersionCommand::Create {
+            org,
+            repo,
+            version,
+            labels,
+        } => {
+            let body = CreateVersion {
+                version,
+                labels: labels.into_iter().collect(),
+            };
+            render(
+                format,
+                &client.create_version(&org, &repo, &body).await?,
+                out,
+            )
+        }
+        VersionCommand::Update {
+            org,
+            repo,
+            version,
+            labels,
+        } => {
+            let body = UpdateVersion {
+                labels: labels.into_iter().collect(),
+            };
+            let updated = client.update_version(&org, &repo, &version, &body).await?;
+            render(format, &updated, out)
+        }
+        VersionCommand::Delete { org, repo, version } => {
+            let status: DeleteStatus = client.delete_version(&org, &repo, &version).await?.into();
+            render(format, &status, out)
+        }
+        VersionCommand::Promote {
+            org,
+            repo,
+            version,
+            label,
+            value,
+        } => {
+            let mut labels = client.get_version(&org, &repo, &version).await?.labels;
+            labels.labels.insert(label, value);
+
+            let body = UpdateVersion { labels };
+            let updated = client.update_version(&org, &repo, &version, &body).await?;
+            render(format, &updated, out)
+        }
+    }
+}
@@ -0,0 +1,562 @@
+use crate::error::{ClientError, ErrorBody, Result};
+use dumont_api_models::*;
+use futures_util::stream::{self, Stream, TryStreamExt};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::{Method, Url};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::debug;
+
+/// How requests that failed for a transient reason are retried.
+///
+/// Connection failures are retried for every request. Timeouts and
+/// `502`/`503`/`504` responses are only retried for requests that are safe to
+/// repeat, so a create is never sent twice.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt));
+        std::cmp::min(backoff, self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+/// A single page of a list call.
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub has_more: bool,
+    pub total: u64,
+}
+
+pub struct DumontClientBuilder {
+    base_url: String,
+    timeout: Duration,
+    connect_timeout: Duration,
+    retry_policy: RetryPolicy,
+    page_size: u32,
+    bearer_token: Option<String>,
+}
+
+impl DumontClientBuilder {
+    /// Total time allowed for a single attempt of a request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Number of items requested per page by the `list_*` streams.
+    pub fn page_size(mut self, page_size: u32) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Sent as `Authorization: Bearer <token>` with every request, for servers
+    /// that sit behind an authenticating proxy.
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.bearer_token = Some(token.into());
+        self
+    }
+
+    pub fn build(self) -> Result<DumontClient> {
+        let base_url = Url::parse(&self.base_url).map_err(|e| ClientError::InvalidUrl {
+            reason: e.to_string(),
+        })?;
+        if base_url.cannot_be_a_base() {
+            return Err(ClientError::InvalidUrl {
+                reason: format!("{} can not be used as a base URL", base_url),
+            });
+        }
+
+        let mut headers = HeaderMap::new();
+        if let Some(token) = &self.bearer_token {
+            let mut value = HeaderValue::from_str(&format!("Bearer {}", token)).map_err(|e| {
+                ClientError::InvalidToken {
+                    reason: e.to_string(),
+                }
+            })?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+
+        let http = reqwest::Client::builder()
+            .default_headers(headers)
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout)
+            .build()?;
+
+        Ok(DumontClient {
+            http,
+            base_url,
+            retry_policy: self.retry_policy,
+            page_size: self.page_size,
+            last_write: Default::default(),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct ResponseEnvelope<T> {
+    status: ResponseStatus,
+    data: Option<T>,
+    page: Option<PaginationState>,
+}
+
+#[derive(Deserialize)]
+struct ResponseStatus {
+    #[serde(default)]
+    error: Option<Vec<String>>,
+    #[serde(default)]
+    error_code: Option<String>,
+    #[serde(default)]
+    error_details: BTreeMap<String, String>,
+    #[serde(default)]
+    violations: Option<Vec<PolicyViolation>>,
+}
+
+impl From<ResponseStatus> for ErrorBody {
+    fn from(status: ResponseStatus) -> Self {
+        Self {
+            errors: status.error.unwrap_or_default(),
+            error_code: status.error_code,
+            details: status.error_details,
+            violations: status.violations.unwrap_or_default(),
+        }
+    }
+}
+
+/// Client for the Dumont REST API. Cloning is cheap, the clones share a
+/// connection pool.
+///
+/// The client sends back the latest write marker the server gave it, so it reads
+/// what it wrote even when the server reads from a lagging replica. Clones share
+/// the marker too.
+#[derive(Clone)]
+pub struct DumontClient {
+    http: reqwest::Client,
+    base_url: Url,
+    retry_policy: RetryPolicy,
+    page_size: u32,
+    last_write: Arc<Mutex<Option<i64>>>,
+}
+
+impl DumontClient {
+    pub fn builder(base_url: impl Into<String>) -> DumontClientBuilder {
+        DumontClientBuilder {
+            base_url: base_url.into(),
+            timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(5),
+            retry_policy: Default::default(),
+            page_size: 50,
+            bearer_token: None,
+        }
+    }
+
+    pub fn new(base_url: impl Into<String>) -> Result<Self> {
+        Self::builder(base_url).build()
+    }
+
+    pub async fn create_org(&self, org: &str) -> Result<GetOrganization> {
+        let body = CreateOrganization {
+            org: org.to_owned(),
+        };
+        self.send(Method::POST, &["api", "org"], Some(&body)).await
+    }
+
+    pub async fn get_org(&self, org: &str) -> Result<GetOrganization> {
+        self.send(Method::GET, &["api", "org", org], None::<&()>)
+            .await
+    }
+
+    pub async fn delete_org(&self, org: &str) -> Result<bool> {
+        let status: DeleteStatus = self
+            .send(Method::DELETE, &["api", "org", org], None::<&()>)
+            .await?;
+        Ok(status.deleted)
+    }
+
+    pub async fn list_orgs_page(&self, page: u32, size: u32) -> Result<Page<GetOrganization>> {
+        self.get_page(self.url(&["api", "org"])?, page, size).await
+    }
+
+    /// Every organization, fetching further pages as the stream is consumed.
+    pub fn list_orgs(&self) -> impl Stream<Item = Result<GetOrganization>> + Send + 'static {
+        self.paginate(self.url(&["api", "org"]))
+    }
+
+    pub async fn create_repo(&self, org: &str, repo: &CreateRepository) -> Result<GetRepository> {
+        self.send(Method::POST, &["api", "org", org, "repo"], Some(repo))
+            .await
+    }
+
+    pub async fn get_repo(&self, org: &str, repo: &str) -> Result<GetRepository> {
+        self.send(Method::GET, &["api", "org", org, "repo", repo], None::<&()>)
+            .await
+    }
+
+    pub async fn update_repo(
+        &self,
+        org: &str,
+        repo: &str,
+        update: &UpdateRepository,
+    ) -> Result<GetRepository> {
+        self.send(
+            Method::PUT,
+            &["api", "org", org, "repo", repo],
+            Some(update),
+        )
+        .await
+    }
+
+    pub async fn delete_repo(&self, org: &str, repo: &str) -> Result<bool> {
+        let status: DeleteStatus = self
+            .send(
+                Method::DELETE,
+                &["api", "org", org, "repo", repo],
+                None::<&()>,
+            )
+            .await?;
+        Ok(status.deleted)
+    }
+
+    pub async fn list_repos_page(
+        &self,
+        org: &str,
+        page: u32,
+        size: u32,
+    ) -> Result<Page<GetRepository>> {
+        self.get_page(self.url(&["api", "org", org, "repo"])?, page, size)
+            .await
+    }
+
+    /// Every repository in `org`, fetching further pages as the stream is consumed.
+    pub fn list_repos(
+        &self,
+        org: &str,
+    ) -> impl Stream<Item = Result<GetRepository>> + Send + 'static {
+        self.paginate(self.url(&["api", "org", org, "repo"]))
+    }
+
+    pub async fn create_version(
+        &self,
+        org: &str,
+        repo: &str,
+        version: &CreateVersion,
+    ) -> Result<GetVersion> {
+        self.send(
+            Method::POST,
+            &["api", "org", org, "repo", repo, "version"],
+            Some(version),
+        )
+        .await
+    }
+
+    pub async fn get_version(&self, org: &str, repo: &str, version: &str) -> Result<GetVersion> {
+        self.send(
+            Method::GET,
+            &["api", "org", org, "repo", repo, "version", version],
+            None::<&()>,
+        )
+        .await
+    }
+
+    pub async fn update_version(
+        &self,
+        org: &str,
+        repo: &str,
+        version: &str,
+        update: &UpdateVersion,
+    ) -> Result<GetVersion> {
+        self.send(
+            Method::PUT,
+            &["api", "org", org, "repo", repo, "version", version],
+            Some(update),
+        )
+        .await
+    }
+
+    pub async fn delete_version(&self, org: &str, repo: &str, version: &str) -> Result<bool> {
+        let status: DeleteStatus = self
+            .send(
+                Method::DELETE,
+                &["api", "org", org, "repo", repo, "version", version],
+                None::<&()>,
+            )
+            .await?;
+        Ok(status.deleted)
+    }
+
+    pub async fn list_versions_page(
+        &self,
+        org: &str,
+        repo: &str,
+        page: u32,
+        size: u32,
+    ) -> Result<Page<GetVersion>> {
+        self.get_page(
+            self.url(&["api", "org", org, "repo", repo, "version"])?,
+            page,
+            size,
+        )
+        .await
+    }
+
+    /// Every version in `org/repo`, fetching further pages as the stream is consumed.
+    pub fn list_versions(
+        &self,
+        org: &str,
+        repo: &str,
+    ) -> impl Stream<Item = Result<GetVersion>> + Send + 'static {
+        self.paginate(self.url(&["api", "org", org, "repo", repo, "version"]))
+    }
+
+    /// Evaluates every stored repo and version against the server's current
+    /// policies. Nothing is changed.
+    pub async fn compliance_report(&self) -> Result<ComplianceReport> {
+        self.send(Method::GET, &["api", "compliance"], None::<&()>)
+            .await
+    }
+
+    /// Deletes the versions expired by the retention rules of the server's current
+    /// policies. With `dry_run`, the expired versions are only listed.
+    ///
+    /// This is served from the admin address, so the client has to be built with
+    /// the admin URL rather than the one of the API.
+    pub async fn collect_garbage(&self, dry_run: bool) -> Result<GcReport> {
+        let mut url = self.url(&["admin", "gc"])?;
+        if dry_run {
+            url.query_pairs_mut().append_pair("dry_run", "true");
+        }
+
+        let envelope: ResponseEnvelope<GcReport> =
+            self.execute(Method::POST, url, None::<&()>).await?;
+        envelope.data.ok_or_else(|| ClientError::InvalidResponse {
+            reason: "response did not contain any data".to_owned(),
+        })
+    }
+
+    /// Builds a URL from path segments, escaping each of them.
+    fn url(&self, segments: &[&str]) -> Result<Url> {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| ClientError::InvalidUrl {
+                reason: format!("{} can not be used as a base URL", self.base_url),
+            })?
+            .pop_if_empty()
+            .extend(segments);
+        Ok(url)
+    }
+
+    fn paginate<T>(&self, url: Result<Url>) -> impl Stream<Item = Result<T>> + Send + 'static
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let client = self.clone();
+        let page_size = self.page_size;
+
+        stream::try_unfold((url, Some(0)), move |(url, next_page)| {
+            let client = client.clone();
+            async move {
+                let page_number = match next_page {
+                    Some(page_number) => page_number,
+                    None => return Ok::<_, ClientError>(None),
+                };
+                let url = url?;
+                let page: Page<T> = client.get_page(url.clone(), page_number, page_size).await?;
+                let next_page = if page.has_more {
+                    Some(page_number + 1)
+                } else {
+                    None
+                };
+
+                let items = stream::iter(page.items.into_iter().map(Ok));
+                Ok(Some((items, (Ok(url), next_page))))
+            }
+        })
+        .try_flatten()
+    }
+
+    async fn get_page<T: DeserializeOwned>(
+        &self,
+        mut url: Url,
+        page: u32,
+        size: u32,
+    ) -> Result<Page<T>> {
+        url.query_pairs_mut()
+            .append_pair("page", &page.to_string())
+            .append_pair("size", &size.to_string());
+
+        let envelope: ResponseEnvelope<Vec<T>> =
+            self.execute(Method::GET, url, None::<&()>).await?;
+        let page = envelope.page.unwrap_or(PaginationState {
+            has_more: false,
+            total: 0,
+        });
+
+        Ok(Page {
+            items: envelope.data.unwrap_or_default(),
+            has_more: page.has_more,
+            total: page.total,
+        })
+    }
+
+    async fn send<T, B>(&self, method: Method, segments: &[&str], body: Option<&B>) -> Result<T>
+    where
+        T: DeserializeOwned,
+        B: Serialize + ?Sized,
+    {
+        let envelope: ResponseEnvelope<T> = self.execute(method, self.url(segments)?, body).await?;
+        envelope.data.ok_or_else(|| ClientError::InvalidResponse {
+            reason: "response did not contain any data".to_owned(),
+        })
+    }
+
+    async fn execute<T, B>(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<&B>,
+    ) -> Result<ResponseEnvelope<T>>
+    where
+        T: DeserializeOwned,
+        B: Serialize + ?Sized,
+    {
+        let mut attempt = 0;
+        loop {
+            let mut request = self.http.request(method.clone(), url.clone());
+            if let Some(body) = body {
+                request = request.json(body);
+            }
+            let last_write = *self.last_write();
+            if let Some(last_write) = last_write {
+                request = request.header(LAST_WRITE_HEADER, last_write);
+            }
+
+            let result = match request.send().await {
+                Ok(response) => {
+                    self.record_last_write(response.headers());
+                    Self::decode(response).await
+                }
+                Err(e) => Err(e.into()),
+            };
+
+            match result {
+                Err(e) if attempt < self.retry_policy.max_retries && should_retry(&method, &e) => {
+                    let backoff = self.retry_policy.backoff(attempt);
+                    debug!(
+                        "{} {} failed ({}), retrying in {:?}",
+                        method, url, e, backoff
+                    );
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn last_write(&self) -> std::sync::MutexGuard<'_, Option<i64>> {
+        self.last_write
+            .lock()
+            .expect("Last write lock to not be poisoned")
+    }
+
+    /// Keeps the latest of the write markers, responses can arrive out of order.
+    fn record_last_write(&self, headers: &HeaderMap) {
+        let marker = headers
+            .get(LAST_WRITE_HEADER)
+            .and_then(|value| value.to_str().ok()?.parse().ok());
+        if let Some(marker) = marker {
+            let mut last_write = self.last_write();
+            *last_write = std::cmp::max(*last_write, Some(marker));
+        }
+    }
+
+    async fn decode<T: DeserializeOwned>(
+        response: reqwest::Response,
+    ) -> Result<ResponseEnvelope<T>> {
+        let code = response.status();
+        let body = response.bytes().await?;
+
+        match serde_json::from_slice::<ResponseEnvelope<T>>(&body) {
+            Ok(envelope) if code.is_success() => Ok(envelope),
+            Ok(envelope) => Err(ClientError::from_status(code, envelope.status.into())),
+            Err(e) if code.is_success() => Err(ClientError::InvalidResponse {
+                reason: e.to_string(),
+            }),
+            // Something other than dumont answered, e.g. a proxy in front of it.
+            Err(_) => Err(ClientError::from_status(
+                code,
+                ErrorBody {
+                    errors: vec![String::from_utf8_lossy(&body).into_owned()],
+                    ..Default::default()
+                },
+            )),
+        }
+    }
+}
+
+fn should_retry(method: &Method, error: &ClientError) -> bool {
+    let idempotent = method != Method::POST;
+    match error {
+        ClientError::Transport { source } => {
+            source.is_connect() || (idempotent && source.is_timeout())
+        }
+        ClientError::Server { code, .. } => idempotent && (502..=504).contains(code),
+        _ => false,
+    }
+}
+
+#[test]
+fn validate_url_escapes_segments() {
+    let client = DumontClient::new("http://localhost:3030/prefix/").unwrap();
+    let url = client.url(&["api", "org", "a/b c"]).unwrap();
+    assert_eq!(
+        url.as_str(),
+        "http://localhost:3030/prefix/api/org/a%2Fb%20c"
+    );
+}
+
+#[test]
+fn validate_backoff_is_capped() {
+    let policy = RetryPolicy::default();
+    assert_eq!(policy.backoff(0), Duration::from_millis(100));
+    assert_eq!(policy.backoff(2), Duration::from_millis(400));
+    assert_eq!(policy.backoff(10), Duration::from_secs(2));
+}
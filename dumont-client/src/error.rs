@@ -0,0 +1,87 @@
+use dumont_api_models::PolicyViolation;
+use reqwest::StatusCode;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// Errors returned by the client. Responses from the server are mapped onto a
+/// variant by their status code, and keep what the server said in `ErrorBody`.
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("Not found: {}", .body.errors.join(", "))]
+    NotFound { body: ErrorBody },
+    #[error("Already exists: {}", .body.errors.join(", "))]
+    AlreadyExists { body: ErrorBody },
+    #[error("Bad request: {}", .body.errors.join(", "))]
+    BadRequest { body: ErrorBody },
+    #[error("Server responded with {code}: {}", .body.errors.join(", "))]
+    Server { code: u16, body: ErrorBody },
+    #[error("Unable to understand the response from the server: {reason}")]
+    InvalidResponse { reason: String },
+    #[error("Unable to build request URL: {reason}")]
+    InvalidUrl { reason: String },
+    #[error("Unable to use the bearer token: {reason}")]
+    InvalidToken { reason: String },
+    #[error(transparent)]
+    Transport {
+        #[from]
+        source: reqwest::Error,
+    },
+}
+
+/// The `status` of an error response, see the Errors section of `docs/api.md`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorBody {
+    /// The messages from `status.error`.
+    pub errors: Vec<String>,
+    /// A stable name for the error, such as `REPO_NOT_FOUND`. None when something
+    /// other than dumont answered, e.g. a proxy in front of it.
+    pub error_code: Option<String>,
+    /// The fields the error is about, such as `org` and `repo`.
+    pub details: BTreeMap<String, String>,
+    /// Every policy violation behind the error, in the same order as `errors`.
+    pub violations: Vec<PolicyViolation>,
+}
+
+impl ClientError {
+    pub(crate) fn from_status(code: StatusCode, body: ErrorBody) -> Self {
+        match code {
+            StatusCode::NOT_FOUND => ClientError::NotFound { body },
+            StatusCode::CONFLICT => ClientError::AlreadyExists { body },
+            StatusCode::BAD_REQUEST => ClientError::BadRequest { body },
+            _ => ClientError::Server {
+                code: code.as_u16(),
+                body,
+            },
+        }
+    }
+
+    /// What the server said about the failure, when it answered with an error.
+    pub fn body(&self) -> Option<&ErrorBody> {
+        match self {
+            ClientError::NotFound { body }
+            | ClientError::AlreadyExists { body }
+            | ClientError::BadRequest { body }
+            | ClientError::Server { body, .. } => Some(body),
+            _ => None,
+        }
+    }
+
+    /// The messages the server gave for the failure, if it gave any.
+    pub fn errors(&self) -> &[String] {
+        self.body().map(|body| &body.errors[..]).unwrap_or_default()
+    }
+
+    /// The stable name of the error, such as `REPO_NOT_FOUND`, if the server gave one.
+    pub fn error_code(&self) -> Option<&str> {
+        self.body().and_then(|body| body.error_code.as_deref())
+    }
+
+    /// The policy violations the server rejected the request for.
+    pub fn violations(&self) -> &[PolicyViolation] {
+        self.body()
+            .map(|body| &body.violations[..])
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,21 @@
+//! Typed async client for the Dumont REST API.
+//!
+//! ```no_run
+//! # async fn example() -> dumont_client::Result<()> {
+//! use dumont_client::DumontClient;
+//! use futures_util::TryStreamExt;
+//!
+//! let client = DumontClient::new("http://localhost:3030")?;
+//! client.create_org("example").await?;
+//!
+//! let orgs: Vec<_> = client.list_orgs().try_collect().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod client;
+mod error;
+
+pub use client::{DumontClient, DumontClientBuilder, Page, RetryPolicy};
+pub use dumont_api_models as models;
+pub use error::{ClientError, ErrorBody, Result};
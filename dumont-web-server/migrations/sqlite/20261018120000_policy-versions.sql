@@ -0,0 +1,21 @@
+CREATE TABLE policy_version(
+    policy_version_id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    evaluation TEXT NOT NULL,
+    -- Versions are never changed, so the same policies are stored once.
+    document_hash TEXT NOT NULL UNIQUE,
+    comment TEXT,
+    active BOOLEAN NOT NULL DEFAULT false,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- At most one version is enforced at a time.
+CREATE UNIQUE INDEX policy_version_active ON policy_version(active) WHERE active;
+
+CREATE TABLE policy_definition(
+    policy_definition_id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    policy_version_id INTEGER NOT NULL REFERENCES policy_version(policy_version_id) ON DELETE CASCADE,
+    policy_name TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    definition TEXT NOT NULL,
+    UNIQUE(policy_version_id, policy_name)
+);
@@ -0,0 +1,719 @@
+//! Admin-only routes, served from the admin address rather than the public API.
+
+use super::prelude::*;
+use crate::api::repos::GetRepository;
+use crate::api::versions::GetVersion;
+use crate::backend::models::DataStorePolicyVersion;
+use crate::models::GenericLabels;
+use crate::policy::PolicyDefinitionContainer;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use tracing_attributes::instrument;
+use utoipa::{IntoParams, ToSchema};
+use warp::{http::StatusCode, Filter, Rejection, Reply};
+
+pub use dumont_api_models::{ExpiredVersion, GcReport};
+
+/// Replaces the labels of a repo or version, including immutable ones.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct OverrideLabels {
+    #[serde(flatten)]
+    pub labels: GenericLabels,
+    /// Why the override was needed, recorded in the audit log.
+    pub reason: String,
+}
+
+impl OverrideLabels {
+    fn validate(self) -> Result<Self, ErrorStatusResponse> {
+        if self.reason.trim().is_empty() {
+            return Err(ErrorStatusResponse::from_error_message(
+                StatusCode::BAD_REQUEST,
+                "An override requires a reason".to_owned(),
+            )
+            .with_error_code("OVERRIDE_REASON_REQUIRED", vec![]));
+        }
+
+        Ok(self)
+    }
+}
+
+/// A new policy version, in the same structure as the policy document.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreatePolicyVersion {
+    #[serde(flatten)]
+    pub document: PolicyDefinitionContainer,
+    /// Why the policies changed, kept with the version.
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GetPolicyVersion {
+    pub version: i32,
+    pub hash: String,
+    pub active: bool,
+    pub comment: Option<String>,
+    pub created_at: String,
+    #[serde(flatten)]
+    pub document: PolicyDefinitionContainer,
+}
+
+impl From<DataStorePolicyVersion> for GetPolicyVersion {
+    fn from(version: DataStorePolicyVersion) -> Self {
+        Self {
+            version: version.version,
+            hash: version.hash,
+            active: version.active,
+            comment: version.comment,
+            created_at: version.created_at.to_rfc3339(),
+            document: version.document,
+        }
+    }
+}
+
+impl From<crate::backend::models::DataStoreGcReport> for GcReport {
+    fn from(model: crate::backend::models::DataStoreGcReport) -> Self {
+        Self {
+            dry_run: model.dry_run,
+            versions_checked: model.versions_checked,
+            expired: model
+                .expired
+                .into_iter()
+                .map(|expired| ExpiredVersion {
+                    org: expired.org_name,
+                    repo: expired.repo_name,
+                    version: expired.version,
+                    policy: expired.policy_name,
+                    created_at: expired.created_at.to_rfc3339(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GcOptions {
+    /// List the expired versions without deleting them. Defaults to false.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Policy documents are larger than the bodies of the public API.
+fn policy_body() -> impl Filter<Extract = (CreatePolicyVersion,), Error = Rejection> + Clone {
+    warp::body::content_length_limit(1024 * 256).and(warp::body::json())
+}
+
+pub fn create_admin_api(
+    db: crate::Backend,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    override_repo_labels(db.clone())
+        .or(override_version_labels(db.clone()))
+        .or(reload_policies(db.clone()))
+        .or(list_policy_versions(db.clone()))
+        .or(create_policy_version(db.clone()))
+        .or(get_policy_version(db.clone()))
+        .or(activate_policy_version(db.clone()))
+        .or(delete_policy_version(db.clone()))
+        .or(collect_garbage(db))
+}
+
+fn override_repo_labels(
+    db: crate::Backend,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    info!("PUT /admin/org/{{org}}/repo/{{repo}}");
+    warp::path!("admin" / "org" / String / "repo" / String)
+        .and(warp::put())
+        .and(json_body::<OverrideLabels>())
+        .and(with_db(db))
+        .and_then(override_repo_labels_impl)
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/org/{org}/repo/{repo}",
+    tag = "admin",
+    params(("org" = String, Path, description = "Name of the organization"), ("repo" = String, Path, description = "Name of the repository")),
+    request_body = OverrideLabels,
+    responses(
+        (status = 200, description = "Repository labels were replaced, including immutable ones", body = RepositoryResponse),
+        (status = 400, description = "No reason was given, or the labels were rejected by a policy", body = ErrorResponse),
+        (status = 404, description = "Repository not found", body = ErrorResponse),
+    )
+)]
+#[instrument(name = "admin_repo_override", skip(db, update))]
+async fn override_repo_labels_impl(
+    org: String,
+    repo: String,
+    update: OverrideLabels,
+    db: crate::Backend,
+) -> Result<impl Reply, Rejection> {
+    let update = update.validate()?;
+    let result = db
+        .override_repo_labels(&org, &repo, update.labels.labels, &update.reason)
+        .await;
+    let result = result
+        .map(GetRepository::from)
+        .map(PaginatedWrapperResponse::without_page)
+        .map_err(ErrorStatusResponse::from);
+    wrap_body(result)
+}
+
+fn override_version_labels(
+    db: crate::Backend,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    info!("PUT /admin/org/{{org}}/repo/{{repo}}/version/{{version}}");
+    warp::path!("admin" / "org" / String / "repo" / String / "version" / String)
+        .and(warp::put())
+        .and(json_body::<OverrideLabels>())
+        .and(with_db(db))
+        .and_then(override_version_labels_impl)
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/org/{org}/repo/{repo}/version/{version}",
+    tag = "admin",
+    params(("org" = String, Path, description = "Name of the organization"), ("repo" = String, Path, description = "Name of the repository"), ("version" = String, Path, description = "Name of the version")),
+    request_body = OverrideLabels,
+    responses(
+        (status = 200, description = "Version labels were replaced, including immutable ones", body = VersionResponse),
+        (status = 400, description = "No reason was given, or the labels were rejected by a policy", body = ErrorResponse),
+        (status = 404, description = "Version not found", body = ErrorResponse),
+    )
+)]
+#[instrument(name = "admin_version_override", skip(db, update))]
+async fn override_version_labels_impl(
+    org: String,
+    repo: String,
+    version: String,
+    update: OverrideLabels,
+    db: crate::Backend,
+) -> Result<impl Reply, Rejection> {
+    let update = update.validate()?;
+    let result = db
+        .override_version_labels(&org, &repo, &version, update.labels, &update.reason)
+        .await;
+    let result = result
+        .map(GetVersion::from)
+        .map(PaginatedWrapperResponse::without_page)
+        .map_err(ErrorStatusResponse::from);
+    wrap_body(result)
+}
+
+fn reload_policies(
+    db: crate::Backend,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    info!("POST /admin/policy/reload");
+    warp::path!("admin" / "policy" / "reload")
+        .and(warp::post())
+        .and(with_db(db))
+        .and_then(reload_policies_impl)
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/policy/reload",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The policies that are enforced after the reload", body = PolicyReloadResponse),
+        (status = 400, description = "The policies failed to load, the current ones are kept", body = ErrorResponse),
+    )
+)]
+#[instrument(name = "admin_policy_reload", skip(db))]
+async fn reload_policies_impl(db: crate::Backend) -> Result<impl Reply, Rejection> {
+    let result = db
+        .reload_policies()
+        .await
+        .map(PaginatedWrapperResponse::without_page)
+        .map_err(ErrorStatusResponse::from);
+    wrap_body(result)
+}
+
+fn list_policy_versions(
+    db: crate::Backend,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    info!("GET /admin/policy/version");
+    warp::path!("admin" / "policy" / "version")
+        .and(warp::get())
+        .and(warp::query::<ApiPagination>())
+        .and(with_db(db))
+        .and_then(list_policy_versions_impl)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/policy/version",
+    tag = "admin",
+    params(ApiPagination),
+    responses(
+        (status = 200, description = "A page of policy versions, newest first", body = PolicyVersionListResponse),
+    )
+)]
+#[instrument(name = "admin_policy_version_list", skip(db))]
+async fn list_policy_versions_impl(
+    pagination: ApiPagination,
+    db: crate::Backend,
+) -> Result<impl Reply, Rejection> {
+    let result = db
+        .list_policy_versions(pagination.into())
+        .await
+        .map(|list| {
+            let body: Vec<GetPolicyVersion> = list
+                .versions
+                .into_iter()
+                .map(GetPolicyVersion::from)
+                .collect();
+            PaginatedWrapperResponse::with_page(body, list.total_count, list.has_more)
+        })
+        .map_err(ErrorStatusResponse::from);
+    wrap_body(result)
+}
+
+fn create_policy_version(
+    db: crate::Backend,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    info!("POST /admin/policy/version");
+    warp::path!("admin" / "policy" / "version")
+        .and(warp::post())
+        .and(policy_body())
+        .and(with_db(db))
+        .and_then(create_policy_version_impl)
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/policy/version",
+    tag = "admin",
+    request_body = CreatePolicyVersion,
+    responses(
+        (status = 200, description = "Policy version was stored, inactive", body = PolicyVersionResponse),
+        (status = 400, description = "The policies are invalid", body = ErrorResponse),
+        (status = 409, description = "A version has the same policies", body = ErrorResponse),
+    )
+)]
+#[instrument(name = "admin_policy_version_create", skip(db, create))]
+async fn create_policy_version_impl(
+    create: CreatePolicyVersion,
+    db: crate::Backend,
+) -> Result<impl Reply, Rejection> {
+    let result = db
+        .create_policy_version(create.document, create.comment.as_deref())
+        .await
+        .map(GetPolicyVersion::from)
+        .map(PaginatedWrapperResponse::without_page)
+        .map_err(ErrorStatusResponse::from);
+    wrap_body(result)
+}
+
+fn get_policy_version(
+    db: crate::Backend,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    info!("GET /admin/policy/version/{{version}}");
+    warp::path!("admin" / "policy" / "version" / i32)
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(get_policy_version_impl)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/policy/version/{version}",
+    tag = "admin",
+    params(("version" = i32, Path, description = "Number of the policy version")),
+    responses(
+        (status = 200, description = "The policy version", body = PolicyVersionResponse),
+        (status = 404, description = "Policy version not found", body = ErrorResponse),
+    )
+)]
+#[instrument(name = "admin_policy_version_get", skip(db))]
+async fn get_policy_version_impl(
+    version: i32,
+    db: crate::Backend,
+) -> Result<impl Reply, Rejection> {
+    let result = db
+        .get_policy_version(version)
+        .await
+        .map(GetPolicyVersion::from)
+        .map(PaginatedWrapperResponse::without_page)
+        .map_err(ErrorStatusResponse::from);
+    wrap_body(result)
+}
+
+fn activate_policy_version(
+    db: crate::Backend,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    info!("POST /admin/policy/version/{{version}}/activate");
+    warp::path!("admin" / "policy" / "version" / i32 / "activate")
+        .and(warp::post())
+        .and(with_db(db))
+        .and_then(activate_policy_version_impl)
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/policy/version/{version}/activate",
+    tag = "admin",
+    params(("version" = i32, Path, description = "Number of the policy version")),
+    responses(
+        (status = 200, description = "Policy version is now the enforced one", body = PolicyVersionResponse),
+        (status = 404, description = "Policy version not found", body = ErrorResponse),
+    )
+)]
+#[instrument(name = "admin_policy_version_activate", skip(db))]
+async fn activate_policy_version_impl(
+    version: i32,
+    db: crate::Backend,
+) -> Result<impl Reply, Rejection> {
+    let result = db
+        .activate_policy_version(version)
+        .await
+        .map(GetPolicyVersion::from)
+        .map(PaginatedWrapperResponse::without_page)
+        .map_err(ErrorStatusResponse::from);
+    wrap_body(result)
+}
+
+fn delete_policy_version(
+    db: crate::Backend,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    info!("DELETE /admin/policy/version/{{version}}");
+    warp::path!("admin" / "policy" / "version" / i32)
+        .and(warp::delete())
+        .and(with_db(db))
+        .and_then(delete_policy_version_impl)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/policy/version/{version}",
+    tag = "admin",
+    params(("version" = i32, Path, description = "Number of the policy version")),
+    responses(
+        (status = 200, description = "Policy version was deleted", body = DeleteResponse),
+        (status = 400, description = "The active policy version can not be deleted", body = ErrorResponse),
+        (status = 404, description = "Policy version not found", body = ErrorResponse),
+    )
+)]
+#[instrument(name = "admin_policy_version_delete", skip(db))]
+async fn delete_policy_version_impl(
+    version: i32,
+    db: crate::Backend,
+) -> Result<impl Reply, Rejection> {
+    let result = db
+        .delete_policy_version(version)
+        .await
+        .map(DeleteStatus::from)
+        .map(PaginatedWrapperResponse::without_page)
+        .map_err(ErrorStatusResponse::from);
+    wrap_body(result)
+}
+
+fn collect_garbage(
+    db: crate::Backend,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    info!("POST /admin/gc");
+    warp::path!("admin" / "gc")
+        .and(warp::post())
+        .and(warp::query::<GcOptions>())
+        .and(with_db(db))
+        .and_then(collect_garbage_impl)
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/gc",
+    tag = "admin",
+    params(GcOptions),
+    responses(
+        (status = 200, description = "Versions expired by the retention rules of the current policies", body = GcResponse),
+    )
+)]
+#[instrument(name = "admin_gc", skip(db))]
+async fn collect_garbage_impl(
+    options: GcOptions,
+    db: crate::Backend,
+) -> Result<impl Reply, Rejection> {
+    let result = db
+        .collect_garbage(options.dry_run)
+        .await
+        .map(GcReport::from)
+        .map(PaginatedWrapperResponse::without_page)
+        .map_err(ErrorStatusResponse::from);
+    wrap_body(result)
+}
+
+#[cfg(test)]
+mod integ_test {
+    use crate::backend::DefaultBackend;
+    use crate::policy::*;
+    use crate::test_utils::*;
+    use json::object;
+    use serial_test::serial;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use warp::test::request;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn test_override_immutable_label() {
+        let backend = Arc::new(DefaultBackend {
+            database: make_storage().await,
+            policy_container: RealizedPolicyContainer {
+                policies: vec![RealizedPolicy::test_new_different_labels(
+                    "example/.*",
+                    vec![RequiredLabel::new("owner", vec![], None).immutable()],
+                    vec![],
+                )],
+                ..Default::default()
+            }
+            .into(),
+        });
+        backend.create_organization("example").await.unwrap();
+        backend
+            .create_repo(
+                "example",
+                "repo-1",
+                BTreeMap::from_iter(vec![("owner".to_owned(), "team-a".to_owned())]),
+            )
+            .await
+            .unwrap();
+        let filter = crate::api::create_admin_filters(backend);
+
+        let response = request()
+            .method("PUT")
+            .path("/admin/org/example/repo/repo-1")
+            .json(&serde_json::json!({ "labels": { "owner": "team-b" }, "reason": "  " }))
+            .reply(&filter)
+            .await;
+        assert_response(
+            response,
+            http::StatusCode::BAD_REQUEST,
+            object! {
+                "status": {
+                    "code": 400,
+                    "error": ["An override requires a reason"],
+                    "error_code": "OVERRIDE_REASON_REQUIRED",
+                },
+            },
+        );
+
+        let response = request()
+            .method("PUT")
+            .path("/admin/org/example/repo/repo-1")
+            .json(&serde_json::json!({ "labels": { "owner": "team-b" }, "reason": "team-a was renamed" }))
+            .reply(&filter)
+            .await;
+        assert_response(
+            response,
+            http::StatusCode::OK,
+            object! {
+                "status": { "code": 200 },
+                "data": {
+                    "org": "example",
+                    "repo": "repo-1",
+                    "labels": { "dumont.io/created-at": TEST_CREATED_AT, "owner": "team-b" },
+                    "applied_policies": ["test"],
+                },
+            },
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn test_reload_without_policy_document() {
+        let filter = crate::api::create_admin_filters(make_backend().await);
+
+        let response = request()
+            .method("POST")
+            .path("/admin/policy/reload")
+            .reply(&filter)
+            .await;
+        assert_response(
+            response,
+            http::StatusCode::BAD_REQUEST,
+            object! {
+                "status": {
+                    "code": 400,
+                    "error": ["No policy version is active and the server was started without a policy document, so there is nothing to reload."],
+                    "error_code": "POLICY_NO_POLICY_DOCUMENT",
+                },
+            },
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn test_policy_versions() {
+        let backend = make_backend().await;
+        backend.create_organization("example").await.unwrap();
+        let filter = crate::api::create_admin_filters(backend.clone());
+
+        let response = request()
+            .method("POST")
+            .path("/admin/policy/version")
+            .json(&serde_json::json!({
+                "policy": [{
+                    "name": "owned",
+                    "repository_pattern": ".*",
+                    "required_repo_labels": [{ "name": "owner", "pattern": "team-[a-z" }],
+                    "required_version_labels": [],
+                }],
+            }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+
+        let response = request()
+            .method("POST")
+            .path("/admin/policy/version")
+            .json(&serde_json::json!({
+                "comment": "Every repo needs an owner",
+                "policy": [{
+                    "name": "owned",
+                    "repository_pattern": ".*",
+                    "required_repo_labels": [{ "name": "owner" }],
+                    "required_version_labels": [],
+                }],
+            }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let created: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        let version = created["data"]["version"].as_i64().unwrap();
+        assert_eq!(created["data"]["active"], false);
+        assert_eq!(created["data"]["comment"], "Every repo needs an owner");
+
+        // Nothing is enforced until the version is activated.
+        backend
+            .create_repo("example", "repo-1", BTreeMap::new())
+            .await
+            .unwrap();
+
+        let response = request()
+            .method("POST")
+            .path(&format!("/admin/policy/version/{}/activate", version))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert_eq!(
+            backend.policy_container.current().hash,
+            created["data"]["hash"].as_str().unwrap()
+        );
+        assert_eq!(
+            backend
+                .create_repo("example", "repo-2", BTreeMap::new())
+                .await
+                .unwrap_err()
+                .to_string(),
+            "Policy `owned` required that label `owner` be set, however it was not and no default was specified."
+        );
+
+        let response = request()
+            .method("GET")
+            .path("/admin/policy/version")
+            .reply(&filter)
+            .await;
+        let listed: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(listed["data"][0]["version"], version);
+        assert_eq!(listed["data"][0]["active"], true);
+        assert_eq!(listed["data"][0]["policy"][0]["name"], "owned");
+
+        let response = request()
+            .method("DELETE")
+            .path(&format!("/admin/policy/version/{}", version))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+
+        let response = request()
+            .method("GET")
+            .path("/admin/policy/version/1000")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn test_collect_garbage() {
+        let backend = Arc::new(DefaultBackend {
+            database: make_storage().await,
+            policy_container: RealizedPolicyContainer {
+                policies: vec![RealizedPolicy::test_new("example/.*", vec![])
+                    .with_version_scheme(VersionScheme::Semver, None)
+                    .with_retention(vec![RetentionRule::new(
+                        LabelSelector::new(vec![("release_state", "pre-release")]),
+                        Some(1),
+                        None,
+                    )])],
+                ..Default::default()
+            }
+            .into(),
+        });
+        backend.create_organization("example").await.unwrap();
+        backend
+            .create_repo("example", "repo-1", BTreeMap::new())
+            .await
+            .unwrap();
+        for (version, release_state) in [
+            ("1.0.0-rc.0", "pre-release"),
+            ("1.0.0-rc.1", "pre-release"),
+            ("0.9.0", "released"),
+        ] {
+            let labels =
+                BTreeMap::from_iter(vec![("release_state".to_owned(), release_state.to_owned())]);
+            backend
+                .create_version("example", "repo-1", version, labels)
+                .await
+                .unwrap();
+        }
+        let filter = crate::api::create_admin_filters(backend.clone());
+        let expired = |dry_run: bool| {
+            object! {
+                "status": { "code": 200 },
+                "data": {
+                    "dry_run": dry_run,
+                    "versions_checked": 3,
+                    "expired": [{
+                        "org": "example",
+                        "repo": "repo-1",
+                        "version": "1.0.0-rc.0",
+                        "policy": "test",
+                        "created_at": "2026-01-01T00:00:00+00:00",
+                    }],
+                },
+            }
+        };
+
+        let response = request()
+            .method("POST")
+            .path("/admin/gc?dry_run=true")
+            .reply(&filter)
+            .await;
+        assert_response(response, http::StatusCode::OK, expired(true));
+        assert!(backend
+            .get_version("example", "repo-1", "1.0.0-rc.0")
+            .await
+            .is_ok());
+
+        let response = request()
+            .method("POST")
+            .path("/admin/gc")
+            .reply(&filter)
+            .await;
+        assert_response(response, http::StatusCode::OK, expired(false));
+        assert!(backend
+            .get_version("example", "repo-1", "1.0.0-rc.0")
+            .await
+            .is_err());
+        assert!(backend
+            .get_version("example", "repo-1", "1.0.0-rc.1")
+            .await
+            .is_ok());
+        assert!(backend
+            .get_version("example", "repo-1", "0.9.0")
+            .await
+            .is_ok());
+    }
+}
@@ -0,0 +1,382 @@
+//! Exercises `dumont-client` and `dumont-cli` against the real filters, served
+//! over HTTP from an in-memory SQLite backend.
+
+use super::write_markers;
+use crate::backend::CREATED_AT_LABEL;
+use crate::test_utils::*;
+use dumont_client::models::*;
+use dumont_client::{ClientError, DumontClient, RetryPolicy};
+use futures_util::TryStreamExt;
+use serial_test::serial;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+async fn start_server() -> String {
+    start_servers().await.0
+}
+
+async fn start_servers() -> (String, String) {
+    serve(make_backend().await).await
+}
+
+/// Starts the API and the admin server on the same backend, like `main` does,
+/// returning their URLs.
+async fn serve(backend: crate::Backend) -> (String, String) {
+    let any_port = ([127, 0, 0, 1], 0).into();
+    let filters = super::create_filters(backend.clone()).await;
+    let (addr, server) = write_markers::serve(warp::service(filters), &any_port).unwrap();
+    tokio::spawn(server);
+    let admin_filters = super::create_admin_filters(backend);
+    let (admin_addr, admin_server) =
+        write_markers::serve(warp::service(admin_filters), &any_port).unwrap();
+    tokio::spawn(admin_server);
+
+    (format!("http://{}", addr), format!("http://{}", admin_addr))
+}
+
+async fn start_server_with_client() -> DumontClient {
+    DumontClient::builder(start_server().await)
+        .page_size(2)
+        .retry_policy(RetryPolicy::none())
+        .build()
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+#[serial]
+async fn test_org_lifecycle() {
+    let client = start_server_with_client().await;
+
+    for org in ["org-1", "org-2", "org-3", "org-4", "org-5"] {
+        let created = client.create_org(org).await.unwrap();
+        assert_eq!(created.org, org);
+    }
+
+    let page = client.list_orgs_page(0, 2).await.unwrap();
+    assert_eq!(page.items.len(), 2);
+    assert!(page.has_more);
+    assert_eq!(page.total, 5);
+
+    let orgs: Vec<String> = client
+        .list_orgs()
+        .map_ok(|org| org.org)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(orgs, vec!["org-1", "org-2", "org-3", "org-4", "org-5"]);
+
+    assert_eq!(client.get_org("org-1").await.unwrap().org, "org-1");
+    assert!(client.delete_org("org-1").await.unwrap());
+
+    match client.get_org("org-1").await {
+        Err(ClientError::NotFound { body }) => {
+            assert_eq!(body.errors, vec!["Org org-1 not found"]);
+            assert_eq!(body.error_code.as_deref(), Some("ORG_NOT_FOUND"));
+            assert_eq!(
+                body.details,
+                BTreeMap::from([("org".to_owned(), "org-1".to_owned())])
+            );
+        }
+        other => panic!("expected NotFound, got {:?}", other),
+    }
+
+    match client.create_org("org-2").await {
+        Err(ClientError::AlreadyExists { body }) => {
+            assert_eq!(body.errors, vec!["Org org-2 exists"]);
+            assert_eq!(body.error_code.as_deref(), Some("ORG_ALREADY_EXISTS"));
+        }
+        other => panic!("expected AlreadyExists, got {:?}", other),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+#[serial]
+async fn test_repo_and_version_lifecycle() {
+    let client = start_server_with_client().await;
+    client.create_org("example").await.unwrap();
+
+    let repo = client
+        .create_repo(
+            "example",
+            &CreateRepository {
+                repo: "example-repo".to_owned(),
+                labels: vec![("owner", "team-a")].into(),
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.labels,
+        vec![("owner", "team-a"), (CREATED_AT_LABEL, TEST_CREATED_AT)].into()
+    );
+
+    let repo = client
+        .update_repo(
+            "example",
+            "example-repo",
+            &UpdateRepository {
+                labels: vec![("owner", "team-b")].into(),
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.labels,
+        vec![("owner", "team-b"), (CREATED_AT_LABEL, TEST_CREATED_AT)].into()
+    );
+    assert_eq!(
+        client.get_repo("example", "example-repo").await.unwrap(),
+        repo
+    );
+
+    for version in ["1.0.0", "1.1.0", "1.2.0"] {
+        client
+            .create_version(
+                "example",
+                "example-repo",
+                &CreateVersion {
+                    version: version.to_owned(),
+                    labels: Default::default(),
+                },
+            )
+            .await
+            .unwrap();
+    }
+
+    let version = client
+        .update_version(
+            "example",
+            "example-repo",
+            "1.1.0",
+            &UpdateVersion {
+                labels: vec![("released", "true")].into(),
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        version.labels,
+        vec![("released", "true"), (CREATED_AT_LABEL, TEST_CREATED_AT)].into()
+    );
+
+    let versions: Vec<GetVersion> = client
+        .list_versions("example", "example-repo")
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(versions.len(), 3);
+    assert_eq!(versions[1], version);
+
+    match client
+        .create_version(
+            "example",
+            "example-repo",
+            &CreateVersion {
+                version: "abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyz".to_owned(),
+                labels: Default::default(),
+            },
+        )
+        .await
+    {
+        Err(ClientError::BadRequest { body }) => assert_eq!(body.errors.len(), 1),
+        other => panic!("expected BadRequest, got {:?}", other),
+    }
+
+    assert!(client
+        .delete_version("example", "example-repo", "1.0.0")
+        .await
+        .unwrap());
+    assert!(client.delete_repo("example", "example-repo").await.unwrap());
+
+    let repos: Vec<GetRepository> = client.list_repos("example").try_collect().await.unwrap();
+    assert!(repos.is_empty());
+}
+
+async fn run_cli(server_url: &str, args: &[&str]) -> Result<String, anyhow::Error> {
+    use clap::Parser;
+
+    let opts = dumont_cli::Opts::try_parse_from(
+        ["dumont", "--server-url", server_url]
+            .iter()
+            .chain(args.iter()),
+    )?;
+    let mut out = Vec::new();
+    dumont_cli::run(opts, &mut out).await?;
+    Ok(String::from_utf8(out)?)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+#[serial]
+async fn test_cli_commands() {
+    let (url, admin_url) = start_servers().await;
+
+    run_cli(&url, &["org", "create", "example"]).await.unwrap();
+    run_cli(
+        &url,
+        &["repo", "create", "example", "clu", "-l", "owner=kevin"],
+    )
+    .await
+    .unwrap();
+    run_cli(&url, &["version", "create", "example", "clu", "1.6.9"])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        run_cli(&url, &["repo", "list", "example"]).await.unwrap(),
+        "ORG      REPO  LABELS\nexample  clu   dumont.io/created-at=2026-01-01T00:00:00Z,owner=kevin\n"
+    );
+
+    run_cli(&url, &["label", "create", "example/clu", "scm", "git"])
+        .await
+        .unwrap();
+    assert!(
+        run_cli(&url, &["label", "create", "example/clu", "scm", "svn"])
+            .await
+            .is_err()
+    );
+    run_cli(&url, &["label", "update", "example/clu", "owner", "flynn"])
+        .await
+        .unwrap();
+    run_cli(&url, &["label", "delete", "example/clu", "scm"])
+        .await
+        .unwrap();
+    assert_eq!(
+        run_cli(&url, &["-o", "json", "label", "list", "example/clu"])
+            .await
+            .unwrap(),
+        "{\n  \"labels\": {\n    \"dumont.io/created-at\": \"2026-01-01T00:00:00Z\",\n    \"owner\": \"flynn\"\n  }\n}\n"
+    );
+
+    run_cli(
+        &url,
+        &["label", "create", "example/clu/1.6.9", "git", "abc123"],
+    )
+    .await
+    .unwrap();
+    let promoted = run_cli(
+        &url,
+        &[
+            "-o", "yaml", "version", "promote", "example", "clu", "1.6.9", "stage", "prod",
+        ],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        promoted,
+        "version: 1.6.9\nlabels:\n  dumont.io/created-at: 2026-01-01T00:00:00Z\n  git: abc123\n  stage: prod\n"
+    );
+
+    let error = run_cli(&url, &["org", "get", "missing"]).await.unwrap_err();
+    assert_eq!(error.to_string(), "Not found: Org missing not found");
+
+    assert_eq!(
+        run_cli(&url, &["policy", "check"]).await.unwrap(),
+        "ORG  REPO  VERSION  POLICY  LABEL  MESSAGE\n"
+    );
+    assert_eq!(
+        run_cli(&url, &["--admin-url", &admin_url, "gc", "--dry-run"])
+            .await
+            .unwrap(),
+        "ORG  REPO  VERSION  POLICY  CREATED_AT\n"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+#[serial]
+async fn test_policy_violations() {
+    use crate::policy::{RealizedPolicy, RealizedPolicyContainer, RequiredLabel};
+
+    let backend = Arc::new(crate::backend::DefaultBackend {
+        database: make_storage().await,
+        policy_container: RealizedPolicyContainer {
+            policies: vec![RealizedPolicy::test_new(
+                "example/.*",
+                vec![
+                    RequiredLabel::new("owner", vec![], None),
+                    RequiredLabel::new("team", vec![], None),
+                ],
+            )],
+            ..Default::default()
+        }
+        .into(),
+    });
+    let (url, _) = serve(backend).await;
+    let client = DumontClient::builder(&url)
+        .retry_policy(RetryPolicy::none())
+        .build()
+        .unwrap();
+    client.create_org("example").await.unwrap();
+
+    let error = client
+        .create_repo(
+            "example",
+            &CreateRepository {
+                repo: "clu".to_owned(),
+                labels: Default::default(),
+            },
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(error.error_code(), Some("POLICY_VIOLATIONS"));
+    let labels: Vec<_> = error
+        .violations()
+        .iter()
+        .map(|violation| violation.label.as_deref().unwrap())
+        .collect();
+    assert_eq!(labels, vec!["owner", "team"]);
+    assert_eq!(error.errors().len(), 2);
+
+    let error = run_cli(&url, &["repo", "create", "example", "clu"])
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Rejected by the policies:\n  \
+         - Policy `test` required that label `owner` be set, however it was not and no default was specified.\n  \
+         - Policy `test` required that label `team` be set, however it was not and no default was specified."
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+#[serial]
+async fn test_clients_read_their_writes_from_the_primary() {
+    use crate::database::{BackendDatabase, CacheOptions, CachedStorage, ReadReplica};
+    use std::time::Duration;
+
+    // The replica never catches up, so a read shows where it was sent.
+    let replica = BackendDatabase {
+        db: setup_schema().await.unwrap(),
+        date_time_provider: test_date_time_provider(),
+        read_replica: None,
+        id_cache: Default::default(),
+    };
+    let database = BackendDatabase {
+        db: setup_schema().await.unwrap(),
+        date_time_provider: test_date_time_provider(),
+        read_replica: Some(Box::new(ReadReplica::new(replica, Duration::from_secs(60)))),
+        id_cache: Default::default(),
+    };
+    let backend = Arc::new(crate::backend::DefaultBackend {
+        database: CachedStorage::new(database, CacheOptions::disabled()),
+        policy_container: Default::default(),
+    });
+    let (url, _) = serve(backend).await;
+    let client = || {
+        DumontClient::builder(&url)
+            .retry_policy(RetryPolicy::none())
+            .build()
+            .unwrap()
+    };
+    let (writer, other) = (client(), client());
+
+    writer.create_org("example").await.unwrap();
+    assert_eq!(writer.get_org("example").await.unwrap().org, "example");
+    assert_eq!(
+        writer.clone().get_org("example").await.unwrap().org,
+        "example"
+    );
+    match other.get_org("example").await {
+        Err(ClientError::NotFound { .. }) => {}
+        other => panic!("expected NotFound, got {:?}", other),
+    }
+}
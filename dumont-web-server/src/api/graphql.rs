@@ -0,0 +1,699 @@
+use super::prelude::*;
+use async_graphql::{
+    dataloader::{DataLoader, Loader},
+    Context, EmptySubscription, Error, ErrorExtensions, InputObject, Object, Schema, SimpleObject,
+};
+use std::collections::{BTreeMap, HashMap};
+use tracing::info;
+use tracing_attributes::instrument;
+use warp::{Filter, Rejection, Reply};
+
+use crate::backend::{
+    models::{DataStoreRepository, DataStoreRevision, DataStoreVersionList},
+    BackendError,
+};
+use crate::database::ReadScope;
+use crate::models::GenericLabels;
+use chrono::{DateTime, Utc};
+
+pub type DumontSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+pub fn build_schema(db: crate::Backend) -> DumontSchema {
+    let version_loader = DataLoader::new(
+        VersionLoader {
+            backend: db.clone(),
+        },
+        tokio::spawn,
+    );
+    Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+        .data(db)
+        .data(version_loader)
+        .finish()
+}
+
+pub fn create_graphql_api(
+    db: crate::Backend,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let schema = build_schema(db);
+
+    info!("POST /api/graphql");
+    warp::path!("api" / "graphql")
+        .and(warp::post())
+        .and(json_body::<async_graphql::Request>())
+        .and(warp::any().map(move || schema.clone()))
+        .and_then(graphql_impl)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/graphql",
+    tag = "graphql",
+    request_body(content = Object, description = "A GraphQL request with `query`, and optionally `variables` and `operationName`"),
+    responses(
+        (status = 200, description = "A GraphQL response with `data` and/or `errors`", body = Object),
+    )
+)]
+#[instrument(name = "graphql_execute", skip(request, schema))]
+async fn graphql_impl(
+    request: async_graphql::Request,
+    schema: DumontSchema,
+) -> Result<impl Reply, Rejection> {
+    let response = schema.execute(request).await;
+    Ok(warp::reply::json(&response))
+}
+
+/// Convert a backend error into a GraphQL error, keeping the HTTP status the
+/// REST API would have used so clients can branch on it.
+fn to_graphql_error(error: BackendError) -> Error {
+    let status = ErrorStatusResponse::from(&error);
+    Error::new(error.to_string()).extend_with(|_, e| e.set("status", status.code.as_u16()))
+}
+
+fn backend<'a>(ctx: &Context<'a>) -> &'a crate::Backend {
+    ctx.data_unchecked::<crate::Backend>()
+}
+
+fn pagination(page: Option<u32>, size: Option<u32>) -> ApiPagination {
+    ApiPagination { page, size }
+}
+
+fn to_label_list(labels: &GenericLabels) -> Vec<Label> {
+    labels
+        .iter()
+        .map(|(name, value)| Label {
+            name: name.clone(),
+            value: value.clone(),
+        })
+        .collect()
+}
+
+fn into_label_map(labels: Option<Vec<LabelInput>>) -> BTreeMap<String, String> {
+    labels
+        .unwrap_or_default()
+        .into_iter()
+        .map(|label| (label.name, label.value))
+        .collect()
+}
+
+/// A page of the versions of one repository, see `VersionLoader`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VersionPageKey {
+    repo_id: i32,
+    labels: BTreeMap<String, String>,
+    page: Option<u32>,
+    size: Option<u32>,
+    /// The loader runs outside of the request, so the write its reads have to see
+    /// is passed along, see `ReadScope`.
+    last_write: Option<DateTime<Utc>>,
+}
+
+/// Loads the `versions` of every repository in a response together, rather than
+/// with a query per repository.
+pub struct VersionLoader {
+    backend: crate::Backend,
+}
+
+impl Loader<VersionPageKey> for VersionLoader {
+    type Value = DataStoreVersionList;
+    type Error = Error;
+
+    async fn load(
+        &self,
+        keys: &[VersionPageKey],
+    ) -> Result<HashMap<VersionPageKey, DataStoreVersionList>, Error> {
+        // The repositories of a response usually share the labels and page, so this is
+        // almost always a single batch.
+        let mut batches: HashMap<_, Vec<i32>> = HashMap::new();
+        for key in keys {
+            batches
+                .entry((&key.labels, key.page, key.size, key.last_write))
+                .or_default()
+                .push(key.repo_id);
+        }
+
+        let mut loaded = HashMap::new();
+        for ((labels, page, size, last_write), repo_ids) in batches {
+            let (version_lists, _) = ReadScope::new(last_write)
+                .run(self.backend.list_versions_of_repos(
+                    &repo_ids,
+                    labels,
+                    pagination(page, size).into(),
+                ))
+                .await;
+            for (repo_id, version_list) in version_lists.map_err(to_graphql_error)? {
+                let key = VersionPageKey {
+                    repo_id,
+                    labels: labels.clone(),
+                    page,
+                    size,
+                    last_write,
+                };
+                loaded.insert(key, version_list);
+            }
+        }
+        Ok(loaded)
+    }
+}
+
+#[derive(SimpleObject, Debug, Clone)]
+pub struct Label {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(InputObject, Debug, Clone)]
+pub struct LabelInput {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(SimpleObject)]
+pub struct OrganizationPage {
+    pub total: u64,
+    pub has_more: bool,
+    pub items: Vec<Organization>,
+}
+
+#[derive(SimpleObject)]
+pub struct RepositoryPage {
+    pub total: u64,
+    pub has_more: bool,
+    pub items: Vec<Repository>,
+}
+
+#[derive(SimpleObject)]
+pub struct VersionPage {
+    pub total: u64,
+    pub has_more: bool,
+    pub items: Vec<Version>,
+}
+
+#[derive(SimpleObject)]
+pub struct DeleteResult {
+    pub deleted: bool,
+}
+
+pub struct Organization {
+    name: String,
+}
+
+#[Object]
+impl Organization {
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Repositories in this organization. When `labels` is set, only the
+    /// repositories with all of those labels are paged through and counted.
+    async fn repositories(
+        &self,
+        ctx: &Context<'_>,
+        page: Option<u32>,
+        size: Option<u32>,
+        labels: Option<Vec<LabelInput>>,
+    ) -> async_graphql::Result<RepositoryPage> {
+        let repo_list = backend(ctx)
+            .list_repos_with_labels(
+                &self.name,
+                &into_label_map(labels),
+                pagination(page, size).into(),
+            )
+            .await
+            .map_err(to_graphql_error)?;
+
+        Ok(RepositoryPage {
+            total: repo_list.total_count,
+            has_more: repo_list.has_more,
+            items: repo_list.repos.into_iter().map(Repository::from).collect(),
+        })
+    }
+
+    async fn repository(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+    ) -> async_graphql::Result<Repository> {
+        backend(ctx)
+            .get_repo(&self.name, &name)
+            .await
+            .map(Repository::from)
+            .map_err(to_graphql_error)
+    }
+}
+
+pub struct Repository {
+    id: i32,
+    org: String,
+    name: String,
+    labels: GenericLabels,
+}
+
+impl From<DataStoreRepository> for Repository {
+    fn from(source: DataStoreRepository) -> Self {
+        Self {
+            id: source.id,
+            org: source.org_name,
+            name: source.repo_name,
+            labels: source.labels,
+        }
+    }
+}
+
+#[Object]
+impl Repository {
+    async fn org(&self) -> &str {
+        &self.org
+    }
+
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn labels(&self) -> Vec<Label> {
+        to_label_list(&self.labels)
+    }
+
+    async fn label(&self, name: String) -> Option<String> {
+        self.labels.get(&name).cloned()
+    }
+
+    /// Versions of this repository. When `labels` is set, only the versions
+    /// with all of those labels are paged through and counted.
+    async fn versions(
+        &self,
+        ctx: &Context<'_>,
+        page: Option<u32>,
+        size: Option<u32>,
+        labels: Option<Vec<LabelInput>>,
+    ) -> async_graphql::Result<VersionPage> {
+        let key = VersionPageKey {
+            repo_id: self.id,
+            labels: into_label_map(labels),
+            page,
+            size,
+            last_write: ReadScope::last_write(),
+        };
+        let version_list = ctx
+            .data_unchecked::<DataLoader<VersionLoader>>()
+            .load_one(key)
+            .await?
+            .expect("Versions to be loaded for every repository");
+
+        Ok(VersionPage {
+            total: version_list.total_count,
+            has_more: version_list.has_more,
+            items: version_list
+                .versions
+                .into_iter()
+                .map(|version| Version::from_revision(&self.org, &self.name, version))
+                .collect(),
+        })
+    }
+
+    async fn version(&self, ctx: &Context<'_>, name: String) -> async_graphql::Result<Version> {
+        backend(ctx)
+            .get_version(&self.org, &self.name, &name)
+            .await
+            .map(|version| Version::from_revision(&self.org, &self.name, version))
+            .map_err(to_graphql_error)
+    }
+}
+
+pub struct Version {
+    org: String,
+    repo: String,
+    name: String,
+    labels: GenericLabels,
+}
+
+impl Version {
+    fn from_revision(org: &str, repo: &str, source: DataStoreRevision) -> Self {
+        Self {
+            org: org.to_owned(),
+            repo: repo.to_owned(),
+            name: source.version,
+            labels: source.labels,
+        }
+    }
+}
+
+#[Object]
+impl Version {
+    async fn org(&self) -> &str {
+        &self.org
+    }
+
+    async fn repo(&self) -> &str {
+        &self.repo
+    }
+
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn labels(&self) -> Vec<Label> {
+        to_label_list(&self.labels)
+    }
+
+    async fn label(&self, name: String) -> Option<String> {
+        self.labels.get(&name).cloned()
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn organizations(
+        &self,
+        ctx: &Context<'_>,
+        page: Option<u32>,
+        size: Option<u32>,
+    ) -> async_graphql::Result<OrganizationPage> {
+        let org_list = backend(ctx)
+            .list_organizations(pagination(page, size).into())
+            .await
+            .map_err(to_graphql_error)?;
+
+        Ok(OrganizationPage {
+            total: org_list.total_count,
+            has_more: org_list.has_more,
+            items: org_list
+                .orgs
+                .into_iter()
+                .map(|org| Organization { name: org.name })
+                .collect(),
+        })
+    }
+
+    async fn organization(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+    ) -> async_graphql::Result<Organization> {
+        backend(ctx)
+            .get_organization(&name)
+            .await
+            .map(|org| Organization { name: org.name })
+            .map_err(to_graphql_error)
+    }
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    async fn create_organization(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+    ) -> async_graphql::Result<Organization> {
+        backend(ctx)
+            .create_organization(&name)
+            .await
+            .map(|org| Organization { name: org.name })
+            .map_err(to_graphql_error)
+    }
+
+    async fn delete_organization(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+    ) -> async_graphql::Result<DeleteResult> {
+        backend(ctx)
+            .delete_organization(&name)
+            .await
+            .map(|deleted| DeleteResult { deleted })
+            .map_err(to_graphql_error)
+    }
+
+    async fn create_repository(
+        &self,
+        ctx: &Context<'_>,
+        org: String,
+        name: String,
+        labels: Option<Vec<LabelInput>>,
+    ) -> async_graphql::Result<Repository> {
+        backend(ctx)
+            .create_repo(&org, &name, into_label_map(labels))
+            .await
+            .map(Repository::from)
+            .map_err(to_graphql_error)
+    }
+
+    /// Replaces all labels on the repository, the same as the REST PUT.
+    async fn update_repository(
+        &self,
+        ctx: &Context<'_>,
+        org: String,
+        name: String,
+        labels: Option<Vec<LabelInput>>,
+    ) -> async_graphql::Result<Repository> {
+        backend(ctx)
+            .update_repo(&org, &name, into_label_map(labels))
+            .await
+            .map(Repository::from)
+            .map_err(to_graphql_error)
+    }
+
+    async fn delete_repository(
+        &self,
+        ctx: &Context<'_>,
+        org: String,
+        name: String,
+    ) -> async_graphql::Result<DeleteResult> {
+        backend(ctx)
+            .delete_repo(&org, &name)
+            .await
+            .map(|deleted| DeleteResult { deleted })
+            .map_err(to_graphql_error)
+    }
+
+    async fn create_version(
+        &self,
+        ctx: &Context<'_>,
+        org: String,
+        repo: String,
+        name: String,
+        labels: Option<Vec<LabelInput>>,
+    ) -> async_graphql::Result<Version> {
+        backend(ctx)
+            .create_version(&org, &repo, &name, into_label_map(labels))
+            .await
+            .map(|version| Version::from_revision(&org, &repo, version))
+            .map_err(to_graphql_error)
+    }
+
+    /// Replaces all labels on the version, the same as the REST PUT.
+    async fn update_version(
+        &self,
+        ctx: &Context<'_>,
+        org: String,
+        repo: String,
+        name: String,
+        labels: Option<Vec<LabelInput>>,
+    ) -> async_graphql::Result<Version> {
+        backend(ctx)
+            .update_version(&org, &repo, &name, into_label_map(labels).into())
+            .await
+            .map(|version| Version::from_revision(&org, &repo, version))
+            .map_err(to_graphql_error)
+    }
+
+    async fn delete_version(
+        &self,
+        ctx: &Context<'_>,
+        org: String,
+        repo: String,
+        name: String,
+    ) -> async_graphql::Result<DeleteResult> {
+        backend(ctx)
+            .delete_version(&org, &repo, &name)
+            .await
+            .map(|deleted| DeleteResult { deleted })
+            .map_err(to_graphql_error)
+    }
+}
+
+#[cfg(test)]
+mod integ_test {
+    use super::*;
+    use crate::test_utils::*;
+    use json::object;
+    use serial_test::serial;
+    use warp::test::request;
+
+    /// Fields are resolved concurrently and written in the order they finish, so
+    /// the response is compared without the order of the keys.
+    fn assert_graphql_response(response: http::Response<bytes::Bytes>, data: json::JsonValue) {
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let body = String::from_utf8(response.body().to_vec()).unwrap();
+        assert_eq!(
+            json::parse(&body).unwrap(),
+            object! { "data": data },
+            "{}",
+            body
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn test_nested_query() {
+        let backend = make_backend().await;
+        let filter = create_graphql_api(backend.clone());
+
+        backend
+            .create_test_org_and_repos("example", vec!["example-repo-1", "example-repo-2"])
+            .await
+            .unwrap();
+        backend
+            .create_test_version("example", "example-repo-1", "1.2.3")
+            .await
+            .unwrap();
+
+        let response = request()
+            .path("/api/graphql")
+            .body(json::stringify(object! {
+                "query": "{ organizations { total items { name repositories(size: 1) { total hasMore items { name versions { items { name labels { name value } } } } } } } }"
+            }))
+            .method("POST")
+            .reply(&filter)
+            .await;
+
+        assert_graphql_response(
+            response,
+            object! {
+                "organizations": {
+                    "total": 1,
+                    "items": [{
+                        "name": "example",
+                        "repositories": {
+                            "total": 2,
+                            "hasMore": true,
+                            "items": [{
+                                "name": "example-repo-1",
+                                "versions": {
+                                    "items": [{
+                                        "name": "1.2.3",
+                                        "labels": [{ "name": "version", "value": "1.2.3" }]
+                                    }]
+                                }
+                            }]
+                        }
+                    }]
+                }
+            },
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn test_label_filters_page_the_matches() {
+        let backend = make_backend().await;
+        let filter = create_graphql_api(backend.clone());
+
+        backend.create_organization("example").await.unwrap();
+        for (repo, team) in [("repo-1", "a"), ("repo-2", "b"), ("repo-3", "a")] {
+            backend
+                .create_repo(
+                    "example",
+                    repo,
+                    BTreeMap::from([("team".into(), team.into())]),
+                )
+                .await
+                .unwrap();
+            for (version, state) in [("1.0.0", "draft"), ("1.0.1", "released")] {
+                let labels = BTreeMap::from([("state".into(), state.into())]);
+                backend
+                    .create_version("example", repo, version, labels)
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let response = request()
+            .path("/api/graphql")
+            .body(json::stringify(object! {
+                "query": r#"{ organization(name: "example") {
+                    repositories(size: 1, labels: [{name: "team", value: "a"}]) {
+                        total hasMore items {
+                            name
+                            versions(labels: [{name: "state", value: "released"}]) { total hasMore items { name } }
+                        }
+                    }
+                    second: repositories(page: 1, size: 1, labels: [{name: "team", value: "a"}]) {
+                        total hasMore items { name versions(size: 1) { total hasMore items { name } } }
+                    }
+                } }"#
+            }))
+            .method("POST")
+            .reply(&filter)
+            .await;
+
+        assert_graphql_response(
+            response,
+            object! {
+                "organization": {
+                    "repositories": {
+                        "total": 2,
+                        "hasMore": true,
+                        "items": [{
+                            "name": "repo-1",
+                            "versions": { "total": 1, "hasMore": false, "items": [{ "name": "1.0.1" }] }
+                        }]
+                    },
+                    "second": {
+                        "total": 2,
+                        "hasMore": false,
+                        "items": [{
+                            "name": "repo-3",
+                            "versions": { "total": 2, "hasMore": true, "items": [{ "name": "1.0.0" }] }
+                        }]
+                    }
+                }
+            },
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn test_mutations() {
+        let backend = make_backend().await;
+        let filter = create_graphql_api(backend.clone());
+
+        let response = request()
+            .path("/api/graphql")
+            .body(json::stringify(object! {
+                "query": r#"mutation {
+                    createOrganization(name: "example") { name }
+                    createRepository(org: "example", name: "repo", labels: [{name: "owner", value: "bob"}]) { name label(name: "owner") }
+                    createVersion(org: "example", repo: "repo", name: "1.0.0") { name }
+                }"#
+            }))
+            .method("POST")
+            .reply(&filter)
+            .await;
+
+        assert_graphql_response(
+            response,
+            object! {
+                "createOrganization": { "name": "example" },
+                "createRepository": { "name": "repo", "label": "bob" },
+                "createVersion": { "name": "1.0.0" },
+            },
+        );
+
+        let response = request()
+            .path("/api/graphql")
+            .body(json::stringify(object! {
+                "query": r#"{ organization(name: "missing") { name } }"#
+            }))
+            .method("POST")
+            .reply(&filter)
+            .await;
+
+        let body = json::parse(std::str::from_utf8(response.body()).unwrap()).unwrap();
+        assert_eq!(body["errors"][0]["message"], "Org missing not found");
+        assert_eq!(body["errors"][0]["extensions"]["status"], 404);
+    }
+}
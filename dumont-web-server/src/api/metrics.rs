@@ -1,7 +1,7 @@
 use lazy_static::lazy_static;
 use prometheus::{
-    labels, opts, register_counter, register_counter_vec, register_histogram_vec, Counter,
-    CounterVec, Encoder, HistogramVec, TextEncoder,
+    labels, opts, register_counter, register_counter_vec, register_histogram_vec,
+    register_int_gauge_vec, Counter, CounterVec, Encoder, HistogramVec, IntGaugeVec, TextEncoder,
 };
 
 use warp::http::header::CONTENT_TYPE;
@@ -25,6 +25,42 @@ lazy_static! {
         &["method", "path"]
     )
     .unwrap();
+    static ref POLICY_INFO: IntGaugeVec = register_int_gauge_vec!(
+        "policy_info",
+        "Always 1, labeled with the SHA-256 of the loaded policy document.",
+        &["hash"]
+    )
+    .unwrap();
+    static ref POLICY_RELOADS: CounterVec = register_counter_vec!(
+        "policy_reloads_total",
+        "Policy reloads that changed the enforced policies, and reloads that failed, by result.",
+        &["result"]
+    )
+    .unwrap();
+    static ref GC_RUNS: CounterVec = register_counter_vec!(
+        "gc_runs_total",
+        "Garbage collection runs, by whether they completed and whether they were dry runs.",
+        &["result", "dry_run"]
+    )
+    .unwrap();
+    static ref GC_VERSIONS_DELETED: CounterVec = register_counter_vec!(
+        "gc_versions_deleted_total",
+        "Versions deleted by garbage collection, by the policy whose retention rule expired them.",
+        &["policy"]
+    )
+    .unwrap();
+    static ref CACHE_LOOKUPS: CounterVec = register_counter_vec!(
+        "cache_lookups_total",
+        "Lookups in the org, repo and version cache, by whether they were found.",
+        &["cache", "result"]
+    )
+    .unwrap();
+    static ref GC_VERSIONS_EXPIRED: IntGaugeVec = register_int_gauge_vec!(
+        "gc_versions_expired",
+        "Versions the last garbage collection run found expired.",
+        &["dry_run"]
+    )
+    .unwrap();
 }
 
 #[tracing::instrument]
@@ -58,7 +94,43 @@ pub fn track_status(info: warp::filters::log::Info) {
         .observe(duration_to_seconds(info.elapsed()));
 }
 
+pub fn set_policy_hash(hash: &str) {
+    POLICY_INFO.reset();
+    POLICY_INFO.with_label_values(&[hash]).set(1);
+}
+
+pub fn record_policy_reload(success: bool) {
+    let result = if success { "success" } else { "failure" };
+    POLICY_RELOADS.with_label_values(&[result]).inc();
+}
+
+/// Records a garbage collection run, and how many versions it found expired when it
+/// completed.
+pub fn record_gc_run(dry_run: bool, expired: Option<usize>) {
+    let result = if expired.is_some() {
+        "success"
+    } else {
+        "failure"
+    };
+    let dry_run = dry_run.to_string();
+    GC_RUNS.with_label_values(&[result, &dry_run]).inc();
+    if let Some(expired) = expired {
+        GC_VERSIONS_EXPIRED
+            .with_label_values(&[&dry_run])
+            .set(expired as i64);
+    }
+}
+
+pub fn record_gc_deletion(policy: &str) {
+    GC_VERSIONS_DELETED.with_label_values(&[policy]).inc();
+}
+
 fn duration_to_seconds(d: std::time::Duration) -> f64 {
     let nanos = f64::from(d.subsec_nanos()) / 1e9;
     d.as_secs() as f64 + nanos
 }
+
+pub fn record_cache_lookup(cache: &str, hit: bool) {
+    let result = if hit { "hit" } else { "miss" };
+    CACHE_LOOKUPS.with_label_values(&[cache, result]).inc();
+}
@@ -1,22 +1,55 @@
+mod admin;
+#[cfg(test)]
+mod client_integ_test;
+mod graphql;
 pub mod metrics;
+mod openapi;
 mod orgs;
+mod policy;
 mod repos;
 mod versions;
+pub mod write_markers;
 
-use warp::{Filter, Reply};
+use std::convert::Infallible;
+use warp::{Filter, Rejection, Reply};
 
 pub async fn create_filters(
     db: crate::Backend,
 ) -> impl Filter<Extract = (impl Reply,)> + Clone + Send + Sync + 'static {
-    filters::api(db)
-        .recover(canned_response::handle_rejection)
-        .with(warp::trace::request())
+    recover_errors(filters::api(db)).with(warp::trace::request())
+}
+
+/// Routes served from the admin address, next to `/metrics` and `/status`.
+pub fn create_admin_filters(
+    db: crate::Backend,
+) -> impl Filter<Extract = (impl Reply,), Error = Infallible> + Clone + Send + Sync + 'static {
+    recover_errors(admin::create_admin_api(db))
+}
+
+/// Answers rejections with an `ErrorResponse`, or with RFC 7807 problem details when the
+/// request accepts `application/problem+json`.
+fn recover_errors<F, R>(
+    filter: F,
+) -> impl Filter<Extract = (impl Reply,), Error = Infallible> + Clone + Send + Sync + 'static
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    canned_response::accepts_problem_json()
+        .and(
+            filter
+                .clone()
+                .recover(canned_response::handle_problem_rejection),
+        )
+        .or(filter.recover(canned_response::handle_rejection))
 }
 
 pub mod prelude {
     pub use super::models::*;
     use crate::backend::models::PaginationOptions;
+    pub use dumont_api_models::DeleteStatus;
     use serde::{de::DeserializeOwned, Deserialize, Serialize};
+    use utoipa::IntoParams;
     use warp::{reject::Reject, Filter, Rejection, Reply};
 
     pub fn json_body<T: Send + DeserializeOwned>(
@@ -54,9 +87,12 @@ pub mod prelude {
         Ok(warp::reply::json(&response))
     }
 
-    #[derive(Debug, Deserialize, Serialize)]
+    #[derive(Debug, Deserialize, Serialize, IntoParams)]
+    #[into_params(parameter_in = Query)]
     pub struct ApiPagination {
+        /// Page to fetch, starting at 0. Defaults to 0.
         pub page: Option<u32>,
+        /// Number of items per page. Defaults to 50.
         pub size: Option<u32>,
     }
 
@@ -78,17 +114,6 @@ pub mod prelude {
         }
     }
 
-    #[derive(Deserialize, Serialize)]
-    pub struct DeleteStatus {
-        pub deleted: bool,
-    }
-
-    impl From<bool> for DeleteStatus {
-        fn from(source: bool) -> Self {
-            Self { deleted: source }
-        }
-    }
-
     pub struct PaginatedWrapperResponse<T>
     where
         T: Serialize,
@@ -115,41 +140,51 @@ pub mod prelude {
 }
 
 mod models {
+    pub use dumont_api_models::PolicyViolation;
     use serde::Serialize;
+    use std::collections::BTreeMap;
+    use utoipa::ToSchema;
     use warp::http::StatusCode;
 
-    #[derive(Debug, Serialize)]
-    pub struct PaginationState {
-        #[serde(rename = "more")]
-        pub has_more: bool,
-        pub total: u64,
-    }
+    pub use dumont_api_models::PaginationState;
 
-    #[derive(Serialize)]
+    #[derive(Serialize, ToSchema)]
     #[serde(remote = "StatusCode")]
-    struct StatusCodeDef {
+    #[schema(as = StatusCode)]
+    pub struct StatusCodeDef {
         #[serde(getter = "StatusCode::as_u16")]
         code: u16,
     }
 
-    #[derive(Debug, Serialize)]
+    #[derive(Debug, Serialize, ToSchema)]
     #[serde(untagged)]
     pub enum StatusResponse {
         Success(SuccessfulStatusResponse),
         Error(ErrorStatusResponse),
     }
 
-    #[derive(Debug, Serialize)]
+    #[derive(Debug, Serialize, ToSchema)]
     pub struct SuccessfulStatusResponse {
         #[serde(flatten, with = "StatusCodeDef")]
+        #[schema(value_type = StatusCodeDef)]
         pub code: StatusCode,
     }
 
-    #[derive(Debug, Serialize)]
+    #[derive(Debug, Serialize, ToSchema)]
     pub struct ErrorStatusResponse {
         #[serde(flatten, with = "StatusCodeDef")]
+        #[schema(value_type = StatusCodeDef)]
         pub code: StatusCode,
         pub error: Option<Vec<String>>,
+        /// A stable name for the error, such as `ORG_NOT_FOUND`. Errors without a more
+        /// specific code use the HTTP status, such as `BAD_REQUEST`.
+        pub error_code: String,
+        /// The fields the error is about, such as `org` and `repo`.
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        pub error_details: BTreeMap<String, String>,
+        /// Every policy violation behind the error, in the same order as `error`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub violations: Option<Vec<PolicyViolation>>,
     }
 
     impl ErrorStatusResponse {
@@ -157,6 +192,98 @@ mod models {
             Self {
                 code,
                 error: Some(vec![error]),
+                error_code: code
+                    .canonical_reason()
+                    .unwrap_or("ERROR")
+                    .to_uppercase()
+                    .replace(' ', "_"),
+                error_details: BTreeMap::new(),
+                violations: None,
+            }
+        }
+
+        pub fn with_error_code(
+            mut self,
+            error_code: impl Into<String>,
+            details: Vec<(&'static str, String)>,
+        ) -> Self {
+            self.error_code = error_code.into();
+            self.error_details = details
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value))
+                .collect();
+            self
+        }
+
+        pub fn from_policy_error(code: StatusCode, error: &crate::policy::PolicyError) -> Self {
+            if !error.is_violation() {
+                return Self::from_error_message(code, error.to_string())
+                    .with_error_code(error.code(), error.details());
+            }
+            let violations: Vec<PolicyViolation> = error
+                .violations()
+                .iter()
+                .map(|violation| PolicyViolation {
+                    policy: violation.policy_name().map(str::to_owned),
+                    label: violation.label_name().map(str::to_owned),
+                    kind: violation.kind().to_owned(),
+                    value: violation.value().map(str::to_owned),
+                    message: violation.to_string(),
+                })
+                .collect();
+            Self {
+                code,
+                error: Some(violations.iter().map(|v| v.message.clone()).collect()),
+                error_code: error.code(),
+                error_details: error
+                    .details()
+                    .into_iter()
+                    .map(|(key, value)| (key.to_owned(), value))
+                    .collect(),
+                violations: Some(violations),
+            }
+        }
+    }
+
+    /// An error in the `application/problem+json` format of RFC 7807, sent in place of
+    /// an `ErrorResponse` when the request accepts it.
+    #[derive(Debug, Serialize, ToSchema)]
+    pub struct ProblemDetails {
+        /// Always `about:blank`, the problem is identified by `code`.
+        #[serde(rename = "type")]
+        pub problem_type: String,
+        /// The reason phrase of the HTTP status.
+        pub title: String,
+        pub status: u16,
+        /// The error messages, joined together.
+        pub detail: String,
+        /// Same as `status.error_code` in an `ErrorResponse`.
+        pub code: String,
+        /// Same as `status.error` in an `ErrorResponse`.
+        pub errors: Vec<String>,
+        /// Same as `status.error_details` in an `ErrorResponse`.
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        pub details: BTreeMap<String, String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub violations: Option<Vec<PolicyViolation>>,
+    }
+
+    impl From<ErrorStatusResponse> for ProblemDetails {
+        fn from(source: ErrorStatusResponse) -> Self {
+            let errors = source.error.unwrap_or_default();
+            Self {
+                problem_type: "about:blank".to_owned(),
+                title: source
+                    .code
+                    .canonical_reason()
+                    .unwrap_or_default()
+                    .to_owned(),
+                status: source.code.as_u16(),
+                detail: errors.join(" "),
+                code: source.error_code,
+                errors,
+                details: source.error_details,
+                violations: source.violations,
             }
         }
     }
@@ -176,7 +303,23 @@ mod models {
         }
     }
 
-    #[derive(Debug, Serialize)]
+    #[derive(Debug, Serialize, ToSchema)]
+    #[aliases(
+        OrganizationResponse = ApplicationResponse<crate::api::orgs::CreateOrganization>,
+        OrganizationListResponse = ApplicationResponse<Vec<crate::api::orgs::CreateOrganization>>,
+        RepositoryResponse = ApplicationResponse<crate::api::repos::GetRepository>,
+        RepositoryListResponse = ApplicationResponse<Vec<crate::api::repos::GetRepository>>,
+        VersionResponse = ApplicationResponse<crate::api::versions::GetVersion>,
+        VersionListResponse = ApplicationResponse<Vec<crate::api::versions::GetVersion>>,
+        DeleteResponse = ApplicationResponse<crate::api::prelude::DeleteStatus>,
+        PolicyResponse = ApplicationResponse<crate::policy::RealizedPolicyContainer>,
+        ComplianceResponse = ApplicationResponse<crate::api::policy::ComplianceReport>,
+        GcResponse = ApplicationResponse<crate::api::admin::GcReport>,
+        PolicyReloadResponse = ApplicationResponse<crate::policy::PolicyReload>,
+        PolicyVersionResponse = ApplicationResponse<crate::api::admin::GetPolicyVersion>,
+        PolicyVersionListResponse = ApplicationResponse<Vec<crate::api::admin::GetPolicyVersion>>,
+        ErrorResponse = ApplicationResponse<serde_json::Value>,
+    )]
     pub struct ApplicationResponse<T>
     where
         T: Serialize,
@@ -218,7 +361,10 @@ mod filters {
     ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
         super::orgs::create_org_api(db.clone())
             .or(super::repos::create_repo_api(db.clone()))
-            .or(super::versions::create_version_api(db))
+            .or(super::versions::create_version_api(db.clone()))
+            .or(super::graphql::create_graphql_api(db.clone()))
+            .or(super::policy::create_policy_api(db.clone()))
+            .or(super::openapi::create_openapi_api())
             .with(warp::log::custom(super::metrics::track_status))
     }
 }
@@ -230,7 +376,7 @@ mod canned_response {
     use std::convert::Infallible;
     use std::error::Error;
     use tracing::error;
-    use warp::{http::StatusCode, reject::Reject, Rejection, Reply};
+    use warp::{http::StatusCode, reject::Reject, Filter, Rejection, Reply};
 
     impl From<Rejection> for ErrorStatusResponse {
         fn from(source: Rejection) -> Self {
@@ -249,6 +395,7 @@ mod canned_response {
                     None => "BAD_REQUEST".into(),
                 };
                 ErrorStatusResponse::from_error_message(StatusCode::BAD_REQUEST, message_body)
+                    .with_error_code("INVALID_BODY", vec![])
             } else if source.find::<warp::reject::MethodNotAllowed>().is_some() {
                 ErrorStatusResponse::from_error_message(
                     StatusCode::METHOD_NOT_ALLOWED,
@@ -269,6 +416,9 @@ mod canned_response {
             Self {
                 code: source.code,
                 error: source.error.clone(),
+                error_code: source.error_code.clone(),
+                error_details: source.error_details.clone(),
+                violations: source.violations.clone(),
             }
         }
     }
@@ -287,12 +437,14 @@ mod canned_response {
                     DatabaseError::NotFound { error } => ErrorStatusResponse::from_error_message(
                         StatusCode::NOT_FOUND,
                         error.to_string(),
-                    ),
+                    )
+                    .with_error_code(error.code(), error.details()),
                     DatabaseError::AlreadyExists { error } => {
                         ErrorStatusResponse::from_error_message(
                             StatusCode::CONFLICT,
                             error.to_string(),
                         )
+                        .with_error_code(error.code(), error.details())
                     }
                     _ => {
                         error!("Internal Error: {}", source);
@@ -307,11 +459,11 @@ mod canned_response {
                         StatusCode::BAD_REQUEST,
                         reason.to_string(),
                     )
+                    .with_error_code(reason.code(), reason.details())
+                }
+                BackendError::PolicyViolation { error } => {
+                    ErrorStatusResponse::from_policy_error(StatusCode::BAD_REQUEST, error)
                 }
-                BackendError::PolicyViolation { error } => ErrorStatusResponse::from_error_message(
-                    StatusCode::BAD_REQUEST,
-                    error.to_string(),
-                ),
             }
         }
     }
@@ -321,7 +473,7 @@ mod canned_response {
     pub async fn handle_rejection(err: Rejection) -> std::result::Result<impl Reply, Infallible> {
         let status = StatusResponse::Error(err.into());
         let status_code = status.status();
-        let response: ApplicationResponse<()> = ApplicationResponse {
+        let response: ErrorResponse = ApplicationResponse {
             data: None,
             status,
             page: None,
@@ -331,4 +483,32 @@ mod canned_response {
 
         Ok(warp::reply::with_status(json, status_code))
     }
+
+    const PROBLEM_JSON: &str = "application/problem+json";
+
+    /// Passes when the `Accept` header asks for `application/problem+json`.
+    pub fn accepts_problem_json() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+        warp::header::optional::<String>("accept")
+            .and_then(|accept: Option<String>| async move {
+                match accept {
+                    Some(accept) if accept.contains(PROBLEM_JSON) => Ok(()),
+                    _ => Err(warp::reject::not_found()),
+                }
+            })
+            .untuple_one()
+    }
+
+    pub async fn handle_problem_rejection(
+        err: Rejection,
+    ) -> std::result::Result<impl Reply, Infallible> {
+        let error = ErrorStatusResponse::from(err);
+        let status_code = error.code;
+        let json = warp::reply::json(&ProblemDetails::from(error));
+
+        Ok(warp::reply::with_header(
+            warp::reply::with_status(json, status_code),
+            warp::http::header::CONTENT_TYPE,
+            PROBLEM_JSON,
+        ))
+    }
 }
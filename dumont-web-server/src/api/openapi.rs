@@ -0,0 +1,288 @@
+use super::prelude::*;
+use tracing::info;
+use utoipa::OpenApi;
+use warp::{Filter, Rejection, Reply};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Dumont",
+        description = "Service for managing versions and the state they are in."
+    ),
+    paths(
+        super::orgs::create_org_impl,
+        super::orgs::list_orgs_impl,
+        super::orgs::get_an_org_impl,
+        super::orgs::delete_org_impl,
+        super::repos::create_repo_impl,
+        super::repos::list_repos_impl,
+        super::repos::get_repo_impl,
+        super::repos::update_repo_impl,
+        super::repos::delete_repo_impl,
+        super::versions::create_version_impl,
+        super::versions::list_versions_impl,
+        super::versions::get_version_impl,
+        super::versions::update_version_impl,
+        super::versions::delete_version_impl,
+        super::policy::get_policies_impl,
+        super::policy::get_compliance_impl,
+        super::graphql::graphql_impl,
+        super::admin::override_repo_labels_impl,
+        super::admin::override_version_labels_impl,
+        super::admin::reload_policies_impl,
+        super::admin::list_policy_versions_impl,
+        super::admin::create_policy_version_impl,
+        super::admin::get_policy_version_impl,
+        super::admin::activate_policy_version_impl,
+        super::admin::delete_policy_version_impl,
+        super::admin::collect_garbage_impl,
+    ),
+    components(schemas(
+        crate::models::GenericLabels,
+        super::orgs::CreateOrganization,
+        super::repos::CreateRepository,
+        super::repos::GetRepository,
+        super::repos::UpdateRepository,
+        super::versions::CreateVersion,
+        super::versions::GetVersion,
+        super::versions::UpdateVersion,
+        DeleteStatus,
+        PaginationState,
+        StatusCodeDef,
+        StatusResponse,
+        SuccessfulStatusResponse,
+        ErrorStatusResponse,
+        ProblemDetails,
+        PolicyViolation,
+        ErrorResponse,
+        OrganizationResponse,
+        OrganizationListResponse,
+        RepositoryResponse,
+        RepositoryListResponse,
+        VersionResponse,
+        VersionListResponse,
+        DeleteResponse,
+        PolicyResponse,
+        ComplianceResponse,
+        super::policy::ComplianceReport,
+        super::policy::ComplianceViolation,
+        GcResponse,
+        super::admin::GcReport,
+        super::admin::ExpiredVersion,
+        super::admin::OverrideLabels,
+        super::admin::CreatePolicyVersion,
+        super::admin::GetPolicyVersion,
+        PolicyReloadResponse,
+        PolicyVersionResponse,
+        PolicyVersionListResponse,
+        crate::policy::PolicyReload,
+        crate::policy::PolicyDefinitionContainer,
+        crate::policy::PolicyDefinition,
+        crate::policy::PolicyEvaluation,
+        crate::policy::RetentionRule,
+        crate::policy::RealizedPolicyContainer,
+        crate::policy::RealizedPolicy,
+        crate::policy::RequiredLabel,
+        crate::policy::LabelType,
+        crate::policy::VersionScheme,
+        crate::policy::LabelSelector,
+        crate::policy::LabelSelectorRequirement,
+        crate::policy::SelectorOperator,
+    ))
+)]
+pub struct ApiDoc;
+
+pub fn create_openapi_api() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    info!("GET /api/openapi.json");
+    warp::path!("api" / "openapi.json")
+        .and(warp::get())
+        .map(|| warp::reply::json(&ApiDoc::openapi()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+    use serial_test::serial;
+    use std::collections::BTreeSet;
+    use std::sync::{Arc, Mutex};
+    use warp::test::request;
+
+    const SPEC_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../docs/openapi.json");
+
+    /// The spec is checked in so changes to it show up in review. Run the tests
+    /// with `UPDATE_OPENAPI=1` to regenerate it after changing a route or model.
+    #[test]
+    fn openapi_spec_is_current() {
+        let generated = ApiDoc::openapi().to_pretty_json().unwrap() + "\n";
+
+        if std::env::var("UPDATE_OPENAPI").is_ok() {
+            std::fs::write(SPEC_PATH, &generated).unwrap();
+        }
+
+        let checked_in = std::fs::read_to_string(SPEC_PATH).unwrap_or_default();
+        assert!(
+            checked_in == generated,
+            "docs/openapi.json is out of date, re-run the tests with UPDATE_OPENAPI=1 to update it"
+        );
+    }
+
+    /// What the filters log while they're built, each route logs `METHOD /path`.
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const METHODS: &[&str] = &["get", "post", "put", "patch", "delete"];
+
+    fn documented_routes() -> BTreeSet<(String, String)> {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        spec["paths"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .flat_map(|(path, operations)| {
+                operations
+                    .as_object()
+                    .unwrap()
+                    .keys()
+                    .map(move |method| (method.clone(), path.clone()))
+            })
+            .collect()
+    }
+
+    /// The routes of the API and the admin API, as the filters built by
+    /// `create_filters` and `create_admin_filters` log them.
+    async fn built_routes(backend: crate::Backend) -> BTreeSet<(String, String)> {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .without_time()
+            .with_level(false)
+            .with_target(false)
+            .finish();
+
+        {
+            let _logging = tracing::subscriber::set_default(subscriber);
+            crate::api::create_filters(backend.clone()).await;
+            crate::api::create_admin_filters(backend);
+        }
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        logs.lines()
+            .filter_map(|line| line.trim().split_once(' '))
+            .map(|(method, path)| (method.to_lowercase(), path.to_owned()))
+            .filter(|(method, path)| METHODS.contains(&method.as_str()) && path.starts_with('/'))
+            .collect()
+    }
+
+    /// Turns a documented path into one the filters match, for things that exist.
+    fn concrete_path(path: &str, policy_version: i32) -> String {
+        // Policy versions are numbered, the versions of a repo are named.
+        let version = if path.starts_with("/admin/policy/version") {
+            policy_version.to_string()
+        } else {
+            "1.2.3".to_owned()
+        };
+        path.replace("{org}", "example")
+            .replace("{repo}", "example-repo")
+            .replace("{version}", &version)
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn every_served_route_is_documented() {
+        let backend = make_backend().await;
+        let mut built = built_routes(backend).await;
+        assert!(
+            built.remove(&("get".to_owned(), "/api/openapi.json".to_owned())),
+            "the routes weren't logged while the filters were built"
+        );
+
+        let documented = documented_routes();
+        let undocumented: Vec<_> = built.difference(&documented).collect();
+        assert!(
+            undocumented.is_empty(),
+            "served but not in docs/openapi.json: {:?}",
+            undocumented
+        );
+    }
+
+    /// Sends a request for every documented path and method through the filters the
+    /// server runs. The org, repo and versions in the paths exist, so a 404 or 405
+    /// means the route itself is missing. Every other method on a documented path
+    /// has to be rejected, so a route can't hide behind a path that's documented.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn every_documented_route_is_served() {
+        let backend = make_backend().await;
+        backend
+            .create_test_org_and_repos("example", vec!["example-repo"])
+            .await
+            .unwrap();
+        backend
+            .create_test_version("example", "example-repo", "1.2.3")
+            .await
+            .unwrap();
+        let policy_version = backend
+            .create_policy_version(Default::default(), None)
+            .await
+            .unwrap()
+            .version;
+        let api = crate::api::create_filters(backend.clone()).await;
+        let admin = crate::api::create_admin_filters(backend);
+
+        let documented = documented_routes();
+        let paths: BTreeSet<&String> = documented.iter().map(|(_, path)| path).collect();
+        let mut routes: Vec<(&str, &String)> = paths
+            .into_iter()
+            .flat_map(|path| METHODS.iter().map(move |method| (*method, path)))
+            .collect();
+        // Deleting the org, repo and versions last keeps them around for the other
+        // requests, the most nested one goes first.
+        routes.sort_by_key(|(method, path)| (*method == "delete", std::cmp::Reverse(path.len())));
+
+        for (method, path) in routes {
+            let request = request()
+                .path(&concrete_path(path, policy_version))
+                .method(&method.to_uppercase())
+                .header("content-type", "application/json")
+                .body("{}");
+            let response = if path.starts_with("/admin/") {
+                request.reply(&admin).await
+            } else {
+                request.reply(&api).await
+            };
+
+            let body = String::from_utf8(response.body().to_vec()).unwrap();
+            let unrouted = matches!(
+                response.status(),
+                http::StatusCode::NOT_FOUND | http::StatusCode::METHOD_NOT_ALLOWED
+            );
+            if documented.contains(&(method.to_owned(), path.clone())) {
+                assert!(
+                    !unrouted,
+                    "{} {} is documented but not served: {}",
+                    method, path, body
+                );
+            } else {
+                assert!(
+                    unrouted,
+                    "{} {} is served but not in docs/openapi.json: {}",
+                    method, path, body
+                );
+            }
+        }
+    }
+}
@@ -3,14 +3,7 @@ use tracing::info;
 use tracing_attributes::instrument;
 use warp::{Filter, Rejection, Reply};
 
-use serde::{Deserialize, Serialize};
-
-type GetOrganization = CreateOrganization;
-
-#[derive(Debug, Serialize, Deserialize)]
-struct CreateOrganization {
-    pub org: String,
-}
+pub use dumont_api_models::{CreateOrganization, GetOrganization};
 
 impl From<crate::backend::models::DataStoreOrganization> for GetOrganization {
     fn from(model: crate::backend::models::DataStoreOrganization) -> Self {
@@ -46,6 +39,16 @@ fn create_org(
         .and_then(create_org_impl)
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/org",
+    tag = "orgs",
+    request_body = CreateOrganization,
+    responses(
+        (status = 200, description = "Organization was created", body = OrganizationResponse),
+        (status = 409, description = "Organization already exists", body = ErrorResponse),
+    )
+)]
 #[instrument(name = "rest_org_create", skip(db))]
 async fn create_org_impl(
     org: CreateOrganization,
@@ -69,6 +72,16 @@ fn delete_org(
         .and_then(delete_org_impl)
 }
 
+#[utoipa::path(
+    delete,
+    path = "/api/org/{org}",
+    tag = "orgs",
+    params(("org" = String, Path, description = "Name of the organization")),
+    responses(
+        (status = 200, description = "Organization was deleted", body = DeleteResponse),
+        (status = 404, description = "Organization not found", body = ErrorResponse),
+    )
+)]
 #[instrument(name = "rest_org_delete", skip(db))]
 async fn delete_org_impl(org_name: String, db: crate::Backend) -> Result<impl Reply, Rejection> {
     let result = db.delete_organization(&org_name).await;
@@ -90,6 +103,15 @@ fn list_orgs(
         .and_then(list_orgs_impl)
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/org",
+    tag = "orgs",
+    params(ApiPagination),
+    responses(
+        (status = 200, description = "A page of organizations", body = OrganizationListResponse),
+    )
+)]
 #[instrument(name = "rest_org_list", skip(db))]
 async fn list_orgs_impl(
     pageination: ApiPagination,
@@ -122,6 +144,16 @@ fn get_an_org(
         .and_then(get_an_org_impl)
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/org/{org}",
+    tag = "orgs",
+    params(("org" = String, Path, description = "Name of the organization")),
+    responses(
+        (status = 200, description = "The organization", body = OrganizationResponse),
+        (status = 404, description = "Organization not found", body = ErrorResponse),
+    )
+)]
 #[instrument(name = "rest_org_get", skip(db))]
 async fn get_an_org_impl(org_name: String, db: crate::Backend) -> Result<impl Reply, Rejection> {
     let result = db.get_organization(&org_name).await;
@@ -188,6 +220,8 @@ mod integ_test {
         assert_error_response(
             response,
             http::StatusCode::CONFLICT,
+            "ORG_ALREADY_EXISTS",
+            object! { "org": "example-org" },
             "Org example-org exists",
         );
     }
@@ -0,0 +1,256 @@
+use super::prelude::*;
+use tracing::info;
+use tracing_attributes::instrument;
+use warp::{Filter, Rejection, Reply};
+
+pub use dumont_api_models::{ComplianceReport, ComplianceViolation};
+
+impl From<crate::backend::models::DataStoreComplianceReport> for ComplianceReport {
+    fn from(model: crate::backend::models::DataStoreComplianceReport) -> Self {
+        Self {
+            repos_checked: model.repos_checked,
+            versions_checked: model.versions_checked,
+            violations: model
+                .violations
+                .into_iter()
+                .map(|violation| ComplianceViolation {
+                    org: violation.org_name,
+                    repo: violation.repo_name,
+                    version: violation.version,
+                    policy: violation.policy_name,
+                    label: violation.label_name,
+                    message: violation.message,
+                })
+                .collect(),
+        }
+    }
+}
+
+pub fn create_policy_api(
+    db: crate::Backend,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    get_policies(db.clone()).or(get_compliance(db))
+}
+
+fn get_policies(
+    db: crate::Backend,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    info!("GET /api/policy");
+    warp::path!("api" / "policy")
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(get_policies_impl)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/policy",
+    tag = "policy",
+    responses(
+        (status = 200, description = "The policies the server enforces, in the order they are matched", body = PolicyResponse),
+    )
+)]
+#[instrument(name = "rest_policy_get", skip(db))]
+async fn get_policies_impl(db: crate::Backend) -> Result<impl Reply, Rejection> {
+    let response = ApplicationResponse {
+        status: StatusResponse::ok(),
+        data: Some(&db.policy_container.current().container),
+        page: None,
+    };
+
+    Ok(warp::reply::json(&response))
+}
+
+fn get_compliance(
+    db: crate::Backend,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    info!("GET /api/compliance");
+    warp::path!("api" / "compliance")
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(get_compliance_impl)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/compliance",
+    tag = "policy",
+    responses(
+        (status = 200, description = "Every stored repo and version the current policies would reject", body = ComplianceResponse),
+    )
+)]
+#[instrument(name = "rest_compliance_get", skip(db))]
+async fn get_compliance_impl(db: crate::Backend) -> Result<impl Reply, Rejection> {
+    let result = db
+        .compliance_report()
+        .await
+        .map(ComplianceReport::from)
+        .map(PaginatedWrapperResponse::without_page)
+        .map_err(ErrorStatusResponse::from);
+    wrap_body(result)
+}
+
+#[cfg(test)]
+mod integ_test {
+    use super::*;
+    use crate::backend::DefaultBackend;
+    use crate::database::prelude::*;
+    use crate::database::Storage;
+    use crate::policy::*;
+    use crate::test_utils::*;
+    use json::object;
+    use serial_test::serial;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use warp::test::request;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn test_get_policies() {
+        let backend = Arc::new(DefaultBackend {
+            database: make_storage().await,
+            policy_container: RealizedPolicyContainer {
+                policies: vec![RealizedPolicy::test_new_different_labels(
+                    "example/.*",
+                    vec![],
+                    vec![RequiredLabel::new("build_number", vec![], None)
+                        .with_type(LabelType::Integer)],
+                )],
+                ..Default::default()
+            }
+            .into(),
+        });
+        let filter = create_policy_api(backend);
+
+        let response = request()
+            .method("GET")
+            .path("/api/policy")
+            .reply(&filter)
+            .await;
+
+        assert_response(
+            response,
+            http::StatusCode::OK,
+            object! {
+                "status": { "code": 200 },
+                "data": {
+                    "evaluation": "first_match",
+                    "policies": [{
+                        "name": "test",
+                        "repository_regex": "^example/.*$",
+                        "priority": 0,
+                        "extends": [],
+                        "required_repo_labels": [],
+                        "required_version_labels": [{
+                            "name": "build_number",
+                            "type": "integer",
+                            "one_of": [],
+                            "default_value": null,
+                            "pattern": null,
+                            "min_length": null,
+                            "max_length": null,
+                            "immutable": false,
+                        }],
+                        "optional_repo_labels": [],
+                        "optional_version_labels": [],
+                        "additional_repo_labels": true,
+                        "additional_version_labels": true,
+                        "immutable_labels": [],
+                        "version_scheme": null,
+                        "max_version_length": null,
+                        "retention": [],
+                    }],
+                },
+            },
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn test_get_compliance() {
+        let backend = Arc::new(DefaultBackend {
+            database: make_storage().await,
+            policy_container: RealizedPolicyContainer {
+                policies: vec![RealizedPolicy::test_new_different_labels(
+                    "example/.*",
+                    vec![RequiredLabel::new("owner", vec![], None)],
+                    vec![RequiredLabel::new("build_number", vec![], None)
+                        .with_type(LabelType::Integer)],
+                )],
+                ..Default::default()
+            }
+            .into(),
+        });
+
+        // Written straight to the database, as if the policy was tightened afterwards.
+        backend.database.create_org("example").await.unwrap();
+        for (repo, owner) in [("repo-1", None), ("repo-2", Some("team-a"))] {
+            let labels: BTreeMap<String, String> = owner
+                .map(|owner| ("owner".to_owned(), owner.to_owned()))
+                .into_iter()
+                .collect();
+            backend
+                .database
+                .create_repo(
+                    &RepoParam::new("example", repo),
+                    CreateRepoParam {
+                        labels: labels.into(),
+                    },
+                )
+                .await
+                .unwrap();
+        }
+        for (version, build_number) in [("1.0.0", "12"), ("1.0.1", "abc")] {
+            let labels =
+                BTreeMap::from_iter(vec![("build_number".to_owned(), build_number.to_owned())]);
+            backend
+                .database
+                .create_revision(
+                    &RevisionParam::new("example", "repo-2", version),
+                    &CreateRevisionParam {
+                        artifact_url: None,
+                        labels: labels.into(),
+                    },
+                )
+                .await
+                .unwrap();
+        }
+        let filter = create_policy_api(backend.clone());
+
+        let response = request()
+            .method("GET")
+            .path("/api/compliance")
+            .reply(&filter)
+            .await;
+
+        assert_response(
+            response,
+            http::StatusCode::OK,
+            object! {
+                "status": { "code": 200 },
+                "data": {
+                    "repos_checked": 2,
+                    "versions_checked": 2,
+                    "violations": [{
+                        "org": "example",
+                        "repo": "repo-1",
+                        "policy": "test",
+                        "label": "owner",
+                        "message": "Policy `test` required that label `owner` be set, however it was not and no default was specified.",
+                    }, {
+                        "org": "example",
+                        "repo": "repo-2",
+                        "version": "1.0.1",
+                        "policy": "test",
+                        "label": "build_number",
+                        "message": "Policy `test` required that label `build_number` be a integer, however `abc` was not.",
+                    }],
+                },
+            },
+        );
+
+        // Nothing was changed by the scan.
+        let repo = backend.get_repo("example", "repo-1").await.unwrap();
+        assert!(repo.labels.labels.is_empty());
+    }
+}
@@ -3,62 +3,7 @@ use tracing::info;
 use tracing_attributes::instrument;
 use warp::{Filter, Rejection, Reply};
 
-use serde::{Deserialize, Serialize};
-
-type RepoLabels = crate::models::GenericLabels;
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CreateRepository {
-    pub repo: String,
-    #[serde(flatten, default)]
-    pub labels: RepoLabels,
-}
-
-#[test]
-fn validate_create_repo_deserialize() {
-    use json::object;
-
-    let _foo: CreateRepository = serde_json::from_str(&json::stringify(object! {
-        "repo":  "example",
-    }))
-    .unwrap();
-
-    let _foo: CreateRepository = serde_json::from_str(&json::stringify(object! {
-        "repo":  "example",
-        "labels": {
-            "foo": "bar"
-        }
-    }))
-    .unwrap();
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct GetRepository {
-    pub org: String,
-    pub repo: String,
-    #[serde(flatten)]
-    pub labels: RepoLabels,
-}
-
-#[test]
-fn validate_get_repo_deserialize() {
-    use json::object;
-
-    let _foo: GetRepository = serde_json::from_str(&json::stringify(object! {
-        "org": "foo",
-        "repo":  "example",
-    }))
-    .unwrap();
-
-    let _foo: GetRepository = serde_json::from_str(&json::stringify(object! {
-        "org": "foo",
-        "repo":  "example",
-        "labels": {
-            "foo": "bar"
-        }
-    }))
-    .unwrap();
-}
+pub use dumont_api_models::{CreateRepository, GetRepository, UpdateRepository};
 
 impl From<&crate::backend::models::DataStoreRepository> for GetRepository {
     fn from(model: &crate::backend::models::DataStoreRepository) -> Self {
@@ -66,6 +11,7 @@ impl From<&crate::backend::models::DataStoreRepository> for GetRepository {
             org: model.org_name.clone(),
             repo: model.repo_name.clone(),
             labels: model.labels.clone(),
+            applied_policies: model.applied_policies.clone(),
         }
     }
 }
@@ -76,16 +22,11 @@ impl From<crate::backend::models::DataStoreRepository> for GetRepository {
             org: model.org_name.clone(),
             repo: model.repo_name.clone(),
             labels: model.labels,
+            applied_policies: model.applied_policies,
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UpdateRepository {
-    #[serde(flatten, default)]
-    pub labels: RepoLabels,
-}
-
 pub fn create_repo_api(
     db: crate::Backend,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
@@ -107,6 +48,19 @@ fn create_repo(
         .and_then(create_repo_impl)
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/org/{org}/repo",
+    tag = "repos",
+    params(("org" = String, Path, description = "Name of the organization")),
+    request_body = CreateRepository,
+    responses(
+        (status = 200, description = "Repository was created", body = RepositoryResponse),
+        (status = 400, description = "Repository was rejected by a policy", body = ErrorResponse),
+        (status = 404, description = "Organization not found", body = ErrorResponse),
+        (status = 409, description = "Repository already exists", body = ErrorResponse),
+    )
+)]
 #[instrument(name = "rest_org_create", fields(repo = %repo.repo), skip(repo, db))]
 async fn create_repo_impl(
     org: String,
@@ -132,6 +86,16 @@ fn list_repos(
         .and_then(list_repos_impl)
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/org/{org}/repo",
+    tag = "repos",
+    params(("org" = String, Path, description = "Name of the organization"), ApiPagination),
+    responses(
+        (status = 200, description = "A page of repositories", body = RepositoryListResponse),
+        (status = 404, description = "Organization not found", body = ErrorResponse),
+    )
+)]
 #[instrument(name = "rest_org_list", skip(db))]
 async fn list_repos_impl(
     org: String,
@@ -162,6 +126,16 @@ fn get_repo(db: crate::Backend) -> impl Filter<Extract = (impl Reply,), Error =
         .and_then(get_repo_impl)
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/org/{org}/repo/{repo}",
+    tag = "repos",
+    params(("org" = String, Path, description = "Name of the organization"), ("repo" = String, Path, description = "Name of the repository")),
+    responses(
+        (status = 200, description = "The repository", body = RepositoryResponse),
+        (status = 404, description = "Repository not found", body = ErrorResponse),
+    )
+)]
 #[instrument(name = "rest_org_get", skip(db))]
 async fn get_repo_impl(
     org: String,
@@ -186,6 +160,16 @@ fn delete_repo(
         .and_then(delete_repo_impl)
 }
 
+#[utoipa::path(
+    delete,
+    path = "/api/org/{org}/repo/{repo}",
+    tag = "repos",
+    params(("org" = String, Path, description = "Name of the organization"), ("repo" = String, Path, description = "Name of the repository")),
+    responses(
+        (status = 200, description = "Repository was deleted", body = DeleteResponse),
+        (status = 404, description = "Repository not found", body = ErrorResponse),
+    )
+)]
 #[instrument(name = "rest_org_delete", skip(db))]
 async fn delete_repo_impl(
     org: String,
@@ -211,6 +195,18 @@ fn update_repo(
         .and_then(update_repo_impl)
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/org/{org}/repo/{repo}",
+    tag = "repos",
+    params(("org" = String, Path, description = "Name of the organization"), ("repo" = String, Path, description = "Name of the repository")),
+    request_body = UpdateRepository,
+    responses(
+        (status = 200, description = "Repository labels were replaced", body = RepositoryResponse),
+        (status = 400, description = "Labels were rejected by a policy", body = ErrorResponse),
+        (status = 404, description = "Repository not found", body = ErrorResponse),
+    )
+)]
 #[instrument(name = "rest_org_update", skip(db, update))]
 async fn update_repo_impl(
     org: String,
@@ -230,6 +226,7 @@ async fn update_repo_impl(
 mod integ_test {
     use super::*;
     use crate::database::prelude::*;
+    use crate::database::Storage;
     use crate::test_utils::*;
     use json::object;
     use serial_test::serial;
@@ -262,6 +259,7 @@ mod integ_test {
                 "org":  "example",
                 "repo":  "example-repo-1",
                 "labels": {
+                    "dumont.io/created-at": TEST_CREATED_AT,
                     "scm_url": "https://github.com/example/example-repo-1"
                 }
             },
@@ -445,6 +443,8 @@ mod integ_test {
         assert_error_response(
             response,
             http::StatusCode::NOT_FOUND,
+            "REPO_NOT_FOUND",
+            object! { "org": "example", "repo": "example-repo-1" },
             "Repo example/example-repo-1 not found",
         );
     }
@@ -467,7 +467,53 @@ mod integ_test {
         assert_error_response(
             response,
             http::StatusCode::NOT_FOUND,
+            "REPO_NOT_FOUND",
+            object! { "org": "example", "repo": "example-repo-1" },
             "Repo example/example-repo-1 not found",
         );
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn test_get_non_existent_repo_as_problem_json() {
+        let backend = make_backend().await;
+        let filter = crate::api::create_filters(backend.clone()).await;
+
+        backend.database.create_org("example").await.unwrap();
+
+        let response = request()
+            .path("/api/org/example/repo/example-repo-1")
+            .header("accept", "application/problem+json, application/json")
+            .method("GET")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "application/problem+json"
+        );
+        assert_response(
+            response,
+            http::StatusCode::NOT_FOUND,
+            object! {
+                "type": "about:blank",
+                "title": "Not Found",
+                "status": 404,
+                "detail": "Repo example/example-repo-1 not found",
+                "code": "REPO_NOT_FOUND",
+                "errors": ["Repo example/example-repo-1 not found"],
+                "details": { "org": "example", "repo": "example-repo-1" },
+            },
+        );
+
+        let response = request()
+            .path("/api/org/example/repo/example-repo-1")
+            .method("GET")
+            .reply(&filter)
+            .await;
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "application/json"
+        );
+    }
 }
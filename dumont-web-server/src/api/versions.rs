@@ -3,79 +3,7 @@ use tracing::info;
 use tracing_attributes::instrument;
 use warp::{Filter, Rejection, Reply};
 
-use serde::{Deserialize, Serialize};
-
-type RevisionLabels = crate::models::GenericLabels;
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CreateVersion {
-    pub version: String,
-    #[serde(default, flatten)]
-    pub labels: RevisionLabels,
-}
-
-#[test]
-fn validate_create_version_deserialize() {
-    use json::object;
-
-    let _foo: CreateVersion = serde_json::from_str(&json::stringify(object! {
-        "version":  "abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyz",
-        "labels": {
-            "foo": "bar"
-        }
-    }))
-    .unwrap();
-
-    let _foo: CreateVersion = serde_json::from_str(&json::stringify(object! {
-        "version":  "abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyz",
-    }))
-    .unwrap();
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UpdateVersion {
-    #[serde(flatten)]
-    pub labels: RevisionLabels,
-}
-
-#[test]
-fn validate_update_version_deserialize() {
-    use json::object;
-
-    let _foo: UpdateVersion = serde_json::from_str(&json::stringify(object! {
-        "labels": {
-            "foo": "bar"
-        }
-    }))
-    .unwrap();
-
-    let _foo: UpdateVersion = serde_json::from_str(&json::stringify(object! {})).unwrap();
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct GetVersion {
-    pub version: String,
-    #[serde(flatten)]
-    pub labels: RevisionLabels,
-}
-
-#[test]
-fn validate_get_version_deserialize() {
-    use json::object;
-
-    let _foo: GetVersion = serde_json::from_str(&json::stringify(object! {
-        "version":  "abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyz",
-        "labels": {
-            "foo": "bar"
-        }
-    }))
-    .unwrap();
-
-    let _foo: GetVersion = serde_json::from_str(&json::stringify(object! {
-        "version":  "abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyz",
-    }))
-    .unwrap();
-}
+pub use dumont_api_models::{CreateVersion, GetVersion, UpdateVersion};
 
 impl From<crate::backend::models::DataStoreRevision> for GetVersion {
     fn from(source: crate::backend::models::DataStoreRevision) -> Self {
@@ -88,6 +16,7 @@ impl From<&crate::backend::models::DataStoreRevision> for GetVersion {
         Self {
             version: source.version.clone(),
             labels: source.labels.clone(),
+            applied_policies: source.applied_policies.clone(),
         }
     }
 }
@@ -113,6 +42,19 @@ fn create_version(
         .and_then(create_version_impl)
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/org/{org}/repo/{repo}/version",
+    tag = "versions",
+    params(("org" = String, Path, description = "Name of the organization"), ("repo" = String, Path, description = "Name of the repository")),
+    request_body = CreateVersion,
+    responses(
+        (status = 200, description = "Version was created", body = VersionResponse),
+        (status = 400, description = "Version was rejected by a policy or constraint", body = ErrorResponse),
+        (status = 404, description = "Repository not found", body = ErrorResponse),
+        (status = 409, description = "Version already exists", body = ErrorResponse),
+    )
+)]
 #[instrument(name = "rest_version_create", fields(version = %version.version), skip(version, db))]
 async fn create_version_impl(
     org: String,
@@ -141,6 +83,18 @@ fn update_version(
         .and_then(update_version_impl)
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/org/{org}/repo/{repo}/version/{version}",
+    tag = "versions",
+    params(("org" = String, Path, description = "Name of the organization"), ("repo" = String, Path, description = "Name of the repository"), ("version" = String, Path, description = "Name of the version")),
+    request_body = UpdateVersion,
+    responses(
+        (status = 200, description = "Version labels were replaced", body = VersionResponse),
+        (status = 400, description = "Labels were rejected by a policy", body = ErrorResponse),
+        (status = 404, description = "Version not found", body = ErrorResponse),
+    )
+)]
 #[instrument(name = "rest_version_update", skip(db, update))]
 async fn update_version_impl(
     org: String,
@@ -169,6 +123,16 @@ fn delete_version(
         .and_then(delete_version_impl)
 }
 
+#[utoipa::path(
+    delete,
+    path = "/api/org/{org}/repo/{repo}/version/{version}",
+    tag = "versions",
+    params(("org" = String, Path, description = "Name of the organization"), ("repo" = String, Path, description = "Name of the repository"), ("version" = String, Path, description = "Name of the version")),
+    responses(
+        (status = 200, description = "Version was deleted", body = DeleteResponse),
+        (status = 404, description = "Version not found", body = ErrorResponse),
+    )
+)]
 #[instrument(name = "rest_version_delete", skip(db))]
 async fn delete_version_impl(
     org: String,
@@ -195,6 +159,16 @@ fn list_versions(
         .and_then(list_versions_impl)
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/org/{org}/repo/{repo}/version",
+    tag = "versions",
+    params(("org" = String, Path, description = "Name of the organization"), ("repo" = String, Path, description = "Name of the repository"), ApiPagination),
+    responses(
+        (status = 200, description = "A page of versions", body = VersionListResponse),
+        (status = 404, description = "Repository not found", body = ErrorResponse),
+    )
+)]
 #[instrument(name = "rest_version_list", skip(db))]
 async fn list_versions_impl(
     org: String,
@@ -228,6 +202,16 @@ fn get_version(
         .and_then(get_version_impl)
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/org/{org}/repo/{repo}/version/{version}",
+    tag = "versions",
+    params(("org" = String, Path, description = "Name of the organization"), ("repo" = String, Path, description = "Name of the repository"), ("version" = String, Path, description = "Name of the version")),
+    responses(
+        (status = 200, description = "The version", body = VersionResponse),
+        (status = 404, description = "Version not found", body = ErrorResponse),
+    )
+)]
 #[instrument(name = "rest_version_get", skip(db))]
 async fn get_version_impl(
     org: String,
@@ -248,6 +232,7 @@ mod integ_test {
     use super::*;
     use crate::backend::models::PaginationOptions;
     use crate::database::prelude::*;
+    use crate::database::Storage;
     use crate::test_utils::*;
     use json::{array, object};
     use serial_test::serial;
@@ -282,6 +267,7 @@ mod integ_test {
             object! {
                 "version":  "1.2.3",
                 "labels": {
+                    "dumont.io/created-at": TEST_CREATED_AT,
                     "release_status": "pre-release"
                 }
             },
@@ -357,6 +343,7 @@ mod integ_test {
             object! {
                 "version":  "1.2.3",
                 "labels": {
+                    "dumont.io/created-at": TEST_CREATED_AT,
                     "release_status": "pre-release"
                 }
             },
@@ -378,6 +365,7 @@ mod integ_test {
             object! {
                 "version":  "1.2.3",
                 "labels": {
+                    "dumont.io/created-at": TEST_CREATED_AT,
                     "release_status": "release"
                 }
             },
@@ -471,9 +459,99 @@ mod integ_test {
             .reply(&filter)
             .await;
 
-        assert_error_response(
+        let message = "Version `abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyz` is 52 characters long, more than the limit of 30.";
+        assert_response(
             response,
-            http::StatusCode::BAD_REQUEST, "Version string 'abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyz' was more than the 30 character limit"
+            http::StatusCode::BAD_REQUEST,
+            object! {
+                "status": {
+                    "code": 400,
+                    "error": [message],
+                    "error_code": "POLICY_VERSION_TOO_LONG",
+                    "error_details": {
+                        "value": "abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyz",
+                    },
+                    "violations": [{
+                        "kind": "version_too_long",
+                        "value": "abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyz",
+                        "message": message,
+                    }],
+                },
+            },
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn test_create_version_reports_every_violation() {
+        use crate::backend::DefaultBackend;
+        use crate::policy::{PolicyDefinitionContainer, RealizedPolicyContainer};
+        use std::sync::Arc;
+
+        let container: PolicyDefinitionContainer = toml::from_str(
+            r#"
+[[policy]]
+name = "service"
+repository_pattern = "example/.*"
+required_repo_labels = []
+required_version_labels = [
+  {name = "owner"},
+  {name = "release_state", one_of = ["built", "deployed"]},
+]
+"#,
+        )
+        .unwrap();
+        let backend: crate::Backend = Arc::new(DefaultBackend {
+            database: make_storage().await,
+            policy_container: RealizedPolicyContainer::try_from(container).unwrap().into(),
+        });
+        let filter = create_version_api(backend.clone())
+            .recover(crate::api::canned_response::handle_rejection);
+
+        backend
+            .create_test_org_and_repos("example", vec!["example-repo-1"])
+            .await
+            .unwrap();
+
+        let response = request()
+            .path("/api/org/example/repo/example-repo-1/version")
+            .body(json::stringify(object! {
+                "version":  "1.2.3",
+                "labels": {
+                    "release_state": "released"
+                }
+            }))
+            .method("POST")
+            .reply(&filter)
+            .await;
+
+        let missing = "Policy `service` required that label `owner` be set, however it was not and no default was specified.";
+        let not_in_set = "Policy `service` required that label `release_state` be one of a set values, however `released` was not in that set.";
+        assert_response(
+            response,
+            http::StatusCode::BAD_REQUEST,
+            object! {
+                "status": {
+                    "code": 400,
+                    "error": [missing, not_in_set],
+                    "error_code": "POLICY_VIOLATIONS",
+                    "violations": [
+                        {
+                            "policy": "service",
+                            "label": "owner",
+                            "kind": "label_not_defined",
+                            "message": missing,
+                        },
+                        {
+                            "policy": "service",
+                            "label": "release_state",
+                            "kind": "label_not_in_set",
+                            "value": "released",
+                            "message": not_in_set,
+                        },
+                    ],
+                },
+            },
         );
     }
 }
@@ -0,0 +1,95 @@
+use crate::database::ReadScope;
+use chrono::{DateTime, TimeZone, Utc};
+use dumont_api_models::LAST_WRITE_HEADER;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use warp::http::{HeaderMap, HeaderValue, Request, Response};
+use warp::hyper::{self, service::Service, Body};
+
+/**
+ * WriteMarkers runs each request in a `ReadScope`, so that its reads see the write
+ * its `dumont-last-write` header stands for. When the request wrote, the response
+ * carries the marker for the client to send back. Markers are the time of the
+ * write, in milliseconds since the epoch.
+ */
+#[derive(Debug, Clone)]
+pub struct WriteMarkers<S> {
+    inner: S,
+}
+
+impl<S> WriteMarkers<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for WriteMarkers<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Send,
+    ReqBody: Send + 'static,
+    ResBody: Send,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        // The service that was polled ready handles the request, the clone is kept.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let scope = ReadScope::new(last_write(request.headers()));
+
+        Box::pin(async move {
+            let (result, wrote_at) = scope.run(async move { inner.call(request).await }).await;
+            let mut response = result?;
+            if let Some(wrote_at) = wrote_at {
+                response.headers_mut().insert(
+                    LAST_WRITE_HEADER,
+                    HeaderValue::from(wrote_at.timestamp_millis()),
+                );
+            }
+            Ok(response)
+        })
+    }
+}
+
+/// Markers that can't be read are ignored, the request then reads like one without.
+fn last_write(headers: &HeaderMap) -> Option<DateTime<Utc>> {
+    let millis = headers
+        .get(LAST_WRITE_HEADER)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()?;
+    Utc.timestamp_millis_opt(millis).single()
+}
+
+/// Serves `service`, e.g. a `warp::service`, on `addr` with write markers. Returns
+/// the address it's bound to, along with the server to run.
+pub fn serve<S>(
+    service: S,
+    addr: &SocketAddr,
+) -> Result<(SocketAddr, impl Future<Output = hyper::Result<()>>), hyper::Error>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    let make_service = hyper::service::make_service_fn(move |_| {
+        let service = WriteMarkers::new(service.clone());
+        async move { Ok::<_, Infallible>(service) }
+    });
+    let server = hyper::Server::try_bind(addr)?.serve(make_service);
+    Ok((server.local_addr(), server))
+}
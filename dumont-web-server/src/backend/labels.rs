@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::{BackendError, ConstraintViolation};
+
+/// Labels under this prefix are written by the server, and can't be set by clients.
+pub const RESERVED_LABEL_PREFIX: &str = "dumont.io/";
+
+/// Set on every repo and version when it is created.
+pub const CREATED_AT_LABEL: &str = "dumont.io/created-at";
+
+type Labels = BTreeMap<String, String>;
+
+const MAX_NAME_LENGTH: usize = 63;
+const MAX_PREFIX_LENGTH: usize = 253;
+
+lazy_static! {
+    static ref LABEL_NAME: Regex =
+        Regex::new(r"^[A-Za-z0-9]([A-Za-z0-9_.-]*[A-Za-z0-9])?$").unwrap();
+    static ref LABEL_PREFIX: Regex =
+        Regex::new(r"^[a-z0-9]([a-z0-9-]*[a-z0-9])?(\.[a-z0-9]([a-z0-9-]*[a-z0-9])?)*$").unwrap();
+}
+
+/// Checks a label key follows the Kubernetes syntax, an optional DNS subdomain prefix
+/// and a `/`, followed by a name of up to 63 letters, digits, `-`, `_` and `.`.
+pub fn check_label_key(key: &str) -> Result<(), String> {
+    let (prefix, name) = match key.split_once('/') {
+        Some((prefix, name)) => (Some(prefix), name),
+        None => (None, key),
+    };
+
+    if let Some(prefix) = prefix {
+        if prefix.len() > MAX_PREFIX_LENGTH || !LABEL_PREFIX.is_match(prefix) {
+            return Err(format!(
+                "the prefix must be a lowercase DNS subdomain of at most {} characters",
+                MAX_PREFIX_LENGTH
+            ));
+        }
+    }
+
+    if name.is_empty() {
+        return Err("the name must not be empty".to_owned());
+    }
+    if name.len() > MAX_NAME_LENGTH {
+        return Err(format!(
+            "the name must be at most {} characters",
+            MAX_NAME_LENGTH
+        ));
+    }
+    if !LABEL_NAME.is_match(name) {
+        return Err("the name must start and end with a letter or digit, and only contain letters, digits, `-`, `_` and `.`".to_owned());
+    }
+
+    Ok(())
+}
+
+pub fn is_reserved_label(key: &str) -> bool {
+    key.starts_with(RESERVED_LABEL_PREFIX)
+}
+
+/// Removes the server's labels, leaving the ones policies apply to.
+pub fn without_reserved_labels(labels: &Labels) -> Labels {
+    labels
+        .iter()
+        .filter(|(key, _)| !is_reserved_label(key))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// Validates the keys a client sent, and splits off the reserved labels.
+///
+/// Clients may send back a reserved label when it matches `existing_labels`, so labels
+/// read from the server can be written again. The returned reserved labels are the
+/// stored ones, which are kept whether or not the client sent them.
+pub fn split_client_labels(
+    provided_labels: &Labels,
+    existing_labels: Option<&Labels>,
+) -> Result<(Labels, Labels), BackendError> {
+    let mut labels = BTreeMap::new();
+    for (key, value) in provided_labels {
+        if is_reserved_label(key) {
+            let existing = existing_labels.and_then(|existing| existing.get(key));
+            if existing != Some(value) {
+                return Err(BackendError::ConstraintViolation {
+                    reason: ConstraintViolation::ReservedLabel { key: key.clone() },
+                });
+            }
+            continue;
+        }
+
+        if let Err(reason) = check_label_key(key) {
+            return Err(BackendError::ConstraintViolation {
+                reason: ConstraintViolation::InvalidLabelKey {
+                    key: key.clone(),
+                    reason,
+                },
+            });
+        }
+        labels.insert(key.clone(), value.clone());
+    }
+
+    let reserved = existing_labels
+        .map(|existing| {
+            existing
+                .iter()
+                .filter(|(key, _)| is_reserved_label(key))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok((labels, reserved))
+}
+
+#[test]
+fn validate_label_keys() {
+    for key in [
+        "owners",
+        "git_hash",
+        "release-state",
+        "a",
+        "example.com/team",
+        "build.v2",
+        "dumont.io/created-at",
+        "A1",
+    ] {
+        assert_eq!(check_label_key(key), Ok(()), "{}", key);
+    }
+
+    for key in [
+        "",
+        " ",
+        "has space",
+        "-leading",
+        "trailing.",
+        "example.com/",
+        "/name",
+        "Example.com/team",
+        "example..com/team",
+        "a/b/c",
+        "emoji-\u{1F600}",
+    ] {
+        assert!(check_label_key(key).is_err(), "{}", key);
+    }
+
+    assert!(check_label_key(&"a".repeat(63)).is_ok());
+    assert_eq!(
+        check_label_key(&"a".repeat(64)),
+        Err("the name must be at most 63 characters".to_owned())
+    );
+    assert!(check_label_key(&format!("{}/name", "a".repeat(253))).is_ok());
+    assert!(check_label_key(&format!("{}/name", "a".repeat(254))).is_err());
+}
+
+#[test]
+fn validate_split_client_labels() {
+    let labels = |pairs: &[(&str, &str)]| -> Labels {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    };
+    let existing = labels(&[("owner", "me"), (CREATED_AT_LABEL, "2026-01-01T00:00:00Z")]);
+
+    let (client, reserved) = split_client_labels(&labels(&[("owner", "you")]), None).unwrap();
+    assert_eq!(client, labels(&[("owner", "you")]));
+    assert!(reserved.is_empty());
+
+    let (client, reserved) = split_client_labels(
+        &labels(&[("owner", "you"), (CREATED_AT_LABEL, "2026-01-01T00:00:00Z")]),
+        Some(&existing),
+    )
+    .unwrap();
+    assert_eq!(client, labels(&[("owner", "you")]));
+    assert_eq!(
+        reserved,
+        labels(&[(CREATED_AT_LABEL, "2026-01-01T00:00:00Z")])
+    );
+
+    let (_, reserved) = split_client_labels(&BTreeMap::new(), Some(&existing)).unwrap();
+    assert_eq!(
+        reserved,
+        labels(&[(CREATED_AT_LABEL, "2026-01-01T00:00:00Z")])
+    );
+
+    assert!(matches!(
+        split_client_labels(&labels(&[(CREATED_AT_LABEL, "yesterday")]), Some(&existing)),
+        Err(BackendError::ConstraintViolation {
+            reason: ConstraintViolation::ReservedLabel { .. }
+        })
+    ));
+    assert!(matches!(
+        split_client_labels(&labels(&[("dumont.io/owner", "me")]), None),
+        Err(BackendError::ConstraintViolation {
+            reason: ConstraintViolation::ReservedLabel { .. }
+        })
+    ));
+    assert!(matches!(
+        split_client_labels(&labels(&[("has space", "me")]), None),
+        Err(BackendError::ConstraintViolation {
+            reason: ConstraintViolation::InvalidLabelKey { .. }
+        })
+    ));
+}
@@ -1,14 +1,22 @@
+mod labels;
 pub mod models;
 use std::collections::BTreeMap;
 
 use crate::models::GenericLabels;
+use chrono::SecondsFormat;
+pub use labels::CREATED_AT_LABEL;
+use labels::{split_client_labels, without_reserved_labels};
 use models::*;
 use thiserror::Error;
-use tracing::{error, info};
+use tracing::{info, warn};
 use tracing_attributes::instrument;
 
 use crate::database::prelude::*;
-use crate::policy::{PolicyError, RealizedPolicyContainer};
+use crate::database::Storage;
+use crate::policy::{
+    expired_versions, policy_hash, LabelChange, PolicyDefinitionContainer, PolicyError,
+    PolicyHandle, PolicyReload, RealizedPolicyContainer, RetentionCandidate,
+};
 
 #[derive(Error, Debug)]
 pub enum BackendError {
@@ -28,31 +36,239 @@ pub enum BackendError {
 
 #[derive(Error, Debug)]
 pub enum ConstraintViolation {
-    #[error("Version string '{version}' was more than the 30 character limit")]
-    VersionToLong { version: String },
+    #[error("Policy version {version} is active, activate another version before deleting it")]
+    ActivePolicyVersion { version: i32 },
+    #[error("Label key `{key}` is invalid, {reason}")]
+    InvalidLabelKey { key: String, reason: String },
+    #[error(
+        "Label `{key}` uses the reserved prefix `dumont.io/` and can only be set by the server"
+    )]
+    ReservedLabel { key: String },
 }
 
-pub struct DefaultBackend {
-    pub database: BackendDatabase,
-    pub policy_container: RealizedPolicyContainer,
+impl ConstraintViolation {
+    /// A stable name for the error, used in API responses.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ConstraintViolation::ActivePolicyVersion { .. } => "POLICY_VERSION_ACTIVE",
+            ConstraintViolation::InvalidLabelKey { .. } => "INVALID_LABEL_KEY",
+            ConstraintViolation::ReservedLabel { .. } => "RESERVED_LABEL",
+        }
+    }
+
+    pub fn details(&self) -> Vec<(&'static str, String)> {
+        match self {
+            ConstraintViolation::ActivePolicyVersion { version } => {
+                vec![("policy_version", version.to_string())]
+            }
+            ConstraintViolation::InvalidLabelKey { key, .. }
+            | ConstraintViolation::ReservedLabel { key } => vec![("label", key.clone())],
+        }
+    }
+}
+
+/// The backend behind the API. It keeps its data in `S`, the database by default,
+/// see `Storage`.
+pub struct DefaultBackend<S = BackendDatabase> {
+    pub database: S,
+    pub policy_container: PolicyHandle,
 }
 
-impl DefaultBackend {
-    pub async fn new(
-        db_connection_string: String,
-        policy_container: RealizedPolicyContainer,
-    ) -> Result<Self, BackendError> {
+impl<S: Storage> DefaultBackend<S> {
+    pub fn new(database: S, policy_container: PolicyHandle) -> Self {
         info!(
             "Policies Configured\n{}",
-            toml::to_string_pretty(&policy_container)
+            toml::to_string_pretty(&policy_container.current().container)
                 .unwrap_or_else(|_| "Policy failed to render".to_owned())
         );
-        Ok(Self {
-            database: BackendDatabase::new(db_connection_string).await?,
+        Self {
+            database,
             policy_container,
+        }
+    }
+
+    /// Enforces the active policy version from the database. The policy document is
+    /// imported first, so an edit to it becomes the active version, see
+    /// `import_policy_document`.
+    ///
+    /// Reloads that changed the policies, and ones that failed, are counted in
+    /// `policy_reloads_total`. Having no policies at all isn't counted as a failure.
+    pub async fn reload_policies(&self) -> Result<PolicyReload, BackendError> {
+        let result = self.try_reload_policies().await;
+        match &result {
+            Ok(reload) if reload.changed => crate::api::metrics::record_policy_reload(true),
+            Ok(_)
+            | Err(BackendError::PolicyViolation {
+                error: PolicyError::NoPolicyDocument,
+            }) => {}
+            Err(_) => crate::api::metrics::record_policy_reload(false),
+        }
+
+        result
+    }
+
+    async fn try_reload_policies(&self) -> Result<PolicyReload, BackendError> {
+        if self.policy_container.has_document() {
+            self.import_policy_document().await?;
+        }
+
+        match self.database.get_active_policy_version().await? {
+            Some(version) => {
+                let version = policy_version(version)?;
+                Ok(self
+                    .policy_container
+                    .replace(version.hash, || Ok(version.document))?)
+            }
+            None => Err(PolicyError::NoPolicyDocument.into()),
+        }
+    }
+
+    /// Activates the policy document when it changed since it was last imported,
+    /// storing it as a new version unless an earlier version has the same policies.
+    ///
+    /// At startup the document is only imported when no version has its policies,
+    /// or none is enforced yet, so a version activated through the admin API since
+    /// isn't replaced by a restart. A server starting alongside another can find the
+    /// document stored but not yet activated, and then activates it itself.
+    async fn import_policy_document(&self) -> Result<(), BackendError> {
+        let document = self.policy_container.read_document()?;
+        let hash = document_hash(&document)?;
+        let imported_hash = self.policy_container.imported_hash();
+        if imported_hash.as_ref() == Some(&hash) {
+            return Ok(());
+        }
+
+        let version = match self.database.find_policy_version_by_hash(&hash).await? {
+            Some(stored) if imported_hash.is_none() => {
+                match self.database.get_active_policy_version().await? {
+                    Some(_) => None,
+                    None => Some(stored.policy_version_id),
+                }
+            }
+            Some(stored) => Some(stored.policy_version_id),
+            None => {
+                match self
+                    .create_policy_version(document, Some("Imported from the policy document"))
+                    .await
+                {
+                    Ok(created) => Some(created.version),
+                    // Another server imported the same document since it was looked for.
+                    Err(BackendError::DatabaseError {
+                        source:
+                            DatabaseError::AlreadyExists {
+                                error: AlreadyExistsError::PolicyVersion { version },
+                            },
+                    }) => Some(version),
+                    Err(e) => return Err(e),
+                }
+            }
+        };
+        if let Some(version) = version {
+            self.database.activate_policy_version(version).await?;
+            warn!(target: "audit", version, %hash, "Policy document imported");
+        }
+
+        self.policy_container.set_imported_hash(hash);
+        Ok(())
+    }
+
+    /// Imports the policy document, see `reload_policies`, and enforces the active
+    /// version. Without either, no policies are enforced.
+    pub async fn bootstrap_policies(&self) -> Result<(), BackendError> {
+        match self.reload_policies().await {
+            Err(BackendError::PolicyViolation {
+                error: PolicyError::NoPolicyDocument,
+            }) => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+
+    /// Stores a new, inactive, policy version. The document is realized first, so
+    /// only versions that can be enforced are stored.
+    #[instrument(skip(self, document))]
+    pub async fn create_policy_version(
+        &self,
+        document: PolicyDefinitionContainer,
+        comment: Option<&str>,
+    ) -> Result<DataStorePolicyVersion, BackendError> {
+        RealizedPolicyContainer::try_from(document.clone())?;
+
+        let evaluation =
+            serde_json::to_value(document.evaluation()).map_err(stored_policy_error)?;
+        let definitions = document
+            .policies()
+            .iter()
+            .map(|policy| {
+                Ok(DbPolicyDefinition {
+                    policy_name: policy.name().to_owned(),
+                    definition: serde_json::to_string(policy).map_err(stored_policy_error)?,
+                })
+            })
+            .collect::<Result<_, DatabaseError>>()?;
+
+        let version = self
+            .database
+            .create_policy_version(&CreatePolicyVersionParam {
+                evaluation: evaluation.as_str().unwrap_or_default(),
+                document_hash: &document_hash(&document)?,
+                comment,
+                definitions,
+            })
+            .await?;
+        policy_version(version)
+    }
+
+    #[instrument(skip(self, pagination))]
+    pub async fn list_policy_versions(
+        &self,
+        pagination: PaginationOptions,
+    ) -> Result<DataStorePolicyVersionList, BackendError> {
+        let versions = self
+            .database
+            .list_policy_versions(&pagination)
+            .await?
+            .into_iter()
+            .map(policy_version)
+            .collect::<Result<_, _>>()?;
+        let total_count = self.database.count_policy_versions().await?;
+        Ok(DataStorePolicyVersionList {
+            versions,
+            total_count,
+            has_more: pagination.has_more(total_count),
         })
     }
 
+    #[instrument(skip(self))]
+    pub async fn get_policy_version(
+        &self,
+        version: i32,
+    ) -> Result<DataStorePolicyVersion, BackendError> {
+        policy_version(self.database.get_policy_version(version).await?)
+    }
+
+    /// Makes `version` the enforced policy version. This replica enforces it
+    /// immediately, the others when they next poll the database.
+    #[instrument(skip(self))]
+    pub async fn activate_policy_version(
+        &self,
+        version: i32,
+    ) -> Result<DataStorePolicyVersion, BackendError> {
+        let activated = policy_version(self.database.activate_policy_version(version).await?)?;
+        warn!(target: "audit", version, hash = %activated.hash, "Policy version activated");
+        self.reload_policies().await?;
+        Ok(activated)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn delete_policy_version(&self, version: i32) -> Result<bool, BackendError> {
+        if self.database.get_policy_version(version).await?.active {
+            return Err(BackendError::ConstraintViolation {
+                reason: ConstraintViolation::ActivePolicyVersion { version },
+            });
+        }
+        Ok(self.database.delete_policy_version(version).await?)
+    }
+
     #[instrument(skip(self))]
     pub async fn create_organization(
         &self,
@@ -97,9 +313,13 @@ impl DefaultBackend {
         repo_name: &str,
         provided_labels: BTreeMap<String, String>,
     ) -> Result<DataStoreRepository, BackendError> {
-        let mut labels = provided_labels.clone();
-        self.policy_container
-            .execute_repo_policies(org_name, repo_name, &mut labels)?;
+        let (mut labels, _) = split_client_labels(&provided_labels, None)?;
+        let applied_policies = self
+            .policy_container
+            .current()
+            .container
+            .execute_repo_policies(org_name, repo_name, &mut labels, LabelChange::Create)?;
+        labels.insert(CREATED_AT_LABEL.to_owned(), self.created_at());
 
         let repo = self
             .database
@@ -111,7 +331,7 @@ impl DefaultBackend {
             )
             .await?;
 
-        Ok(repo.into())
+        Ok(DataStoreRepository::from(repo).with_applied_policies(applied_policies))
     }
 
     #[instrument(skip(self, pagination))]
@@ -129,6 +349,30 @@ impl DefaultBackend {
         ))
     }
 
+    /// Like `list_repos`, but only the repos with every one of `labels`, so the
+    /// page is full and the count is of the matching repos.
+    #[instrument(skip(self, pagination))]
+    pub async fn list_repos_with_labels(
+        &self,
+        org_name: &str,
+        labels: &BTreeMap<String, String>,
+        pagination: PaginationOptions,
+    ) -> Result<DataStoreRepositoryList, BackendError> {
+        let repos = self
+            .database
+            .list_repos_with_labels(org_name, labels, &pagination)
+            .await?;
+        let total_count = self
+            .database
+            .count_repos_with_labels(org_name, labels)
+            .await?;
+        Ok(DataStoreRepositoryList::from(
+            repos,
+            total_count,
+            pagination.has_more(total_count),
+        ))
+    }
+
     #[instrument(skip(self))]
     pub async fn get_repo(
         &self,
@@ -157,18 +401,73 @@ impl DefaultBackend {
         repo_name: &str,
         provided_labels: BTreeMap<String, String>,
     ) -> Result<DataStoreRepository, BackendError> {
-        let mut labels = provided_labels.clone();
-        self.policy_container
-            .execute_repo_policies(org_name, repo_name, &mut labels)?;
+        self.replace_repo_labels(org_name, repo_name, provided_labels, None)
+            .await
+    }
+
+    /// Replaces a repo's labels as an admin, allowing immutable labels to change.
+    /// Every override is written to the `audit` log with its reason.
+    #[instrument(skip(self, provided_labels))]
+    pub async fn override_repo_labels(
+        &self,
+        org_name: &str,
+        repo_name: &str,
+        provided_labels: BTreeMap<String, String>,
+        reason: &str,
+    ) -> Result<DataStoreRepository, BackendError> {
+        self.replace_repo_labels(org_name, repo_name, provided_labels, Some(reason))
+            .await
+    }
 
-        self.database
-            .set_repo_labels(&RepoParam::new(org_name, repo_name), labels)
-            .await?;
+    async fn replace_repo_labels(
+        &self,
+        org_name: &str,
+        repo_name: &str,
+        provided_labels: BTreeMap<String, String>,
+        override_reason: Option<&str>,
+    ) -> Result<DataStoreRepository, BackendError> {
+        let policies = self.policy_container.current();
+        let mut applied_policies = Vec::new();
+        let mut old_labels = BTreeMap::new();
+
+        // The labels are checked against the stored ones in the same transaction as
+        // the write, so concurrent updates can't both pass the checks.
         let repo = self
             .database
-            .get_repo(&RepoParam::new(org_name, repo_name))
+            .update_repo_labels(&RepoParam::new(org_name, repo_name), |existing| {
+                let existing_labels = &existing.labels.labels;
+                let change = match override_reason {
+                    Some(_) => LabelChange::Override { existing_labels },
+                    None => LabelChange::Update { existing_labels },
+                };
+
+                let (mut labels, reserved) =
+                    split_client_labels(&provided_labels, Some(existing_labels))?;
+                applied_policies = policies.container.execute_repo_policies(
+                    org_name,
+                    repo_name,
+                    &mut labels,
+                    change,
+                )?;
+                labels.extend(reserved);
+                old_labels = existing_labels.clone();
+                Ok::<_, BackendError>(labels)
+            })
             .await?;
-        Ok(repo.into())
+
+        if let Some(reason) = override_reason {
+            warn!(
+                target: "audit",
+                org = org_name,
+                repo = repo_name,
+                reason,
+                old_labels = ?old_labels,
+                new_labels = ?repo.labels.labels,
+                "Repo labels overridden by admin"
+            );
+        }
+
+        Ok(DataStoreRepository::from(repo).with_applied_policies(applied_policies))
     }
 
     #[instrument(skip(self, provided_labels))]
@@ -179,17 +478,25 @@ impl DefaultBackend {
         version_name: &str,
         provided_labels: BTreeMap<String, String>,
     ) -> Result<DataStoreRevision, BackendError> {
-        if version_name.len() > 30 {
-            return Err(BackendError::ConstraintViolation {
-                reason: ConstraintViolation::VersionToLong {
-                    version: version_name.to_owned(),
-                },
-            });
-        }
+        let policies = self.policy_container.current();
+        policies
+            .container
+            .check_version_name(org_name, repo_name, version_name)?;
 
-        let mut labels = provided_labels.clone();
-        self.policy_container
-            .execute_version_policies(org_name, repo_name, &mut labels)?;
+        let repo = self
+            .database
+            .get_repo(&RepoParam::new(org_name, repo_name))
+            .await?;
+        let (mut labels, _) = split_client_labels(&provided_labels, None)?;
+        let applied_policies = policies.container.execute_version_policies(
+            org_name,
+            repo_name,
+            version_name,
+            &repo.labels.labels,
+            &mut labels,
+            LabelChange::Create,
+        )?;
+        labels.insert(CREATED_AT_LABEL.to_owned(), self.created_at());
 
         let param = RevisionParam::new(org_name, repo_name, version_name);
         self.database
@@ -203,7 +510,7 @@ impl DefaultBackend {
             .await?;
 
         let revision = self.database.get_revision(&param).await?;
-        Ok(revision.into())
+        Ok(DataStoreRevision::from(revision).with_applied_policies(applied_policies))
     }
 
     #[instrument(skip(self, provided_labels))]
@@ -214,15 +521,89 @@ impl DefaultBackend {
         version_name: &str,
         provided_labels: GenericLabels,
     ) -> Result<DataStoreRevision, BackendError> {
-        let mut labels = provided_labels.labels.clone();
-        self.policy_container
-            .execute_version_policies(org_name, repo_name, &mut labels)?;
+        self.replace_version_labels(org_name, repo_name, version_name, provided_labels, None)
+            .await
+    }
 
+    /// Replaces a version's labels as an admin, allowing immutable labels to change.
+    /// Every override is written to the `audit` log with its reason.
+    #[instrument(skip(self, provided_labels))]
+    pub async fn override_version_labels(
+        &self,
+        org_name: &str,
+        repo_name: &str,
+        version_name: &str,
+        provided_labels: GenericLabels,
+        reason: &str,
+    ) -> Result<DataStoreRevision, BackendError> {
+        self.replace_version_labels(
+            org_name,
+            repo_name,
+            version_name,
+            provided_labels,
+            Some(reason),
+        )
+        .await
+    }
+
+    async fn replace_version_labels(
+        &self,
+        org_name: &str,
+        repo_name: &str,
+        version_name: &str,
+        provided_labels: GenericLabels,
+        override_reason: Option<&str>,
+    ) -> Result<DataStoreRevision, BackendError> {
         let param = RevisionParam::new(org_name, repo_name, version_name);
-        self.database.set_revision_labels(&param, &labels).await?;
+        let repo = self
+            .database
+            .get_repo(&RepoParam::new(org_name, repo_name))
+            .await?;
+        let policies = self.policy_container.current();
+        let mut applied_policies = Vec::new();
+        let mut old_labels = BTreeMap::new();
 
-        let revision = self.database.get_revision(&param).await?;
-        Ok(revision.into())
+        // Checked against the stored labels in the same transaction as the write, like
+        // the repo labels.
+        let revision = self
+            .database
+            .update_revision_labels(&param, |existing| {
+                let existing_labels = &existing.labels.labels;
+                let change = match override_reason {
+                    Some(_) => LabelChange::Override { existing_labels },
+                    None => LabelChange::Update { existing_labels },
+                };
+
+                let (mut labels, reserved) =
+                    split_client_labels(&provided_labels.labels, Some(existing_labels))?;
+                applied_policies = policies.container.execute_version_policies(
+                    org_name,
+                    repo_name,
+                    version_name,
+                    &repo.labels.labels,
+                    &mut labels,
+                    change,
+                )?;
+                labels.extend(reserved);
+                old_labels = existing_labels.clone();
+                Ok::<_, BackendError>(labels)
+            })
+            .await?;
+
+        if let Some(reason) = override_reason {
+            warn!(
+                target: "audit",
+                org = org_name,
+                repo = repo_name,
+                version = version_name,
+                reason,
+                old_labels = ?old_labels,
+                new_labels = ?revision.labels.labels,
+                "Version labels overridden by admin"
+            );
+        }
+
+        Ok(DataStoreRevision::from(revision).with_applied_policies(applied_policies))
     }
 
     #[instrument(skip(self))]
@@ -257,6 +638,38 @@ impl DefaultBackend {
         ))
     }
 
+    /// Lists the same page of the versions with every one of `labels` of each repo,
+    /// keyed by repo id, with a couple of queries for all of them.
+    #[instrument(skip(self, pagination))]
+    pub async fn list_versions_of_repos(
+        &self,
+        repo_ids: &[i32],
+        labels: &BTreeMap<String, String>,
+        pagination: PaginationOptions,
+    ) -> Result<BTreeMap<i32, DataStoreVersionList>, BackendError> {
+        let mut revisions = self
+            .database
+            .list_revisions_of_repos(repo_ids, labels, &pagination)
+            .await?;
+        let total_counts = self
+            .database
+            .count_revisions_of_repos(repo_ids, labels)
+            .await?;
+
+        Ok(repo_ids
+            .iter()
+            .map(|repo_id| {
+                let total_count = total_counts.get(repo_id).copied().unwrap_or_default();
+                let version_list = DataStoreVersionList::from(
+                    revisions.remove(repo_id).unwrap_or_default(),
+                    total_count,
+                    pagination.has_more(total_count),
+                );
+                (*repo_id, version_list)
+            })
+            .collect())
+    }
+
     #[instrument(skip(self))]
     pub async fn get_version(
         &self,
@@ -268,6 +681,279 @@ impl DefaultBackend {
         let revision = self.database.get_revision(&param).await?;
         Ok(revision.into())
     }
+
+    /// Evaluates every stored repo and version against the current policies, as if
+    /// they were created now, except that missing labels aren't defaulted. Nothing is
+    /// written.
+    #[instrument(skip(self))]
+    pub async fn compliance_report(&self) -> Result<DataStoreComplianceReport, BackendError> {
+        let policies = self.policy_container.current();
+        let mut report = DataStoreComplianceReport::default();
+
+        for page_number in 0.. {
+            let orgs = self
+                .database
+                .list_orgs(&PaginationOptions::new(page_number, COMPLIANCE_PAGE_SIZE))
+                .await?;
+            for org in &orgs {
+                self.check_org_compliance(&policies.container, &org.org_name, &mut report)
+                    .await?;
+            }
+            if (orgs.len() as u64) < COMPLIANCE_PAGE_SIZE {
+                break;
+            }
+        }
+
+        Ok(report)
+    }
+
+    async fn check_org_compliance(
+        &self,
+        policies: &RealizedPolicyContainer,
+        org_name: &str,
+        report: &mut DataStoreComplianceReport,
+    ) -> Result<(), BackendError> {
+        for page_number in 0.. {
+            let repos = self
+                .database
+                .list_repos(
+                    org_name,
+                    &PaginationOptions::new(page_number, COMPLIANCE_PAGE_SIZE),
+                )
+                .await?;
+            for repo in &repos {
+                let repo = DataStoreRepository::from(repo);
+                report.repos_checked += 1;
+                let labels = without_reserved_labels(&repo.labels.labels);
+                if let Err(e) = policies.check_repo_labels(org_name, &repo.repo_name, &labels) {
+                    report.violations.extend(e.violations().iter().map(|error| {
+                        DataStoreViolation::new(org_name, &repo.repo_name, None, error)
+                    }));
+                }
+
+                self.check_repo_compliance(policies, &repo, report).await?;
+            }
+            if (repos.len() as u64) < COMPLIANCE_PAGE_SIZE {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn check_repo_compliance(
+        &self,
+        policies: &RealizedPolicyContainer,
+        repo: &DataStoreRepository,
+        report: &mut DataStoreComplianceReport,
+    ) -> Result<(), BackendError> {
+        let (org_name, repo_name) = (repo.org_name.as_str(), repo.repo_name.as_str());
+        let repo_param = RepoParam::new(org_name, repo_name);
+        for page_number in 0.. {
+            let revisions = self
+                .database
+                .list_revisions(
+                    &repo_param,
+                    &PaginationOptions::new(page_number, COMPLIANCE_PAGE_SIZE),
+                )
+                .await?;
+            for revision in &revisions {
+                let version = DataStoreRevision::from(revision);
+                report.versions_checked += 1;
+                let labels = without_reserved_labels(&version.labels.labels);
+                let result = policies
+                    .check_version_name(org_name, repo_name, &version.version)
+                    .and_then(|_| {
+                        policies.check_version_labels(
+                            org_name,
+                            repo_name,
+                            &version.version,
+                            &repo.labels.labels,
+                            &labels,
+                        )
+                    });
+                if let Err(e) = result {
+                    report.violations.extend(e.violations().iter().map(|error| {
+                        DataStoreViolation::new(org_name, repo_name, Some(&version.version), error)
+                    }));
+                }
+            }
+            if (revisions.len() as u64) < COMPLIANCE_PAGE_SIZE {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds the versions expired by the retention rules of the current policies, and
+    /// deletes them unless `dry_run` is set. Repos without retention rules are skipped.
+    /// Every run is counted in `gc_runs_total`, by whether it completed.
+    #[instrument(skip(self))]
+    pub async fn collect_garbage(&self, dry_run: bool) -> Result<DataStoreGcReport, BackendError> {
+        let policies = self.policy_container.current();
+        let mut report = DataStoreGcReport {
+            dry_run,
+            ..Default::default()
+        };
+
+        let result = self
+            .collect_all_garbage(&policies.container, &mut report)
+            .await;
+        crate::api::metrics::record_gc_run(
+            dry_run,
+            result.as_ref().ok().map(|_| report.expired.len()),
+        );
+        result.map(|_| report)
+    }
+
+    async fn collect_all_garbage(
+        &self,
+        policies: &RealizedPolicyContainer,
+        report: &mut DataStoreGcReport,
+    ) -> Result<(), BackendError> {
+        for page_number in 0.. {
+            let orgs = self
+                .database
+                .list_orgs(&PaginationOptions::new(page_number, COMPLIANCE_PAGE_SIZE))
+                .await?;
+            for org in &orgs {
+                self.collect_org_garbage(policies, &org.org_name, report)
+                    .await?;
+            }
+            if (orgs.len() as u64) < COMPLIANCE_PAGE_SIZE {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn collect_org_garbage(
+        &self,
+        policies: &RealizedPolicyContainer,
+        org_name: &str,
+        report: &mut DataStoreGcReport,
+    ) -> Result<(), BackendError> {
+        for page_number in 0.. {
+            let repos = self
+                .database
+                .list_repos(
+                    org_name,
+                    &PaginationOptions::new(page_number, COMPLIANCE_PAGE_SIZE),
+                )
+                .await?;
+            for repo in &repos {
+                self.collect_repo_garbage(policies, org_name, &repo.repo_name, report)
+                    .await?;
+            }
+            if (repos.len() as u64) < COMPLIANCE_PAGE_SIZE {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn collect_repo_garbage(
+        &self,
+        policies: &RealizedPolicyContainer,
+        org_name: &str,
+        repo_name: &str,
+        report: &mut DataStoreGcReport,
+    ) -> Result<(), BackendError> {
+        let rules = policies.retention_rules(org_name, repo_name);
+        if rules.is_empty() {
+            return Ok(());
+        }
+
+        // Every version is needed to rank them, so the whole repo is read before
+        // anything is deleted.
+        let repo_param = RepoParam::new(org_name, repo_name);
+        let mut versions = Vec::new();
+        for page_number in 0.. {
+            let revisions = self
+                .database
+                .list_revisions(
+                    &repo_param,
+                    &PaginationOptions::new(page_number, COMPLIANCE_PAGE_SIZE),
+                )
+                .await?;
+            versions.extend(revisions.iter().map(DataStoreRevision::from));
+            if (revisions.len() as u64) < COMPLIANCE_PAGE_SIZE {
+                break;
+            }
+        }
+        report.versions_checked += versions.len() as u64;
+
+        let candidates: Vec<RetentionCandidate> = versions
+            .iter()
+            .map(|version| RetentionCandidate {
+                version: &version.version,
+                labels: &version.labels.labels,
+                created_at: version.created_at,
+            })
+            .collect();
+        let expired = expired_versions(
+            &rules,
+            &candidates,
+            policies.version_scheme(org_name, repo_name),
+            self.database.now(),
+        );
+
+        for (index, policy_name) in expired {
+            let version = &versions[index];
+            if !report.dry_run {
+                self.database
+                    .delete_revision(&RevisionParam::new(org_name, repo_name, &version.version))
+                    .await?;
+                info!(
+                    "Deleted {}/{}@{}, expired by the retention rules of policy `{}`",
+                    org_name, repo_name, version.version, policy_name
+                );
+                crate::api::metrics::record_gc_deletion(policy_name);
+            }
+            report.expired.push(DataStoreExpiredVersion {
+                org_name: org_name.to_owned(),
+                repo_name: repo_name.to_owned(),
+                version: version.version.clone(),
+                policy_name: policy_name.to_owned(),
+                created_at: version.created_at,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Value of the `dumont.io/created-at` label for a repo or version created now.
+    fn created_at(&self) -> String {
+        self.database
+            .now()
+            .to_rfc3339_opts(SecondsFormat::Secs, true)
+    }
+}
+
+/// Page size used to walk every stored repo and version for a compliance report or
+/// garbage collection.
+const COMPLIANCE_PAGE_SIZE: u64 = 100;
+
+/// Policy versions are realized before they are written, so failing to convert one
+/// to or from JSON means the stored version was changed by hand.
+fn stored_policy_error(error: serde_json::Error) -> DatabaseError {
+    DatabaseError::BackendError {
+        source: anyhow::Error::new(error)
+            .context("Policy version could not be converted to or from JSON"),
+    }
+}
+
+/// The hash of `document` as it's stored in a policy version.
+fn document_hash(document: &PolicyDefinitionContainer) -> Result<String, DatabaseError> {
+    let serialized = serde_json::to_string(document).map_err(stored_policy_error)?;
+    Ok(policy_hash(&serialized))
+}
+
+fn policy_version(version: DbPolicyVersionModel) -> Result<DataStorePolicyVersion, BackendError> {
+    Ok(DataStorePolicyVersion::try_from(version).map_err(stored_policy_error)?)
 }
 
 #[cfg(test)]
@@ -277,14 +963,138 @@ mod integ_test {
     use crate::test_utils::*;
     use serial_test::serial;
 
-    fn make_policy() -> RealizedPolicyContainer {
+    fn make_policy() -> PolicyHandle {
         RealizedPolicyContainer {
             policies: vec![RealizedPolicy::test_new_different_labels(
                 "example/repo-1",
                 vec![RequiredLabel::new("owner", vec!["bob"], None)],
                 vec![RequiredLabel::new("git_sha", vec![], None)],
             )],
+            ..Default::default()
         }
+        .into()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn reload_imports_the_edited_policy_document() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("dumont-reload-{}.toml", std::process::id()));
+        let write = |owner: &str| {
+            let mut file = std::fs::File::create(&path).unwrap();
+            write!(
+                file,
+                r#"
+[[policy]]
+name = "owned"
+repository_pattern = ".*"
+required_repo_labels = [{{name = "owner", pattern = "{}"}}]
+required_version_labels = []
+"#,
+                owner
+            )
+            .unwrap();
+        };
+        let owner = |value: &str| BTreeMap::from([("owner".to_owned(), value.to_owned())]);
+
+        write("team-a");
+        let backend = DefaultBackend::new(
+            make_storage().await,
+            PolicyHandle::load(Some(path.clone())).unwrap(),
+        );
+        backend.bootstrap_policies().await.unwrap();
+        backend.create_organization("example").await.unwrap();
+        assert!(backend
+            .create_repo("example", "repo-1", owner("team-b"))
+            .await
+            .is_err());
+
+        write("team-b");
+        assert!(backend.reload_policies().await.unwrap().changed);
+        assert!(backend
+            .create_repo("example", "repo-1", owner("team-b"))
+            .await
+            .is_ok());
+        assert_eq!(backend.database.count_policy_versions().await.unwrap(), 2);
+
+        // An unchanged document doesn't undo a version activated through the admin API.
+        backend.activate_policy_version(1).await.unwrap();
+        assert!(!backend.reload_policies().await.unwrap().changed);
+        assert!(backend
+            .create_repo("example", "repo-2", owner("team-b"))
+            .await
+            .is_err());
+
+        // Going back to an earlier document activates its version again.
+        write("team-a");
+        assert!(!backend.reload_policies().await.unwrap().changed);
+        write("team-b");
+        assert!(backend.reload_policies().await.unwrap().changed);
+        assert_eq!(
+            backend
+                .database
+                .get_active_policy_version()
+                .await
+                .unwrap()
+                .unwrap()
+                .policy_version_id,
+            2
+        );
+        assert_eq!(backend.database.count_policy_versions().await.unwrap(), 2);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn servers_starting_together_import_the_policy_document_once() {
+        let path = std::env::temp_dir().join(format!("dumont-import-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+[[policy]]
+name = "owned"
+repository_pattern = ".*"
+required_repo_labels = [{name = "owner"}]
+required_version_labels = []
+"#,
+        )
+        .unwrap();
+
+        let db = setup_schema().await.unwrap();
+        let server = || DefaultBackend {
+            database: BackendDatabase {
+                db: db.clone(),
+                date_time_provider: test_date_time_provider(),
+                read_replica: None,
+                id_cache: Default::default(),
+            },
+            policy_container: PolicyHandle::load(Some(path.clone())).unwrap(),
+        };
+        let (first, second) = (server(), server());
+
+        let (first_started, second_started) =
+            tokio::join!(first.bootstrap_policies(), second.bootstrap_policies());
+        first_started.unwrap();
+        second_started.unwrap();
+
+        let versions = first
+            .list_policy_versions(PaginationOptions::new(0, 50))
+            .await
+            .unwrap();
+        assert_eq!(versions.total_count, 1);
+        assert!(versions.versions[0].active);
+        assert_eq!(
+            versions.versions[0].hash,
+            second.policy_container.current().hash
+        );
+        assert_eq!(
+            first.policy_container.current().hash,
+            second.policy_container.current().hash
+        );
+
+        std::fs::remove_file(&path).unwrap();
     }
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
@@ -292,7 +1102,9 @@ mod integ_test {
     async fn policy_enforcement_repo_label_create() {
         let db = BackendDatabase {
             db: setup_schema().await.unwrap(),
-            date_time_provider: DateTimeProvider::RealDateTime,
+            date_time_provider: test_date_time_provider(),
+            read_replica: None,
+            id_cache: Default::default(),
         };
 
         let backend = DefaultBackend {
@@ -326,7 +1138,9 @@ mod integ_test {
     async fn policy_enforcement_repo_label_update() {
         let db = BackendDatabase {
             db: setup_schema().await.unwrap(),
-            date_time_provider: DateTimeProvider::RealDateTime,
+            date_time_provider: test_date_time_provider(),
+            read_replica: None,
+            id_cache: Default::default(),
         };
 
         let backend = DefaultBackend {
@@ -359,7 +1173,9 @@ mod integ_test {
     async fn policy_enforcement_version_label_create() {
         let db = BackendDatabase {
             db: setup_schema().await.unwrap(),
-            date_time_provider: DateTimeProvider::RealDateTime,
+            date_time_provider: test_date_time_provider(),
+            read_replica: None,
+            id_cache: Default::default(),
         };
 
         let backend = DefaultBackend {
@@ -403,7 +1219,9 @@ mod integ_test {
     async fn policy_enforcement_version_label_update() {
         let db = BackendDatabase {
             db: setup_schema().await.unwrap(),
-            date_time_provider: DateTimeProvider::RealDateTime,
+            date_time_provider: test_date_time_provider(),
+            read_replica: None,
+            id_cache: Default::default(),
         };
 
         let backend = DefaultBackend {
@@ -441,4 +1259,339 @@ mod integ_test {
                 .await
                 .unwrap_err().to_string(), "Policy `test` required that label `git_sha` be set, however it was not and no default was specified.");
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn policy_enforcement_version_label_transition() {
+        let db = BackendDatabase {
+            db: setup_schema().await.unwrap(),
+            date_time_provider: test_date_time_provider(),
+            read_replica: None,
+            id_cache: Default::default(),
+        };
+
+        let release_state = RequiredLabel::new(
+            "release_state",
+            vec!["built", "canary", "deployed", "replaced"],
+            Some("built"),
+        )
+        .with_transitions(vec![
+            ("built", vec!["canary", "deployed"]),
+            ("canary", vec!["deployed"]),
+            ("deployed", vec!["replaced"]),
+        ]);
+
+        let backend = DefaultBackend {
+            database: db,
+            policy_container: RealizedPolicyContainer {
+                policies: vec![RealizedPolicy::test_new_different_labels(
+                    "example/repo-1",
+                    vec![],
+                    vec![release_state],
+                )],
+                ..Default::default()
+            }
+            .into(),
+        };
+
+        backend.create_organization("example").await.unwrap();
+        backend
+            .create_repo("example", "repo-1", BTreeMap::default())
+            .await
+            .unwrap();
+        backend
+            .create_version("example", "repo-1", "1.2.3", BTreeMap::default())
+            .await
+            .unwrap();
+
+        let labels = |value: &str| GenericLabels::from(vec![("release_state", value)]);
+
+        for state in ["canary", "deployed", "replaced"] {
+            backend
+                .update_version("example", "repo-1", "1.2.3", labels(state))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            backend
+                .update_version("example", "repo-1", "1.2.3", labels("canary"))
+                .await
+                .unwrap_err()
+                .to_string(),
+            "Policy `test` does not allow label `release_state` to change from `replaced` to `canary`."
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn policy_enforcement_immutable_label() {
+        let db = BackendDatabase {
+            db: setup_schema().await.unwrap(),
+            date_time_provider: test_date_time_provider(),
+            read_replica: None,
+            id_cache: Default::default(),
+        };
+
+        let backend = DefaultBackend {
+            database: db,
+            policy_container: RealizedPolicyContainer {
+                policies: vec![RealizedPolicy::test_new_different_labels(
+                    "example/repo-1",
+                    vec![],
+                    vec![RequiredLabel::new("git_sha", vec![], None).immutable()],
+                )],
+                ..Default::default()
+            }
+            .into(),
+        };
+
+        backend.create_organization("example").await.unwrap();
+        backend
+            .create_repo("example", "repo-1", BTreeMap::default())
+            .await
+            .unwrap();
+        backend
+            .create_version(
+                "example",
+                "repo-1",
+                "1.2.3",
+                BTreeMap::from_iter(vec![("git_sha".to_owned(), "abc".to_owned())]),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            backend
+                .update_version(
+                    "example",
+                    "repo-1",
+                    "1.2.3",
+                    GenericLabels::from(vec![("git_sha", "def")])
+                )
+                .await
+                .unwrap_err()
+                .to_string(),
+            "Policy `test` does not allow immutable label `git_sha` to be changed or removed once set, it is `abc`."
+        );
+
+        let version = backend
+            .override_version_labels(
+                "example",
+                "repo-1",
+                "1.2.3",
+                GenericLabels::from(vec![("git_sha", "def")]),
+                "tagged the wrong commit",
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            version.labels,
+            GenericLabels::from(vec![
+                ("git_sha", "def"),
+                (CREATED_AT_LABEL, TEST_CREATED_AT)
+            ])
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn reserved_labels_are_set_by_the_server() {
+        let backend = make_backend().await;
+        backend.create_organization("example").await.unwrap();
+
+        assert_eq!(
+            backend
+                .create_repo(
+                    "example",
+                    "repo-1",
+                    BTreeMap::from_iter(vec![(
+                        CREATED_AT_LABEL.to_owned(),
+                        "2020-01-01T00:00:00Z".to_owned()
+                    )]),
+                )
+                .await
+                .unwrap_err()
+                .to_string(),
+            "Requested action was not allowed because: Label `dumont.io/created-at` uses the reserved prefix `dumont.io/` and can only be set by the server"
+        );
+        assert_eq!(
+            backend
+                .create_repo(
+                    "example",
+                    "repo-1",
+                    BTreeMap::from_iter(vec![("has space".to_owned(), "a".to_owned())]),
+                )
+                .await
+                .unwrap_err()
+                .to_string(),
+            "Requested action was not allowed because: Label key `has space` is invalid, the name must start and end with a letter or digit, and only contain letters, digits, `-`, `_` and `.`"
+        );
+
+        let repo = backend
+            .create_repo("example", "repo-1", BTreeMap::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            repo.labels,
+            GenericLabels::from(vec![(CREATED_AT_LABEL, TEST_CREATED_AT)])
+        );
+
+        // Labels read from the server can be written back, and leaving out the
+        // reserved labels doesn't remove them.
+        let repo = backend
+            .update_repo("example", "repo-1", repo.labels.labels.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            repo.labels,
+            GenericLabels::from(vec![(CREATED_AT_LABEL, TEST_CREATED_AT)])
+        );
+        let repo = backend
+            .update_repo(
+                "example",
+                "repo-1",
+                BTreeMap::from_iter(vec![("owner".to_owned(), "me".to_owned())]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            repo.labels,
+            GenericLabels::from(vec![("owner", "me"), (CREATED_AT_LABEL, TEST_CREATED_AT)])
+        );
+
+        backend
+            .create_version("example", "repo-1", "1.2.3", BTreeMap::default())
+            .await
+            .unwrap();
+        assert!(matches!(
+            backend
+                .override_version_labels(
+                    "example",
+                    "repo-1",
+                    "1.2.3",
+                    GenericLabels::from(vec![(CREATED_AT_LABEL, "2020-01-01T00:00:00Z")]),
+                    "backdating",
+                )
+                .await,
+            Err(BackendError::ConstraintViolation {
+                reason: ConstraintViolation::ReservedLabel { .. }
+            })
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn templated_defaults_read_the_repo() {
+        let container: PolicyDefinitionContainer = toml::from_str(
+            r#"
+[[policy]]
+name = "service"
+repository_pattern = "example/.*"
+required_repo_labels = []
+required_version_labels = [
+  {name = "owners", default_value = "{repo.labels.owners}"},
+  {name = "image", default_value = "registry.example.com/{org}/{repo}:{version}"},
+]
+"#,
+        )
+        .unwrap();
+        let backend = DefaultBackend {
+            database: BackendDatabase {
+                db: setup_schema().await.unwrap(),
+                date_time_provider: test_date_time_provider(),
+                read_replica: None,
+                id_cache: Default::default(),
+            },
+            policy_container: RealizedPolicyContainer::try_from(container).unwrap().into(),
+        };
+        backend.create_organization("example").await.unwrap();
+        backend
+            .create_repo(
+                "example",
+                "repo-1",
+                BTreeMap::from_iter(vec![("owners".to_owned(), "team-a".to_owned())]),
+            )
+            .await
+            .unwrap();
+
+        let version = backend
+            .create_version("example", "repo-1", "1.0.0", BTreeMap::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            version.labels,
+            GenericLabels::from(vec![
+                (CREATED_AT_LABEL, TEST_CREATED_AT),
+                ("image", "registry.example.com/example/repo-1:1.0.0"),
+                ("owners", "team-a"),
+            ])
+        );
+
+        // Defaults only fill in missing labels, on updates too.
+        let version = backend
+            .update_version(
+                "example",
+                "repo-1",
+                "1.0.0",
+                GenericLabels::from(vec![("owners", "team-b")]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(version.labels.labels.get("owners").unwrap(), "team-b");
+        assert_eq!(
+            version.labels.labels.get("image").unwrap(),
+            "registry.example.com/example/repo-1:1.0.0"
+        );
+    }
+
+    #[tokio::test]
+    async fn memory_storage_backend() {
+        let backend = DefaultBackend {
+            database: crate::database::MemoryStorage::new(test_date_time_provider()),
+            policy_container: make_policy(),
+        };
+
+        backend.create_organization("example").await.unwrap();
+        assert_eq!(backend
+            .create_repo(
+                "example",
+                "repo-1",
+                BTreeMap::from_iter(vec![("owner".to_owned(), "alice".to_owned())])
+            )
+            .await
+            .unwrap_err().to_string(), "Policy `test` required that label `owner` be one of a set values, however `alice` was not in that set.");
+
+        let repo = backend
+            .create_repo(
+                "example",
+                "repo-1",
+                BTreeMap::from_iter(vec![("owner".to_owned(), "bob".to_owned())]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            repo.labels,
+            GenericLabels::from(vec![("owner", "bob"), (CREATED_AT_LABEL, TEST_CREATED_AT)])
+        );
+
+        backend
+            .create_version(
+                "example",
+                "repo-1",
+                "1.0.0",
+                BTreeMap::from_iter(vec![("git_sha".to_owned(), "abc".to_owned())]),
+            )
+            .await
+            .unwrap();
+        let versions = backend
+            .list_versions("example", "repo-1", PaginationOptions::new(0, 50))
+            .await
+            .unwrap();
+        assert_eq!(versions.total_count, 1);
+        assert_eq!(versions.versions[0].version, "1.0.0");
+
+        assert!(backend.delete_repo("example", "repo-1").await.unwrap());
+        assert!(backend.delete_organization("example").await.unwrap());
+    }
 }
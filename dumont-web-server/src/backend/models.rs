@@ -73,9 +73,19 @@ impl DataStoreOrganizationList {
 
 #[derive(Debug, Clone)]
 pub struct DataStoreRepository {
+    pub id: i32,
     pub org_name: String,
     pub repo_name: String,
     pub labels: crate::models::GenericLabels,
+    /// Policies applied to the labels, when they were just written.
+    pub applied_policies: Vec<String>,
+}
+
+impl DataStoreRepository {
+    pub fn with_applied_policies(mut self, applied_policies: Vec<String>) -> Self {
+        self.applied_policies = applied_policies;
+        self
+    }
 }
 
 impl From<crate::database::prelude::DbRepoModel> for DataStoreRepository {
@@ -87,9 +97,11 @@ impl From<crate::database::prelude::DbRepoModel> for DataStoreRepository {
 impl From<&crate::database::prelude::DbRepoModel> for DataStoreRepository {
     fn from(source: &crate::database::prelude::DbRepoModel) -> Self {
         Self {
+            id: source.repo_id,
             org_name: source.get_org_name(),
             repo_name: source.get_repo_name(),
             labels: source.labels.clone(),
+            applied_policies: Vec::new(),
         }
     }
 }
@@ -144,6 +156,16 @@ impl DataStoreVersionList {
 pub struct DataStoreRevision {
     pub version: String,
     pub labels: crate::models::GenericLabels,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Policies applied to the labels, when they were just written.
+    pub applied_policies: Vec<String>,
+}
+
+impl DataStoreRevision {
+    pub fn with_applied_policies(mut self, applied_policies: Vec<String>) -> Self {
+        self.applied_policies = applied_policies;
+        self
+    }
 }
 
 impl From<crate::database::prelude::DbRevisionModel> for DataStoreRevision {
@@ -157,6 +179,106 @@ impl From<&crate::database::prelude::DbRevisionModel> for DataStoreRevision {
         Self {
             version: source.revision_name.clone(),
             labels: source.labels.clone(),
+            created_at: source.created_at,
+            applied_policies: Vec::new(),
         }
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct DataStorePolicyVersionList {
+    pub versions: Vec<DataStorePolicyVersion>,
+    pub total_count: u64,
+    pub has_more: bool,
+}
+
+/// A version of the policies managed through the admin API.
+#[derive(Debug, Clone)]
+pub struct DataStorePolicyVersion {
+    pub version: i32,
+    pub hash: String,
+    pub active: bool,
+    pub comment: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub document: crate::policy::PolicyDefinitionContainer,
+}
+
+impl TryFrom<crate::database::prelude::DbPolicyVersionModel> for DataStorePolicyVersion {
+    type Error = serde_json::Error;
+
+    fn try_from(
+        source: crate::database::prelude::DbPolicyVersionModel,
+    ) -> Result<Self, Self::Error> {
+        let evaluation = serde_json::from_value(serde_json::Value::String(source.evaluation))?;
+        let policies = source
+            .definitions
+            .iter()
+            .map(|definition| serde_json::from_str(&definition.definition))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            version: source.policy_version_id,
+            hash: source.document_hash,
+            active: source.active,
+            comment: source.comment,
+            created_at: source.created_at,
+            document: crate::policy::PolicyDefinitionContainer::new(evaluation, policies),
+        })
+    }
+}
+
+/// Result of evaluating every stored repo and version against the current policies.
+#[derive(Debug, Clone, Default)]
+pub struct DataStoreComplianceReport {
+    pub repos_checked: u64,
+    pub versions_checked: u64,
+    pub violations: Vec<DataStoreViolation>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DataStoreViolation {
+    pub org_name: String,
+    pub repo_name: String,
+    /// Set when the violation is on a version's labels.
+    pub version: Option<String>,
+    pub policy_name: Option<String>,
+    pub label_name: Option<String>,
+    pub message: String,
+}
+
+impl DataStoreViolation {
+    pub fn new(
+        org_name: &str,
+        repo_name: &str,
+        version: Option<&str>,
+        error: &crate::policy::PolicyError,
+    ) -> Self {
+        Self {
+            org_name: org_name.to_owned(),
+            repo_name: repo_name.to_owned(),
+            version: version.map(str::to_owned),
+            policy_name: error.policy_name().map(str::to_owned),
+            label_name: error.label_name().map(str::to_owned),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Versions the retention rules expired, and whether they were deleted.
+#[derive(Debug, Clone, Default)]
+pub struct DataStoreGcReport {
+    /// When set, the expired versions were only listed.
+    pub dry_run: bool,
+    pub versions_checked: u64,
+    pub expired: Vec<DataStoreExpiredVersion>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DataStoreExpiredVersion {
+    pub org_name: String,
+    pub repo_name: String,
+    pub version: String,
+    /// The policy with the retention rule that expired the version.
+    pub policy_name: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
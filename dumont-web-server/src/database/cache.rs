@@ -0,0 +1,788 @@
+use crate::backend::models::PaginationOptions;
+use crate::database::prelude::*;
+use crate::database::{entity, storage::Storage};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// How many entries of each kind are kept, and for how long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheOptions {
+    /// Entries per kind, 0 disables the cache.
+    pub capacity: usize,
+    pub time_to_live: Duration,
+}
+
+impl CacheOptions {
+    pub fn disabled() -> Self {
+        Self {
+            capacity: 0,
+            time_to_live: Duration::ZERO,
+        }
+    }
+}
+
+/// A short time to live, since changes made through other instances are only seen
+/// once entries expire. The server leaves the cache off unless `--cache-size` is set.
+impl Default for CacheOptions {
+    fn default() -> Self {
+        Self {
+            capacity: 10_000,
+            time_to_live: Duration::from_secs(5),
+        }
+    }
+}
+
+/// A map that forgets the least recently used entry when it's full, and entries
+/// older than the time to live.
+#[derive(Debug)]
+struct Lru<K, V> {
+    options: CacheOptions,
+    entries: HashMap<K, LruEntry<V>>,
+    /// The keys, least recently used first.
+    order: BTreeMap<u64, K>,
+    next_use: u64,
+}
+
+#[derive(Debug)]
+struct LruEntry<V> {
+    value: V,
+    last_use: u64,
+    inserted_at: Instant,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Lru<K, V> {
+    fn new(options: CacheOptions) -> Self {
+        Self {
+            options,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            next_use: 0,
+        }
+    }
+
+    fn get(&mut self, key: &K) -> Option<V> {
+        let entry = self.entries.get_mut(key)?;
+        if entry.inserted_at.elapsed() >= self.options.time_to_live {
+            self.remove(key);
+            return None;
+        }
+
+        self.order.remove(&entry.last_use);
+        entry.last_use = self.next_use;
+        self.order.insert(self.next_use, key.clone());
+        self.next_use += 1;
+        Some(entry.value.clone())
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        self.remove(&key);
+        while self.entries.len() >= self.options.capacity {
+            match self.order.pop_first() {
+                Some((_, oldest)) => self.entries.remove(&oldest),
+                None => return,
+            };
+        }
+
+        self.order.insert(self.next_use, key.clone());
+        self.entries.insert(
+            key,
+            LruEntry {
+                value,
+                last_use: self.next_use,
+                inserted_at: Instant::now(),
+            },
+        );
+        self.next_use += 1;
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.last_use);
+        }
+    }
+
+    fn retain(&mut self, keep: impl Fn(&K) -> bool) {
+        let removed: Vec<K> = self
+            .entries
+            .keys()
+            .filter(|key| !keep(key))
+            .cloned()
+            .collect();
+        for key in removed {
+            self.remove(&key);
+        }
+    }
+}
+
+type RepoKey = (String, String);
+type RevisionKey = (String, String, String);
+
+#[derive(Debug)]
+struct CacheState {
+    orgs: Lru<String, DbOrganizationModel>,
+    repos: Lru<RepoKey, DbRepoModel>,
+    revisions: Lru<RevisionKey, DbRevisionModel>,
+    /// Bumped by every write, so a read that raced a write doesn't cache what it read.
+    generation: u64,
+}
+
+fn repo_key(repo: &RepoParam<'_>) -> RepoKey {
+    (repo.org_name.to_owned(), repo.repo_name.to_owned())
+}
+
+fn revision_key(revision: &RevisionParam<'_>) -> RevisionKey {
+    (
+        revision.org_name.to_owned(),
+        revision.repo_name.to_owned(),
+        revision.revision.to_owned(),
+    )
+}
+
+/**
+ * IdCache keeps the orgs and repos `BackendDatabase` resolved by name, so that the
+ * lists, the writes, and the lookups `CachedStorage` missed don't resolve them
+ * again. Only the org and repo rows are cached, never their labels, and the label
+ * updates don't use it. A deleted org or repo is forgotten, but like `CachedStorage`
+ * it only sees the deletes of this process.
+ *
+ * The primary and its replica share the cache.
+ */
+#[derive(Debug)]
+pub struct IdCache {
+    state: Mutex<IdCacheState>,
+}
+
+#[derive(Debug)]
+struct IdCacheState {
+    orgs: Lru<String, entity::organization::Model>,
+    repos: Lru<RepoKey, entity::repository::Model>,
+    /// Bumped by every delete, like `CacheState::generation`.
+    generation: u64,
+}
+
+impl IdCache {
+    pub fn new(options: CacheOptions) -> Self {
+        Self {
+            state: Mutex::new(IdCacheState {
+                orgs: Lru::new(options),
+                repos: Lru::new(options),
+                generation: 0,
+            }),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, IdCacheState> {
+        self.state.lock().expect("Id cache lock to not be poisoned")
+    }
+
+    pub async fn org(
+        &self,
+        org_name: &str,
+        load: impl std::future::Future<Output = DbResult<entity::organization::Model>>,
+    ) -> DbResult<entity::organization::Model> {
+        self.cached("org_id", |state| &mut state.orgs, org_name.to_owned(), load)
+            .await
+    }
+
+    pub async fn repo(
+        &self,
+        org_name: &str,
+        repo_name: &str,
+        load: impl std::future::Future<Output = DbResult<entity::repository::Model>>,
+    ) -> DbResult<entity::repository::Model> {
+        let key = (org_name.to_owned(), repo_name.to_owned());
+        self.cached("repo_id", |state| &mut state.repos, key, load)
+            .await
+    }
+
+    /// Forgets the org and its repos, once it was deleted.
+    pub fn forget_org(&self, org_name: &str) {
+        let mut state = self.state();
+        state.generation += 1;
+        state.orgs.remove(&org_name.to_owned());
+        state.repos.retain(|(org, _)| org != org_name);
+    }
+
+    /// Forgets the repo, once it was deleted.
+    pub fn forget_repo(&self, org_name: &str, repo_name: &str) {
+        let mut state = self.state();
+        state.generation += 1;
+        state
+            .repos
+            .remove(&(org_name.to_owned(), repo_name.to_owned()));
+    }
+
+    async fn cached<K, V>(
+        &self,
+        kind: &'static str,
+        lru: impl Fn(&mut IdCacheState) -> &mut Lru<K, V>,
+        key: K,
+        load: impl std::future::Future<Output = DbResult<V>>,
+    ) -> DbResult<V>
+    where
+        K: Hash + Eq + Clone,
+        V: Clone,
+    {
+        let generation = {
+            let mut state = self.state();
+            if lru(&mut state).options.capacity == 0 {
+                None
+            } else if let Some(found) = lru(&mut state).get(&key) {
+                crate::api::metrics::record_cache_lookup(kind, true);
+                return Ok(found);
+            } else {
+                crate::api::metrics::record_cache_lookup(kind, false);
+                Some(state.generation)
+            }
+        };
+
+        let loaded = load.await?;
+        if let Some(generation) = generation {
+            let mut state = self.state();
+            if state.generation == generation {
+                lru(&mut state).insert(key, loaded.clone());
+            }
+        }
+        Ok(loaded)
+    }
+}
+
+impl Default for IdCache {
+    fn default() -> Self {
+        Self::new(CacheOptions::disabled())
+    }
+}
+
+/**
+ * CachedStorage keeps the orgs, repos and versions that were looked up in memory,
+ * so a request doesn't have to resolve the org, repo and version again.
+ *
+ * Lists and counts always go to `S`. Every write through the cache forgets the
+ * entries it changed, but writes made by another process are only seen once the
+ * entries expire.
+ */
+#[derive(Debug)]
+pub struct CachedStorage<S = BackendDatabase> {
+    pub storage: S,
+    state: Mutex<CacheState>,
+}
+
+impl<S: Storage> CachedStorage<S> {
+    pub fn new(storage: S, options: CacheOptions) -> Self {
+        Self {
+            storage,
+            state: Mutex::new(CacheState {
+                orgs: Lru::new(options),
+                repos: Lru::new(options),
+                revisions: Lru::new(options),
+                generation: 0,
+            }),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, CacheState> {
+        self.state.lock().expect("Cache lock to not be poisoned")
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.state().orgs.options.capacity > 0
+    }
+
+    /// Looks the value up in `get`, falling back to `load` and caching what it returned.
+    async fn cached<T, F>(
+        &self,
+        kind: &'static str,
+        get: impl Fn(&mut CacheState) -> Option<T>,
+        load: F,
+        insert: impl FnOnce(&mut CacheState, T),
+    ) -> DbResult<T>
+    where
+        T: Clone,
+        F: std::future::Future<Output = DbResult<T>>,
+    {
+        if !self.is_enabled() {
+            return load.await;
+        }
+
+        let generation = {
+            let mut state = self.state();
+            if let Some(found) = get(&mut state) {
+                crate::api::metrics::record_cache_lookup(kind, true);
+                return Ok(found);
+            }
+            state.generation
+        };
+        crate::api::metrics::record_cache_lookup(kind, false);
+
+        let loaded = load.await?;
+        let mut state = self.state();
+        if state.generation == generation {
+            insert(&mut state, loaded.clone());
+        }
+        Ok(loaded)
+    }
+
+    /// Forgets what `write` changes, once it's done.
+    async fn write<T, E>(
+        &self,
+        write: impl std::future::Future<Output = Result<T, E>>,
+        forget: impl FnOnce(&mut CacheState),
+    ) -> Result<T, E> {
+        let result = write.await;
+        let mut state = self.state();
+        state.generation += 1;
+        forget(&mut state);
+        result
+    }
+}
+
+#[async_trait]
+impl<S: Storage> Storage for CachedStorage<S> {
+    fn now(&self) -> DateTime<Utc> {
+        self.storage.now()
+    }
+
+    async fn create_org(&self, org_name: &str) -> DbResult<DbOrganizationModel> {
+        self.write(self.storage.create_org(org_name), |state| {
+            state.orgs.remove(&org_name.to_owned())
+        })
+        .await
+    }
+
+    async fn find_org(&self, org_name: &str) -> DbResult<DbOrganizationModel> {
+        let key = org_name.to_owned();
+        self.cached(
+            "org",
+            |state| state.orgs.get(&key),
+            self.storage.find_org(org_name),
+            |state, org| state.orgs.insert(key.clone(), org),
+        )
+        .await
+    }
+
+    async fn list_orgs(
+        &self,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbOrganizationModel>> {
+        self.storage.list_orgs(pagination).await
+    }
+
+    async fn count_orgs(&self) -> DbResult<u64> {
+        self.storage.count_orgs().await
+    }
+
+    async fn delete_org(&self, org_name: &str) -> DbResult<bool> {
+        self.write(self.storage.delete_org(org_name), |state| {
+            state.orgs.remove(&org_name.to_owned());
+            state.repos.retain(|(org, _)| org != org_name);
+            state.revisions.retain(|(org, _, _)| org != org_name);
+        })
+        .await
+    }
+
+    async fn create_repo(
+        &self,
+        repo: &RepoParam<'_>,
+        create_params: CreateRepoParam,
+    ) -> DbResult<DbRepoModel> {
+        self.write(self.storage.create_repo(repo, create_params), |state| {
+            state.repos.remove(&repo_key(repo))
+        })
+        .await
+    }
+
+    async fn get_repo(&self, repo: &RepoParam<'_>) -> DbResult<DbRepoModel> {
+        let key = repo_key(repo);
+        self.cached(
+            "repo",
+            |state| state.repos.get(&key),
+            self.storage.get_repo(repo),
+            |state, found| state.repos.insert(key.clone(), found),
+        )
+        .await
+    }
+
+    async fn list_repos(
+        &self,
+        org_name: &str,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbRepoModel>> {
+        self.storage.list_repos(org_name, pagination).await
+    }
+
+    async fn count_repos(&self, org_name: &str) -> DbResult<u64> {
+        self.storage.count_repos(org_name).await
+    }
+
+    async fn list_repos_with_labels(
+        &self,
+        org_name: &str,
+        labels: &BTreeMap<String, String>,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbRepoModel>> {
+        self.storage
+            .list_repos_with_labels(org_name, labels, pagination)
+            .await
+    }
+
+    async fn count_repos_with_labels(
+        &self,
+        org_name: &str,
+        labels: &BTreeMap<String, String>,
+    ) -> DbResult<u64> {
+        self.storage.count_repos_with_labels(org_name, labels).await
+    }
+
+    async fn delete_repo(&self, repo: &RepoParam<'_>) -> DbResult<bool> {
+        self.write(self.storage.delete_repo(repo), |state| {
+            state.repos.remove(&repo_key(repo));
+            state
+                .revisions
+                .retain(|(org, repo_name, _)| org != repo.org_name || repo_name != repo.repo_name);
+        })
+        .await
+    }
+
+    /// Never reads the cache, the stored labels are checked before they're replaced.
+    async fn update_repo_labels<F, E>(
+        &self,
+        repo: &RepoParam<'_>,
+        update: F,
+    ) -> Result<DbRepoModel, E>
+    where
+        F: FnOnce(&DbRepoModel) -> Result<BTreeMap<String, String>, E> + Send,
+        E: From<DatabaseError> + Send,
+    {
+        self.write(self.storage.update_repo_labels(repo, update), |state| {
+            state.repos.remove(&repo_key(repo))
+        })
+        .await
+    }
+
+    async fn create_revision(
+        &self,
+        revision_param: &RevisionParam<'_>,
+        create_revision_param: &CreateRevisionParam<'_>,
+    ) -> DbResult<DbRevisionModel> {
+        self.write(
+            self.storage
+                .create_revision(revision_param, create_revision_param),
+            |state| state.revisions.remove(&revision_key(revision_param)),
+        )
+        .await
+    }
+
+    async fn get_revision(&self, revision_param: &RevisionParam<'_>) -> DbResult<DbRevisionModel> {
+        let key = revision_key(revision_param);
+        self.cached(
+            "version",
+            |state| state.revisions.get(&key),
+            self.storage.get_revision(revision_param),
+            |state, found| state.revisions.insert(key.clone(), found),
+        )
+        .await
+    }
+
+    async fn list_revisions(
+        &self,
+        repo_param: &RepoParam<'_>,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbRevisionModel>> {
+        self.storage.list_revisions(repo_param, pagination).await
+    }
+
+    async fn count_revisions(&self, repo_param: &RepoParam<'_>) -> DbResult<u64> {
+        self.storage.count_revisions(repo_param).await
+    }
+
+    async fn list_revisions_of_repos(
+        &self,
+        repo_ids: &[i32],
+        labels: &BTreeMap<String, String>,
+        pagination: &PaginationOptions,
+    ) -> DbResult<BTreeMap<i32, Vec<DbRevisionModel>>> {
+        self.storage
+            .list_revisions_of_repos(repo_ids, labels, pagination)
+            .await
+    }
+
+    async fn count_revisions_of_repos(
+        &self,
+        repo_ids: &[i32],
+        labels: &BTreeMap<String, String>,
+    ) -> DbResult<BTreeMap<i32, u64>> {
+        self.storage
+            .count_revisions_of_repos(repo_ids, labels)
+            .await
+    }
+
+    async fn delete_revision(&self, revision_param: &RevisionParam<'_>) -> DbResult<bool> {
+        self.write(self.storage.delete_revision(revision_param), |state| {
+            state.revisions.remove(&revision_key(revision_param))
+        })
+        .await
+    }
+
+    /// Never reads the cache, like `update_repo_labels`.
+    async fn update_revision_labels<F, E>(
+        &self,
+        revision_param: &RevisionParam<'_>,
+        update: F,
+    ) -> Result<DbRevisionModel, E>
+    where
+        F: FnOnce(&DbRevisionModel) -> Result<BTreeMap<String, String>, E> + Send,
+        E: From<DatabaseError> + Send,
+    {
+        self.write(
+            self.storage.update_revision_labels(revision_param, update),
+            |state| state.revisions.remove(&revision_key(revision_param)),
+        )
+        .await
+    }
+
+    async fn create_policy_version(
+        &self,
+        create_param: &CreatePolicyVersionParam<'_>,
+    ) -> DbResult<DbPolicyVersionModel> {
+        self.storage.create_policy_version(create_param).await
+    }
+
+    async fn get_policy_version(&self, version: i32) -> DbResult<DbPolicyVersionModel> {
+        self.storage.get_policy_version(version).await
+    }
+
+    async fn get_active_policy_version(&self) -> DbResult<Option<DbPolicyVersionModel>> {
+        self.storage.get_active_policy_version().await
+    }
+
+    async fn find_policy_version_by_hash(
+        &self,
+        document_hash: &str,
+    ) -> DbResult<Option<DbPolicyVersionModel>> {
+        self.storage
+            .find_policy_version_by_hash(document_hash)
+            .await
+    }
+
+    async fn list_policy_versions(
+        &self,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbPolicyVersionModel>> {
+        self.storage.list_policy_versions(pagination).await
+    }
+
+    async fn count_policy_versions(&self) -> DbResult<u64> {
+        self.storage.count_policy_versions().await
+    }
+
+    async fn activate_policy_version(&self, version: i32) -> DbResult<DbPolicyVersionModel> {
+        self.storage.activate_policy_version(version).await
+    }
+
+    async fn delete_policy_version(&self, version: i32) -> DbResult<bool> {
+        self.storage.delete_policy_version(version).await
+    }
+}
+
+#[test]
+fn lru_forgets_the_least_recently_used_entry() {
+    let mut lru = Lru::new(CacheOptions {
+        capacity: 2,
+        time_to_live: Duration::from_secs(60),
+    });
+    lru.insert("a", 1);
+    lru.insert("b", 2);
+    assert_eq!(lru.get(&"a"), Some(1));
+
+    lru.insert("c", 3);
+    assert_eq!(lru.get(&"b"), None);
+    assert_eq!(lru.get(&"a"), Some(1));
+    assert_eq!(lru.get(&"c"), Some(3));
+
+    lru.insert("c", 4);
+    assert_eq!(lru.get(&"c"), Some(4));
+    assert_eq!(lru.entries.len(), 2);
+    assert_eq!(lru.order.len(), 2);
+
+    let mut expired = Lru::new(CacheOptions {
+        capacity: 2,
+        time_to_live: Duration::ZERO,
+    });
+    expired.insert("a", 1);
+    assert_eq!(expired.get(&"a"), None);
+    assert!(expired.order.is_empty());
+}
+
+#[cfg(test)]
+mod integ_test {
+    use super::*;
+    use crate::database::memory::MemoryStorage;
+    use crate::test_utils::test_date_time_provider;
+
+    fn cached_storage() -> CachedStorage<MemoryStorage> {
+        CachedStorage::new(
+            MemoryStorage::new(test_date_time_provider()),
+            CacheOptions::default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_reads_are_cached() {
+        let storage = cached_storage();
+        storage.create_org("foo").await.unwrap();
+        storage
+            .create_repo(&RepoParam::new("foo", "bar"), CreateRepoParam::default())
+            .await
+            .unwrap();
+        storage
+            .get_repo(&RepoParam::new("foo", "bar"))
+            .await
+            .unwrap();
+
+        // Changed behind the cache's back, so the cached repo is still returned.
+        storage
+            .storage
+            .update_repo_labels(&RepoParam::new("foo", "bar"), |_| {
+                Ok::<_, DatabaseError>(BTreeMap::from_iter(vec![(
+                    "owner".to_owned(),
+                    "bob".to_owned(),
+                )]))
+            })
+            .await
+            .unwrap();
+        let repo = storage
+            .get_repo(&RepoParam::new("foo", "bar"))
+            .await
+            .unwrap();
+        assert!(repo.labels.labels.is_empty());
+
+        // Label updates are checked against the stored labels, never the cached ones.
+        storage
+            .update_repo_labels(&RepoParam::new("foo", "bar"), |existing| {
+                assert_eq!(existing.labels.get("owner").unwrap(), "bob");
+                Ok::<_, DatabaseError>(existing.labels.labels.clone())
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_writes_invalidate_the_cache() {
+        let storage = cached_storage();
+        let repo = RepoParam::new("foo", "bar");
+        let revision = RevisionParam::new("foo", "bar", "1.0.0");
+        storage.create_org("foo").await.unwrap();
+        storage
+            .create_repo(&repo, CreateRepoParam::default())
+            .await
+            .unwrap();
+        storage
+            .create_revision(
+                &revision,
+                &CreateRevisionParam {
+                    artifact_url: None,
+                    labels: Default::default(),
+                },
+            )
+            .await
+            .unwrap();
+        storage.find_org("foo").await.unwrap();
+        storage.get_repo(&repo).await.unwrap();
+        storage.get_revision(&revision).await.unwrap();
+
+        let labels = BTreeMap::from_iter(vec![("owner".to_owned(), "bob".to_owned())]);
+        storage
+            .update_repo_labels(&repo, |_| Ok::<_, DatabaseError>(labels.clone()))
+            .await
+            .unwrap();
+        storage
+            .update_revision_labels(&revision, |_| Ok::<_, DatabaseError>(labels.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.get_repo(&repo).await.unwrap().labels,
+            labels.clone().into()
+        );
+        assert_eq!(
+            storage.get_revision(&revision).await.unwrap().labels,
+            labels.into()
+        );
+
+        storage.delete_repo(&repo).await.unwrap();
+        assert!(storage.get_repo(&repo).await.is_err());
+        assert!(storage.get_revision(&revision).await.is_err());
+
+        storage.delete_org("foo").await.unwrap();
+        assert!(storage.find_org("foo").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_disabled_cache_reads_through() {
+        let storage = CachedStorage::new(
+            MemoryStorage::new(test_date_time_provider()),
+            CacheOptions::disabled(),
+        );
+        storage.create_org("foo").await.unwrap();
+        storage.find_org("foo").await.unwrap();
+        storage.storage.delete_org("foo").await.unwrap();
+        assert!(storage.find_org("foo").await.is_err());
+    }
+}
+
+#[cfg(test)]
+mod id_cache_integ_test {
+    use super::CacheOptions;
+    use crate::database::prelude::*;
+    use crate::test_utils::test_date_time_provider;
+
+    #[tokio::test]
+    async fn test_ids_are_cached_until_deleted() {
+        let db = BackendDatabase {
+            db: crate::test_utils::setup_schema().await.unwrap(),
+            date_time_provider: test_date_time_provider(),
+            read_replica: None,
+            id_cache: Default::default(),
+        }
+        .with_id_cache(CacheOptions::default());
+        let repo = RepoParam::new("foo", "bar");
+        let repo_key = ("foo".to_owned(), "bar".to_owned());
+        db.create_org("foo").await.unwrap();
+        db.create_repo(&repo, CreateRepoParam::default())
+            .await
+            .unwrap();
+        let first = db.get_repo(&repo).await.unwrap();
+        let org_id = db.id_cache.state().orgs.get(&"foo".to_owned()).unwrap();
+        let repo_id = db.id_cache.state().repos.get(&repo_key).unwrap();
+        assert_eq!(org_id.org_name, "foo");
+        assert_eq!(repo_id.repo_name, "bar");
+
+        // A repo created again under the same name is resolved to its new id.
+        db.delete_repo(&repo).await.unwrap();
+        assert!(db.id_cache.state().repos.get(&repo_key).is_none());
+        for repo in [RepoParam::new("foo", "baz"), RepoParam::new("foo", "bar")] {
+            db.create_repo(&repo, CreateRepoParam::default())
+                .await
+                .unwrap();
+        }
+        let second = db.get_repo(&repo).await.unwrap();
+        assert_ne!(first.repo_id, second.repo_id);
+
+        // So is an org.
+        for repo in [RepoParam::new("foo", "baz"), RepoParam::new("foo", "bar")] {
+            db.delete_repo(&repo).await.unwrap();
+        }
+        db.delete_org("foo").await.unwrap();
+        assert!(db.id_cache.state().orgs.get(&"foo".to_owned()).is_none());
+        db.create_org("baz").await.unwrap();
+        let org = db.create_org("foo").await.unwrap();
+        assert_ne!(org.org_id, org_id.org_id);
+        db.create_repo(&repo, CreateRepoParam::default())
+            .await
+            .unwrap();
+        assert_eq!(db.count_repos("foo").await.unwrap(), 1);
+    }
+}
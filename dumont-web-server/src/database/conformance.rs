@@ -0,0 +1,541 @@
+//! The behaviour every `Storage` has to share. Each implementation runs the same
+//! checks, so the backend can't tell which one it's using.
+
+use crate::backend::models::PaginationOptions;
+use crate::database::memory::MemoryStorage;
+use crate::database::prelude::{
+    AlreadyExistsError, BackendDatabase, CreatePolicyVersionParam, CreateRepoParam,
+    CreateRevisionParam, DatabaseError, DbPolicyDefinition, NotFoundError, RepoParam,
+    RevisionParam,
+};
+use crate::database::storage::Storage;
+use crate::test_utils::*;
+use serial_test::serial;
+use std::collections::BTreeMap;
+
+fn labels(values: Vec<(&str, &str)>) -> BTreeMap<String, String> {
+    values
+        .into_iter()
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .collect()
+}
+
+async fn check_orgs(storage: &impl Storage) {
+    let created = storage.create_org("foo").await.unwrap();
+    assert_eq!(created.org_name, "foo");
+    assert_eq!(storage.find_org("foo").await.unwrap(), created);
+
+    match storage.create_org("foo").await {
+        Err(DatabaseError::AlreadyExists {
+            error: AlreadyExistsError::Organization { org },
+        }) => assert_eq!(org, "foo"),
+        failed => unreachable!("Should not have gotten {:?}", failed),
+    }
+    match storage.find_org("missing").await {
+        Err(DatabaseError::NotFound {
+            error: NotFoundError::Organization { org },
+        }) => assert_eq!(org, "missing"),
+        failed => unreachable!("Should not have gotten {:?}", failed),
+    }
+
+    for i in 0..5 {
+        storage.create_org(&format!("org-{}", i)).await.unwrap();
+    }
+    assert_eq!(storage.count_orgs().await.unwrap(), 6);
+
+    let names = |orgs: Vec<crate::database::prelude::DbOrganizationModel>| -> Vec<String> {
+        orgs.into_iter().map(|org| org.org_name).collect()
+    };
+    let first = storage.list_orgs(&PaginationOptions::new(0, 4)).await;
+    assert_eq!(
+        names(first.unwrap()),
+        vec!["foo", "org-0", "org-1", "org-2"]
+    );
+    let second = storage.list_orgs(&PaginationOptions::new(1, 4)).await;
+    assert_eq!(names(second.unwrap()), vec!["org-3", "org-4"]);
+    let past_the_end = storage.list_orgs(&PaginationOptions::new(2, 4)).await;
+    assert!(past_the_end.unwrap().is_empty());
+
+    assert!(storage.delete_org("org-4").await.unwrap());
+    assert_eq!(storage.count_orgs().await.unwrap(), 5);
+    assert!(matches!(
+        storage.delete_org("org-4").await,
+        Err(DatabaseError::NotFound { .. })
+    ));
+}
+
+async fn check_repos(storage: &impl Storage) {
+    let repo = RepoParam::new("foo", "bar");
+    match storage.create_repo(&repo, CreateRepoParam::default()).await {
+        Err(DatabaseError::NotFound {
+            error: NotFoundError::Organization { org },
+        }) => assert_eq!(org, "foo"),
+        failed => unreachable!("Should not have gotten {:?}", failed),
+    }
+
+    storage.create_org("foo").await.unwrap();
+    let created = storage
+        .create_repo(
+            &repo,
+            CreateRepoParam {
+                labels: vec![("owner", "team-a")].into(),
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(created.org_name, "foo");
+    assert_eq!(created.repo_name, "bar");
+    assert_eq!(created.labels, vec![("owner", "team-a")].into());
+    assert_eq!(storage.get_repo(&repo).await.unwrap(), created);
+
+    match storage.create_repo(&repo, CreateRepoParam::default()).await {
+        Err(DatabaseError::AlreadyExists {
+            error: AlreadyExistsError::Repo { org, repo },
+        }) => assert_eq!((org.as_str(), repo.as_str()), ("foo", "bar")),
+        failed => unreachable!("Should not have gotten {:?}", failed),
+    }
+    assert_eq!(
+        storage
+            .get_repo(&RepoParam::new("foo", "flig"))
+            .await
+            .unwrap_err()
+            .to_string(),
+        "Repo foo/flig not found"
+    );
+
+    let updated = storage
+        .update_repo_labels(&repo, |existing| {
+            assert_eq!(existing.repo_name, "bar");
+            Ok::<_, DatabaseError>(labels(vec![("tier", "1")]))
+        })
+        .await
+        .unwrap();
+    assert_eq!(updated.labels, vec![("tier", "1")].into());
+    assert_eq!(
+        storage.get_repo(&repo).await.unwrap().labels,
+        vec![("tier", "1")].into()
+    );
+    // A failed update writes nothing.
+    storage
+        .update_repo_labels(&repo, |existing| {
+            assert_eq!(existing.labels, vec![("tier", "1")].into());
+            Err::<BTreeMap<_, _>, DatabaseError>(anyhow::anyhow!("Rejected").into())
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(
+        storage.get_repo(&repo).await.unwrap().labels,
+        vec![("tier", "1")].into()
+    );
+
+    for name in ["repo-1", "repo-2"] {
+        storage
+            .create_repo(&RepoParam::new("foo", name), CreateRepoParam::default())
+            .await
+            .unwrap();
+    }
+    assert_eq!(storage.count_repos("foo").await.unwrap(), 3);
+    let listed = storage
+        .list_repos("foo", &PaginationOptions::new(0, 2))
+        .await
+        .unwrap();
+    let names: Vec<_> = listed.iter().map(|repo| repo.repo_name.as_str()).collect();
+    assert_eq!(names, vec!["bar", "repo-1"]);
+    assert_eq!(listed[0].labels, vec![("tier", "1")].into());
+    let listed = storage
+        .list_repos("foo", &PaginationOptions::new(1, 2))
+        .await
+        .unwrap();
+    assert_eq!(listed.len(), 1);
+
+    // An org can't be deleted while it has repos.
+    assert!(storage.delete_org("foo").await.is_err());
+    assert!(storage.find_org("foo").await.is_ok());
+
+    for name in ["bar", "repo-1", "repo-2"] {
+        assert!(storage
+            .delete_repo(&RepoParam::new("foo", name))
+            .await
+            .unwrap());
+    }
+    assert_eq!(storage.count_repos("foo").await.unwrap(), 0);
+    assert!(matches!(
+        storage.delete_repo(&repo).await,
+        Err(DatabaseError::NotFound {
+            error: NotFoundError::Repo { .. }
+        })
+    ));
+    assert!(storage.delete_org("foo").await.unwrap());
+}
+
+async fn check_revisions(storage: &impl Storage) {
+    let repo = RepoParam::new("foo", "bar");
+    let revision = RevisionParam::new("foo", "bar", "1.2.3");
+    let create = |artifact_url| CreateRevisionParam {
+        artifact_url,
+        labels: vec![("git_sha", "abc")].into(),
+    };
+
+    storage.create_org("foo").await.unwrap();
+    match storage.create_revision(&revision, &create(None)).await {
+        Err(DatabaseError::NotFound {
+            error: NotFoundError::Repo { org, repo },
+        }) => assert_eq!((org.as_str(), repo.as_str()), ("foo", "bar")),
+        failed => unreachable!("Should not have gotten {:?}", failed),
+    }
+
+    storage
+        .create_repo(&repo, CreateRepoParam::default())
+        .await
+        .unwrap();
+    let created = storage
+        .create_revision(&revision, &create(Some("https://example.com/1.2.3")))
+        .await
+        .unwrap();
+    assert_eq!(created.revision_name, "1.2.3");
+    assert_eq!(
+        created.artifact_url.as_deref(),
+        Some("https://example.com/1.2.3")
+    );
+    assert_eq!(created.labels, vec![("git_sha", "abc")].into());
+    assert_eq!(created.created_at, storage.now());
+    assert_eq!(storage.get_revision(&revision).await.unwrap(), created);
+
+    match storage.create_revision(&revision, &create(None)).await {
+        Err(DatabaseError::AlreadyExists {
+            error: AlreadyExistsError::Revision { revision, .. },
+        }) => assert_eq!(revision, "1.2.3"),
+        failed => unreachable!("Should not have gotten {:?}", failed),
+    }
+    match storage
+        .get_revision(&RevisionParam::new("foo", "bar", "9.9.9"))
+        .await
+    {
+        Err(DatabaseError::NotFound {
+            error: NotFoundError::Revision { revision, .. },
+        }) => assert_eq!(revision, "9.9.9"),
+        failed => unreachable!("Should not have gotten {:?}", failed),
+    }
+
+    let updated = storage
+        .update_revision_labels(&revision, |existing| {
+            assert_eq!(existing.revision_name, "1.2.3");
+            Ok::<_, DatabaseError>(labels(vec![("state", "released")]))
+        })
+        .await
+        .unwrap();
+    assert_eq!(updated.labels, vec![("state", "released")].into());
+    assert_eq!(
+        storage.get_revision(&revision).await.unwrap().labels,
+        vec![("state", "released")].into()
+    );
+
+    // Concurrent updates each see the labels written by the one before them.
+    let count_up = || {
+        storage.update_revision_labels(&revision, |existing| {
+            let count: u32 = existing
+                .labels
+                .get("count")
+                .map_or(0, |c| c.parse().unwrap());
+            Ok::<_, DatabaseError>(labels(vec![("count", &(count + 1).to_string())]))
+        })
+    };
+    let (first, second) = tokio::join!(count_up(), count_up());
+    first.unwrap();
+    second.unwrap();
+    assert_eq!(
+        storage.get_revision(&revision).await.unwrap().labels,
+        vec![("count", "2")].into()
+    );
+
+    for name in ["1.2.4", "1.2.5"] {
+        storage
+            .create_revision(&RevisionParam::new("foo", "bar", name), &create(None))
+            .await
+            .unwrap();
+    }
+    assert_eq!(storage.count_revisions(&repo).await.unwrap(), 3);
+    let listed = storage
+        .list_revisions(&repo, &PaginationOptions::new(0, 2))
+        .await
+        .unwrap();
+    let names: Vec<_> = listed
+        .iter()
+        .map(|revision| revision.revision_name.as_str())
+        .collect();
+    assert_eq!(names, vec!["1.2.3", "1.2.4"]);
+    let listed = storage
+        .list_revisions(&repo, &PaginationOptions::new(1, 2))
+        .await
+        .unwrap();
+    assert_eq!(listed.len(), 1);
+
+    assert!(storage.delete_revision(&revision).await.unwrap());
+    assert!(matches!(
+        storage.delete_revision(&revision).await,
+        Err(DatabaseError::NotFound {
+            error: NotFoundError::Revision { .. }
+        })
+    ));
+    assert_eq!(storage.count_revisions(&repo).await.unwrap(), 2);
+
+    // Deleting the repo takes its versions with it.
+    storage.delete_repo(&repo).await.unwrap();
+    storage
+        .create_repo(&repo, CreateRepoParam::default())
+        .await
+        .unwrap();
+    assert_eq!(storage.count_revisions(&repo).await.unwrap(), 0);
+}
+
+async fn check_label_filters(storage: &impl Storage) {
+    storage.create_org("foo").await.unwrap();
+    let mut repo_ids = Vec::new();
+    for (name, tier) in [("repo-1", "1"), ("repo-2", "2"), ("repo-3", "1")] {
+        let created = storage
+            .create_repo(
+                &RepoParam::new("foo", name),
+                CreateRepoParam {
+                    labels: vec![("tier", tier), ("owner", "team-a")].into(),
+                },
+            )
+            .await
+            .unwrap();
+        repo_ids.push(created.repo_id);
+    }
+
+    let tier_1 = labels(vec![("tier", "1"), ("owner", "team-a")]);
+    assert_eq!(
+        storage
+            .count_repos_with_labels("foo", &tier_1)
+            .await
+            .unwrap(),
+        2
+    );
+    let listed = storage
+        .list_repos_with_labels("foo", &tier_1, &PaginationOptions::new(1, 1))
+        .await
+        .unwrap();
+    let names: Vec<_> = listed.iter().map(|repo| repo.repo_name.as_str()).collect();
+    assert_eq!(names, vec!["repo-3"]);
+    assert_eq!(
+        storage
+            .count_repos_with_labels("foo", &BTreeMap::new())
+            .await
+            .unwrap(),
+        3
+    );
+
+    for (repo, version, state) in [
+        ("repo-1", "1.0.0", "released"),
+        ("repo-1", "1.0.1", "draft"),
+        ("repo-1", "1.0.2", "released"),
+        ("repo-1", "1.0.3", "released"),
+        ("repo-2", "2.0.0", "released"),
+        ("repo-3", "3.0.0", "draft"),
+    ] {
+        storage
+            .create_revision(
+                &RevisionParam::new("foo", repo, version),
+                &CreateRevisionParam {
+                    artifact_url: None,
+                    labels: vec![("state", state)].into(),
+                },
+            )
+            .await
+            .unwrap();
+    }
+
+    let released = labels(vec![("state", "released")]);
+    let counts = storage
+        .count_revisions_of_repos(&repo_ids, &released)
+        .await
+        .unwrap();
+    assert_eq!(counts, BTreeMap::from([(repo_ids[0], 3), (repo_ids[1], 1)]));
+
+    let listed = storage
+        .list_revisions_of_repos(&repo_ids, &released, &PaginationOptions::new(1, 2))
+        .await
+        .unwrap();
+    let names: BTreeMap<_, Vec<_>> = listed
+        .iter()
+        .map(|(repo_id, revisions)| {
+            let names = revisions
+                .iter()
+                .map(|revision| revision.revision_name.as_str())
+                .collect();
+            (*repo_id, names)
+        })
+        .collect();
+    assert_eq!(names, BTreeMap::from([(repo_ids[0], vec!["1.0.3"])]));
+    assert_eq!(
+        listed[&repo_ids[0]][0].labels,
+        vec![("state", "released")].into()
+    );
+
+    let listed = storage
+        .list_revisions_of_repos(
+            &repo_ids[1..],
+            &BTreeMap::new(),
+            &PaginationOptions::new(0, 2),
+        )
+        .await
+        .unwrap();
+    assert_eq!(listed.len(), 2);
+    assert!(storage
+        .list_revisions_of_repos(&[], &BTreeMap::new(), &PaginationOptions::new(0, 2))
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+async fn check_policy_versions(storage: &impl Storage) {
+    let create = |hash| CreatePolicyVersionParam {
+        evaluation: "first_match",
+        document_hash: hash,
+        comment: Some("a comment"),
+        definitions: ["z", "b"]
+            .iter()
+            .map(|name| DbPolicyDefinition {
+                policy_name: name.to_string(),
+                definition: format!("{{\"name\":\"{}\"}}", name),
+            })
+            .collect(),
+    };
+
+    assert_eq!(storage.get_active_policy_version().await.unwrap(), None);
+    assert_eq!(storage.count_policy_versions().await.unwrap(), 0);
+
+    let first = storage.create_policy_version(&create("a")).await.unwrap();
+    assert!(!first.active);
+    assert_eq!(first.comment.as_deref(), Some("a comment"));
+    assert_eq!(first.created_at, storage.now());
+    let names: Vec<_> = first
+        .definitions
+        .iter()
+        .map(|definition| definition.policy_name.as_str())
+        .collect();
+    assert_eq!(names, vec!["z", "b"]);
+    assert_eq!(
+        storage
+            .get_policy_version(first.policy_version_id)
+            .await
+            .unwrap(),
+        first
+    );
+
+    let second = storage.create_policy_version(&create("b")).await.unwrap();
+    let third = storage.create_policy_version(&create("c")).await.unwrap();
+    assert!(first.policy_version_id < second.policy_version_id);
+    assert_eq!(storage.count_policy_versions().await.unwrap(), 3);
+
+    let listed = storage
+        .list_policy_versions(&PaginationOptions::new(0, 2))
+        .await
+        .unwrap();
+    let hashes: Vec<_> = listed
+        .iter()
+        .map(|version| version.document_hash.as_str())
+        .collect();
+    assert_eq!(hashes, vec!["c", "b"]);
+
+    match storage.create_policy_version(&create("b")).await {
+        Err(DatabaseError::AlreadyExists {
+            error: AlreadyExistsError::PolicyVersion { version },
+        }) => assert_eq!(version, second.policy_version_id),
+        failed => unreachable!("Should not have gotten {:?}", failed),
+    }
+    assert_eq!(storage.count_policy_versions().await.unwrap(), 3);
+    assert_eq!(
+        storage.find_policy_version_by_hash("b").await.unwrap(),
+        Some(second.clone())
+    );
+    assert_eq!(
+        storage.find_policy_version_by_hash("d").await.unwrap(),
+        None
+    );
+
+    let activated = storage
+        .activate_policy_version(first.policy_version_id)
+        .await
+        .unwrap();
+    assert!(activated.active);
+    storage
+        .activate_policy_version(third.policy_version_id)
+        .await
+        .unwrap();
+    let active = storage.get_active_policy_version().await.unwrap().unwrap();
+    assert_eq!(active.policy_version_id, third.policy_version_id);
+    assert!(
+        !storage
+            .get_policy_version(first.policy_version_id)
+            .await
+            .unwrap()
+            .active
+    );
+
+    assert!(storage
+        .delete_policy_version(second.policy_version_id)
+        .await
+        .unwrap());
+    match storage.get_policy_version(second.policy_version_id).await {
+        Err(DatabaseError::NotFound {
+            error: NotFoundError::PolicyVersion { version },
+        }) => assert_eq!(version, second.policy_version_id),
+        failed => unreachable!("Should not have gotten {:?}", failed),
+    }
+    assert!(matches!(
+        storage
+            .activate_policy_version(second.policy_version_id)
+            .await,
+        Err(DatabaseError::NotFound { .. })
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+#[serial]
+async fn sea_orm_storage_conforms() {
+    let make = || async {
+        BackendDatabase {
+            db: setup_schema().await.unwrap(),
+            date_time_provider: test_date_time_provider(),
+            read_replica: None,
+            id_cache: Default::default(),
+        }
+    };
+
+    check_orgs(&make().await).await;
+    check_repos(&make().await).await;
+    check_revisions(&make().await).await;
+    check_label_filters(&make().await).await;
+    check_policy_versions(&make().await).await;
+}
+
+#[tokio::test]
+async fn memory_storage_conforms() {
+    let make = || MemoryStorage::new(test_date_time_provider());
+
+    check_orgs(&make()).await;
+    check_repos(&make()).await;
+    check_revisions(&make()).await;
+    check_label_filters(&make()).await;
+    check_policy_versions(&make()).await;
+}
+
+#[tokio::test]
+async fn cached_storage_conforms() {
+    use crate::database::cache::{CacheOptions, CachedStorage};
+
+    let make = || {
+        CachedStorage::new(
+            MemoryStorage::new(test_date_time_provider()),
+            CacheOptions::default(),
+        )
+    };
+
+    check_orgs(&make()).await;
+    check_repos(&make()).await;
+    check_revisions(&make()).await;
+    check_label_filters(&make()).await;
+    check_policy_versions(&make()).await;
+}
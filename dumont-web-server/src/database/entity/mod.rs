@@ -4,6 +4,8 @@ pub mod prelude;
 
 pub mod flyway_schema_history;
 pub mod organization;
+pub mod policy_definition;
+pub mod policy_version;
 pub mod repository;
 pub mod repository_label;
 pub mod repository_revision;
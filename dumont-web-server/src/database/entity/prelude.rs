@@ -2,6 +2,8 @@
 
 pub use super::flyway_schema_history::Entity as FlywaySchemaHistory;
 pub use super::organization::Entity as Organization;
+pub use super::policy_definition::Entity as PolicyDefinition;
+pub use super::policy_version::Entity as PolicyVersion;
 pub use super::repository::Entity as Repository;
 pub use super::repository_label::Entity as RepositoryLabel;
 pub use super::repository_revision::Entity as RepositoryRevision;
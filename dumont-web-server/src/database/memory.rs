@@ -0,0 +1,494 @@
+use crate::backend::models::PaginationOptions;
+use crate::database::prelude::*;
+use crate::database::storage::Storage;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+/**
+ * MemoryStorage keeps everything in memory, it's lost when the process exits.
+ *
+ * Ids are handed out the way the database sequences do, one counter per table
+ * starting at 1, and lists are ordered the same way as the sea-orm queries.
+ */
+#[derive(Debug)]
+pub struct MemoryStorage {
+    state: Mutex<MemoryState>,
+    pub date_time_provider: DateTimeProvider,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    orgs: Vec<DbOrganizationModel>,
+    repos: Vec<DbRepoModel>,
+    revisions: Vec<DbRevisionModel>,
+    policy_versions: Vec<DbPolicyVersionModel>,
+    last_org_id: i32,
+    last_repo_id: i32,
+    last_revision_id: i32,
+    last_policy_version_id: i32,
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        Self::new(DateTimeProvider::RealDateTime)
+    }
+}
+
+impl MemoryStorage {
+    pub fn new(date_time_provider: DateTimeProvider) -> Self {
+        Self {
+            state: Default::default(),
+            date_time_provider,
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, MemoryState> {
+        self.state
+            .lock()
+            .expect("Memory storage lock to not be poisoned")
+    }
+}
+
+fn has_labels(found: &crate::models::GenericLabels, labels: &BTreeMap<String, String>) -> bool {
+    labels
+        .iter()
+        .all(|(name, value)| found.get(name) == Some(value))
+}
+
+fn page<T>(items: impl Iterator<Item = T>, pagination: &PaginationOptions) -> Vec<T> {
+    items
+        .skip((pagination.page_number * pagination.page_size) as usize)
+        .take(pagination.page_size as usize)
+        .collect()
+}
+
+impl MemoryState {
+    fn find_org(&self, org_name: &str) -> DbResult<&DbOrganizationModel> {
+        self.orgs
+            .iter()
+            .find(|org| org.org_name == org_name)
+            .ok_or_else(|| DatabaseError::NotFound {
+                error: NotFoundError::Organization {
+                    org: org_name.to_owned(),
+                },
+            })
+    }
+
+    fn find_repo(&self, org_name: &str, repo_name: &str) -> DbResult<Option<usize>> {
+        let org_id = self.find_org(org_name)?.org_id;
+        Ok(self
+            .repos
+            .iter()
+            .position(|repo| repo.org_id == org_id && repo.repo_name == repo_name))
+    }
+
+    fn get_repo(&self, repo: &RepoParam<'_>) -> DbResult<usize> {
+        self.find_repo(repo.org_name, repo.repo_name)?
+            .ok_or_else(|| DatabaseError::NotFound {
+                error: NotFoundError::Repo {
+                    org: repo.org_name.to_owned(),
+                    repo: repo.repo_name.to_owned(),
+                },
+            })
+    }
+
+    fn find_revision(&self, revision_param: &RevisionParam<'_>) -> Option<usize> {
+        let repo_id = match self.find_repo(revision_param.org_name, revision_param.repo_name) {
+            Ok(Some(index)) => self.repos[index].repo_id,
+            _ => return None,
+        };
+        self.revisions.iter().position(|revision| {
+            revision.repo_id == repo_id && revision.revision_name == revision_param.revision
+        })
+    }
+
+    fn get_revision(&self, revision_param: &RevisionParam<'_>) -> DbResult<usize> {
+        self.find_revision(revision_param)
+            .ok_or_else(|| DatabaseError::NotFound {
+                error: NotFoundError::Revision {
+                    org: revision_param.org_name.to_owned(),
+                    repo: revision_param.repo_name.to_owned(),
+                    revision: revision_param.revision.to_owned(),
+                },
+            })
+    }
+
+    fn get_policy_version(&self, version: i32) -> DbResult<usize> {
+        self.policy_versions
+            .iter()
+            .position(|found| found.policy_version_id == version)
+            .ok_or(DatabaseError::NotFound {
+                error: NotFoundError::PolicyVersion { version },
+            })
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    fn now(&self) -> DateTime<Utc> {
+        self.date_time_provider.now()
+    }
+
+    async fn create_org(&self, org_name: &str) -> DbResult<DbOrganizationModel> {
+        let mut state = self.state();
+        if state.find_org(org_name).is_ok() {
+            return Err(DatabaseError::AlreadyExists {
+                error: AlreadyExistsError::Organization {
+                    org: org_name.to_owned(),
+                },
+            });
+        }
+
+        state.last_org_id += 1;
+        let org = DbOrganizationModel {
+            org_id: state.last_org_id,
+            org_name: org_name.to_owned(),
+        };
+        state.orgs.push(org.clone());
+        Ok(org)
+    }
+
+    async fn find_org(&self, org_name: &str) -> DbResult<DbOrganizationModel> {
+        self.state().find_org(org_name).cloned()
+    }
+
+    async fn list_orgs(
+        &self,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbOrganizationModel>> {
+        Ok(page(self.state().orgs.iter().cloned(), pagination))
+    }
+
+    async fn count_orgs(&self) -> DbResult<u64> {
+        Ok(self.state().orgs.len() as u64)
+    }
+
+    async fn delete_org(&self, org_name: &str) -> DbResult<bool> {
+        let mut state = self.state();
+        let org_id = state.find_org(org_name)?.org_id;
+        if state.repos.iter().any(|repo| repo.org_id == org_id) {
+            return Err(anyhow!("Org {} still has repos", org_name).into());
+        }
+
+        state.orgs.retain(|org| org.org_id != org_id);
+        Ok(true)
+    }
+
+    async fn create_repo(
+        &self,
+        repo: &RepoParam<'_>,
+        create_params: CreateRepoParam,
+    ) -> DbResult<DbRepoModel> {
+        let mut state = self.state();
+        if state.find_repo(repo.org_name, repo.repo_name)?.is_some() {
+            return Err(DatabaseError::AlreadyExists {
+                error: AlreadyExistsError::Repo {
+                    org: repo.org_name.to_owned(),
+                    repo: repo.repo_name.to_owned(),
+                },
+            });
+        }
+
+        let org = state.find_org(repo.org_name)?.clone();
+        state.last_repo_id += 1;
+        let created = DbRepoModel {
+            org_id: org.org_id,
+            org_name: org.org_name,
+            repo_id: state.last_repo_id,
+            repo_name: repo.repo_name.to_owned(),
+            labels: create_params.labels,
+        };
+        state.repos.push(created.clone());
+        Ok(created)
+    }
+
+    async fn get_repo(&self, repo: &RepoParam<'_>) -> DbResult<DbRepoModel> {
+        let state = self.state();
+        let index = state.get_repo(repo)?;
+        Ok(state.repos[index].clone())
+    }
+
+    async fn list_repos(
+        &self,
+        org_name: &str,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbRepoModel>> {
+        let state = self.state();
+        let org_id = state.find_org(org_name)?.org_id;
+        let repos = state.repos.iter().filter(|repo| repo.org_id == org_id);
+        Ok(page(repos.cloned(), pagination))
+    }
+
+    async fn count_repos(&self, org_name: &str) -> DbResult<u64> {
+        let state = self.state();
+        let org_id = state.find_org(org_name)?.org_id;
+        Ok(state
+            .repos
+            .iter()
+            .filter(|repo| repo.org_id == org_id)
+            .count() as u64)
+    }
+
+    async fn list_repos_with_labels(
+        &self,
+        org_name: &str,
+        labels: &BTreeMap<String, String>,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbRepoModel>> {
+        let state = self.state();
+        let org_id = state.find_org(org_name)?.org_id;
+        let repos = state
+            .repos
+            .iter()
+            .filter(|repo| repo.org_id == org_id && has_labels(&repo.labels, labels));
+        Ok(page(repos.cloned(), pagination))
+    }
+
+    async fn count_repos_with_labels(
+        &self,
+        org_name: &str,
+        labels: &BTreeMap<String, String>,
+    ) -> DbResult<u64> {
+        let state = self.state();
+        let org_id = state.find_org(org_name)?.org_id;
+        Ok(state
+            .repos
+            .iter()
+            .filter(|repo| repo.org_id == org_id && has_labels(&repo.labels, labels))
+            .count() as u64)
+    }
+
+    async fn delete_repo(&self, repo: &RepoParam<'_>) -> DbResult<bool> {
+        let mut state = self.state();
+        let index = state.get_repo(repo)?;
+        let removed = state.repos.remove(index);
+        state
+            .revisions
+            .retain(|revision| revision.repo_id != removed.repo_id);
+        Ok(true)
+    }
+
+    async fn update_repo_labels<F, E>(
+        &self,
+        repo: &RepoParam<'_>,
+        update: F,
+    ) -> Result<DbRepoModel, E>
+    where
+        F: FnOnce(&DbRepoModel) -> Result<BTreeMap<String, String>, E> + Send,
+        E: From<DatabaseError> + Send,
+    {
+        let mut state = self.state();
+        let index = state.get_repo(repo)?;
+        state.repos[index].labels = update(&state.repos[index])?.into();
+        Ok(state.repos[index].clone())
+    }
+
+    async fn create_revision(
+        &self,
+        revision_param: &RevisionParam<'_>,
+        create_revision_param: &CreateRevisionParam<'_>,
+    ) -> DbResult<DbRevisionModel> {
+        let mut state = self.state();
+        if state.find_revision(revision_param).is_some() {
+            return Err(DatabaseError::AlreadyExists {
+                error: AlreadyExistsError::Revision {
+                    org: revision_param.org_name.to_owned(),
+                    repo: revision_param.repo_name.to_owned(),
+                    revision: revision_param.revision.to_owned(),
+                },
+            });
+        }
+
+        let repo_index = state.get_repo(&RepoParam::new(
+            revision_param.org_name,
+            revision_param.repo_name,
+        ))?;
+        state.last_revision_id += 1;
+        let created = DbRevisionModel {
+            repo_id: state.repos[repo_index].repo_id,
+            revision_id: state.last_revision_id,
+            revision_name: revision_param.revision.to_owned(),
+            artifact_url: create_revision_param.artifact_url.map(|s| s.to_owned()),
+            labels: create_revision_param.labels.clone(),
+            created_at: self.now(),
+        };
+        state.revisions.push(created.clone());
+        Ok(created)
+    }
+
+    async fn get_revision(&self, revision_param: &RevisionParam<'_>) -> DbResult<DbRevisionModel> {
+        let state = self.state();
+        let index = state.get_revision(revision_param)?;
+        Ok(state.revisions[index].clone())
+    }
+
+    async fn list_revisions(
+        &self,
+        repo_param: &RepoParam<'_>,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbRevisionModel>> {
+        let state = self.state();
+        let repo_id = state.repos[state.get_repo(repo_param)?].repo_id;
+        let revisions = state
+            .revisions
+            .iter()
+            .filter(|revision| revision.repo_id == repo_id);
+        Ok(page(revisions.cloned(), pagination))
+    }
+
+    async fn count_revisions(&self, repo_param: &RepoParam<'_>) -> DbResult<u64> {
+        let state = self.state();
+        let repo_id = state.repos[state.get_repo(repo_param)?].repo_id;
+        let count = state
+            .revisions
+            .iter()
+            .filter(|revision| revision.repo_id == repo_id)
+            .count();
+        Ok(count as u64)
+    }
+
+    async fn list_revisions_of_repos(
+        &self,
+        repo_ids: &[i32],
+        labels: &BTreeMap<String, String>,
+        pagination: &PaginationOptions,
+    ) -> DbResult<BTreeMap<i32, Vec<DbRevisionModel>>> {
+        let state = self.state();
+        let mut found = BTreeMap::new();
+        for repo_id in repo_ids {
+            let revisions = state.revisions.iter().filter(|revision| {
+                revision.repo_id == *repo_id && has_labels(&revision.labels, labels)
+            });
+            let revisions = page(revisions.cloned(), pagination);
+            if !revisions.is_empty() {
+                found.insert(*repo_id, revisions);
+            }
+        }
+        Ok(found)
+    }
+
+    async fn count_revisions_of_repos(
+        &self,
+        repo_ids: &[i32],
+        labels: &BTreeMap<String, String>,
+    ) -> DbResult<BTreeMap<i32, u64>> {
+        let state = self.state();
+        let mut found = BTreeMap::new();
+        for revision in &state.revisions {
+            if repo_ids.contains(&revision.repo_id) && has_labels(&revision.labels, labels) {
+                *found.entry(revision.repo_id).or_default() += 1;
+            }
+        }
+        Ok(found)
+    }
+
+    async fn delete_revision(&self, revision_param: &RevisionParam<'_>) -> DbResult<bool> {
+        let mut state = self.state();
+        let index = state.get_revision(revision_param)?;
+        state.revisions.remove(index);
+        Ok(true)
+    }
+
+    async fn update_revision_labels<F, E>(
+        &self,
+        revision_param: &RevisionParam<'_>,
+        update: F,
+    ) -> Result<DbRevisionModel, E>
+    where
+        F: FnOnce(&DbRevisionModel) -> Result<BTreeMap<String, String>, E> + Send,
+        E: From<DatabaseError> + Send,
+    {
+        let mut state = self.state();
+        let index = state.get_revision(revision_param)?;
+        state.revisions[index].labels = update(&state.revisions[index])?.into();
+        Ok(state.revisions[index].clone())
+    }
+
+    async fn create_policy_version(
+        &self,
+        create_param: &CreatePolicyVersionParam<'_>,
+    ) -> DbResult<DbPolicyVersionModel> {
+        let mut state = self.state();
+        if let Some(existing) = state
+            .policy_versions
+            .iter()
+            .find(|version| version.document_hash == create_param.document_hash)
+        {
+            return Err(DatabaseError::AlreadyExists {
+                error: AlreadyExistsError::PolicyVersion {
+                    version: existing.policy_version_id,
+                },
+            });
+        }
+        state.last_policy_version_id += 1;
+        let created = DbPolicyVersionModel {
+            policy_version_id: state.last_policy_version_id,
+            evaluation: create_param.evaluation.to_owned(),
+            document_hash: create_param.document_hash.to_owned(),
+            comment: create_param.comment.map(|s| s.to_owned()),
+            active: false,
+            created_at: self.now(),
+            definitions: create_param.definitions.clone(),
+        };
+        state.policy_versions.push(created.clone());
+        Ok(created)
+    }
+
+    async fn get_policy_version(&self, version: i32) -> DbResult<DbPolicyVersionModel> {
+        let state = self.state();
+        let index = state.get_policy_version(version)?;
+        Ok(state.policy_versions[index].clone())
+    }
+
+    async fn get_active_policy_version(&self) -> DbResult<Option<DbPolicyVersionModel>> {
+        let state = self.state();
+        let active = state.policy_versions.iter().find(|version| version.active);
+        Ok(active.cloned())
+    }
+
+    async fn find_policy_version_by_hash(
+        &self,
+        document_hash: &str,
+    ) -> DbResult<Option<DbPolicyVersionModel>> {
+        let state = self.state();
+        let found = state
+            .policy_versions
+            .iter()
+            .find(|version| version.document_hash == document_hash);
+        Ok(found.cloned())
+    }
+
+    async fn list_policy_versions(
+        &self,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbPolicyVersionModel>> {
+        Ok(page(
+            self.state().policy_versions.iter().rev().cloned(),
+            pagination,
+        ))
+    }
+
+    async fn count_policy_versions(&self) -> DbResult<u64> {
+        Ok(self.state().policy_versions.len() as u64)
+    }
+
+    async fn activate_policy_version(&self, version: i32) -> DbResult<DbPolicyVersionModel> {
+        let mut state = self.state();
+        let index = state.get_policy_version(version)?;
+        for found in state.policy_versions.iter_mut() {
+            found.active = false;
+        }
+        state.policy_versions[index].active = true;
+        Ok(state.policy_versions[index].clone())
+    }
+
+    async fn delete_policy_version(&self, version: i32) -> DbResult<bool> {
+        let mut state = self.state();
+        let index = state.get_policy_version(version)?;
+        state.policy_versions.remove(index);
+        Ok(true)
+    }
+}
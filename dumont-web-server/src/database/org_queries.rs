@@ -108,6 +108,7 @@ impl OrganizationQueries for BackendDatabase {
             .filter(Column::OrgName.eq(org_name.clone()))
             .exec(&self.db)
             .await?;
+        self.id_cache.forget_org(&org_name);
 
         if resp.rows_affected == 0 {
             return Err(DatabaseError::NotFound {
@@ -165,18 +166,21 @@ impl OrganizationQueries for BackendDatabase {
         Ok(resp)
     }
 
+    /// Resolves the org through the `IdCache`.
     #[instrument(skip(self))]
     async fn sql_get_org(&self, org_name: &str) -> DbResult<entity::organization::Model> {
-        match self.sql_get_raw_org(org_name).await? {
-            Some(org) => Ok(org),
-            None => {
-                return Err(DatabaseError::NotFound {
-                    error: NotFoundError::Organization {
-                        org: org_name.to_owned(),
-                    },
-                })
-            }
-        }
+        self.id_cache
+            .org(org_name, async {
+                match self.sql_get_raw_org(org_name).await? {
+                    Some(org) => Ok(org),
+                    None => Err(DatabaseError::NotFound {
+                        error: NotFoundError::Organization {
+                            org: org_name.to_owned(),
+                        },
+                    }),
+                }
+            })
+            .await
     }
 }
 
@@ -193,6 +197,8 @@ mod integ_test {
         let db = BackendDatabase {
             db: setup_schema().await.unwrap(),
             date_time_provider: DateTimeProvider::RealDateTime,
+            read_replica: None,
+            id_cache: Default::default(),
         };
 
         let new_org = db.create_org("foo").await.unwrap();
@@ -241,6 +247,8 @@ mod integ_test {
         let db = BackendDatabase {
             db: setup_schema().await.unwrap(),
             date_time_provider: DateTimeProvider::RealDateTime,
+            read_replica: None,
+            id_cache: Default::default(),
         };
 
         for i in 0..100 {
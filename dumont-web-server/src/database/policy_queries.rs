@@ -0,0 +1,416 @@
+use crate::backend::models::PaginationOptions;
+use crate::database::{
+    entity::{self, prelude::*},
+    AlreadyExistsError, BackendDatabase, DatabaseError, DbResult, NotFoundError,
+};
+use async_trait::async_trait;
+use sea_orm::{entity::*, query::*, sea_query::Expr, ConnectionTrait, TransactionTrait};
+use std::collections::BTreeMap;
+use tracing_attributes::instrument;
+
+/**
+ * PolicyQueries is a collection of api calls against the database focused
+ * on the policy versions managed through the admin API.
+ *
+ * Versions are never changed once created, a change to the policies is a new
+ * version. Exactly one version, if any, is active and enforced.
+ */
+#[async_trait]
+pub trait PolicyQueries {
+    async fn create_policy_version(
+        &self,
+        create_param: &CreatePolicyVersionParam<'_>,
+    ) -> DbResult<DbPolicyVersionModel>;
+
+    async fn get_policy_version(&self, version: i32) -> DbResult<DbPolicyVersionModel>;
+
+    async fn get_active_policy_version(&self) -> DbResult<Option<DbPolicyVersionModel>>;
+
+    async fn find_policy_version_by_hash(
+        &self,
+        document_hash: &str,
+    ) -> DbResult<Option<DbPolicyVersionModel>>;
+
+    async fn list_policy_versions(
+        &self,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbPolicyVersionModel>>;
+
+    async fn count_policy_versions(&self) -> DbResult<u64>;
+
+    async fn activate_policy_version(&self, version: i32) -> DbResult<DbPolicyVersionModel>;
+
+    async fn delete_policy_version(&self, version: i32) -> DbResult<bool>;
+
+    async fn sql_get_policy_version(&self, version: i32)
+        -> DbResult<entity::policy_version::Model>;
+}
+
+pub mod models {
+    use crate::database::entity;
+    use chrono::{DateTime, Utc};
+
+    #[derive(Debug)]
+    pub struct CreatePolicyVersionParam<'a> {
+        pub evaluation: &'a str,
+        pub document_hash: &'a str,
+        pub comment: Option<&'a str>,
+        pub definitions: Vec<DbPolicyDefinition>,
+    }
+
+    /// A single policy of a version, serialized as JSON.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct DbPolicyDefinition {
+        pub policy_name: String,
+        pub definition: String,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct DbPolicyVersionModel {
+        pub policy_version_id: i32,
+        pub evaluation: String,
+        pub document_hash: String,
+        pub comment: Option<String>,
+        pub active: bool,
+        pub created_at: DateTime<Utc>,
+        /// The policies in the order they were defined.
+        pub definitions: Vec<DbPolicyDefinition>,
+    }
+
+    impl DbPolicyVersionModel {
+        pub fn from(
+            version: entity::policy_version::Model,
+            mut definitions: Vec<entity::policy_definition::Model>,
+        ) -> Self {
+            definitions.sort_by_key(|definition| definition.position);
+            Self {
+                policy_version_id: version.policy_version_id,
+                evaluation: version.evaluation,
+                document_hash: version.document_hash,
+                comment: version.comment,
+                active: version.active,
+                created_at: version.created_at,
+                definitions: definitions
+                    .into_iter()
+                    .map(|definition| DbPolicyDefinition {
+                        policy_name: definition.policy_name,
+                        definition: definition.definition,
+                    })
+                    .collect(),
+            }
+        }
+    }
+}
+
+use models::*;
+
+impl BackendDatabase {
+    async fn sql_get_policy_definitions<C: ConnectionTrait>(
+        db: &C,
+        version_ids: &[i32],
+    ) -> DbResult<BTreeMap<i32, Vec<entity::policy_definition::Model>>> {
+        use entity::policy_definition::Column;
+
+        let definitions = PolicyDefinition::find()
+            .filter(Column::PolicyVersionId.is_in(version_ids.to_vec()))
+            .all(db)
+            .await?;
+
+        let mut by_version: BTreeMap<i32, Vec<_>> = BTreeMap::new();
+        for definition in definitions {
+            by_version
+                .entry(definition.policy_version_id)
+                .or_default()
+                .push(definition);
+        }
+
+        Ok(by_version)
+    }
+
+    async fn to_policy_version_model(
+        &self,
+        version: entity::policy_version::Model,
+    ) -> DbResult<DbPolicyVersionModel> {
+        let mut definitions =
+            Self::sql_get_policy_definitions(&self.db, &[version.policy_version_id]).await?;
+        let definitions = definitions
+            .remove(&version.policy_version_id)
+            .unwrap_or_default();
+
+        Ok(DbPolicyVersionModel::from(version, definitions))
+    }
+}
+
+#[async_trait]
+impl PolicyQueries for BackendDatabase {
+    #[instrument(skip(self, create_param))]
+    async fn create_policy_version(
+        &self,
+        create_param: &CreatePolicyVersionParam<'_>,
+    ) -> DbResult<DbPolicyVersionModel> {
+        let already_exists = |existing: DbPolicyVersionModel| DatabaseError::AlreadyExists {
+            error: AlreadyExistsError::PolicyVersion {
+                version: existing.policy_version_id,
+            },
+        };
+        if let Some(existing) = self
+            .find_policy_version_by_hash(create_param.document_hash)
+            .await?
+        {
+            return Err(already_exists(existing));
+        }
+
+        let txn = self.db.begin().await?;
+
+        let model = entity::policy_version::ActiveModel {
+            evaluation: Set(create_param.evaluation.to_owned()),
+            document_hash: Set(create_param.document_hash.to_owned()),
+            comment: Set(create_param.comment.map(|s| s.to_owned())),
+            active: Set(false),
+            created_at: Set(self.date_time_provider.now()),
+            ..Default::default()
+        };
+        let response: InsertResult<_> = match PolicyVersion::insert(model).exec(&txn).await {
+            Ok(response) => response,
+            // The hash is unique, another server may have stored the same policies
+            // since they were looked for.
+            Err(e) => {
+                txn.rollback().await?;
+                return match self
+                    .find_policy_version_by_hash(create_param.document_hash)
+                    .await?
+                {
+                    Some(existing) => Err(already_exists(existing)),
+                    None => Err(e.into()),
+                };
+            }
+        };
+        let version_id = response.last_insert_id;
+
+        let definitions: Vec<_> = create_param
+            .definitions
+            .iter()
+            .enumerate()
+            .map(
+                |(position, definition)| entity::policy_definition::ActiveModel {
+                    policy_version_id: Set(version_id),
+                    policy_name: Set(definition.policy_name.clone()),
+                    position: Set(position as i32),
+                    definition: Set(definition.definition.clone()),
+                    ..Default::default()
+                },
+            )
+            .collect();
+        if !definitions.is_empty() {
+            PolicyDefinition::insert_many(definitions)
+                .exec(&txn)
+                .await?;
+        }
+
+        txn.commit().await?;
+
+        self.get_policy_version(version_id).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_policy_version(&self, version: i32) -> DbResult<DbPolicyVersionModel> {
+        let version = self.sql_get_policy_version(version).await?;
+        self.to_policy_version_model(version).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_active_policy_version(&self) -> DbResult<Option<DbPolicyVersionModel>> {
+        use entity::policy_version::Column;
+
+        let version = PolicyVersion::find()
+            .filter(Column::Active.eq(true))
+            .one(&self.db)
+            .await?;
+
+        match version {
+            Some(version) => Ok(Some(self.to_policy_version_model(version).await?)),
+            None => Ok(None),
+        }
+    }
+
+    #[instrument(skip(self))]
+    async fn find_policy_version_by_hash(
+        &self,
+        document_hash: &str,
+    ) -> DbResult<Option<DbPolicyVersionModel>> {
+        use entity::policy_version::Column;
+
+        let version = PolicyVersion::find()
+            .filter(Column::DocumentHash.eq(document_hash))
+            .one(&self.db)
+            .await?;
+
+        match version {
+            Some(version) => Ok(Some(self.to_policy_version_model(version).await?)),
+            None => Ok(None),
+        }
+    }
+
+    #[instrument(skip(self))]
+    async fn list_policy_versions(
+        &self,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbPolicyVersionModel>> {
+        use entity::policy_version::Column;
+
+        let select = PolicyVersion::find()
+            .order_by_desc(Column::PolicyVersionId)
+            .paginate(&self.db, pagination.page_size)
+            .fetch_page(pagination.page_number)
+            .await?;
+
+        let version_ids: Vec<i32> = select.iter().map(|it| it.policy_version_id).collect();
+        let mut definitions = Self::sql_get_policy_definitions(&self.db, &version_ids).await?;
+
+        let versions = select
+            .into_iter()
+            .map(|version| {
+                let version_definitions = definitions
+                    .remove(&version.policy_version_id)
+                    .unwrap_or_default();
+                DbPolicyVersionModel::from(version, version_definitions)
+            })
+            .collect();
+
+        Ok(versions)
+    }
+
+    #[instrument(skip(self))]
+    async fn count_policy_versions(&self) -> DbResult<u64> {
+        let count = PolicyVersion::find().count(&self.db).await?;
+
+        Ok(count)
+    }
+
+    #[instrument(skip(self))]
+    async fn activate_policy_version(&self, version: i32) -> DbResult<DbPolicyVersionModel> {
+        use entity::policy_version::Column;
+
+        let found = self.sql_get_policy_version(version).await?;
+
+        let txn = self.db.begin().await?;
+        PolicyVersion::update_many()
+            .col_expr(Column::Active, Expr::value(false))
+            .filter(Column::Active.eq(true))
+            .exec(&txn)
+            .await?;
+        let mut found: entity::policy_version::ActiveModel = found.into();
+        found.active = Set(true);
+        found.update(&txn).await?;
+        txn.commit().await?;
+
+        self.get_policy_version(version).await
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_policy_version(&self, version: i32) -> DbResult<bool> {
+        let found: entity::policy_version::ActiveModel =
+            self.sql_get_policy_version(version).await?.into();
+        let res = found.delete(&self.db).await?;
+
+        Ok(res.rows_affected == 1)
+    }
+
+    #[instrument(skip(self))]
+    async fn sql_get_policy_version(
+        &self,
+        version: i32,
+    ) -> DbResult<entity::policy_version::Model> {
+        match PolicyVersion::find_by_id(version).one(&self.db).await? {
+            Some(found) => Ok(found),
+            None => Err(DatabaseError::NotFound {
+                error: NotFoundError::PolicyVersion { version },
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod integ_test {
+    use super::*;
+    use crate::database::DateTimeProvider;
+    use crate::test_utils::*;
+    use serial_test::serial;
+
+    fn create_param<'a>(hash: &'a str, names: &[&str]) -> CreatePolicyVersionParam<'a> {
+        CreatePolicyVersionParam {
+            evaluation: "first_match",
+            document_hash: hash,
+            comment: None,
+            definitions: names
+                .iter()
+                .map(|name| DbPolicyDefinition {
+                    policy_name: name.to_string(),
+                    definition: format!("{{\"name\":\"{}\"}}", name),
+                })
+                .collect(),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn test_policy_versions() {
+        let db = BackendDatabase {
+            db: setup_schema().await.unwrap(),
+            date_time_provider: DateTimeProvider::RealDateTime,
+            read_replica: None,
+            id_cache: Default::default(),
+        };
+
+        assert_eq!(db.get_active_policy_version().await.unwrap(), None);
+
+        let first = db
+            .create_policy_version(&create_param("a", &["z", "b"]))
+            .await
+            .unwrap();
+        assert!(!first.active);
+        let names: Vec<_> = first
+            .definitions
+            .iter()
+            .map(|definition| definition.policy_name.as_str())
+            .collect();
+        assert_eq!(names, vec!["z", "b"]);
+
+        let second = db
+            .create_policy_version(&create_param("b", &[]))
+            .await
+            .unwrap();
+        assert!(second.definitions.is_empty());
+        assert_eq!(db.count_policy_versions().await.unwrap(), 2);
+
+        db.activate_policy_version(first.policy_version_id)
+            .await
+            .unwrap();
+        db.activate_policy_version(second.policy_version_id)
+            .await
+            .unwrap();
+        let active = db.get_active_policy_version().await.unwrap().unwrap();
+        assert_eq!(active.document_hash, "b");
+
+        // Newest first.
+        let listed = db
+            .list_policy_versions(&PaginationOptions::new(0, 50))
+            .await
+            .unwrap();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].policy_version_id, second.policy_version_id);
+        assert!(!listed[1].active);
+        assert_eq!(listed[1].definitions, first.definitions);
+
+        assert!(db
+            .delete_policy_version(first.policy_version_id)
+            .await
+            .unwrap());
+        match db.get_policy_version(first.policy_version_id).await {
+            Err(DatabaseError::NotFound {
+                error: NotFoundError::PolicyVersion { version },
+            }) => assert_eq!(version, first.policy_version_id),
+            failed => unreachable!("Should not have gotten {:?}", failed),
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use crate::database::BackendDatabase;
+use chrono::{DateTime, Utc};
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/**
+ * ReadReplica is a read-only copy of the database that takes the reads off the
+ * primary.
+ *
+ * Replicas lag behind the primary, so a client that just created a version could
+ * fail to GET it. To avoid that, a response to a request that wrote carries a
+ * write marker, the time of the write, and a client that sends the marker back
+ * has its reads go to the primary for `stickiness` after the write. The window
+ * should be longer than the replica usually lags. The marker works across
+ * instances, as long as their clocks agree to well within the window.
+ *
+ * Requests without a marker, or with one outside the window, read from the
+ * replica, so other clients' writes don't move the reads to the primary.
+ */
+#[derive(Debug)]
+pub struct ReadReplica {
+    pub database: BackendDatabase,
+    pub stickiness: Duration,
+}
+
+impl ReadReplica {
+    pub fn new(database: BackendDatabase, stickiness: Duration) -> Self {
+        Self {
+            database,
+            stickiness,
+        }
+    }
+
+    /// A marker from the future, from an instance whose clock is ahead, keeps the
+    /// reads on the primary too.
+    fn is_sticky(&self) -> bool {
+        match ReadScope::last_write() {
+            Some(last_write) => (Utc::now() - last_write)
+                .to_std()
+                .map_or(true, |elapsed| elapsed < self.stickiness),
+            None => false,
+        }
+    }
+}
+
+tokio::task_local! {
+    static READ_SCOPE: ReadScope;
+}
+
+/**
+ * ReadScope is the writes the reads of one request have to see: the one the
+ * client's write marker stands for, and the ones the request made itself.
+ *
+ * Reads outside of a scope, e.g. by the background tasks, go to the replica.
+ */
+#[derive(Debug, Default)]
+pub struct ReadScope {
+    last_write: Option<DateTime<Utc>>,
+    wrote_at: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl ReadScope {
+    /// `last_write` is the write marker the client sent, if any.
+    pub fn new(last_write: Option<DateTime<Utc>>) -> Self {
+        Self {
+            last_write,
+            wrote_at: Mutex::new(None),
+        }
+    }
+
+    /// Runs `future` in this scope, returning when it last wrote, if it did.
+    pub async fn run<F: Future>(self, future: F) -> (F::Output, Option<DateTime<Utc>>) {
+        READ_SCOPE
+            .scope(self, async move {
+                let output = future.await;
+                (output, READ_SCOPE.with(|scope| scope.wrote_at()))
+            })
+            .await
+    }
+
+    /// The latest write the current request has to read, its own or the client's.
+    pub fn last_write() -> Option<DateTime<Utc>> {
+        READ_SCOPE
+            .try_with(|scope| scope.wrote_at().max(scope.last_write))
+            .ok()
+            .flatten()
+    }
+
+    fn record_write() {
+        let _ = READ_SCOPE.try_with(|scope| {
+            *scope
+                .wrote_at
+                .lock()
+                .expect("Write time lock to not be poisoned") = Some(Utc::now())
+        });
+    }
+
+    fn wrote_at(&self) -> Option<DateTime<Utc>> {
+        *self
+            .wrote_at
+            .lock()
+            .expect("Write time lock to not be poisoned")
+    }
+}
+
+impl BackendDatabase {
+    /// The database to read from, the replica unless the request has to see a write
+    /// made within its stickiness window.
+    pub fn reader(&self) -> &BackendDatabase {
+        match &self.read_replica {
+            Some(replica) if !replica.is_sticky() => &replica.database,
+            _ => self,
+        }
+    }
+
+    /// Keeps the reads of the request on the primary for the stickiness window, and
+    /// has its response carry the write marker. Called when a write finished.
+    pub fn record_write(&self) {
+        if self.read_replica.is_some() {
+            ReadScope::record_write();
+        }
+    }
+}
+
+#[cfg(test)]
+mod integ_test {
+    use super::*;
+    use crate::database::prelude::{CreateRepoParam, DatabaseError, NotFoundError, RepoParam};
+    use crate::database::Storage;
+    use crate::test_utils::*;
+
+    async fn sqlite_database() -> BackendDatabase {
+        BackendDatabase {
+            db: setup_sqlite_schema().await.unwrap(),
+            date_time_provider: test_date_time_provider(),
+            read_replica: None,
+            id_cache: Default::default(),
+        }
+    }
+
+    async fn replicated_database(stickiness: Duration) -> BackendDatabase {
+        // The replica never catches up, so a read shows where it was sent.
+        let replica = ReadReplica::new(sqlite_database().await, stickiness);
+        BackendDatabase {
+            read_replica: Some(Box::new(replica)),
+            ..sqlite_database().await
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_reads_go_to_the_replica() {
+        let database = replicated_database(Duration::ZERO).await;
+        let replica = &database.read_replica.as_ref().unwrap().database;
+        replica.create_org("only-on-the-replica").await.unwrap();
+
+        assert_eq!(database.count_orgs().await.unwrap(), 1);
+        database.create_org("example").await.unwrap();
+        match database.find_org("example").await {
+            Err(DatabaseError::NotFound {
+                error: NotFoundError::Organization { org },
+            }) => assert_eq!(org, "example"),
+            failed => unreachable!("Should not have gotten {:?}", failed),
+        }
+        assert!(database.find_org("only-on-the-replica").await.is_ok());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_reads_follow_writes_to_the_primary() {
+        let database = replicated_database(Duration::from_millis(200)).await;
+
+        let (_, marker) = ReadScope::default()
+            .run(async {
+                // Nothing was written yet, so the reads start on the replica.
+                assert_eq!(database.count_orgs().await.unwrap(), 0);
+                assert!(std::ptr::eq(
+                    database.reader(),
+                    &database.read_replica.as_ref().unwrap().database
+                ));
+
+                database.create_org("example").await.unwrap();
+                assert_eq!(
+                    database.find_org("example").await.unwrap().org_name,
+                    "example"
+                );
+                assert!(std::ptr::eq(database.reader(), &database));
+            })
+            .await;
+        assert!(marker.is_some());
+
+        let (found, wrote_at) = ReadScope::new(marker)
+            .run(database.find_org("example"))
+            .await;
+        assert!(found.is_ok());
+        assert_eq!(wrote_at, None);
+        let (found, _) = ReadScope::default().run(database.find_org("example")).await;
+        assert!(found.is_err());
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        let (found, _) = ReadScope::new(marker)
+            .run(database.find_org("example"))
+            .await;
+        assert!(found.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_write_markers_work_across_instances() {
+        let primary = sqlite_database().await;
+        let replica = sqlite_database().await;
+        let instance = || BackendDatabase {
+            db: primary.db.clone(),
+            date_time_provider: test_date_time_provider(),
+            read_replica: Some(Box::new(ReadReplica::new(
+                BackendDatabase {
+                    db: replica.db.clone(),
+                    date_time_provider: test_date_time_provider(),
+                    read_replica: None,
+                    id_cache: Default::default(),
+                },
+                Duration::from_secs(60),
+            ))),
+            id_cache: Default::default(),
+        };
+        let (first, second) = (instance(), instance());
+
+        let (created, marker) = ReadScope::default().run(first.create_org("example")).await;
+        created.unwrap();
+        assert!(marker.is_some());
+
+        for instance in [&first, &second] {
+            // Only the client that wrote has its reads sent to the primary.
+            let (found, _) = ReadScope::default().run(instance.find_org("example")).await;
+            assert!(found.is_err());
+            let (found, _) = ReadScope::new(marker)
+                .run(instance.find_org("example"))
+                .await;
+            assert_eq!(found.unwrap().org_name, "example");
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_label_updates_read_the_primary() {
+        let database = replicated_database(Duration::ZERO).await;
+        let replica = &database.read_replica.as_ref().unwrap().database;
+        let repo = RepoParam::new("example", "example-repo");
+        for (database, state) in [(&database, "released"), (replica, "draft")] {
+            database.create_org("example").await.unwrap();
+            database
+                .create_repo(
+                    &repo,
+                    CreateRepoParam {
+                        labels: vec![("release_state", state)].into(),
+                    },
+                )
+                .await
+                .unwrap();
+        }
+
+        let updated = Storage::update_repo_labels(&database, &repo, |existing| {
+            assert_eq!(existing.labels.get("release_state").unwrap(), "released");
+            Ok::<_, DatabaseError>(existing.labels.labels.clone())
+        })
+        .await
+        .unwrap();
+        assert_eq!(updated.labels.get("release_state").unwrap(), "released");
+    }
+}
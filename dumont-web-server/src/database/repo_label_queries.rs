@@ -1,41 +1,25 @@
 use crate::database::{
     entity::{self, prelude::*},
-    repo_queries::{RepoParam, RepoQueries},
-    BackendDatabase, DbResult,
+    repo_queries::{models::DbRepoModel, RepoParam, RepoQueries},
+    BackendDatabase, DatabaseError, DbResult, NotFoundError,
 };
 use async_trait::async_trait;
-use sea_orm::{entity::*, query::*};
+use chrono::{DateTime, Utc};
+use sea_orm::{entity::*, query::*, ConnectionTrait};
 use std::collections::BTreeMap;
 use tracing::info;
 use tracing_attributes::instrument;
 
 pub mod models {
     use crate::database::entity;
-    use std::collections::BTreeMap;
 
     pub type RepoLabels = crate::models::GenericLabels;
 
-    impl From<&[entity::repository_label::Model]> for RepoLabels {
-        fn from(source: &[entity::repository_label::Model]) -> Self {
-            let mut labels: BTreeMap<String, String> = Default::default();
-            for value in source.iter() {
-                labels.insert(value.label_name.to_string(), value.label_value.to_string());
-            }
-
-            Self { labels }
-        }
-    }
-
-    impl From<&Vec<entity::repository_label::Model>> for RepoLabels {
-        fn from(source: &Vec<entity::repository_label::Model>) -> Self {
-            source.as_slice().into()
-        }
-    }
-
-    impl From<Vec<entity::repository_label::Model>> for RepoLabels {
-        fn from(source: Vec<entity::repository_label::Model>) -> Self {
-            source.as_slice().into()
-        }
+    pub fn repo_labels(source: &[entity::repository_label::Model]) -> RepoLabels {
+        source
+            .iter()
+            .map(|value| (value.label_name.to_string(), value.label_value.to_string()))
+            .collect()
     }
 }
 
@@ -54,11 +38,17 @@ pub trait RepoLabelQueries {
         labels: &BTreeMap<String, String>,
     ) -> DbResult<()>;
 
-    async fn set_repo_labels(
+    /// Replaces every label of the repo with the ones `update` returns for it. The
+    /// repo and its labels are read from the primary in the same transaction as the
+    /// write, and the repo row stays locked until the transaction ends.
+    async fn update_repo_labels<F, E>(
         &self,
         repo_param: &RepoParam<'_>,
-        labels: BTreeMap<String, String>,
-    ) -> DbResult<()>;
+        update: F,
+    ) -> Result<DbRepoModel, E>
+    where
+        F: FnOnce(&DbRepoModel) -> Result<BTreeMap<String, String>, E> + Send,
+        E: From<DatabaseError> + Send;
 
     async fn get_repo_labels(&self, repo_param: &RepoParam<'_>) -> DbResult<RepoLabels>;
 
@@ -67,10 +57,10 @@ pub trait RepoLabelQueries {
         repo: &entity::repository::Model,
     ) -> DbResult<Vec<entity::repository_label::Model>>;
 
-    async fn sql_get_repo_labels_by_repo_id(
+    async fn sql_get_repo_labels_by_repo_ids(
         &self,
-        repo_id: i32,
-    ) -> DbResult<Vec<entity::repository_label::Model>>;
+        repo_ids: &[i32],
+    ) -> DbResult<BTreeMap<i32, Vec<entity::repository_label::Model>>>;
 }
 
 #[async_trait]
@@ -82,20 +72,36 @@ impl RepoLabelQueries for BackendDatabase {
             .await?;
 
         let labels = self.sql_get_repo_labels(&repo).await?;
-        Ok(labels.into())
+        Ok(repo_labels(&labels))
     }
 
-    #[instrument(skip(self))]
-    async fn set_repo_labels(
+    #[instrument(skip(self, update))]
+    async fn update_repo_labels<F, E>(
         &self,
         repo_param: &RepoParam<'_>,
-        labels: BTreeMap<String, String>,
-    ) -> DbResult<()> {
-        let repo = self
-            .sql_get_repo(repo_param.org_name, repo_param.repo_name)
-            .await?;
+        update: F,
+    ) -> Result<DbRepoModel, E>
+    where
+        F: FnOnce(&DbRepoModel) -> Result<BTreeMap<String, String>, E> + Send,
+        E: From<DatabaseError> + Send,
+    {
+        // Dropping the transaction when `update` fails rolls it back.
+        let txn = self.db.begin().await.map_err(DatabaseError::from)?;
+        let existing = lock_repo(&txn, repo_param).await?;
+        let labels = update(&existing)?;
+        write_repo_labels(
+            &txn,
+            existing.repo_id,
+            &labels,
+            self.date_time_provider.now(),
+        )
+        .await?;
+        txn.commit().await.map_err(DatabaseError::from)?;
 
-        self.sql_set_repo_labels(repo.repo_id, &labels).await
+        Ok(DbRepoModel {
+            labels: labels.into(),
+            ..existing
+        })
     }
 
     #[instrument(skip(self))]
@@ -104,36 +110,9 @@ impl RepoLabelQueries for BackendDatabase {
         repo_id: i32,
         labels: &BTreeMap<String, String>,
     ) -> DbResult<()> {
-        let mut new_labels = Vec::default();
-
-        for (key, value) in labels {
-            new_labels.push(entity::repository_label::ActiveModel {
-                repo_id: Set(repo_id),
-                label_name: Set(key.to_string()),
-                label_value: Set(value.to_string()),
-                created_at: Set(self.date_time_provider.now()),
-                ..Default::default()
-            })
-        }
-
-        let new_label_count = new_labels.len();
-
         let txn = self.db.begin().await?;
-
-        let del = RepositoryLabel::delete_many()
-            .filter(entity::repository_label::Column::RepoId.eq(repo_id))
-            .exec(&txn)
-            .await?;
-        if !new_labels.is_empty() {
-            RepositoryLabel::insert_many(new_labels).exec(&txn).await?;
-        }
-
+        write_repo_labels(&txn, repo_id, labels, self.date_time_provider.now()).await?;
         txn.commit().await?;
-
-        info!(
-            "Deleted {} rows, Inserted {} rows",
-            del.rows_affected, new_label_count
-        );
         Ok(())
     }
 
@@ -145,17 +124,100 @@ impl RepoLabelQueries for BackendDatabase {
         Ok(repo.find_related(RepositoryLabel).all(&self.db).await?)
     }
 
-    #[instrument(skip(repo_id, self))]
-    async fn sql_get_repo_labels_by_repo_id(
+    #[instrument(skip(self))]
+    async fn sql_get_repo_labels_by_repo_ids(
         &self,
-        repo_id: i32,
-    ) -> DbResult<Vec<entity::repository_label::Model>> {
+        repo_ids: &[i32],
+    ) -> DbResult<BTreeMap<i32, Vec<entity::repository_label::Model>>> {
+        let mut grouped: BTreeMap<i32, Vec<entity::repository_label::Model>> = BTreeMap::new();
+        if repo_ids.is_empty() {
+            return Ok(grouped);
+        }
+
         let labels = RepositoryLabel::find()
-            .filter(entity::repository_label::Column::RepoId.eq(repo_id))
+            .filter(entity::repository_label::Column::RepoId.is_in(repo_ids.to_vec()))
             .all(&self.db)
             .await?;
-        Ok(labels)
+
+        for label in labels {
+            grouped.entry(label.repo_id).or_default().push(label);
+        }
+
+        Ok(grouped)
+    }
+}
+
+/// Reads the repo and its labels, locking the repo row until the transaction ends.
+/// SQLite has no row locks, but it lets one transaction write at a time, so there a
+/// concurrent update waits for the database or fails as busy.
+async fn lock_repo<C: ConnectionTrait>(
+    db: &C,
+    repo_param: &RepoParam<'_>,
+) -> DbResult<DbRepoModel> {
+    let not_found = || DatabaseError::NotFound {
+        error: NotFoundError::Repo {
+            org: repo_param.org_name.to_owned(),
+            repo: repo_param.repo_name.to_owned(),
+        },
+    };
+
+    let org = Organization::find()
+        .filter(entity::organization::Column::OrgName.eq(repo_param.org_name))
+        .one(db)
+        .await?
+        .ok_or_else(|| DatabaseError::NotFound {
+            error: NotFoundError::Organization {
+                org: repo_param.org_name.to_owned(),
+            },
+        })?;
+    let repo = Repository::find()
+        .filter(
+            Condition::all()
+                .add(entity::repository::Column::OrgId.eq(org.org_id))
+                .add(entity::repository::Column::RepoName.eq(repo_param.repo_name)),
+        )
+        .lock_exclusive()
+        .one(db)
+        .await?
+        .ok_or_else(not_found)?;
+    let labels = repo.find_related(RepositoryLabel).all(db).await?;
+
+    Ok(DbRepoModel::from(&org, &repo, &labels))
+}
+
+async fn write_repo_labels<C: ConnectionTrait>(
+    db: &C,
+    repo_id: i32,
+    labels: &BTreeMap<String, String>,
+    now: DateTime<Utc>,
+) -> DbResult<()> {
+    let mut new_labels = Vec::default();
+
+    for (key, value) in labels {
+        new_labels.push(entity::repository_label::ActiveModel {
+            repo_id: Set(repo_id),
+            label_name: Set(key.to_string()),
+            label_value: Set(value.to_string()),
+            created_at: Set(now),
+            ..Default::default()
+        })
     }
+
+    let new_label_count = new_labels.len();
+
+    let del = RepositoryLabel::delete_many()
+        .filter(entity::repository_label::Column::RepoId.eq(repo_id))
+        .exec(db)
+        .await?;
+    if !new_labels.is_empty() {
+        RepositoryLabel::insert_many(new_labels).exec(db).await?;
+    }
+
+    info!(
+        "Deleted {} rows, Inserted {} rows",
+        del.rows_affected, new_label_count
+    );
+    Ok(())
 }
 
 #[cfg(test)]
@@ -173,6 +235,8 @@ mod integ_test {
         let db = BackendDatabase {
             db: setup_schema().await.unwrap(),
             date_time_provider: DateTimeProvider::RealDateTime,
+            read_replica: None,
+            id_cache: Default::default(),
         };
 
         db.create_org("foo").await.unwrap();
@@ -196,9 +260,17 @@ mod integ_test {
         let mut labels = BTreeMap::new();
         labels.insert("scm_url".to_owned(), "https://google.com".to_owned());
 
-        db.set_repo_labels(&RepoParam::new("foo", "bar"), labels)
+        let updated = db
+            .update_repo_labels(&RepoParam::new("foo", "bar"), |existing| {
+                assert_eq!(existing.labels.get("owner").unwrap(), "bobby tables");
+                Ok::<_, DatabaseError>(labels)
+            })
             .await
             .unwrap();
+        assert_eq!(
+            updated.labels.get("scm_url"),
+            Some(&"https://google.com".to_owned())
+        );
 
         let labels = db
             .get_repo_labels(&RepoParam::new("foo", "bar"))
@@ -6,9 +6,8 @@ use crate::database::{
     AlreadyExistsError, BackendDatabase, DatabaseError, DbResult, NotFoundError,
 };
 use async_trait::async_trait;
-use futures_util::future::join_all;
-use futures_util::future::TryFutureExt;
-use sea_orm::{entity::*, query::*};
+use sea_orm::{entity::*, query::*, sea_query::Query};
+use std::collections::BTreeMap;
 use tracing::info;
 use tracing_attributes::instrument;
 
@@ -18,9 +17,13 @@ pub trait DbRepo {
 }
 
 pub mod models {
-    use crate::database::{entity, org_queries::DbOrganization, prelude::RepoLabels};
+    use crate::database::{
+        entity,
+        org_queries::DbOrganization,
+        prelude::{repo_labels, RepoLabels},
+    };
 
-    #[derive(Debug, PartialEq, Eq)]
+    #[derive(Debug, Clone, PartialEq, Eq)]
     pub struct DbRepoModel {
         pub org_id: i32,
         pub org_name: String,
@@ -40,7 +43,7 @@ pub mod models {
                 org_name: org.org_name.clone(),
                 repo_id: repo.repo_id,
                 repo_name: repo.repo_name.clone(),
-                labels: labels.into(),
+                labels: repo_labels(labels),
             }
         }
     }
@@ -125,9 +128,42 @@ pub trait RepoQueries {
 
     async fn count_repos(&self, org_name: &str) -> DbResult<u64>;
 
+    async fn list_repos_with_labels(
+        &self,
+        org_name: &str,
+        labels: &BTreeMap<String, String>,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbRepoModel>>;
+
+    async fn count_repos_with_labels(
+        &self,
+        org_name: &str,
+        labels: &BTreeMap<String, String>,
+    ) -> DbResult<u64>;
+
     async fn delete_repo(&self, repo: &RepoParam<'_>) -> DbResult<bool>;
 }
 
+/// Matches the repos that have every one of `labels`.
+fn repos_with_labels(labels: &BTreeMap<String, String>) -> Condition {
+    use entity::repository_label::Column;
+
+    labels
+        .iter()
+        .fold(Condition::all(), |condition, (name, value)| {
+            condition.add(
+                entity::repository::Column::RepoId.in_subquery(
+                    Query::select()
+                        .column(Column::RepoId)
+                        .from(RepositoryLabel)
+                        .and_where(Column::LabelName.eq(name.as_str()))
+                        .and_where(Column::LabelValue.eq(value.as_str()))
+                        .to_owned(),
+                ),
+            )
+        })
+}
+
 #[async_trait]
 impl RepoQueries for BackendDatabase {
     #[instrument(skip(self))]
@@ -197,11 +233,7 @@ impl RepoQueries for BackendDatabase {
         let repo = self
             .sql_get_repo(repo_param.org_name, repo_param.repo_name)
             .await?;
-        let org = repo
-            .find_related(Organization)
-            .one(&self.db)
-            .await?
-            .expect("Org to exist for repo");
+        let org = self.sql_get_org(repo_param.org_name).await?;
         let labels = self.sql_get_repo_labels(&repo).await?;
 
         Ok(DbRepoModel::from(&org, &repo, &labels))
@@ -209,10 +241,31 @@ impl RepoQueries for BackendDatabase {
 
     #[instrument(skip(self))]
     async fn count_repos(&self, org_name: &str) -> DbResult<u64> {
+        self.count_repos_with_labels(org_name, &BTreeMap::new())
+            .await
+    }
+
+    #[instrument(skip(self))]
+    async fn list_repos(
+        &self,
+        org_name: &str,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbRepoModel>> {
+        self.list_repos_with_labels(org_name, &BTreeMap::new(), pagination)
+            .await
+    }
+
+    #[instrument(skip(self))]
+    async fn count_repos_with_labels(
+        &self,
+        org_name: &str,
+        labels: &BTreeMap<String, String>,
+    ) -> DbResult<u64> {
         use entity::repository::Column;
         let org = self.sql_get_org(org_name).await?;
         let count = org
             .find_related(Repository)
+            .filter(repos_with_labels(labels))
             .order_by_asc(Column::OrgId)
             .count(&self.db)
             .await?;
@@ -221,9 +274,10 @@ impl RepoQueries for BackendDatabase {
     }
 
     #[instrument(skip(self))]
-    async fn list_repos(
+    async fn list_repos_with_labels(
         &self,
         org_name: &str,
+        labels: &BTreeMap<String, String>,
         pagination: &PaginationOptions,
     ) -> DbResult<Vec<DbRepoModel>> {
         use entity::repository::Column;
@@ -231,40 +285,44 @@ impl RepoQueries for BackendDatabase {
         let org = self.sql_get_org(org_name).await?;
         let select = org
             .find_related(Repository)
-            .order_by_asc(Column::OrgId)
+            .filter(repos_with_labels(labels))
+            .order_by_asc(Column::RepoId)
             .paginate(&self.db, pagination.page_size)
             .fetch_page(pagination.page_number)
             .await?;
 
-        let mut future_repos = Vec::new();
-        for repo in select {
-            let repo = repo.clone();
-            let org = org.clone();
-            future_repos.push(
-                self.sql_get_repo_labels_by_repo_id(repo.repo_id)
-                    .map_ok(move |labels| DbRepoModel::from(&org, &repo, &labels)),
-            );
-        }
-
-        let resolved_futures = join_all(future_repos).await;
+        let repo_ids: Vec<i32> = select.iter().map(|repo| repo.repo_id).collect();
+        let mut labels = self.sql_get_repo_labels_by_repo_ids(&repo_ids).await?;
 
-        let mut repos = Vec::new();
-        for future in resolved_futures {
-            repos.push(future?);
-        }
+        let repos = select
+            .iter()
+            .map(|repo| {
+                let repo_labels = labels.remove(&repo.repo_id).unwrap_or_default();
+                DbRepoModel::from(&org, repo, &repo_labels)
+            })
+            .collect();
 
         Ok(repos)
     }
 
     #[instrument(skip(self))]
     async fn delete_repo(&self, repo_param: &RepoParam<'_>) -> DbResult<bool> {
+        // Not resolved through the `IdCache`, a repo of the same name that replaced
+        // the cached one is deleted too.
         let repo = self
-            .sql_get_repo(repo_param.org_name, repo_param.repo_name)
+            .sql_get_raw_repo(repo_param.org_name, repo_param.repo_name)
             .await?;
-        let repo: entity::repository::ActiveModel = repo.into();
-        let res = repo.delete(&self.db).await?;
+        let res = match repo {
+            Some(repo) => {
+                let repo: entity::repository::ActiveModel = repo.into();
+                repo.delete(&self.db).await?.rows_affected
+            }
+            None => 0,
+        };
+        self.id_cache
+            .forget_repo(repo_param.org_name, repo_param.repo_name);
 
-        if res.rows_affected == 0 {
+        if res == 0 {
             return Err(DatabaseError::NotFound {
                 error: NotFoundError::Repo {
                     org: repo_param.org_name.to_owned(),
@@ -273,7 +331,7 @@ impl RepoQueries for BackendDatabase {
             });
         }
 
-        Ok(res.rows_affected == 1)
+        Ok(res == 1)
     }
 
     async fn sql_get_raw_repo(
@@ -292,23 +350,25 @@ impl RepoQueries for BackendDatabase {
         Ok(resp)
     }
 
+    /// Resolves the repo through the `IdCache`.
     async fn sql_get_repo(
         &self,
         org_name: &str,
         repo_name: &str,
     ) -> DbResult<entity::repository::Model> {
-        let resp = self.sql_get_raw_repo(org_name, repo_name).await?;
-        match resp {
-            None => {
-                return Err(DatabaseError::NotFound {
-                    error: NotFoundError::Repo {
-                        org: org_name.to_string(),
-                        repo: repo_name.to_string(),
-                    },
-                })
-            }
-            Some(repo) => Ok(repo),
-        }
+        self.id_cache
+            .repo(org_name, repo_name, async {
+                match self.sql_get_raw_repo(org_name, repo_name).await? {
+                    None => Err(DatabaseError::NotFound {
+                        error: NotFoundError::Repo {
+                            org: org_name.to_string(),
+                            repo: repo_name.to_string(),
+                        },
+                    }),
+                    Some(repo) => Ok(repo),
+                }
+            })
+            .await
     }
 }
 
@@ -325,6 +385,8 @@ mod integ_test {
         let db = BackendDatabase {
             db: setup_schema().await.unwrap(),
             date_time_provider: DateTimeProvider::RealDateTime,
+            read_replica: None,
+            id_cache: Default::default(),
         };
 
         db.create_org("foo").await.unwrap();
@@ -400,6 +462,8 @@ mod integ_test {
         let db = BackendDatabase {
             db: setup_schema().await.unwrap(),
             date_time_provider: DateTimeProvider::RealDateTime,
+            read_replica: None,
+            id_cache: Default::default(),
         };
 
         db.create_org("foo").await.unwrap();
@@ -446,6 +510,8 @@ mod integ_test {
         let db = BackendDatabase {
             db: setup_schema().await.unwrap(),
             date_time_provider: DateTimeProvider::RealDateTime,
+            read_replica: None,
+            id_cache: Default::default(),
         };
 
         db.create_org("foo").await.unwrap();
@@ -1,10 +1,14 @@
 use crate::database::{
     entity::{self, prelude::*},
-    revision_queries::{models::RevisionParam, RevisionQueries},
-    BackendDatabase, DbResult,
+    revision_queries::{
+        models::{DbRevisionModel, RevisionParam},
+        RevisionQueries,
+    },
+    BackendDatabase, DatabaseError, DbResult, NotFoundError,
 };
 use async_trait::async_trait;
-use sea_orm::{entity::*, query::*};
+use chrono::{DateTime, Utc};
+use sea_orm::{entity::*, query::*, ConnectionTrait};
 use std::collections::BTreeMap;
 use tracing::info;
 use tracing_attributes::instrument;
@@ -16,11 +20,17 @@ use tracing_attributes::instrument;
  */
 #[async_trait]
 pub trait RevisionLabelQueries {
-    async fn set_revision_labels(
+    /// Replaces every label of the version with the ones `update` returns for it. The
+    /// version and its labels are read from the primary in the same transaction as the
+    /// write, and the version row stays locked until the transaction ends.
+    async fn update_revision_labels<F, E>(
         &self,
         revision_param: &RevisionParam<'_>,
-        labels: &BTreeMap<String, String>,
-    ) -> DbResult<()>;
+        update: F,
+    ) -> Result<DbRevisionModel, E>
+    where
+        F: FnOnce(&DbRevisionModel) -> Result<BTreeMap<String, String>, E> + Send,
+        E: From<DatabaseError> + Send;
 
     async fn sql_set_revision_labels(
         &self,
@@ -42,29 +52,23 @@ pub trait RevisionLabelQueries {
         &self,
         revision_id: i64,
     ) -> DbResult<Vec<entity::repository_revision_label::Model>>;
+
+    async fn sql_get_revision_labels_by_revision_ids(
+        &self,
+        revision_ids: &[i32],
+    ) -> DbResult<BTreeMap<i32, Vec<entity::repository_revision_label::Model>>>;
 }
 
 pub mod models {
     use crate::database::entity::{self};
-    use std::collections::BTreeMap;
 
     pub type RevisionLabels = crate::models::GenericLabels;
 
-    impl From<&Vec<entity::repository_revision_label::Model>> for RevisionLabels {
-        fn from(source: &Vec<entity::repository_revision_label::Model>) -> Self {
-            let mut labels: BTreeMap<String, String> = Default::default();
-            for value in source.iter() {
-                labels.insert(value.label_name.to_string(), value.label_value.to_string());
-            }
-
-            labels.into()
-        }
-    }
-
-    impl From<Vec<entity::repository_revision_label::Model>> for RevisionLabels {
-        fn from(source: Vec<entity::repository_revision_label::Model>) -> Self {
-            (&source).into()
-        }
+    pub fn revision_labels(source: &[entity::repository_revision_label::Model]) -> RevisionLabels {
+        source
+            .iter()
+            .map(|value| (value.label_name.to_string(), value.label_value.to_string()))
+            .collect()
     }
 }
 
@@ -97,7 +101,7 @@ impl RevisionLabelQueries for BackendDatabase {
     ) -> DbResult<RevisionLabels> {
         let revision = self.sql_get_revision(revision_param).await?;
         let labels = self.sql_get_revision_labels(&revision).await?;
-        Ok(labels.into())
+        Ok(revision_labels(&labels))
     }
 
     #[instrument(skip(self))]
@@ -106,52 +110,39 @@ impl RevisionLabelQueries for BackendDatabase {
         revision_id: i32,
         labels: &BTreeMap<String, String>,
     ) -> DbResult<()> {
-        let mut new_labels = Vec::default();
-
-        for (key, value) in labels {
-            new_labels.push(entity::repository_revision_label::ActiveModel {
-                revision_id: Set(revision_id),
-                label_name: Set(key.to_string()),
-                label_value: Set(value.to_string()),
-                created_at: Set(self.date_time_provider.now()),
-                ..Default::default()
-            })
-        }
-
-        let new_label_count = new_labels.len();
-
         let txn = self.db.begin().await?;
-
-        let del = RepositoryRevisionLabel::delete_many()
-            .filter(entity::repository_revision_label::Column::RevisionId.eq(revision_id))
-            .exec(&txn)
-            .await?;
-        if !new_labels.is_empty() {
-            RepositoryRevisionLabel::insert_many(new_labels)
-                .exec(&txn)
-                .await?;
-        }
-
+        write_revision_labels(&txn, revision_id, labels, self.date_time_provider.now()).await?;
         txn.commit().await?;
-
-        info!(
-            "Deleted {} rows, Inserted {} rows",
-            del.rows_affected, new_label_count
-        );
         Ok(())
     }
 
-    #[instrument(skip(self))]
-    async fn set_revision_labels(
+    #[instrument(skip(self, update))]
+    async fn update_revision_labels<F, E>(
         &self,
         revision_param: &RevisionParam<'_>,
-        labels: &BTreeMap<String, String>,
-    ) -> DbResult<()> {
-        let revision = self.sql_get_revision(revision_param).await?;
+        update: F,
+    ) -> Result<DbRevisionModel, E>
+    where
+        F: FnOnce(&DbRevisionModel) -> Result<BTreeMap<String, String>, E> + Send,
+        E: From<DatabaseError> + Send,
+    {
+        // Dropping the transaction when `update` fails rolls it back.
+        let txn = self.db.begin().await.map_err(DatabaseError::from)?;
+        let existing = lock_revision(&txn, revision_param).await?;
+        let labels = update(&existing)?;
+        write_revision_labels(
+            &txn,
+            existing.revision_id,
+            &labels,
+            self.date_time_provider.now(),
+        )
+        .await?;
+        txn.commit().await.map_err(DatabaseError::from)?;
 
-        self.sql_set_revision_labels(revision.revision_id, labels)
-            .await?;
-        Ok(())
+        Ok(DbRevisionModel {
+            labels: labels.into(),
+            ..existing
+        })
     }
 
     #[instrument(skip(self, revision_id))]
@@ -165,6 +156,116 @@ impl RevisionLabelQueries for BackendDatabase {
             .await?;
         Ok(labels)
     }
+
+    #[instrument(skip(self))]
+    async fn sql_get_revision_labels_by_revision_ids(
+        &self,
+        revision_ids: &[i32],
+    ) -> DbResult<BTreeMap<i32, Vec<entity::repository_revision_label::Model>>> {
+        let mut grouped: BTreeMap<i32, Vec<entity::repository_revision_label::Model>> =
+            BTreeMap::new();
+        if revision_ids.is_empty() {
+            return Ok(grouped);
+        }
+
+        let labels = RepositoryRevisionLabel::find()
+            .filter(
+                entity::repository_revision_label::Column::RevisionId.is_in(revision_ids.to_vec()),
+            )
+            .all(&self.db)
+            .await?;
+
+        for label in labels {
+            grouped.entry(label.revision_id).or_default().push(label);
+        }
+
+        Ok(grouped)
+    }
+}
+
+/// Reads the version and its labels, locking the version row until the transaction
+/// ends. Like `lock_repo`, this only serializes updates on SQLite by its single writer.
+async fn lock_revision<C: ConnectionTrait>(
+    db: &C,
+    revision_param: &RevisionParam<'_>,
+) -> DbResult<DbRevisionModel> {
+    let condition = Condition::all()
+        .add(entity::organization::Column::OrgName.eq(revision_param.org_name))
+        .add(entity::repository::Column::RepoName.eq(revision_param.repo_name))
+        .add(entity::repository_revision::Column::RevisionName.eq(revision_param.revision));
+
+    let found = RepositoryRevision::find()
+        .filter(condition)
+        .join(
+            JoinType::Join,
+            entity::repository_revision::Relation::Repository.def(),
+        )
+        .join(
+            JoinType::Join,
+            entity::repository::Relation::Organization.def(),
+        )
+        .one(db)
+        .await?;
+    // Locked by id, so that the join does not lock the repo and org rows too.
+    let revision = match found {
+        Some(found) => {
+            RepositoryRevision::find_by_id(found.revision_id)
+                .lock_exclusive()
+                .one(db)
+                .await?
+        }
+        None => None,
+    };
+    let revision = revision.ok_or_else(|| DatabaseError::NotFound {
+        error: NotFoundError::Revision {
+            org: revision_param.org_name.to_owned(),
+            repo: revision_param.repo_name.to_owned(),
+            revision: revision_param.revision.to_owned(),
+        },
+    })?;
+    let labels = revision
+        .find_related(RepositoryRevisionLabel)
+        .all(db)
+        .await?;
+
+    Ok(DbRevisionModel::from(revision, labels))
+}
+
+async fn write_revision_labels<C: ConnectionTrait>(
+    db: &C,
+    revision_id: i32,
+    labels: &BTreeMap<String, String>,
+    now: DateTime<Utc>,
+) -> DbResult<()> {
+    let mut new_labels = Vec::default();
+
+    for (key, value) in labels {
+        new_labels.push(entity::repository_revision_label::ActiveModel {
+            revision_id: Set(revision_id),
+            label_name: Set(key.to_string()),
+            label_value: Set(value.to_string()),
+            created_at: Set(now),
+            ..Default::default()
+        })
+    }
+
+    let new_label_count = new_labels.len();
+
+    let del = RepositoryRevisionLabel::delete_many()
+        .filter(entity::repository_revision_label::Column::RevisionId.eq(revision_id))
+        .exec(db)
+        .await?;
+    if !new_labels.is_empty() {
+        RepositoryRevisionLabel::insert_many(new_labels)
+            .exec(db)
+            .await?;
+    }
+
+    info!(
+        "Deleted {} rows, Inserted {} rows",
+        del.rows_affected, new_label_count
+    );
+    Ok(())
 }
 
 #[cfg(test)]
@@ -181,6 +282,8 @@ mod integ_test {
         let db = BackendDatabase {
             db: setup_schema().await.unwrap(),
             date_time_provider: DateTimeProvider::RealDateTime,
+            read_replica: None,
+            id_cache: Default::default(),
         };
 
         db.create_test_org_and_repos("foo", vec!["bar"])
@@ -212,6 +315,8 @@ mod integ_test {
         let db = BackendDatabase {
             db: setup_schema().await.unwrap(),
             date_time_provider: DateTimeProvider::RealDateTime,
+            read_replica: None,
+            id_cache: Default::default(),
         };
 
         db.create_test_org_and_repos("foo", vec!["bar"])
@@ -237,10 +342,13 @@ mod integ_test {
         assert_eq!(revision.labels.get("key").unwrap(), "value");
         assert_eq!(revision.labels.get("foo").unwrap(), "bar");
 
-        db.set_revision_labels(
-            &param,
-            &BTreeMap::from_iter(vec![("fig".to_owned(), "value".to_owned())]),
-        )
+        db.update_revision_labels(&param, |existing| {
+            assert_eq!(existing.labels.get("key").unwrap(), "value");
+            Ok::<_, DatabaseError>(BTreeMap::from_iter(vec![(
+                "fig".to_owned(),
+                "value".to_owned(),
+            )]))
+        })
         .await
         .unwrap();
 
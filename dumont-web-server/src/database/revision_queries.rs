@@ -6,7 +6,13 @@ use crate::database::{
     AlreadyExistsError, BackendDatabase, DatabaseError, DbResult, NotFoundError,
 };
 use async_trait::async_trait;
-use sea_orm::{entity::*, query::*};
+use sea_orm::{
+    entity::*,
+    query::*,
+    sea_query::{Alias, Expr, Query},
+    FromQueryResult,
+};
+use std::collections::BTreeMap;
 use tracing::info;
 use tracing_attributes::instrument;
 
@@ -46,12 +52,54 @@ pub trait RevisionQueries {
 
     async fn count_revisions(&self, repo_param: &RepoParam<'_>) -> DbResult<u64>;
 
+    /// Lists the same page of the versions with all of `labels` of each repo, keyed
+    /// by repo id. Repos without such versions are left out.
+    async fn list_revisions_of_repos(
+        &self,
+        repo_ids: &[i32],
+        labels: &BTreeMap<String, String>,
+        pagination: &PaginationOptions,
+    ) -> DbResult<BTreeMap<i32, Vec<DbRevisionModel>>>;
+
+    /// Counts the versions with all of `labels` of each repo, keyed by repo id.
+    async fn count_revisions_of_repos(
+        &self,
+        repo_ids: &[i32],
+        labels: &BTreeMap<String, String>,
+    ) -> DbResult<BTreeMap<i32, u64>>;
+
     async fn delete_revision(&self, revision_param: &RevisionParam<'_>) -> DbResult<bool>;
 }
 
+/// Matches the revisions of `repo_ids` that have every one of `labels`.
+fn revisions_with_labels(repo_ids: &[i32], labels: &BTreeMap<String, String>) -> Condition {
+    use entity::repository_revision_label::Column;
+
+    let condition = Condition::all()
+        .add(entity::repository_revision::Column::RepoId.is_in(repo_ids.iter().copied()));
+    labels.iter().fold(condition, |condition, (name, value)| {
+        condition.add(
+            entity::repository_revision::Column::RevisionId.in_subquery(
+                Query::select()
+                    .column(Column::RevisionId)
+                    .from(RepositoryRevisionLabel)
+                    .and_where(Column::LabelName.eq(name.as_str()))
+                    .and_where(Column::LabelValue.eq(value.as_str()))
+                    .to_owned(),
+            ),
+        )
+    })
+}
+
+#[derive(Debug, FromQueryResult)]
+struct RevisionCount {
+    repo_id: i32,
+    revision_count: i64,
+}
+
 pub mod models {
     use crate::database::entity::{self};
-    use crate::database::prelude::RevisionLabels;
+    use crate::database::prelude::{revision_labels, RevisionLabels};
 
     #[derive(Debug)]
     pub struct RevisionParam<'a> {
@@ -76,13 +124,14 @@ pub mod models {
         pub labels: RevisionLabels,
     }
 
-    #[derive(Debug, PartialEq, Eq)]
+    #[derive(Debug, Clone, PartialEq, Eq)]
     pub struct DbRevisionModel {
         pub repo_id: i32,
         pub revision_id: i32,
         pub revision_name: String,
         pub artifact_url: Option<String>,
         pub labels: RevisionLabels,
+        pub created_at: chrono::DateTime<chrono::Utc>,
     }
 
     impl DbRevisionModel {
@@ -95,7 +144,8 @@ pub mod models {
                 revision_id: revision.revision_id,
                 revision_name: revision.revision_name,
                 artifact_url: revision.artifact_url,
-                labels: RevisionLabels::from(&labels),
+                labels: revision_labels(&labels),
+                created_at: revision.created_at,
             }
         }
     }
@@ -203,20 +253,103 @@ impl RevisionQueries for BackendDatabase {
             .await?;
         let select = repo
             .find_related(RepositoryRevision)
-            .order_by_asc(entity::repository_revision::Column::RepoId)
+            .order_by_asc(entity::repository_revision::Column::RevisionId)
             .paginate(&self.db, pagination.page_size)
             .fetch_page(pagination.page_number)
             .await?;
 
-        let mut revisions = Vec::new();
+        let revision_ids: Vec<i32> = select.iter().map(|it| it.revision_id).collect();
+        let mut labels = self
+            .sql_get_revision_labels_by_revision_ids(&revision_ids)
+            .await?;
+
+        let revisions = select
+            .into_iter()
+            .map(|revision| {
+                let revision_labels = labels.remove(&revision.revision_id).unwrap_or_default();
+                DbRevisionModel::from(revision, revision_labels)
+            })
+            .collect();
+
+        Ok(revisions)
+    }
+
+    #[instrument(skip(self))]
+    async fn list_revisions_of_repos(
+        &self,
+        repo_ids: &[i32],
+        labels: &BTreeMap<String, String>,
+        pagination: &PaginationOptions,
+    ) -> DbResult<BTreeMap<i32, Vec<DbRevisionModel>>> {
+        use entity::repository_revision::Column;
+
+        // Numbers the versions of each repo, so the page can be cut out of every repo
+        // in the same query.
+        let rank = Alias::new("revision_rank");
+        let ranked = Query::select()
+            .column(Column::RevisionId)
+            .expr_as(
+                Expr::cust("ROW_NUMBER() OVER (PARTITION BY repo_id ORDER BY revision_id)"),
+                rank.clone(),
+            )
+            .from(RepositoryRevision)
+            .cond_where(revisions_with_labels(repo_ids, labels))
+            .to_owned();
+        let first = (pagination.page_number * pagination.page_size) as i64;
+        let on_page = Query::select()
+            .column(Column::RevisionId)
+            .from_subquery(ranked, Alias::new("ranked"))
+            .and_where(Expr::col(rank.clone()).gt(first))
+            .and_where(Expr::col(rank).lte(first + pagination.page_size as i64))
+            .to_owned();
+
+        let select = RepositoryRevision::find()
+            .filter(Column::RevisionId.in_subquery(on_page))
+            .order_by_asc(Column::RevisionId)
+            .all(&self.db)
+            .await?;
+
+        let revision_ids: Vec<i32> = select.iter().map(|it| it.revision_id).collect();
+        let mut labels = self
+            .sql_get_revision_labels_by_revision_ids(&revision_ids)
+            .await?;
+
+        let mut revisions: BTreeMap<i32, Vec<DbRevisionModel>> = BTreeMap::new();
         for revision in select {
-            let labels = self.sql_get_revision_labels(&revision).await?;
-            revisions.push(DbRevisionModel::from(revision, labels));
+            let revision_labels = labels.remove(&revision.revision_id).unwrap_or_default();
+            revisions
+                .entry(revision.repo_id)
+                .or_default()
+                .push(DbRevisionModel::from(revision, revision_labels));
         }
 
         Ok(revisions)
     }
 
+    #[instrument(skip(self))]
+    async fn count_revisions_of_repos(
+        &self,
+        repo_ids: &[i32],
+        labels: &BTreeMap<String, String>,
+    ) -> DbResult<BTreeMap<i32, u64>> {
+        use entity::repository_revision::Column;
+
+        let counts = RepositoryRevision::find()
+            .select_only()
+            .column(Column::RepoId)
+            .column_as(Expr::col(Column::RevisionId).count(), "revision_count")
+            .filter(revisions_with_labels(repo_ids, labels))
+            .group_by(Column::RepoId)
+            .into_model::<RevisionCount>()
+            .all(&self.db)
+            .await?;
+
+        Ok(counts
+            .into_iter()
+            .map(|count| (count.repo_id, count.revision_count as u64))
+            .collect())
+    }
+
     #[instrument(skip(self))]
     async fn sql_get_raw_revision(
         &self,
@@ -280,6 +413,8 @@ mod integ_test {
         let db = BackendDatabase {
             db: setup_schema().await.unwrap(),
             date_time_provider: DateTimeProvider::RealDateTime,
+            read_replica: None,
+            id_cache: Default::default(),
         };
 
         db.create_org("foo").await.unwrap();
@@ -307,6 +442,8 @@ mod integ_test {
         let db = BackendDatabase {
             db: setup_schema().await.unwrap(),
             date_time_provider: DateTimeProvider::RealDateTime,
+            read_replica: None,
+            id_cache: Default::default(),
         };
 
         db.create_org("foo").await.unwrap();
@@ -348,6 +485,8 @@ mod integ_test {
         let db = BackendDatabase {
             db: setup_schema().await.unwrap(),
             date_time_provider: DateTimeProvider::RealDateTime,
+            read_replica: None,
+            id_cache: Default::default(),
         };
 
         db.create_test_org_and_repos("example", vec!["example-repo-1"])
@@ -0,0 +1,342 @@
+use crate::backend::models::PaginationOptions;
+use crate::database::prelude::*;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::future::Future;
+
+/**
+ * Storage is everything `DefaultBackend` needs from the place the orgs, repos,
+ * versions and policy versions are kept.
+ *
+ * `BackendDatabase` keeps them in Postgres or SQLite, and `MemoryStorage` in
+ * memory. Both have to pass the suite in `database::conformance`, so they fail
+ * the same way, e.g. `NotFound` for a missing org and `AlreadyExists` for a
+ * duplicate repo.
+ */
+#[async_trait]
+pub trait Storage: Send + Sync + 'static {
+    /// The time recorded on everything the storage creates.
+    fn now(&self) -> DateTime<Utc>;
+
+    async fn create_org(&self, org_name: &str) -> DbResult<DbOrganizationModel>;
+    async fn find_org(&self, org_name: &str) -> DbResult<DbOrganizationModel>;
+    async fn list_orgs(&self, pagination: &PaginationOptions)
+        -> DbResult<Vec<DbOrganizationModel>>;
+    async fn count_orgs(&self) -> DbResult<u64>;
+    /// Fails while the org still has repos.
+    async fn delete_org(&self, org_name: &str) -> DbResult<bool>;
+
+    async fn create_repo(
+        &self,
+        repo: &RepoParam<'_>,
+        create_params: CreateRepoParam,
+    ) -> DbResult<DbRepoModel>;
+    async fn get_repo(&self, repo: &RepoParam<'_>) -> DbResult<DbRepoModel>;
+    async fn list_repos(
+        &self,
+        org_name: &str,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbRepoModel>>;
+    async fn count_repos(&self, org_name: &str) -> DbResult<u64>;
+    /// Like `list_repos`, but only the repos with every one of `labels`.
+    async fn list_repos_with_labels(
+        &self,
+        org_name: &str,
+        labels: &BTreeMap<String, String>,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbRepoModel>>;
+    async fn count_repos_with_labels(
+        &self,
+        org_name: &str,
+        labels: &BTreeMap<String, String>,
+    ) -> DbResult<u64>;
+    /// Deletes the repo along with its versions.
+    async fn delete_repo(&self, repo: &RepoParam<'_>) -> DbResult<bool>;
+    /// Replaces every label of the repo with the ones `update` returns for the stored
+    /// repo, and returns the repo as it was written. The repo is read from the primary
+    /// database, never from a replica or a cache, and stays locked until the labels are
+    /// written, so concurrent updates are each checked against the labels the one
+    /// before them wrote. Nothing is written when `update` fails.
+    async fn update_repo_labels<F, E>(
+        &self,
+        repo: &RepoParam<'_>,
+        update: F,
+    ) -> Result<DbRepoModel, E>
+    where
+        F: FnOnce(&DbRepoModel) -> Result<BTreeMap<String, String>, E> + Send,
+        E: From<DatabaseError> + Send;
+
+    async fn create_revision(
+        &self,
+        revision_param: &RevisionParam<'_>,
+        create_revision_param: &CreateRevisionParam<'_>,
+    ) -> DbResult<DbRevisionModel>;
+    async fn get_revision(&self, revision_param: &RevisionParam<'_>) -> DbResult<DbRevisionModel>;
+    async fn list_revisions(
+        &self,
+        repo_param: &RepoParam<'_>,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbRevisionModel>>;
+    async fn count_revisions(&self, repo_param: &RepoParam<'_>) -> DbResult<u64>;
+    /// Lists the same page of the versions with every one of `labels` of each repo in
+    /// `repo_ids`, keyed by repo id. Repos without such versions are left out.
+    async fn list_revisions_of_repos(
+        &self,
+        repo_ids: &[i32],
+        labels: &BTreeMap<String, String>,
+        pagination: &PaginationOptions,
+    ) -> DbResult<BTreeMap<i32, Vec<DbRevisionModel>>>;
+    /// Counts the versions with every one of `labels` of each repo in `repo_ids`, keyed
+    /// by repo id. Repos without such versions are left out.
+    async fn count_revisions_of_repos(
+        &self,
+        repo_ids: &[i32],
+        labels: &BTreeMap<String, String>,
+    ) -> DbResult<BTreeMap<i32, u64>>;
+    async fn delete_revision(&self, revision_param: &RevisionParam<'_>) -> DbResult<bool>;
+    /// Like `update_repo_labels`, for the labels of a version.
+    async fn update_revision_labels<F, E>(
+        &self,
+        revision_param: &RevisionParam<'_>,
+        update: F,
+    ) -> Result<DbRevisionModel, E>
+    where
+        F: FnOnce(&DbRevisionModel) -> Result<BTreeMap<String, String>, E> + Send,
+        E: From<DatabaseError> + Send;
+
+    /// Stores a new, inactive, policy version. Fails with `AlreadyExists` when a version
+    /// has the same `document_hash`, even if it was stored concurrently.
+    async fn create_policy_version(
+        &self,
+        create_param: &CreatePolicyVersionParam<'_>,
+    ) -> DbResult<DbPolicyVersionModel>;
+    async fn get_policy_version(&self, version: i32) -> DbResult<DbPolicyVersionModel>;
+    async fn get_active_policy_version(&self) -> DbResult<Option<DbPolicyVersionModel>>;
+    /// The version of the document with `document_hash`, if one was stored.
+    async fn find_policy_version_by_hash(
+        &self,
+        document_hash: &str,
+    ) -> DbResult<Option<DbPolicyVersionModel>>;
+    /// Lists the newest versions first.
+    async fn list_policy_versions(
+        &self,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbPolicyVersionModel>>;
+    async fn count_policy_versions(&self) -> DbResult<u64>;
+    /// Makes `version` the only active version.
+    async fn activate_policy_version(&self, version: i32) -> DbResult<DbPolicyVersionModel>;
+    async fn delete_policy_version(&self, version: i32) -> DbResult<bool>;
+}
+
+impl BackendDatabase {
+    async fn write<T, E>(&self, write: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+        let result = write.await;
+        self.record_write();
+        result
+    }
+}
+
+/// Reads go to the replica, when there is one, see `ReadReplica`.
+#[async_trait]
+impl Storage for BackendDatabase {
+    fn now(&self) -> DateTime<Utc> {
+        self.date_time_provider.now()
+    }
+
+    async fn create_org(&self, org_name: &str) -> DbResult<DbOrganizationModel> {
+        self.write(OrganizationQueries::create_org(self, org_name))
+            .await
+    }
+
+    async fn find_org(&self, org_name: &str) -> DbResult<DbOrganizationModel> {
+        OrganizationQueries::find_org(self.reader(), org_name).await
+    }
+
+    async fn list_orgs(
+        &self,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbOrganizationModel>> {
+        OrganizationQueries::list_orgs(self.reader(), pagination).await
+    }
+
+    async fn count_orgs(&self) -> DbResult<u64> {
+        OrganizationQueries::count_orgs(self.reader()).await
+    }
+
+    async fn delete_org(&self, org_name: &str) -> DbResult<bool> {
+        self.write(OrganizationQueries::delete_org(self, org_name))
+            .await
+    }
+
+    async fn create_repo(
+        &self,
+        repo: &RepoParam<'_>,
+        create_params: CreateRepoParam,
+    ) -> DbResult<DbRepoModel> {
+        self.write(RepoQueries::create_repo(self, repo, create_params))
+            .await
+    }
+
+    async fn get_repo(&self, repo: &RepoParam<'_>) -> DbResult<DbRepoModel> {
+        RepoQueries::get_repo(self.reader(), repo).await
+    }
+
+    async fn list_repos(
+        &self,
+        org_name: &str,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbRepoModel>> {
+        RepoQueries::list_repos(self.reader(), org_name, pagination).await
+    }
+
+    async fn count_repos(&self, org_name: &str) -> DbResult<u64> {
+        RepoQueries::count_repos(self.reader(), org_name).await
+    }
+
+    async fn list_repos_with_labels(
+        &self,
+        org_name: &str,
+        labels: &BTreeMap<String, String>,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbRepoModel>> {
+        RepoQueries::list_repos_with_labels(self.reader(), org_name, labels, pagination).await
+    }
+
+    async fn count_repos_with_labels(
+        &self,
+        org_name: &str,
+        labels: &BTreeMap<String, String>,
+    ) -> DbResult<u64> {
+        RepoQueries::count_repos_with_labels(self.reader(), org_name, labels).await
+    }
+
+    async fn delete_repo(&self, repo: &RepoParam<'_>) -> DbResult<bool> {
+        self.write(RepoQueries::delete_repo(self, repo)).await
+    }
+
+    async fn update_repo_labels<F, E>(
+        &self,
+        repo: &RepoParam<'_>,
+        update: F,
+    ) -> Result<DbRepoModel, E>
+    where
+        F: FnOnce(&DbRepoModel) -> Result<BTreeMap<String, String>, E> + Send,
+        E: From<DatabaseError> + Send,
+    {
+        self.write(RepoLabelQueries::update_repo_labels(self, repo, update))
+            .await
+    }
+
+    async fn create_revision(
+        &self,
+        revision_param: &RevisionParam<'_>,
+        create_revision_param: &CreateRevisionParam<'_>,
+    ) -> DbResult<DbRevisionModel> {
+        self.write(RevisionQueries::create_revision(
+            self,
+            revision_param,
+            create_revision_param,
+        ))
+        .await
+    }
+
+    async fn get_revision(&self, revision_param: &RevisionParam<'_>) -> DbResult<DbRevisionModel> {
+        RevisionQueries::get_revision(self.reader(), revision_param).await
+    }
+
+    async fn list_revisions(
+        &self,
+        repo_param: &RepoParam<'_>,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbRevisionModel>> {
+        RevisionQueries::list_revisions(self.reader(), repo_param, pagination).await
+    }
+
+    async fn count_revisions(&self, repo_param: &RepoParam<'_>) -> DbResult<u64> {
+        RevisionQueries::count_revisions(self.reader(), repo_param).await
+    }
+
+    async fn list_revisions_of_repos(
+        &self,
+        repo_ids: &[i32],
+        labels: &BTreeMap<String, String>,
+        pagination: &PaginationOptions,
+    ) -> DbResult<BTreeMap<i32, Vec<DbRevisionModel>>> {
+        RevisionQueries::list_revisions_of_repos(self.reader(), repo_ids, labels, pagination).await
+    }
+
+    async fn count_revisions_of_repos(
+        &self,
+        repo_ids: &[i32],
+        labels: &BTreeMap<String, String>,
+    ) -> DbResult<BTreeMap<i32, u64>> {
+        RevisionQueries::count_revisions_of_repos(self.reader(), repo_ids, labels).await
+    }
+
+    async fn delete_revision(&self, revision_param: &RevisionParam<'_>) -> DbResult<bool> {
+        self.write(RevisionQueries::delete_revision(self, revision_param))
+            .await
+    }
+
+    async fn update_revision_labels<F, E>(
+        &self,
+        revision_param: &RevisionParam<'_>,
+        update: F,
+    ) -> Result<DbRevisionModel, E>
+    where
+        F: FnOnce(&DbRevisionModel) -> Result<BTreeMap<String, String>, E> + Send,
+        E: From<DatabaseError> + Send,
+    {
+        self.write(RevisionLabelQueries::update_revision_labels(
+            self,
+            revision_param,
+            update,
+        ))
+        .await
+    }
+
+    async fn create_policy_version(
+        &self,
+        create_param: &CreatePolicyVersionParam<'_>,
+    ) -> DbResult<DbPolicyVersionModel> {
+        self.write(PolicyQueries::create_policy_version(self, create_param))
+            .await
+    }
+
+    async fn get_policy_version(&self, version: i32) -> DbResult<DbPolicyVersionModel> {
+        PolicyQueries::get_policy_version(self.reader(), version).await
+    }
+
+    async fn get_active_policy_version(&self) -> DbResult<Option<DbPolicyVersionModel>> {
+        PolicyQueries::get_active_policy_version(self.reader()).await
+    }
+
+    async fn find_policy_version_by_hash(
+        &self,
+        document_hash: &str,
+    ) -> DbResult<Option<DbPolicyVersionModel>> {
+        PolicyQueries::find_policy_version_by_hash(self.reader(), document_hash).await
+    }
+
+    async fn list_policy_versions(
+        &self,
+        pagination: &PaginationOptions,
+    ) -> DbResult<Vec<DbPolicyVersionModel>> {
+        PolicyQueries::list_policy_versions(self.reader(), pagination).await
+    }
+
+    async fn count_policy_versions(&self) -> DbResult<u64> {
+        PolicyQueries::count_policy_versions(self.reader()).await
+    }
+
+    async fn activate_policy_version(&self, version: i32) -> DbResult<DbPolicyVersionModel> {
+        self.write(PolicyQueries::activate_policy_version(self, version))
+            .await
+    }
+
+    async fn delete_policy_version(&self, version: i32) -> DbResult<bool> {
+        self.write(PolicyQueries::delete_policy_version(self, version))
+            .await
+    }
+}
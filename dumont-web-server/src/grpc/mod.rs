@@ -0,0 +1,432 @@
+use std::collections::{BTreeMap, HashMap};
+use tonic::{Request, Response, Status};
+use tracing::error;
+use tracing_attributes::instrument;
+
+use crate::backend::models::{
+    DataStoreOrganization, DataStoreRepository, DataStoreRevision, PaginationOptions,
+};
+use crate::backend::BackendError;
+use crate::database::DatabaseError;
+
+pub mod proto {
+    tonic::include_proto!("dumont.v1");
+}
+
+use proto::dumont_server::{Dumont, DumontServer};
+use proto::*;
+
+pub fn create_grpc_service(db: crate::Backend) -> DumontServer<GrpcBackend> {
+    DumontServer::new(GrpcBackend { db })
+}
+
+pub struct GrpcBackend {
+    db: crate::Backend,
+}
+
+/// Mirrors the HTTP status codes picked by `canned_response`, so both APIs
+/// report the same class of error for the same failure.
+impl From<BackendError> for Status {
+    fn from(error: BackendError) -> Self {
+        let message = error.to_string();
+        match error {
+            BackendError::DatabaseError { source } => match source {
+                DatabaseError::NotFound { error } => Status::not_found(error.to_string()),
+                DatabaseError::AlreadyExists { error } => Status::already_exists(error.to_string()),
+                _ => {
+                    error!("Internal Error: {}", source);
+                    Status::internal(message)
+                }
+            },
+            BackendError::ConstraintViolation { reason } => {
+                Status::invalid_argument(reason.to_string())
+            }
+            BackendError::PolicyViolation { error } => Status::invalid_argument(error.to_string()),
+        }
+    }
+}
+
+impl From<Option<Pagination>> for PaginationOptions {
+    fn from(source: Option<Pagination>) -> Self {
+        let source = source.unwrap_or_default();
+        let page_size = match source.size {
+            0 => 50,
+            size => size,
+        };
+        PaginationOptions::new(source.page as u64, page_size as u64)
+    }
+}
+
+impl From<DataStoreOrganization> for Organization {
+    fn from(source: DataStoreOrganization) -> Self {
+        Self { name: source.name }
+    }
+}
+
+impl From<DataStoreRepository> for Repository {
+    fn from(source: DataStoreRepository) -> Self {
+        Self {
+            org: source.org_name,
+            repo: source.repo_name,
+            labels: source.labels.labels.into_iter().collect(),
+        }
+    }
+}
+
+impl Version {
+    fn from_revision(org: &str, repo: &str, source: DataStoreRevision) -> Self {
+        Self {
+            org: org.to_owned(),
+            repo: repo.to_owned(),
+            version: source.version,
+            labels: source.labels.labels.into_iter().collect(),
+        }
+    }
+}
+
+fn into_labels(labels: HashMap<String, String>) -> BTreeMap<String, String> {
+    labels.into_iter().collect()
+}
+
+#[tonic::async_trait]
+impl Dumont for GrpcBackend {
+    #[instrument(name = "grpc_org_create", skip(self))]
+    async fn create_organization(
+        &self,
+        request: Request<CreateOrganizationRequest>,
+    ) -> Result<Response<Organization>, Status> {
+        let request = request.into_inner();
+        let org = self.db.create_organization(&request.org).await?;
+        Ok(Response::new(org.into()))
+    }
+
+    #[instrument(name = "grpc_org_get", skip(self))]
+    async fn get_organization(
+        &self,
+        request: Request<GetOrganizationRequest>,
+    ) -> Result<Response<Organization>, Status> {
+        let request = request.into_inner();
+        let org = self.db.get_organization(&request.org).await?;
+        Ok(Response::new(org.into()))
+    }
+
+    #[instrument(name = "grpc_org_list", skip(self))]
+    async fn list_organizations(
+        &self,
+        request: Request<ListOrganizationsRequest>,
+    ) -> Result<Response<ListOrganizationsResponse>, Status> {
+        let request = request.into_inner();
+        let org_list = self
+            .db
+            .list_organizations(request.pagination.into())
+            .await?;
+        Ok(Response::new(ListOrganizationsResponse {
+            organizations: org_list.orgs.into_iter().map(Organization::from).collect(),
+            page: Some(PageInfo {
+                total: org_list.total_count,
+                has_more: org_list.has_more,
+            }),
+        }))
+    }
+
+    #[instrument(name = "grpc_org_delete", skip(self))]
+    async fn delete_organization(
+        &self,
+        request: Request<DeleteOrganizationRequest>,
+    ) -> Result<Response<DeleteResponse>, Status> {
+        let request = request.into_inner();
+        let deleted = self.db.delete_organization(&request.org).await?;
+        Ok(Response::new(DeleteResponse { deleted }))
+    }
+
+    #[instrument(name = "grpc_repo_create", skip(self))]
+    async fn create_repository(
+        &self,
+        request: Request<CreateRepositoryRequest>,
+    ) -> Result<Response<Repository>, Status> {
+        let request = request.into_inner();
+        let repo = self
+            .db
+            .create_repo(&request.org, &request.repo, into_labels(request.labels))
+            .await?;
+        Ok(Response::new(repo.into()))
+    }
+
+    #[instrument(name = "grpc_repo_get", skip(self))]
+    async fn get_repository(
+        &self,
+        request: Request<GetRepositoryRequest>,
+    ) -> Result<Response<Repository>, Status> {
+        let request = request.into_inner();
+        let repo = self.db.get_repo(&request.org, &request.repo).await?;
+        Ok(Response::new(repo.into()))
+    }
+
+    #[instrument(name = "grpc_repo_list", skip(self))]
+    async fn list_repositories(
+        &self,
+        request: Request<ListRepositoriesRequest>,
+    ) -> Result<Response<ListRepositoriesResponse>, Status> {
+        let request = request.into_inner();
+        let repo_list = self
+            .db
+            .list_repos(&request.org, request.pagination.into())
+            .await?;
+        Ok(Response::new(ListRepositoriesResponse {
+            repositories: repo_list.repos.into_iter().map(Repository::from).collect(),
+            page: Some(PageInfo {
+                total: repo_list.total_count,
+                has_more: repo_list.has_more,
+            }),
+        }))
+    }
+
+    #[instrument(name = "grpc_repo_update", skip(self))]
+    async fn update_repository(
+        &self,
+        request: Request<UpdateRepositoryRequest>,
+    ) -> Result<Response<Repository>, Status> {
+        let request = request.into_inner();
+        let repo = self
+            .db
+            .update_repo(&request.org, &request.repo, into_labels(request.labels))
+            .await?;
+        Ok(Response::new(repo.into()))
+    }
+
+    #[instrument(name = "grpc_repo_delete", skip(self))]
+    async fn delete_repository(
+        &self,
+        request: Request<DeleteRepositoryRequest>,
+    ) -> Result<Response<DeleteResponse>, Status> {
+        let request = request.into_inner();
+        let deleted = self.db.delete_repo(&request.org, &request.repo).await?;
+        Ok(Response::new(DeleteResponse { deleted }))
+    }
+
+    #[instrument(name = "grpc_version_create", skip(self))]
+    async fn create_version(
+        &self,
+        request: Request<CreateVersionRequest>,
+    ) -> Result<Response<Version>, Status> {
+        let request = request.into_inner();
+        let version = self
+            .db
+            .create_version(
+                &request.org,
+                &request.repo,
+                &request.version,
+                into_labels(request.labels),
+            )
+            .await?;
+        Ok(Response::new(Version::from_revision(
+            &request.org,
+            &request.repo,
+            version,
+        )))
+    }
+
+    #[instrument(name = "grpc_version_get", skip(self))]
+    async fn get_version(
+        &self,
+        request: Request<GetVersionRequest>,
+    ) -> Result<Response<Version>, Status> {
+        let request = request.into_inner();
+        let version = self
+            .db
+            .get_version(&request.org, &request.repo, &request.version)
+            .await?;
+        Ok(Response::new(Version::from_revision(
+            &request.org,
+            &request.repo,
+            version,
+        )))
+    }
+
+    #[instrument(name = "grpc_version_list", skip(self))]
+    async fn list_versions(
+        &self,
+        request: Request<ListVersionsRequest>,
+    ) -> Result<Response<ListVersionsResponse>, Status> {
+        let request = request.into_inner();
+        let version_list = self
+            .db
+            .list_versions(&request.org, &request.repo, request.pagination.into())
+            .await?;
+        Ok(Response::new(ListVersionsResponse {
+            versions: version_list
+                .versions
+                .into_iter()
+                .map(|version| Version::from_revision(&request.org, &request.repo, version))
+                .collect(),
+            page: Some(PageInfo {
+                total: version_list.total_count,
+                has_more: version_list.has_more,
+            }),
+        }))
+    }
+
+    #[instrument(name = "grpc_version_update", skip(self))]
+    async fn update_version(
+        &self,
+        request: Request<UpdateVersionRequest>,
+    ) -> Result<Response<Version>, Status> {
+        let request = request.into_inner();
+        let version = self
+            .db
+            .update_version(
+                &request.org,
+                &request.repo,
+                &request.version,
+                into_labels(request.labels).into(),
+            )
+            .await?;
+        Ok(Response::new(Version::from_revision(
+            &request.org,
+            &request.repo,
+            version,
+        )))
+    }
+
+    #[instrument(name = "grpc_version_delete", skip(self))]
+    async fn delete_version(
+        &self,
+        request: Request<DeleteVersionRequest>,
+    ) -> Result<Response<DeleteResponse>, Status> {
+        let request = request.into_inner();
+        let deleted = self
+            .db
+            .delete_version(&request.org, &request.repo, &request.version)
+            .await?;
+        Ok(Response::new(DeleteResponse { deleted }))
+    }
+}
+
+#[cfg(test)]
+mod integ_test {
+    use super::*;
+    use crate::test_utils::*;
+    use serial_test::serial;
+    use tonic::Code;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn test_repo_and_version_lifecycle() {
+        let service = GrpcBackend {
+            db: make_backend().await,
+        };
+
+        service
+            .create_organization(Request::new(CreateOrganizationRequest {
+                org: "example".to_owned(),
+            }))
+            .await
+            .unwrap();
+
+        let repo = service
+            .create_repository(Request::new(CreateRepositoryRequest {
+                org: "example".to_owned(),
+                repo: "example-repo-1".to_owned(),
+                labels: HashMap::from([("owner".to_owned(), "bob".to_owned())]),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(repo.labels.get("owner"), Some(&"bob".to_owned()));
+
+        for i in 0..3 {
+            service
+                .create_version(Request::new(CreateVersionRequest {
+                    org: "example".to_owned(),
+                    repo: "example-repo-1".to_owned(),
+                    version: format!("1.2.{}", i),
+                    labels: HashMap::default(),
+                }))
+                .await
+                .unwrap();
+        }
+
+        let versions = service
+            .list_versions(Request::new(ListVersionsRequest {
+                org: "example".to_owned(),
+                repo: "example-repo-1".to_owned(),
+                pagination: Some(Pagination { page: 0, size: 2 }),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(versions.versions.len(), 2);
+        assert_eq!(
+            versions.page,
+            Some(PageInfo {
+                total: 3,
+                has_more: true
+            })
+        );
+
+        let version = service
+            .update_version(Request::new(UpdateVersionRequest {
+                org: "example".to_owned(),
+                repo: "example-repo-1".to_owned(),
+                version: "1.2.0".to_owned(),
+                labels: HashMap::from([("release_state".to_owned(), "released".to_owned())]),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            version.labels.get("release_state"),
+            Some(&"released".to_owned())
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn test_error_codes() {
+        let service = GrpcBackend {
+            db: make_backend().await,
+        };
+
+        let status = service
+            .get_organization(Request::new(GetOrganizationRequest {
+                org: "missing".to_owned(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+        assert_eq!(status.message(), "Org missing not found");
+
+        service
+            .create_organization(Request::new(CreateOrganizationRequest {
+                org: "example".to_owned(),
+            }))
+            .await
+            .unwrap();
+        let status = service
+            .create_organization(Request::new(CreateOrganizationRequest {
+                org: "example".to_owned(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::AlreadyExists);
+
+        service
+            .create_repository(Request::new(CreateRepositoryRequest {
+                org: "example".to_owned(),
+                repo: "example-repo-1".to_owned(),
+                labels: HashMap::default(),
+            }))
+            .await
+            .unwrap();
+        let status = service
+            .create_version(Request::new(CreateVersionRequest {
+                org: "example".to_owned(),
+                repo: "example-repo-1".to_owned(),
+                version: "abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyz".to_owned(),
+                labels: HashMap::default(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+}
@@ -1,52 +1,21 @@
 use crate::backend::DefaultBackend;
 use clap::{Args, Parser, Subcommand};
-use futures_util::join;
 use std::sync::Arc;
+use tracing::error;
 
 mod api;
 mod backend;
 mod database;
+mod grpc;
 mod logging;
 mod policy;
 #[cfg(test)]
 pub mod test_utils;
 
-pub type Backend = Arc<DefaultBackend>;
+pub type Backend = Arc<DefaultBackend<database::CachedStorage>>;
 
 pub mod models {
-    use serde::{Deserialize, Serialize};
-    use std::collections::BTreeMap;
-    use std::ops::Deref;
-
-    #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
-    pub struct GenericLabels {
-        #[serde(default)]
-        pub labels: BTreeMap<String, String>,
-    }
-
-    impl From<BTreeMap<String, String>> for GenericLabels {
-        fn from(source: BTreeMap<String, String>) -> Self {
-            Self { labels: source }
-        }
-    }
-
-    impl From<Vec<(&str, &str)>> for GenericLabels {
-        fn from(source: Vec<(&str, &str)>) -> Self {
-            let mut labels: BTreeMap<String, String> = Default::default();
-            for (key, value) in source {
-                labels.insert(key.to_owned(), value.to_owned());
-            }
-
-            labels.into()
-        }
-    }
-
-    impl Deref for GenericLabels {
-        type Target = BTreeMap<String, String>;
-        fn deref(&self) -> &Self::Target {
-            &self.labels
-        }
-    }
+    pub use dumont_api_models::GenericLabels;
 }
 
 #[derive(Parser, Debug)]
@@ -75,15 +44,60 @@ pub enum MainOperation {
 
 #[derive(Args, Debug)]
 pub struct RunWebServerArgs {
-    /// Database Connection String
+    /// Database Connection String, either `postgres://` or `sqlite:`
     #[clap(long = "database-url", env = "DATABASE_URL")]
     db_connection_string: String,
 
+    /// Database Connection String of a read replica. Reads are sent to it, except
+    /// the ones of clients that just wrote, see `--database-read-stickiness`.
+    #[clap(long = "database-read-url", env = "DATABASE_READ_URL")]
+    db_read_connection_string: Option<String>,
+
+    /// How long the reads of a client that sends back the `dumont-last-write`
+    /// marker of its write keep going to the primary database, in seconds, so it
+    /// can read what it wrote while the replica catches up. Should be longer than
+    /// the replica usually lags.
+    #[clap(
+        long = "database-read-stickiness",
+        env = "DATABASE_READ_STICKINESS",
+        default_value("5")
+    )]
+    db_read_stickiness: u64,
+
+    /// How many orgs, repos and versions, each, to keep in memory after they were
+    /// looked up, along with the org and repo ids. The cache is off by default, 0
+    /// keeps it off.
+    #[clap(long = "cache-size", env = "CACHE_SIZE", default_value("0"))]
+    cache_size: usize,
+
+    /// How long to keep an org, repo or version in the cache, in seconds. Changes
+    /// made through another dumont instance are only seen once the entry expires,
+    /// so with several instances keep this to a few seconds.
+    #[clap(long = "cache-ttl", env = "CACHE_TTL", default_value("5"))]
+    cache_ttl: u64,
+
     /// File that represents the policies that need to be applied to
-    /// incoming edits.
+    /// incoming edits. Each edit of the file is stored as a new policy version
+    /// and activated.
     #[clap(long = "policy")]
     policy_document: Option<String>,
 
+    /// How often to check the active policy version, or the policy document,
+    /// for changes, in seconds. Policies are also reloaded on SIGHUP. Set to 0
+    /// to disable polling.
+    #[clap(
+        long = "policy-reload-interval",
+        env = "POLICY_RELOAD_INTERVAL",
+        default_value("30")
+    )]
+    policy_reload_interval: u64,
+
+    /// How often to delete the versions expired by the retention rules of the
+    /// policies, in seconds. Off by default; with several instances, enable it
+    /// on one of them, or run `POST /admin/gc` from a scheduled job instead.
+    #[clap(long = "gc-interval", env = "GC_INTERVAL", default_value("0"))]
+    gc_interval: u64,
+
     /// Address to expose the main API on
     #[clap(
         long = "server-address",
@@ -92,18 +106,26 @@ pub struct RunWebServerArgs {
     )]
     server_address: String,
 
-    /// Address to expose the main API on
+    /// Address to expose the admin API on
     #[clap(
         long = "admin-address",
         env = "ADMIN_ADDRESS",
         default_value("127.0.0.1:3031")
     )]
     admin_address: String,
+
+    /// Address to expose the gRPC API on
+    #[clap(
+        long = "grpc-address",
+        env = "GRPC_ADDRESS",
+        default_value("127.0.0.1:3032")
+    )]
+    grpc_address: String,
 }
 
 #[derive(Args, Debug)]
 pub struct RunDatabaseMigrationsArgs {
-    /// Database Connection String
+    /// Database Connection String, either `postgres://` or `sqlite:`
     #[clap(long = "database-url", env = "DATABASE_URL")]
     db_connection_string: String,
 }
@@ -124,49 +146,146 @@ async fn main() -> Result<(), anyhow::Error> {
 }
 
 async fn run_db_migration(args: RunDatabaseMigrationsArgs) -> Result<(), anyhow::Error> {
-    use sqlx::postgres::PgPoolOptions;
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&args.db_connection_string)
-        .await?;
-
-    sqlx::migrate!().run(&pool).await?;
+    database::run_migrations(&args.db_connection_string).await?;
 
     Ok(())
 }
 
 async fn run_webserver(args: RunWebServerArgs) -> Result<(), anyhow::Error> {
-    use crate::policy::RealizedPolicyContainer;
+    use crate::policy::PolicyHandle;
     use std::net::SocketAddr;
     use warp::Filter;
 
-    let policy_container: RealizedPolicyContainer = match args.policy_document {
-        Some(path) => {
-            let file_string = std::fs::read_to_string(path)?;
-            let policy_container: crate::policy::PolicyDefinitionContainer =
-                toml::from_str(&file_string)?;
-            RealizedPolicyContainer::try_from(policy_container)?
-        }
-        None => Default::default(),
+    let policy_container = PolicyHandle::load(args.policy_document.map(Into::into))?;
+
+    let cache_options = database::CacheOptions {
+        capacity: args.cache_size,
+        time_to_live: std::time::Duration::from_secs(args.cache_ttl),
     };
+    let mut database = database::BackendDatabase::new(args.db_connection_string).await?;
+    if let Some(read_url) = args.db_read_connection_string {
+        let stickiness = std::time::Duration::from_secs(args.db_read_stickiness);
+        database = database.with_read_replica(read_url, stickiness).await?;
+    }
+    let database = database.with_id_cache(cache_options);
+    let database = database::CachedStorage::new(database, cache_options);
+    let backend = Arc::new(backend::DefaultBackend::new(database, policy_container));
 
-    let backend =
-        Arc::new(backend::DefaultBackend::new(args.db_connection_string, policy_container).await?);
+    backend.bootstrap_policies().await?;
+    spawn_policy_reloads(backend.clone(), args.policy_reload_interval)?;
+    spawn_garbage_collection(backend.clone(), args.gc_interval);
 
-    let filters = api::create_filters(backend).await;
+    let filters = api::create_filters(backend.clone()).await;
 
     let api_addr: SocketAddr = args.server_address.parse()?;
-    let api_server = warp::serve(filters).run(api_addr);
+    let (_, api_server) = api::write_markers::serve(warp::service(filters), &api_addr)?;
 
+    let status_backend = backend.clone();
     let admin_server = warp::path("metrics")
         .map(api::metrics::metrics_endpoint)
-        .or(warp::path("status").map(|| "OK"))
+        .or(warp::path("status").map(move || {
+            warp::reply::json(&serde_json::json!({
+                "status": "OK",
+                "policy_hash": status_backend.policy_container.current().hash,
+            }))
+        }))
+        .or(api::create_admin_filters(backend.clone()))
         .with(warp::trace::request());
 
     let admin_addr: SocketAddr = args.admin_address.parse()?;
-    let admin_server = warp::serve(admin_server).run(admin_addr);
+    let (_, admin_server) = api::write_markers::serve(warp::service(admin_server), &admin_addr)?;
+
+    let grpc_addr: SocketAddr = args.grpc_address.parse()?;
+    let grpc_server = tonic::transport::Server::builder()
+        .layer(tower_layer::layer_fn(api::write_markers::WriteMarkers::new))
+        .add_service(grpc::create_grpc_service(backend))
+        .serve(grpc_addr);
+
+    // The process ends when any of the servers stops serving.
+    tokio::select! {
+        result = api_server => {
+            if let Err(e) = &result {
+                error!("API server failed: {}", e);
+            }
+            Ok(result?)
+        }
+        result = admin_server => {
+            if let Err(e) = &result {
+                error!("Admin server failed: {}", e);
+            }
+            Ok(result?)
+        }
+        result = grpc_server => {
+            if let Err(e) = &result {
+                error!("gRPC server failed: {}", e);
+            }
+            Ok(result?)
+        }
+    }
+}
 
-    let (_main, _admin) = join!(api_server, admin_server);
+/// Reloads the policies on SIGHUP, and when polling finds the active policy version
+/// or the policy document changed. Failed reloads are logged, and the current
+/// policies are kept.
+fn spawn_policy_reloads(backend: Backend, interval_seconds: u64) -> Result<(), anyhow::Error> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    let signal_backend = backend.clone();
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            reload_policies(&signal_backend).await;
+        }
+    });
+
+    if interval_seconds > 0 {
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(std::time::Duration::from_secs(interval_seconds));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                reload_policies(&backend).await;
+            }
+        });
+    }
 
     Ok(())
 }
+
+/// Without a policy document or an active policy version, there is nothing to
+/// reload, which isn't logged.
+async fn reload_policies(backend: &Backend) {
+    use crate::backend::BackendError;
+    use crate::policy::PolicyError;
+
+    match backend.reload_policies().await {
+        Ok(_)
+        | Err(BackendError::PolicyViolation {
+            error: PolicyError::NoPolicyDocument,
+        }) => {}
+        Err(e) => error!(
+            "Policies were not reloaded, keeping the current ones: {}",
+            e
+        ),
+    }
+}
+
+/// Deletes expired versions every `interval_seconds`, starting one interval after the
+/// server starts. Failed runs are logged and retried on the next tick.
+fn spawn_garbage_collection(backend: Backend, interval_seconds: u64) {
+    if interval_seconds == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_seconds));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = backend.collect_garbage(false).await {
+                error!("Garbage collection failed: {}", e);
+            }
+        }
+    });
+}
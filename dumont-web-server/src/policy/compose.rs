@@ -0,0 +1,374 @@
+//! Resolves `extends` between policies and detects conflicting label definitions
+//! when the policies are loaded.
+
+use super::{PolicyDefinition, PolicyError, RequiredLabel};
+use std::collections::BTreeMap;
+
+/// Copies the labels of every policy's parents into it, so each returned policy
+/// can be evaluated on its own.
+pub(super) fn resolve_extends(
+    policies: &[PolicyDefinition],
+) -> Result<Vec<PolicyDefinition>, PolicyError> {
+    let mut by_name = BTreeMap::new();
+    for policy in policies {
+        if by_name.insert(policy.name.as_str(), policy).is_some() {
+            return Err(PolicyError::DuplicatePolicy {
+                policy_name: policy.name.clone(),
+            });
+        }
+    }
+
+    policies
+        .iter()
+        .map(|policy| resolve(policy, &by_name, &mut Vec::new()))
+        .collect()
+}
+
+fn resolve(
+    policy: &PolicyDefinition,
+    by_name: &BTreeMap<&str, &PolicyDefinition>,
+    chain: &mut Vec<String>,
+) -> Result<PolicyDefinition, PolicyError> {
+    if chain.contains(&policy.name) {
+        chain.push(policy.name.clone());
+        return Err(PolicyError::CyclicExtends {
+            policy_name: chain[0].clone(),
+            chain: chain.join(" -> "),
+        });
+    }
+
+    chain.push(policy.name.clone());
+    let mut resolved = policy.clone();
+    for parent_name in &policy.extends {
+        let parent =
+            by_name
+                .get(parent_name.as_str())
+                .ok_or_else(|| PolicyError::UnknownParentPolicy {
+                    policy_name: policy.name.clone(),
+                    parent_name: parent_name.clone(),
+                })?;
+        let parent = resolve(parent, by_name, chain)?;
+        inherit(&mut resolved, &parent)?;
+    }
+    chain.pop();
+
+    Ok(resolved)
+}
+
+fn inherit(child: &mut PolicyDefinition, parent: &PolicyDefinition) -> Result<(), PolicyError> {
+    inherit_labels(
+        &child.name,
+        &parent.name,
+        &mut child.required_repo_labels,
+        &mut child.optional_repo_labels,
+        &parent.required_repo_labels,
+        &parent.optional_repo_labels,
+    )?;
+    inherit_labels(
+        &child.name,
+        &parent.name,
+        &mut child.required_version_labels,
+        &mut child.optional_version_labels,
+        &parent.required_version_labels,
+        &parent.optional_version_labels,
+    )?;
+
+    for label_name in &parent.immutable_labels {
+        if !child.immutable_labels.contains(label_name) {
+            child.immutable_labels.push(label_name.clone());
+        }
+    }
+    child.additional_repo_labels &= parent.additional_repo_labels;
+    child.additional_version_labels &= parent.additional_version_labels;
+    if child.version_scheme.is_none() {
+        child.version_scheme = parent.version_scheme.clone();
+    }
+    if child.max_version_length.is_none() {
+        child.max_version_length = parent.max_version_length;
+    }
+    if child.retention.is_empty() {
+        child.retention = parent.retention.clone();
+    }
+
+    Ok(())
+}
+
+/// Adds the parent's labels the child doesn't define. A label both define has to be
+/// defined the same way, and be required in both or optional in both.
+fn inherit_labels(
+    child_name: &str,
+    parent_name: &str,
+    required: &mut Vec<RequiredLabel>,
+    optional: &mut Vec<RequiredLabel>,
+    parent_required: &[RequiredLabel],
+    parent_optional: &[RequiredLabel],
+) -> Result<(), PolicyError> {
+    let parent_labels = parent_required
+        .iter()
+        .map(|label| (label, true))
+        .chain(parent_optional.iter().map(|label| (label, false)));
+
+    for (label, is_required) in parent_labels {
+        let existing = required
+            .iter()
+            .map(|label| (label, true))
+            .chain(optional.iter().map(|label| (label, false)))
+            .find(|(existing, _)| existing.name == label.name);
+
+        match existing {
+            Some(existing) if existing == (label, is_required) => {}
+            Some(_) => {
+                return Err(PolicyError::ConflictingLabel {
+                    policy_name: child_name.to_owned(),
+                    other_policy_name: parent_name.to_owned(),
+                    label_name: label.name.clone(),
+                })
+            }
+            None if is_required => required.push(label.clone()),
+            None => optional.push(label.clone()),
+        }
+    }
+
+    Ok(())
+}
+
+/// When every matching policy applies, two policies defining the same label have to
+/// agree on it. Whether their patterns overlap can't be known ahead of time, so any
+/// two policies are compared.
+pub(super) fn check_conflicts(policies: &[PolicyDefinition]) -> Result<(), PolicyError> {
+    check_label_conflicts(policies, |policy| {
+        (&policy.required_repo_labels, &policy.optional_repo_labels)
+    })?;
+    check_label_conflicts(policies, |policy| {
+        (
+            &policy.required_version_labels,
+            &policy.optional_version_labels,
+        )
+    })
+}
+
+fn check_label_conflicts<F>(policies: &[PolicyDefinition], labels: F) -> Result<(), PolicyError>
+where
+    F: Fn(&PolicyDefinition) -> (&Vec<RequiredLabel>, &Vec<RequiredLabel>),
+{
+    let mut seen: BTreeMap<&str, (&str, &RequiredLabel, bool)> = BTreeMap::new();
+    for policy in policies {
+        let (required, optional) = labels(policy);
+        let policy_labels = required
+            .iter()
+            .map(|label| (label, true))
+            .chain(optional.iter().map(|label| (label, false)));
+
+        for (label, is_required) in policy_labels {
+            match seen.get(label.name.as_str()) {
+                Some((other_policy_name, other, other_required))
+                    if *other != label || *other_required != is_required =>
+                {
+                    return Err(PolicyError::ConflictingLabel {
+                        policy_name: policy.name.clone(),
+                        other_policy_name: other_policy_name.to_string(),
+                        label_name: label.name.clone(),
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    seen.insert(&label.name, (&policy.name, label, is_required));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+fn load(input: &str) -> Result<super::RealizedPolicyContainer, PolicyError> {
+    use std::convert::TryFrom;
+
+    let parsed: super::PolicyDefinitionContainer = toml::from_str(input).unwrap();
+    super::RealizedPolicyContainer::try_from(parsed)
+}
+
+#[test]
+fn extends_inherits_labels() {
+    use super::LabelChange;
+
+    let container = load(
+        r#"
+[[policy]]
+name = "base"
+repository_pattern = "example/.*"
+required_repo_labels = [{name = "owners"}]
+required_version_labels = [{name = "git_hash", immutable = true}]
+
+[[policy]]
+name = "service"
+repository_pattern = "example/.*-service"
+priority = 10
+extends = ["base"]
+required_repo_labels = []
+required_version_labels = [{name = "image_name"}]
+"#,
+    )
+    .unwrap();
+
+    // The higher priority policy is matched first, and carries the labels of `base`.
+    let mut labels = BTreeMap::from_iter(vec![("image_name".to_owned(), "clu".to_owned())]);
+    assert_eq!(
+        container
+            .execute_version_policies("example", "clu-service", "1.0.0", &BTreeMap::new(), &mut labels, LabelChange::Create)
+            .unwrap_err()
+            .to_string(),
+        "Policy `service` required that label `git_hash` be set, however it was not and no default was specified."
+    );
+
+    labels.insert("git_hash".to_owned(), "abc".to_owned());
+    assert_eq!(
+        container
+            .execute_version_policies(
+                "example",
+                "clu-service",
+                "1.0.0",
+                &BTreeMap::new(),
+                &mut labels,
+                LabelChange::Create
+            )
+            .unwrap(),
+        vec!["service"]
+    );
+}
+
+#[test]
+fn all_matching_applies_every_policy() {
+    use super::LabelChange;
+
+    let container = load(
+        r#"
+evaluation = "all_matching"
+
+[[policy]]
+name = "org"
+repository_pattern = ".*"
+required_repo_labels = [{name = "owners"}]
+required_version_labels = []
+
+[[policy]]
+name = "service"
+repository_pattern = "example/.*-service"
+required_repo_labels = [{name = "tier", one_of = ["1", "2"]}]
+required_version_labels = []
+"#,
+    )
+    .unwrap();
+
+    let mut labels = BTreeMap::from_iter(vec![("tier".to_owned(), "1".to_owned())]);
+    assert_eq!(
+        container
+            .execute_repo_policies("example", "clu-service", &mut labels, LabelChange::Create)
+            .unwrap_err()
+            .to_string(),
+        "Policy `org` required that label `owners` be set, however it was not and no default was specified."
+    );
+
+    labels.insert("owners".to_owned(), "team-a".to_owned());
+    assert_eq!(
+        container
+            .execute_repo_policies("example", "clu-service", &mut labels, LabelChange::Create)
+            .unwrap(),
+        vec!["org", "service"]
+    );
+    assert_eq!(
+        container
+            .execute_repo_policies("example", "library", &mut labels, LabelChange::Create)
+            .unwrap(),
+        vec!["org"]
+    );
+}
+
+#[test]
+fn conflicts_are_rejected_at_load() {
+    let error = load(
+        r#"
+evaluation = "all_matching"
+
+[[policy]]
+name = "org"
+repository_pattern = ".*"
+required_repo_labels = [{name = "owners"}]
+required_version_labels = []
+
+[[policy]]
+name = "service"
+repository_pattern = "example/.*-service"
+required_repo_labels = [{name = "owners", pattern = "team-[a-z]+"}]
+required_version_labels = []
+"#,
+    )
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Policies `service` and `org` define the label `owners` differently."
+    );
+
+    let error = load(
+        r#"
+[[policy]]
+name = "base"
+repository_pattern = ".*"
+required_repo_labels = []
+required_version_labels = [{name = "git_hash"}]
+
+[[policy]]
+name = "service"
+repository_pattern = ".*"
+extends = ["base"]
+required_repo_labels = []
+optional_version_labels = [{name = "git_hash"}]
+required_version_labels = []
+"#,
+    )
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Policies `service` and `base` define the label `git_hash` differently."
+    );
+}
+
+#[test]
+fn invalid_extends_are_rejected_at_load() {
+    let error = load(
+        r#"
+[[policy]]
+name = "a"
+repository_pattern = ".*"
+extends = ["b"]
+required_repo_labels = []
+required_version_labels = []
+
+[[policy]]
+name = "b"
+repository_pattern = ".*"
+extends = ["a"]
+required_repo_labels = []
+required_version_labels = []
+"#,
+    )
+    .unwrap_err();
+    assert_eq!(error.to_string(), "Policy `a` extends itself: a -> b -> a.");
+
+    let error = load(
+        r#"
+[[policy]]
+name = "a"
+repository_pattern = ".*"
+extends = ["missing"]
+required_repo_labels = []
+required_version_labels = []
+"#,
+    )
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Policy `a` extends `missing`, which is not defined."
+    );
+}
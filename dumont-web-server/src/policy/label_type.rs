@@ -0,0 +1,126 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use utoipa::ToSchema;
+
+/// The kind of value a label holds. Values are checked against the type, and
+/// rewritten into a canonical form where that doesn't change their meaning.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelType {
+    #[default]
+    String,
+    Integer,
+    Boolean,
+    Semver,
+    Url,
+    Rfc3339Timestamp,
+    Email,
+}
+
+impl fmt::Display for LabelType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LabelType::String => "string",
+            LabelType::Integer => "integer",
+            LabelType::Boolean => "boolean",
+            LabelType::Semver => "semver version",
+            LabelType::Url => "URL",
+            LabelType::Rfc3339Timestamp => "RFC 3339 timestamp",
+            LabelType::Email => "email address",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl LabelType {
+    /// Returns the canonical form of `value`, or `None` if it isn't of this type.
+    ///
+    /// Integers lose leading zeros and `+`, booleans are lowercased, and timestamps
+    /// are converted to UTC. Other types are returned unchanged.
+    pub fn normalize(&self, value: &str) -> Option<String> {
+        match self {
+            LabelType::String => Some(value.to_owned()),
+            LabelType::Integer => value.parse::<i64>().ok().map(|number| number.to_string()),
+            LabelType::Boolean => match value.to_lowercase().as_str() {
+                "true" => Some("true".to_owned()),
+                "false" => Some("false".to_owned()),
+                _ => None,
+            },
+            LabelType::Semver => semver::Version::parse(value).ok().map(|_| value.to_owned()),
+            LabelType::Url => url::Url::parse(value).ok().map(|_| value.to_owned()),
+            LabelType::Rfc3339Timestamp => DateTime::parse_from_rfc3339(value).ok().map(|time| {
+                time.with_timezone(&Utc)
+                    .to_rfc3339_opts(SecondsFormat::AutoSi, true)
+            }),
+            LabelType::Email => is_email(value).then(|| value.to_owned()),
+        }
+    }
+}
+
+/// A deliberately loose check: something before the `@`, and a dotted domain after it.
+fn is_email(value: &str) -> bool {
+    match value.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.split('.').count() > 1
+                && domain.split('.').all(|part| !part.is_empty())
+                && !value.chars().any(char::is_whitespace)
+        }
+        None => false,
+    }
+}
+
+#[test]
+fn validate_normalize() {
+    assert_eq!(
+        LabelType::String.normalize(" Any "),
+        Some(" Any ".to_owned())
+    );
+
+    assert_eq!(LabelType::Integer.normalize("+0042"), Some("42".to_owned()));
+    assert_eq!(LabelType::Integer.normalize("-7"), Some("-7".to_owned()));
+    assert_eq!(LabelType::Integer.normalize("abc"), None);
+    assert_eq!(LabelType::Integer.normalize("1.5"), None);
+
+    assert_eq!(
+        LabelType::Boolean.normalize("TRUE"),
+        Some("true".to_owned())
+    );
+    assert_eq!(
+        LabelType::Boolean.normalize("False"),
+        Some("false".to_owned())
+    );
+    assert_eq!(LabelType::Boolean.normalize("yes"), None);
+
+    assert_eq!(
+        LabelType::Semver.normalize("1.2.3-rc.1+build.5"),
+        Some("1.2.3-rc.1+build.5".to_owned())
+    );
+    assert_eq!(LabelType::Semver.normalize("1.2"), None);
+
+    assert_eq!(
+        LabelType::Url.normalize("https://example.com/clu"),
+        Some("https://example.com/clu".to_owned())
+    );
+    assert_eq!(LabelType::Url.normalize("example.com/clu"), None);
+
+    assert_eq!(
+        LabelType::Rfc3339Timestamp.normalize("2021-12-30T10:41:48-08:00"),
+        Some("2021-12-30T18:41:48Z".to_owned())
+    );
+    assert_eq!(
+        LabelType::Rfc3339Timestamp.normalize("2021-12-30T18:41:48.250Z"),
+        Some("2021-12-30T18:41:48.250Z".to_owned())
+    );
+    assert_eq!(LabelType::Rfc3339Timestamp.normalize("yesterday"), None);
+
+    assert_eq!(
+        LabelType::Email.normalize("kevin@encom.com"),
+        Some("kevin@encom.com".to_owned())
+    );
+    assert_eq!(LabelType::Email.normalize("kevin"), None);
+    assert_eq!(LabelType::Email.normalize("kevin@encom"), None);
+    assert_eq!(LabelType::Email.normalize("kevin flynn@encom.com"), None);
+}
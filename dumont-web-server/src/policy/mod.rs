@@ -1,3 +1,18 @@
+mod compose;
+mod label_type;
+mod reload;
+mod retention;
+mod selector;
+mod template;
+mod version_scheme;
+
+pub use label_type::LabelType;
+pub use reload::{policy_hash, PolicyHandle, PolicyReload};
+pub use retention::{expired_versions, RetentionCandidate, RetentionRule};
+pub use selector::{LabelSelector, LabelSelectorRequirement, SelectorOperator};
+pub use template::{Template, TemplateContext};
+pub use version_scheme::{VersionMatcher, VersionScheme};
+
 use derivative::Derivative;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
@@ -5,6 +20,7 @@ use std::collections::{BTreeMap, BTreeSet};
 use std::convert::TryFrom;
 use thiserror::Error;
 use tracing_attributes::instrument;
+use utoipa::ToSchema;
 
 #[derive(Error, Debug)]
 pub enum PolicyError {
@@ -23,20 +39,406 @@ pub enum PolicyError {
         policy_name: String,
         label_name: String,
     },
+    #[error("Policy `{policy_name}` required that label `{label_name}` be set when {condition}, however it was not and no default was specified.")]
+    ConditionalLabelNotDefined {
+        policy_name: String,
+        label_name: String,
+        condition: LabelSelector,
+    },
+    #[error("Policy `{policy_name}` required that label `{label_name}` be set, however it was not stored. Its default is only filled in when the labels are written.")]
+    LabelNotStored {
+        policy_name: String,
+        label_name: String,
+    },
     #[error("Policy `{policy_name}` required that label `{label_name}` be one of a set values, however `{value}` was not in that set.")]
     LabelNotInSet {
         policy_name: String,
         label_name: String,
         value: String,
     },
+    #[error("Policy `{policy_name}` required that label `{label_name}` be a {label_type}, however `{value}` was not.")]
+    LabelTypeMismatch {
+        policy_name: String,
+        label_name: String,
+        value: String,
+        label_type: LabelType,
+    },
+    #[error("Policy `{policy_name}` required that label `{label_name}` match the pattern `{pattern}`, however `{value}` did not.")]
+    LabelDoesNotMatchPattern {
+        policy_name: String,
+        label_name: String,
+        value: String,
+        pattern: String,
+    },
+    #[error("Policy `{policy_name}` required that label `{label_name}` be at least {min_length} characters long, however `{value}` was {length}.")]
+    LabelTooShort {
+        policy_name: String,
+        label_name: String,
+        value: String,
+        length: usize,
+        min_length: usize,
+    },
+    #[error("Policy `{policy_name}` required that label `{label_name}` be at most {max_length} characters long, however `{value}` was {length}.")]
+    LabelTooLong {
+        policy_name: String,
+        label_name: String,
+        value: String,
+        length: usize,
+        max_length: usize,
+    },
+    #[error("Policy `{policy_name}` does not allow label `{label_name}` to change from `{from}` to `{to}`.")]
+    IllegalTransition {
+        policy_name: String,
+        label_name: String,
+        from: String,
+        to: String,
+    },
+    #[error("Policy `{policy_name}` does not allow immutable label `{label_name}` to be changed or removed once set, it is `{value}`.")]
+    ImmutableLabel {
+        policy_name: String,
+        label_name: String,
+        value: String,
+    },
+    #[error("Policy `{policy_name}` does not declare the label `{label_name}`.{}", did_you_mean(.suggestion))]
+    UnknownLabel {
+        policy_name: String,
+        label_name: String,
+        suggestion: Option<String>,
+    },
+    #[error("Policy document is not valid: {error}")]
+    InvalidDocument {
+        #[from]
+        error: toml::de::Error,
+    },
+    #[error("Unable to read the policy document: {error}")]
+    ReadError {
+        #[from]
+        error: std::io::Error,
+    },
+    #[error("No policy version is active and the server was started without a policy document, so there is nothing to reload.")]
+    NoPolicyDocument,
+    #[error("Policy `{policy_name}` is defined more than once.")]
+    DuplicatePolicy { policy_name: String },
+    #[error("Policy `{policy_name}` extends `{parent_name}`, which is not defined.")]
+    UnknownParentPolicy {
+        policy_name: String,
+        parent_name: String,
+    },
+    #[error("Policy `{policy_name}` extends itself: {chain}.")]
+    CyclicExtends { policy_name: String, chain: String },
+    #[error("Policies `{policy_name}` and `{other_policy_name}` define the label `{label_name}` differently.")]
+    ConflictingLabel {
+        policy_name: String,
+        other_policy_name: String,
+        label_name: String,
+    },
+    #[error("Policy `{policy_name}` has an invalid `when` on label `{label_name}`: {reason}")]
+    InvalidCondition {
+        policy_name: String,
+        label_name: String,
+        reason: String,
+    },
+    #[error(
+        "Policy `{policy_name}` has an invalid default_value on label `{label_name}`: {reason}"
+    )]
+    InvalidDefault {
+        policy_name: String,
+        label_name: String,
+        reason: String,
+    },
+    #[error("Policy `{policy_name}` has an invalid retention rule: {reason}")]
+    InvalidRetention { policy_name: String, reason: String },
+    #[error("Policy `{policy_name}` has an invalid version_scheme: {reason}")]
+    InvalidVersionScheme { policy_name: String, reason: String },
+    #[error("Policy `{policy_name}` required that versions follow {scheme}, however `{version}` does not.")]
+    VersionSchemeMismatch {
+        policy_name: String,
+        version: String,
+        scheme: VersionScheme,
+    },
+    #[error(
+        "Version `{version}` is {length} characters long, more than the limit of {max_length}."
+    )]
+    VersionTooLong {
+        version: String,
+        length: usize,
+        max_length: usize,
+    },
+    /// More than one label broke the policies. Never holds fewer than two errors, or
+    /// another `Violations`.
+    #[error("{}", join_messages(.errors))]
+    Violations { errors: Vec<PolicyError> },
+}
+
+/// Longest version name allowed when no applied policy sets `max_version_length`.
+pub const DEFAULT_MAX_VERSION_LENGTH: usize = 30;
+
+impl PolicyError {
+    /// The policy that was violated, when the error is about a single policy.
+    pub fn policy_name(&self) -> Option<&str> {
+        match self {
+            PolicyError::DuplicateLabel { policy_name, .. }
+            | PolicyError::LabelNotDefined { policy_name, .. }
+            | PolicyError::ConditionalLabelNotDefined { policy_name, .. }
+            | PolicyError::LabelNotStored { policy_name, .. }
+            | PolicyError::LabelNotInSet { policy_name, .. }
+            | PolicyError::LabelTypeMismatch { policy_name, .. }
+            | PolicyError::LabelDoesNotMatchPattern { policy_name, .. }
+            | PolicyError::LabelTooShort { policy_name, .. }
+            | PolicyError::LabelTooLong { policy_name, .. }
+            | PolicyError::IllegalTransition { policy_name, .. }
+            | PolicyError::ImmutableLabel { policy_name, .. }
+            | PolicyError::UnknownLabel { policy_name, .. }
+            | PolicyError::DuplicatePolicy { policy_name }
+            | PolicyError::UnknownParentPolicy { policy_name, .. }
+            | PolicyError::CyclicExtends { policy_name, .. }
+            | PolicyError::ConflictingLabel { policy_name, .. }
+            | PolicyError::InvalidCondition { policy_name, .. }
+            | PolicyError::InvalidDefault { policy_name, .. }
+            | PolicyError::InvalidRetention { policy_name, .. }
+            | PolicyError::InvalidVersionScheme { policy_name, .. }
+            | PolicyError::VersionSchemeMismatch { policy_name, .. } => Some(policy_name),
+            PolicyError::RegexError { .. }
+            | PolicyError::InvalidDocument { .. }
+            | PolicyError::ReadError { .. }
+            | PolicyError::NoPolicyDocument
+            | PolicyError::VersionTooLong { .. }
+            | PolicyError::Violations { .. } => None,
+        }
+    }
+
+    /// The label the error is about, if any.
+    pub fn label_name(&self) -> Option<&str> {
+        match self {
+            PolicyError::DuplicateLabel { label_name, .. }
+            | PolicyError::LabelNotDefined { label_name, .. }
+            | PolicyError::ConditionalLabelNotDefined { label_name, .. }
+            | PolicyError::LabelNotStored { label_name, .. }
+            | PolicyError::LabelNotInSet { label_name, .. }
+            | PolicyError::LabelTypeMismatch { label_name, .. }
+            | PolicyError::LabelDoesNotMatchPattern { label_name, .. }
+            | PolicyError::LabelTooShort { label_name, .. }
+            | PolicyError::LabelTooLong { label_name, .. }
+            | PolicyError::IllegalTransition { label_name, .. }
+            | PolicyError::ImmutableLabel { label_name, .. }
+            | PolicyError::UnknownLabel { label_name, .. }
+            | PolicyError::ConflictingLabel { label_name, .. }
+            | PolicyError::InvalidCondition { label_name, .. }
+            | PolicyError::InvalidDefault { label_name, .. } => Some(label_name),
+            _ => None,
+        }
+    }
+
+    /// Fails with every error in `errors`, or succeeds when there are none.
+    pub fn from_violations(mut errors: Vec<PolicyError>) -> Result<(), PolicyError> {
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(PolicyError::Violations { errors }),
+        }
+    }
+
+    /// The single violations the error is made of.
+    pub fn violations(&self) -> &[PolicyError] {
+        match self {
+            PolicyError::Violations { errors } => errors,
+            error => std::slice::from_ref(error),
+        }
+    }
+
+    pub fn into_violations(self) -> Vec<PolicyError> {
+        match self {
+            PolicyError::Violations { errors } => errors,
+            error => vec![error],
+        }
+    }
+
+    /// Whether the error rejects labels or a version name, rather than coming from a
+    /// broken policy document.
+    pub fn is_violation(&self) -> bool {
+        match self {
+            PolicyError::LabelNotDefined { .. }
+            | PolicyError::ConditionalLabelNotDefined { .. }
+            | PolicyError::LabelNotStored { .. }
+            | PolicyError::LabelNotInSet { .. }
+            | PolicyError::LabelTypeMismatch { .. }
+            | PolicyError::LabelDoesNotMatchPattern { .. }
+            | PolicyError::LabelTooShort { .. }
+            | PolicyError::LabelTooLong { .. }
+            | PolicyError::IllegalTransition { .. }
+            | PolicyError::ImmutableLabel { .. }
+            | PolicyError::UnknownLabel { .. }
+            | PolicyError::VersionSchemeMismatch { .. }
+            | PolicyError::VersionTooLong { .. }
+            | PolicyError::Violations { .. } => true,
+            PolicyError::RegexError { .. }
+            | PolicyError::DuplicateLabel { .. }
+            | PolicyError::InvalidDocument { .. }
+            | PolicyError::ReadError { .. }
+            | PolicyError::NoPolicyDocument
+            | PolicyError::DuplicatePolicy { .. }
+            | PolicyError::UnknownParentPolicy { .. }
+            | PolicyError::CyclicExtends { .. }
+            | PolicyError::ConflictingLabel { .. }
+            | PolicyError::InvalidCondition { .. }
+            | PolicyError::InvalidDefault { .. }
+            | PolicyError::InvalidRetention { .. }
+            | PolicyError::InvalidVersionScheme { .. } => false,
+        }
+    }
+
+    /// A stable name for what went wrong, used in API responses.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PolicyError::RegexError { .. } => "invalid_regex",
+            PolicyError::DuplicateLabel { .. } => "duplicate_label",
+            PolicyError::LabelNotDefined { .. } => "label_not_defined",
+            PolicyError::ConditionalLabelNotDefined { .. } => "conditional_label_not_defined",
+            PolicyError::LabelNotStored { .. } => "label_not_stored",
+            PolicyError::LabelNotInSet { .. } => "label_not_in_set",
+            PolicyError::LabelTypeMismatch { .. } => "label_type_mismatch",
+            PolicyError::LabelDoesNotMatchPattern { .. } => "label_does_not_match_pattern",
+            PolicyError::LabelTooShort { .. } => "label_too_short",
+            PolicyError::LabelTooLong { .. } => "label_too_long",
+            PolicyError::IllegalTransition { .. } => "illegal_transition",
+            PolicyError::ImmutableLabel { .. } => "immutable_label",
+            PolicyError::UnknownLabel { .. } => "unknown_label",
+            PolicyError::InvalidDocument { .. } => "invalid_document",
+            PolicyError::ReadError { .. } => "read_error",
+            PolicyError::NoPolicyDocument => "no_policy_document",
+            PolicyError::DuplicatePolicy { .. } => "duplicate_policy",
+            PolicyError::UnknownParentPolicy { .. } => "unknown_parent_policy",
+            PolicyError::CyclicExtends { .. } => "cyclic_extends",
+            PolicyError::ConflictingLabel { .. } => "conflicting_label",
+            PolicyError::InvalidCondition { .. } => "invalid_condition",
+            PolicyError::InvalidDefault { .. } => "invalid_default",
+            PolicyError::InvalidRetention { .. } => "invalid_retention",
+            PolicyError::InvalidVersionScheme { .. } => "invalid_version_scheme",
+            PolicyError::VersionSchemeMismatch { .. } => "version_scheme_mismatch",
+            PolicyError::VersionTooLong { .. } => "version_too_long",
+            PolicyError::Violations { .. } => "violations",
+        }
+    }
+
+    /// The `kind` as an API error code, e.g. `POLICY_LABEL_NOT_IN_SET`.
+    pub fn code(&self) -> String {
+        format!("POLICY_{}", self.kind().to_uppercase())
+    }
+
+    /// The policy, label and value the error is about, when it has them.
+    pub fn details(&self) -> Vec<(&'static str, String)> {
+        [
+            ("policy", self.policy_name()),
+            ("label", self.label_name()),
+            ("value", self.value()),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|value| (key, value.to_owned())))
+        .collect()
+    }
+
+    /// The value that broke the policy, when there is one.
+    pub fn value(&self) -> Option<&str> {
+        match self {
+            PolicyError::LabelNotInSet { value, .. }
+            | PolicyError::LabelTypeMismatch { value, .. }
+            | PolicyError::LabelDoesNotMatchPattern { value, .. }
+            | PolicyError::LabelTooShort { value, .. }
+            | PolicyError::LabelTooLong { value, .. }
+            | PolicyError::IllegalTransition { to: value, .. }
+            | PolicyError::VersionSchemeMismatch { version: value, .. }
+            | PolicyError::VersionTooLong { version: value, .. } => Some(value),
+            _ => None,
+        }
+    }
+}
+
+fn join_messages(errors: &[PolicyError]) -> String {
+    errors
+        .iter()
+        .map(PolicyError::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn did_you_mean(suggestion: &Option<String>) -> String {
+    match suggestion {
+        Some(suggestion) => format!(" Did you mean `{}`?", suggestion),
+        None => String::new(),
+    }
+}
+
+/// What a set of labels is being checked for, which decides the checks that
+/// compare against the labels already stored.
+#[derive(Debug, Clone, Copy)]
+pub enum LabelChange<'a> {
+    /// The repo or version is being created, there is nothing stored yet.
+    Create,
+    /// The labels stored for the repo or version are being replaced.
+    Update {
+        existing_labels: &'a BTreeMap<String, String>,
+    },
+    /// An admin is replacing the stored labels. Immutable labels may change,
+    /// every other rule still applies.
+    Override {
+        existing_labels: &'a BTreeMap<String, String>,
+    },
+    /// Stored labels are being checked. Defaults aren't filled in, so a required
+    /// label that was never stored is reported even when it has a default.
+    Check,
+}
+
+impl<'a> LabelChange<'a> {
+    fn existing_labels(&self) -> Option<&'a BTreeMap<String, String>> {
+        match self {
+            LabelChange::Create | LabelChange::Check => None,
+            LabelChange::Update { existing_labels } | LabelChange::Override { existing_labels } => {
+                Some(existing_labels)
+            }
+        }
+    }
+
+    fn fills_defaults(&self) -> bool {
+        !matches!(self, LabelChange::Check)
+    }
+}
+
+/// How policies are picked for a repository.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyEvaluation {
+    /// Only the matching policy with the highest priority applies.
+    #[default]
+    FirstMatch,
+    /// Every matching policy applies, in priority order.
+    AllMatching,
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize, ToSchema)]
 pub struct PolicyDefinitionContainer {
+    #[serde(default)]
+    evaluation: PolicyEvaluation,
     #[serde(rename = "policy", default)]
     policies: Vec<PolicyDefinition>,
 }
 
+impl PolicyDefinitionContainer {
+    pub fn new(evaluation: PolicyEvaluation, policies: Vec<PolicyDefinition>) -> Self {
+        Self {
+            evaluation,
+            policies,
+        }
+    }
+
+    pub fn evaluation(&self) -> PolicyEvaluation {
+        self.evaluation
+    }
+
+    pub fn policies(&self) -> &[PolicyDefinition] {
+        &self.policies
+    }
+}
+
 #[test]
 fn parse_policy_container() {
     let input = r#"
@@ -44,13 +446,18 @@ fn parse_policy_container() {
 repository_pattern = "example/.*-service"
 name = "service"
 required_repo_labels = [
-  {name = "owners"}
+  {name = "owners", pattern = "team-[a-z]+"}
 ]
 required_version_labels = [
-  {name = "git_hash"},
+  {name = "git_hash", pattern = "[0-9a-f]+", min_length = 40, max_length = 40},
   {name = "image_name"},
-  {name = "release_state", one_of = ["built", "canary", "deployed", "replaced"]},
+  {name = "build_number", type = "integer"},
+  {name = "release_state", one_of = ["built", "canary", "deployed", "replaced"], transitions = { built = ["canary", "deployed"], canary = ["deployed"], deployed = ["replaced"] }},
+]
+optional_version_labels = [
+  {name = "build_url", type = "url"},
 ]
+additional_version_labels = false
 
 [[policy]]
 repository_pattern = "example/.*"
@@ -68,11 +475,19 @@ required_version_labels = [
     assert_eq!(parsed.policies[0].name, "service");
     assert_eq!(
         parsed.policies[0].required_repo_labels[0],
-        RequiredLabel::new("owners", Vec::new(), None)
+        RequiredLabel::new("owners", Vec::new(), None).with_constraints(
+            Some("team-[a-z]+"),
+            None,
+            None
+        )
     );
     assert_eq!(
         parsed.policies[0].required_version_labels[0],
-        RequiredLabel::new("git_hash", Vec::new(), None)
+        RequiredLabel::new("git_hash", Vec::new(), None).with_constraints(
+            Some("[0-9a-f]+"),
+            Some(40),
+            Some(40)
+        )
     );
     assert_eq!(
         parsed.policies[0].required_version_labels[1],
@@ -80,12 +495,28 @@ required_version_labels = [
     );
     assert_eq!(
         parsed.policies[0].required_version_labels[2],
+        RequiredLabel::new("build_number", Vec::new(), None).with_type(LabelType::Integer)
+    );
+    assert_eq!(
+        parsed.policies[0].required_version_labels[3],
         RequiredLabel::new(
             "release_state",
             vec!["built", "canary", "deployed", "replaced"],
             None
         )
+        .with_transitions(vec![
+            ("built", vec!["canary", "deployed"]),
+            ("canary", vec!["deployed"]),
+            ("deployed", vec!["replaced"]),
+        ])
+    );
+
+    assert_eq!(
+        parsed.policies[0].optional_version_labels,
+        vec![RequiredLabel::new("build_url", Vec::new(), None).with_type(LabelType::Url)]
     );
+    assert!(parsed.policies[0].additional_repo_labels);
+    assert!(!parsed.policies[0].additional_version_labels);
 
     assert_eq!(parsed.policies[1].name, "library");
     assert_eq!(
@@ -131,23 +562,90 @@ required_version_labels = [
         .contains("TOML parse error at"));
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct PolicyDefinition {
     name: String,
     repository_pattern: String,
+    /// Policies with a higher priority are matched first. Equal priorities keep file order.
+    #[serde(default)]
+    priority: i32,
+    /// Names of policies whose labels this policy inherits.
+    #[serde(default)]
+    extends: Vec<String>,
     required_repo_labels: Vec<RequiredLabel>,
     required_version_labels: Vec<RequiredLabel>,
+    #[serde(default)]
+    optional_repo_labels: Vec<RequiredLabel>,
+    #[serde(default)]
+    optional_version_labels: Vec<RequiredLabel>,
+    #[serde(default = "default_true")]
+    additional_repo_labels: bool,
+    #[serde(default = "default_true")]
+    additional_version_labels: bool,
+    #[serde(default)]
+    immutable_labels: Vec<String>,
+    /// How versions of matching repos have to be named.
+    #[serde(default)]
+    version_scheme: Option<VersionScheme>,
+    /// Longest version name allowed, `DEFAULT_MAX_VERSION_LENGTH` when not set.
+    #[serde(default)]
+    max_version_length: Option<usize>,
+    /// Rules for which versions garbage collection deletes. Without any, versions
+    /// are kept forever.
+    #[serde(default)]
+    retention: Vec<RetentionRule>,
+}
+
+impl PolicyDefinition {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+fn default_true() -> bool {
+    true
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, Derivative)]
+#[derive(Clone, Debug, Serialize, Deserialize, Derivative, ToSchema)]
 #[derivative(PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct RequiredLabel {
     name: String,
+    #[serde(default, rename = "type")]
+    label_type: LabelType,
     #[serde(default)]
     one_of: Vec<String>,
+    /// Set when the label is missing. Can be a template reading the org, repo and
+    /// version names and other labels, see docs/policy.md.
     #[serde(default)]
     default_value: Option<String>,
+    /// Allowed moves between values, keyed by the stored value. When set, a
+    /// stored value can only change to one of the values listed for it.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[schema(value_type = Object)]
+    transitions: BTreeMap<String, Vec<String>>,
+    /// Regex the whole value must match.
+    #[serde(default)]
+    pattern: Option<String>,
+    #[serde(default)]
+    min_length: Option<usize>,
+    #[serde(default)]
+    max_length: Option<usize>,
+    /// Once set, the value can not be changed or removed, except by an admin override.
+    #[serde(default)]
+    immutable: bool,
+    /// Only require the label when the other labels match the selector. When they
+    /// don't, the label is still checked if it is set, but its default isn't applied.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    when: Option<LabelSelector>,
+    /// `pattern`, compiled by `RequiredLabel::compile` when the policy is realized.
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    compiled_pattern: Option<Regex>,
+    /// `default_value`, parsed by `RequiredLabel::compile` when the policy is realized.
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    compiled_default: Option<Template>,
 }
 
 impl RequiredLabel {
@@ -156,30 +654,161 @@ impl RequiredLabel {
         let one_of: Vec<String> = one_of.into_iter().map(str::to_string).collect();
         RequiredLabel {
             name: name.to_owned(),
+            label_type: Default::default(),
             one_of,
             default_value: default_value.map(str::to_string),
+            transitions: Default::default(),
+            pattern: None,
+            min_length: None,
+            max_length: None,
+            immutable: false,
+            when: None,
+            compiled_pattern: None,
+            compiled_default: default_value.map(|default| Template::parse(default).unwrap()),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn immutable(mut self) -> Self {
+        self.immutable = true;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn when(mut self, condition: LabelSelector) -> Self {
+        self.when = Some(condition);
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_type(mut self, label_type: LabelType) -> Self {
+        self.label_type = label_type;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_constraints(
+        mut self,
+        pattern: Option<&str>,
+        min_length: Option<usize>,
+        max_length: Option<usize>,
+    ) -> Self {
+        self.pattern = pattern.map(str::to_string);
+        self.min_length = min_length;
+        self.max_length = max_length;
+        self.compile("test").unwrap()
+    }
+
+    /// Compiles `pattern` and `default_value`, so an invalid regex or template is
+    /// reported when the policy is loaded instead of when a label is checked.
+    pub fn compile(mut self, policy_name: &str) -> Result<Self, PolicyError> {
+        self.compiled_pattern = match &self.pattern {
+            Some(pattern) => Some(Regex::new(&format!("^(?:{})$", pattern))?),
+            None => None,
+        };
+        self.compiled_default =
+            match &self.default_value {
+                Some(default) => Some(Template::parse(default).map_err(|reason| {
+                    PolicyError::InvalidDefault {
+                        policy_name: policy_name.to_owned(),
+                        label_name: self.name.clone(),
+                        reason,
+                    }
+                })?),
+                None => None,
+            };
+
+        Ok(self)
+    }
+
+    /// The labels that have to be evaluated before this one, because its `when` or
+    /// its default reads them.
+    fn dependencies(&self) -> impl Iterator<Item = &str> {
+        self.when
+            .iter()
+            .flat_map(LabelSelector::keys)
+            .chain(self.compiled_default.iter().flat_map(Template::label_keys))
+    }
+
+    #[cfg(test)]
+    pub fn with_transitions(mut self, transitions: Vec<(&str, Vec<&str>)>) -> Self {
+        self.transitions = transitions
+            .into_iter()
+            .map(|(from, to)| {
+                (
+                    from.to_owned(),
+                    to.into_iter().map(str::to_string).collect(),
+                )
+            })
+            .collect();
+        self
+    }
+
+    /// Rejects leaving the label out, unless its `when` condition doesn't hold for
+    /// `all_labels`.
+    fn check_defined(
+        &self,
+        policy_name: &str,
+        all_labels: &BTreeMap<String, String>,
+    ) -> Result<(), PolicyError> {
+        if all_labels.contains_key(&self.name) {
+            return Ok(());
+        }
+
+        match &self.when {
+            Some(condition) if !condition.matches(all_labels) => Ok(()),
+            Some(condition) => Err(PolicyError::ConditionalLabelNotDefined {
+                policy_name: policy_name.to_owned(),
+                label_name: self.name.clone(),
+                condition: condition.clone(),
+            }),
+            None => Err(PolicyError::LabelNotDefined {
+                policy_name: policy_name.to_owned(),
+                label_name: self.name.clone(),
+            }),
         }
     }
+
     pub fn process_label(
         &self,
         policy_name: &str,
+        context: &TemplateContext,
         all_labels: &mut BTreeMap<String, String>,
     ) -> Result<(), PolicyError> {
         let label_name = self.name.clone();
+        let required = match &self.when {
+            Some(condition) => condition.matches(all_labels),
+            None => true,
+        };
         let value = match (all_labels.get(&label_name), &self.default_value) {
-            (None, None) => {
-                return Err(PolicyError::LabelNotDefined {
-                    policy_name: policy_name.to_owned(),
-                    label_name: label_name.clone(),
-                });
-            }
+            (None, _) if !required => return Ok(()),
+            (None, None) => return self.check_defined(policy_name, all_labels),
             (None, Some(default)) => {
-                all_labels.insert(label_name.clone(), default.to_string());
-                default.to_string()
+                let default = match &self.compiled_default {
+                    Some(template) => template.render(context, all_labels),
+                    None => default.clone(),
+                };
+                all_labels.insert(label_name.clone(), default.clone());
+                default
             }
             (Some(value), _) => value.clone(),
         };
 
+        let value = match self.label_type.normalize(&value) {
+            Some(normalized) => {
+                all_labels.insert(label_name.clone(), normalized.clone());
+                normalized
+            }
+            None => {
+                return Err(PolicyError::LabelTypeMismatch {
+                    policy_name: policy_name.to_owned(),
+                    label_name,
+                    value,
+                    label_type: self.label_type,
+                });
+            }
+        };
+
         if !self.one_of.is_empty() && !self.one_of.contains(&value) {
             return Err(PolicyError::LabelNotInSet {
                 policy_name: policy_name.to_owned(),
@@ -188,26 +817,95 @@ impl RequiredLabel {
             });
         }
 
+        let length = value.chars().count();
+        if let Some(min_length) = self.min_length {
+            if length < min_length {
+                return Err(PolicyError::LabelTooShort {
+                    policy_name: policy_name.to_owned(),
+                    label_name,
+                    value,
+                    length,
+                    min_length,
+                });
+            }
+        }
+
+        if let Some(max_length) = self.max_length {
+            if length > max_length {
+                return Err(PolicyError::LabelTooLong {
+                    policy_name: policy_name.to_owned(),
+                    label_name,
+                    value,
+                    length,
+                    max_length,
+                });
+            }
+        }
+
+        if let (Some(pattern), Some(regex)) = (&self.pattern, &self.compiled_pattern) {
+            if !regex.is_match(&value) {
+                return Err(PolicyError::LabelDoesNotMatchPattern {
+                    policy_name: policy_name.to_owned(),
+                    label_name,
+                    value,
+                    pattern: pattern.clone(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the move from the stored value to the new one against `transitions`.
+    /// Leaving the value unchanged, or setting it for the first time, is always allowed.
+    pub fn check_transition(
+        &self,
+        policy_name: &str,
+        existing_labels: &BTreeMap<String, String>,
+        new_labels: &BTreeMap<String, String>,
+    ) -> Result<(), PolicyError> {
+        if self.transitions.is_empty() {
+            return Ok(());
+        }
+
+        let (from, to) = match (existing_labels.get(&self.name), new_labels.get(&self.name)) {
+            (Some(from), Some(to)) if from != to => (from, to),
+            _ => return Ok(()),
+        };
+
+        let allowed = self
+            .transitions
+            .get(from)
+            .map(|allowed| allowed.contains(to))
+            .unwrap_or(false);
+
+        if !allowed {
+            return Err(PolicyError::IllegalTransition {
+                policy_name: policy_name.to_owned(),
+                label_name: self.name.clone(),
+                from: from.clone(),
+                to: to.clone(),
+            });
+        }
+
         Ok(())
     }
 }
 
 #[test]
 fn test_validate_label() {
-    let ut = RequiredLabel {
-        name: "test".to_owned(),
-        one_of: vec!["true".to_owned(), "false".to_owned()],
-        default_value: None,
-    };
+    let ut = RequiredLabel::new("test", vec!["true", "false"], None);
 
     let mut value = BTreeMap::from_iter(vec![("test".to_owned(), "true".to_owned())]);
-    assert!(ut.process_label("foo", &mut value).is_ok());
+    assert!(ut
+        .process_label("foo", &TemplateContext::default(), &mut value)
+        .is_ok());
 
     let mut value = BTreeMap::from_iter(vec![("test".to_owned(), "bar".to_owned())]);
-    assert_eq!(ut.process_label("foo", &mut value).unwrap_err().to_string(), "Policy `foo` required that label `test` be one of a set values, however `bar` was not in that set.");
+    assert_eq!(ut.process_label("foo", &TemplateContext::default(), &mut value).unwrap_err().to_string(), "Policy `foo` required that label `test` be one of a set values, however `bar` was not in that set.");
 
     let mut value = BTreeMap::default();
-    assert_eq!(ut.process_label("foo", &mut value).unwrap_err().to_string(), "Policy `foo` required that label `test` be set, however it was not and no default was specified.");
+    assert_eq!(ut.process_label("foo", &TemplateContext::default(), &mut value).unwrap_err().to_string(), "Policy `foo` required that label `test` be set, however it was not and no default was specified.");
 }
 
 #[test]
@@ -215,108 +913,501 @@ fn test_will_set_default_label() {
     let ut = RequiredLabel::new("test", vec!["true", "false"], Some("bar"));
 
     let mut value = BTreeMap::default();
-    assert_eq!(ut.process_label("foo", &mut value).unwrap_err().to_string(), "Policy `foo` required that label `test` be one of a set values, however `bar` was not in that set.");
+    assert_eq!(ut.process_label("foo", &TemplateContext::default(), &mut value).unwrap_err().to_string(), "Policy `foo` required that label `test` be one of a set values, however `bar` was not in that set.");
 
-    let ut = RequiredLabel {
-        name: "test".to_owned(),
-        one_of: vec!["true".to_owned(), "false".to_owned()],
-        default_value: Some("true".to_owned()),
-    };
+    let ut = RequiredLabel::new("test", vec!["true", "false"], Some("true"));
 
     let mut value = BTreeMap::default();
-    assert!(ut.process_label("foo", &mut value).is_ok());
+    assert!(ut
+        .process_label("foo", &TemplateContext::default(), &mut value)
+        .is_ok());
     assert_eq!(value.get("test"), Some(&"true".to_owned()));
 }
 
-#[derive(Debug, Default, Serialize)]
-pub struct RealizedPolicyContainer {
-    pub policies: Vec<RealizedPolicy>,
+#[test]
+fn test_validate_label_constraints() {
+    let git_hash = RequiredLabel::new("git_hash", vec![], None).with_constraints(
+        Some("[0-9a-f]+"),
+        Some(40),
+        Some(40),
+    );
+    let labels = |value: &str| BTreeMap::from_iter(vec![("git_hash".to_owned(), value.to_owned())]);
+
+    assert!(git_hash
+        .process_label(
+            "foo",
+            &TemplateContext::default(),
+            &mut labels("0123456789abcdef0123456789abcdef01234567")
+        )
+        .is_ok());
+    assert_eq!(
+        git_hash.process_label("foo", &TemplateContext::default(), &mut labels("abc123")).unwrap_err().to_string(),
+        "Policy `foo` required that label `git_hash` be at least 40 characters long, however `abc123` was 6."
+    );
+    assert_eq!(
+        git_hash
+            .process_label("foo", &TemplateContext::default(), &mut labels("0123456789abcdef0123456789abcdef012345678"))
+            .unwrap_err()
+            .to_string(),
+        "Policy `foo` required that label `git_hash` be at most 40 characters long, however `0123456789abcdef0123456789abcdef012345678` was 41."
+    );
+    assert_eq!(
+        git_hash
+            .process_label("foo", &TemplateContext::default(), &mut labels("0123456789ABCDEF0123456789abcdef01234567"))
+            .unwrap_err()
+            .to_string(),
+        "Policy `foo` required that label `git_hash` match the pattern `[0-9a-f]+`, however `0123456789ABCDEF0123456789abcdef01234567` did not."
+    );
+
+    // The pattern has to match the whole value, not just part of it.
+    let owners = RequiredLabel::new("owners", vec![], None).with_constraints(
+        Some("team-[a-z]+"),
+        None,
+        None,
+    );
+    let labels = |value: &str| BTreeMap::from_iter(vec![("owners".to_owned(), value.to_owned())]);
+    assert!(owners
+        .process_label("foo", &TemplateContext::default(), &mut labels("team-tron"))
+        .is_ok());
+    assert!(owners
+        .process_label(
+            "foo",
+            &TemplateContext::default(),
+            &mut labels("team-tron|flynn")
+        )
+        .is_err());
+    assert!(owners
+        .process_label(
+            "foo",
+            &TemplateContext::default(),
+            &mut labels("my-team-tron")
+        )
+        .is_err());
 }
 
-impl RealizedPolicyContainer {
-    pub fn execute_repo_policies(
-        &self,
-        org: &str,
-        repo: &str,
-        labels: &mut BTreeMap<String, String>,
-    ) -> Result<(), PolicyError> {
-        let repo_path = format!("{}/{}", org, repo);
-        for policy in &self.policies {
-            if policy.policy_matches_repo(&repo_path) {
-                policy.process_repo_labels(labels)?;
-                break;
-            }
-        }
+#[test]
+fn test_validate_label_type() {
+    let ut = RequiredLabel::new("released_at", vec![], None).with_type(LabelType::Rfc3339Timestamp);
 
-        Ok(())
-    }
+    let mut value = BTreeMap::from_iter(vec![(
+        "released_at".to_owned(),
+        "2021-12-30T10:41:48-08:00".to_owned(),
+    )]);
+    assert!(ut
+        .process_label("foo", &TemplateContext::default(), &mut value)
+        .is_ok());
+    assert_eq!(
+        value.get("released_at"),
+        Some(&"2021-12-30T18:41:48Z".to_owned())
+    );
 
-    pub fn execute_version_policies(
-        &self,
-        org: &str,
-        repo: &str,
-        labels: &mut BTreeMap<String, String>,
-    ) -> Result<(), PolicyError> {
-        let repo_path = format!("{}/{}", org, repo);
-        for policy in &self.policies {
-            if policy.policy_matches_repo(&repo_path) {
-                policy.process_version_labels(labels)?;
-                break;
-            }
-        }
+    let mut value = BTreeMap::from_iter(vec![("released_at".to_owned(), "yesterday".to_owned())]);
+    assert_eq!(ut.process_label("foo", &TemplateContext::default(), &mut value).unwrap_err().to_string(), "Policy `foo` required that label `released_at` be a RFC 3339 timestamp, however `yesterday` was not.");
+
+    // Values are normalized before being compared against `one_of`.
+    let ut = RequiredLabel::new("enabled", vec!["true"], None).with_type(LabelType::Boolean);
+    let mut value = BTreeMap::from_iter(vec![("enabled".to_owned(), "TRUE".to_owned())]);
+    assert!(ut
+        .process_label("foo", &TemplateContext::default(), &mut value)
+        .is_ok());
+    assert_eq!(value.get("enabled"), Some(&"true".to_owned()));
+}
+
+#[test]
+fn invalid_label_pattern_fails_to_load() {
+    let input = r#"
+[[policy]]
+repository_pattern = "example/.*"
+name = "library"
+required_repo_labels = [
+  {name = "owners", pattern = "team-[a-z"}
+]
+required_version_labels = []
+"#;
+
+    let parsed: PolicyDefinitionContainer = toml::from_str(input).unwrap();
+    let error = RealizedPolicyContainer::try_from(parsed).unwrap_err();
+    assert!(matches!(error, PolicyError::RegexError { .. }));
+}
+
+#[test]
+fn test_validate_transition() {
+    let ut = RequiredLabel::new("release_state", vec![], None).with_transitions(vec![
+        ("built", vec!["canary", "deployed"]),
+        ("canary", vec!["deployed"]),
+        ("deployed", vec!["replaced"]),
+    ]);
+
+    let labels =
+        |value: &str| BTreeMap::from_iter(vec![("release_state".to_owned(), value.to_owned())]);
+
+    assert!(ut
+        .check_transition("foo", &labels("built"), &labels("canary"))
+        .is_ok());
+    assert!(ut
+        .check_transition("foo", &labels("canary"), &labels("canary"))
+        .is_ok());
+    assert!(ut
+        .check_transition("foo", &BTreeMap::default(), &labels("canary"))
+        .is_ok());
+    assert_eq!(
+        ut.check_transition("foo", &labels("replaced"), &labels("canary"))
+            .unwrap_err()
+            .to_string(),
+        "Policy `foo` does not allow label `release_state` to change from `replaced` to `canary`."
+    );
+    assert_eq!(
+        ut.check_transition("foo", &labels("deployed"), &labels("built"))
+            .unwrap_err()
+            .to_string(),
+        "Policy `foo` does not allow label `release_state` to change from `deployed` to `built`."
+    );
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct RealizedPolicyContainer {
+    pub evaluation: PolicyEvaluation,
+    /// Sorted by priority, highest first.
+    pub policies: Vec<RealizedPolicy>,
+}
+
+impl RealizedPolicyContainer {
+    /// Returns the names of the policies that were applied.
+    pub fn execute_repo_policies(
+        &self,
+        org: &str,
+        repo: &str,
+        labels: &mut BTreeMap<String, String>,
+        change: LabelChange,
+    ) -> Result<Vec<String>, PolicyError> {
+        let context = TemplateContext {
+            org,
+            repo,
+            ..Default::default()
+        };
+        self.execute_policies(LabelKind::Repo, &context, labels, change)
+    }
+
+    /// Returns the names of the policies that were applied. `repo_labels` are the labels
+    /// of the version's repo, which default templates can read.
+    pub fn execute_version_policies(
+        &self,
+        org: &str,
+        repo: &str,
+        version: &str,
+        repo_labels: &BTreeMap<String, String>,
+        labels: &mut BTreeMap<String, String>,
+        change: LabelChange,
+    ) -> Result<Vec<String>, PolicyError> {
+        let context = TemplateContext {
+            org,
+            repo,
+            version: Some(version),
+            repo_labels: Some(repo_labels),
+        };
+        self.execute_policies(LabelKind::Version, &context, labels, change)
+    }
+
+    /// Evaluates stored repo labels, without changing them. Unlike a create, missing
+    /// labels aren't defaulted, see `LabelChange::Check`.
+    pub fn check_repo_labels(
+        &self,
+        org: &str,
+        repo: &str,
+        labels: &BTreeMap<String, String>,
+    ) -> Result<Vec<String>, PolicyError> {
+        let mut labels = labels.clone();
+        self.execute_repo_policies(org, repo, &mut labels, LabelChange::Check)
+    }
+
+    /// Evaluates stored version labels, without changing them. Unlike a create, missing
+    /// labels aren't defaulted, see `LabelChange::Check`.
+    pub fn check_version_labels(
+        &self,
+        org: &str,
+        repo: &str,
+        version: &str,
+        repo_labels: &BTreeMap<String, String>,
+        labels: &BTreeMap<String, String>,
+    ) -> Result<Vec<String>, PolicyError> {
+        let mut labels = labels.clone();
+        self.execute_version_policies(
+            org,
+            repo,
+            version,
+            repo_labels,
+            &mut labels,
+            LabelChange::Check,
+        )
+    }
+
+    /// Checks the name of a new version against the applied policies' version schemes,
+    /// and the smallest `max_version_length` among them.
+    pub fn check_version_name(
+        &self,
+        org: &str,
+        repo: &str,
+        version: &str,
+    ) -> Result<(), PolicyError> {
+        let matching = self.matching_policies(org, repo);
+
+        let max_length = matching
+            .iter()
+            .filter_map(|policy| policy.max_version_length)
+            .min()
+            .unwrap_or(DEFAULT_MAX_VERSION_LENGTH);
+        let length = version.chars().count();
+        if length > max_length {
+            return Err(PolicyError::VersionTooLong {
+                version: version.to_owned(),
+                length,
+                max_length,
+            });
+        }
+
+        for policy in matching {
+            match &policy.version_scheme {
+                Some(scheme) if !scheme.matches(version) => {
+                    return Err(PolicyError::VersionSchemeMismatch {
+                        policy_name: policy.name.clone(),
+                        version: version.to_owned(),
+                        scheme: scheme.scheme().clone(),
+                    })
+                }
+                _ => {}
+            }
+        }
 
         Ok(())
     }
+
+    /// The version scheme versions of the repo are ordered by, from the first applied
+    /// policy that has one.
+    pub fn version_scheme(&self, org: &str, repo: &str) -> Option<&VersionMatcher> {
+        self.matching_policies(org, repo)
+            .into_iter()
+            .find_map(|policy| policy.version_scheme.as_ref())
+    }
+
+    /// The retention rules of the applied policies, with the name of the policy each
+    /// came from.
+    pub fn retention_rules(&self, org: &str, repo: &str) -> Vec<(&str, &RetentionRule)> {
+        self.matching_policies(org, repo)
+            .into_iter()
+            .flat_map(|policy| {
+                policy
+                    .retention
+                    .iter()
+                    .map(move |rule| (policy.name.as_str(), rule))
+            })
+            .collect()
+    }
+
+    fn matching_policies(&self, org: &str, repo: &str) -> Vec<&RealizedPolicy> {
+        let repo_path = format!("{}/{}", org, repo);
+        let mut matching = self
+            .policies
+            .iter()
+            .filter(|policy| policy.policy_matches_repo(&repo_path));
+        match self.evaluation {
+            PolicyEvaluation::FirstMatch => matching.next().into_iter().collect(),
+            PolicyEvaluation::AllMatching => matching.collect(),
+        }
+    }
+
+    fn execute_policies(
+        &self,
+        kind: LabelKind,
+        context: &TemplateContext,
+        labels: &mut BTreeMap<String, String>,
+        change: LabelChange,
+    ) -> Result<Vec<String>, PolicyError> {
+        let matching = self.matching_policies(context.org, context.repo);
+
+        // A closed schema accepts the labels declared by any of the applied policies.
+        let known_labels: BTreeSet<&str> = matching
+            .iter()
+            .flat_map(|policy| policy.declared_labels(kind))
+            .collect();
+        let mut errors = Vec::new();
+        for policy in &matching {
+            if let Err(e) = policy.process_labels(kind, context, labels, change, &known_labels) {
+                errors.extend(e.into_violations());
+            }
+        }
+        PolicyError::from_violations(errors)?;
+
+        Ok(matching.iter().map(|policy| policy.name.clone()).collect())
+    }
 }
 
 impl TryFrom<PolicyDefinitionContainer> for RealizedPolicyContainer {
     type Error = PolicyError;
 
     fn try_from(container: PolicyDefinitionContainer) -> Result<Self, Self::Error> {
+        let mut definitions = compose::resolve_extends(&container.policies)?;
+        if container.evaluation == PolicyEvaluation::AllMatching {
+            compose::check_conflicts(&definitions)?;
+        }
+        definitions.sort_by_key(|policy| std::cmp::Reverse(policy.priority));
+
         let mut policies = Vec::new();
-        for policy in container.policies {
+        for policy in definitions {
             policies.push(RealizedPolicy::try_from(policy)?);
         }
 
-        Ok(Self { policies })
+        Ok(Self {
+            evaluation: container.evaluation,
+            policies,
+        })
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Copy)]
+enum LabelKind {
+    Repo,
+    Version,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct RealizedPolicy {
     name: String,
     repository_regex: String,
     #[serde(skip)]
     repository_pattern: Regex,
+    priority: i32,
+    /// The policies this one inherited its labels from. Their labels are already
+    /// included below.
+    extends: Vec<String>,
     required_repo_labels: Vec<RequiredLabel>,
     required_version_labels: Vec<RequiredLabel>,
+    /// Labels that are checked when they are set, but may be left out.
+    optional_repo_labels: Vec<RequiredLabel>,
+    optional_version_labels: Vec<RequiredLabel>,
+    /// When false, repo labels that aren't declared by the policy are rejected.
+    additional_repo_labels: bool,
+    /// When false, version labels that aren't declared by the policy are rejected.
+    additional_version_labels: bool,
+    /// Labels that can not change once set, without being required.
+    immutable_labels: Vec<String>,
+    #[schema(value_type = Option<VersionScheme>)]
+    version_scheme: Option<VersionMatcher>,
+    max_version_length: Option<usize>,
+    retention: Vec<RetentionRule>,
 }
 
 impl RealizedPolicy {
-    fn new(
-        name: &str,
-        pattern: &str,
-        required_repo_labels: Vec<RequiredLabel>,
-        required_version_labels: Vec<RequiredLabel>,
-    ) -> Result<Self, PolicyError> {
-        RealizedPolicy::validate_only_one_label(name, &required_repo_labels)?;
-        RealizedPolicy::validate_only_one_label(name, &required_version_labels)?;
+    fn new(policy: PolicyDefinition) -> Result<Self, PolicyError> {
+        let name = policy.name;
+        RealizedPolicy::validate_only_one_label(
+            &name,
+            policy
+                .required_repo_labels
+                .iter()
+                .chain(policy.optional_repo_labels.iter()),
+        )?;
+        RealizedPolicy::validate_only_one_label(
+            &name,
+            policy
+                .required_version_labels
+                .iter()
+                .chain(policy.optional_version_labels.iter()),
+        )?;
 
-        let formatted_pattern = format!("^{}$", pattern);
+        for labels in [
+            (&policy.required_repo_labels, &policy.optional_repo_labels),
+            (
+                &policy.required_version_labels,
+                &policy.optional_version_labels,
+            ),
+        ] {
+            RealizedPolicy::validate_conditions(&name, labels.0, labels.1)?;
+        }
+
+        let formatted_pattern = format!("^{}$", policy.repository_pattern);
         let repository_pattern = Regex::new(&formatted_pattern)?;
+        for rule in &policy.retention {
+            rule.validate(&name)?;
+        }
+        let version_scheme = match &policy.version_scheme {
+            Some(scheme) => Some(scheme.compile(&name)?),
+            None => None,
+        };
+
+        let required_repo_labels =
+            RealizedPolicy::compile_labels(&name, policy.required_repo_labels)?;
+        let optional_repo_labels =
+            RealizedPolicy::compile_labels(&name, policy.optional_repo_labels)?;
+        RealizedPolicy::validate_repo_defaults(
+            &name,
+            required_repo_labels
+                .iter()
+                .chain(optional_repo_labels.iter()),
+        )?;
+        let required_version_labels =
+            RealizedPolicy::compile_labels(&name, policy.required_version_labels)?;
+        let optional_version_labels =
+            RealizedPolicy::compile_labels(&name, policy.optional_version_labels)?;
+        for (required, optional) in [
+            (&required_repo_labels, &optional_repo_labels),
+            (&required_version_labels, &optional_version_labels),
+        ] {
+            evaluation_order(&name, required, optional)?;
+        }
 
         Ok(RealizedPolicy {
-            name: name.to_owned(),
+            name,
             repository_pattern,
             repository_regex: formatted_pattern,
+            priority: policy.priority,
+            extends: policy.extends,
             required_repo_labels,
             required_version_labels,
+            optional_repo_labels,
+            optional_version_labels,
+            additional_repo_labels: policy.additional_repo_labels,
+            additional_version_labels: policy.additional_version_labels,
+            immutable_labels: policy.immutable_labels,
+            version_scheme,
+            max_version_length: policy.max_version_length,
+            retention: policy.retention,
         })
     }
 
-    fn validate_only_one_label(
+    fn compile_labels(
+        policy_name: &str,
+        labels: Vec<RequiredLabel>,
+    ) -> Result<Vec<RequiredLabel>, PolicyError> {
+        labels
+            .into_iter()
+            .map(|label| label.compile(policy_name))
+            .collect()
+    }
+
+    /// Repos have no version name or parent labels for a default template to read.
+    fn validate_repo_defaults<'a>(
+        policy_name: &str,
+        labels: impl Iterator<Item = &'a RequiredLabel>,
+    ) -> Result<(), PolicyError> {
+        for label in labels {
+            if let Some(template) = &label.compiled_default {
+                if template.needs_version() {
+                    return Err(PolicyError::InvalidDefault {
+                        policy_name: policy_name.to_owned(),
+                        label_name: label.name.clone(),
+                        reason: "repo labels can only use `org`, `repo` and `labels.<name>`"
+                            .to_owned(),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_only_one_label<'a>(
         policy_name: &str,
-        labels: &[RequiredLabel],
+        labels: impl Iterator<Item = &'a RequiredLabel>,
     ) -> Result<(), PolicyError> {
         let mut label_names = BTreeSet::default();
         for label in labels {
@@ -332,6 +1423,28 @@ impl RealizedPolicy {
         Ok(())
     }
 
+    /// Checks every `when` is well formed. Whether the labels can be put in an
+    /// evaluation order is checked once their defaults are compiled.
+    fn validate_conditions(
+        policy_name: &str,
+        required_labels: &[RequiredLabel],
+        optional_labels: &[RequiredLabel],
+    ) -> Result<(), PolicyError> {
+        for label in required_labels.iter().chain(optional_labels.iter()) {
+            if let Some(condition) = &label.when {
+                condition
+                    .validate()
+                    .map_err(|reason| PolicyError::InvalidCondition {
+                        policy_name: policy_name.to_owned(),
+                        label_name: label.name.clone(),
+                        reason,
+                    })?;
+            }
+        }
+
+        Ok(())
+    }
+
     #[cfg(test)]
     pub fn test_new(pattern: &str, labels: Vec<RequiredLabel>) -> Self {
         RealizedPolicy::test_new_different_labels(pattern, labels.clone(), labels)
@@ -343,45 +1456,319 @@ impl RealizedPolicy {
         required_repo_labels: Vec<RequiredLabel>,
         required_version_labels: Vec<RequiredLabel>,
     ) -> Self {
-        RealizedPolicy::new(
-            "test",
-            pattern,
+        RealizedPolicy::new(PolicyDefinition {
+            name: "test".to_owned(),
+            repository_pattern: pattern.to_owned(),
+            priority: 0,
+            extends: Vec::new(),
             required_repo_labels,
             required_version_labels,
-        )
+            optional_repo_labels: Vec::new(),
+            optional_version_labels: Vec::new(),
+            additional_repo_labels: true,
+            additional_version_labels: true,
+            immutable_labels: Vec::new(),
+            version_scheme: None,
+            max_version_length: None,
+            retention: Vec::new(),
+        })
         .unwrap()
     }
 
+    #[cfg(test)]
+    pub fn with_optional_labels(
+        mut self,
+        optional_repo_labels: Vec<RequiredLabel>,
+        optional_version_labels: Vec<RequiredLabel>,
+    ) -> Self {
+        self.optional_repo_labels = optional_repo_labels;
+        self.optional_version_labels = optional_version_labels;
+        self
+    }
+
+    /// Rejects repo and version labels the policy doesn't declare.
+    #[cfg(test)]
+    pub fn closed(mut self) -> Self {
+        self.additional_repo_labels = false;
+        self.additional_version_labels = false;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_version_scheme(
+        mut self,
+        version_scheme: VersionScheme,
+        max_version_length: Option<usize>,
+    ) -> Self {
+        self.version_scheme = Some(version_scheme.compile(&self.name).unwrap());
+        self.max_version_length = max_version_length;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_retention(mut self, retention: Vec<RetentionRule>) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_immutable_labels(mut self, immutable_labels: Vec<&str>) -> Self {
+        self.immutable_labels = immutable_labels.into_iter().map(str::to_string).collect();
+        self
+    }
+
     #[instrument(skip(self, repo_path))]
     pub fn policy_matches_repo(&self, repo_path: &str) -> bool {
         self.repository_pattern.is_match(repo_path)
     }
 
-    #[instrument(skip(self, declared_labels))]
+    #[instrument(skip(self, context, declared_labels, change))]
     pub fn process_repo_labels(
         &self,
+        context: &TemplateContext,
+        declared_labels: &mut BTreeMap<String, String>,
+        change: LabelChange,
+    ) -> Result<(), PolicyError> {
+        let known_labels = self.declared_labels(LabelKind::Repo).collect();
+        self.process_labels(
+            LabelKind::Repo,
+            context,
+            declared_labels,
+            change,
+            &known_labels,
+        )
+    }
+
+    #[instrument(skip(self, context, declared_labels, change))]
+    pub fn process_version_labels(
+        &self,
+        context: &TemplateContext,
         declared_labels: &mut BTreeMap<String, String>,
+        change: LabelChange,
     ) -> Result<(), PolicyError> {
-        for label in &self.required_repo_labels {
-            label.process_label(&self.name, declared_labels)?;
+        let known_labels = self.declared_labels(LabelKind::Version).collect();
+        self.process_labels(
+            LabelKind::Version,
+            context,
+            declared_labels,
+            change,
+            &known_labels,
+        )
+    }
+
+    /// Returns the required and optional labels of the kind, and whether labels the
+    /// policy doesn't declare are allowed.
+    fn labels(&self, kind: LabelKind) -> (&[RequiredLabel], &[RequiredLabel], bool) {
+        match kind {
+            LabelKind::Repo => (
+                &self.required_repo_labels,
+                &self.optional_repo_labels,
+                self.additional_repo_labels,
+            ),
+            LabelKind::Version => (
+                &self.required_version_labels,
+                &self.optional_version_labels,
+                self.additional_version_labels,
+            ),
         }
+    }
 
-        Ok(())
+    /// Names of the labels the policy declares, as required, optional, or immutable.
+    fn declared_labels(&self, kind: LabelKind) -> impl Iterator<Item = &str> {
+        let (required_labels, optional_labels, _) = self.labels(kind);
+        required_labels
+            .iter()
+            .chain(optional_labels.iter())
+            .map(|label| label.name.as_str())
+            .chain(self.immutable_labels.iter().map(String::as_str))
     }
 
-    #[instrument(skip(self, declared_labels))]
-    pub fn process_version_labels(
+    fn process_labels(
         &self,
+        kind: LabelKind,
+        context: &TemplateContext,
         declared_labels: &mut BTreeMap<String, String>,
+        change: LabelChange,
+        known_labels: &BTreeSet<&str>,
     ) -> Result<(), PolicyError> {
-        for label in &self.required_version_labels {
-            label.process_label(&self.name, declared_labels)?;
+        let (required_labels, optional_labels, additional_labels) = self.labels(kind);
+        let mut errors = Vec::new();
+        if !additional_labels {
+            errors.extend(self.check_declared(known_labels, declared_labels));
         }
 
-        Ok(())
+        for (label, optional) in evaluation_order(&self.name, required_labels, optional_labels)? {
+            let defaulted = label.default_value.is_some() && change.fills_defaults();
+            if optional && !declared_labels.contains_key(&label.name) && !defaulted {
+                continue;
+            }
+
+            // Checked labels are never defaulted, a missing one is reported even with a
+            // default.
+            if label.default_value.is_some()
+                && !change.fills_defaults()
+                && label.check_defined(&self.name, declared_labels).is_err()
+            {
+                errors.push(PolicyError::LabelNotStored {
+                    policy_name: self.name.clone(),
+                    label_name: label.name.clone(),
+                });
+                continue;
+            }
+            if let Err(e) = label.process_label(&self.name, context, declared_labels) {
+                errors.push(e);
+                continue;
+            }
+            if let Some(existing_labels) = change.existing_labels() {
+                errors.extend(
+                    label
+                        .check_transition(&self.name, existing_labels, declared_labels)
+                        .err(),
+                );
+            }
+        }
+
+        // Every immutable label is checked, including optional ones left out of the
+        // update, so a stored value can't be dropped by not sending it.
+        if let LabelChange::Update { existing_labels } = change {
+            let immutable_labels = required_labels
+                .iter()
+                .chain(optional_labels)
+                .filter(|label| label.immutable)
+                .map(|label| &label.name)
+                .chain(self.immutable_labels.iter());
+            for label_name in immutable_labels {
+                errors.extend(
+                    self.check_immutable(label_name, existing_labels, declared_labels)
+                        .err(),
+                );
+            }
+        }
+
+        PolicyError::from_violations(errors)
+    }
+
+    /// Rejects every label that isn't in `known_labels`, suggesting the closest known
+    /// name to catch typos.
+    fn check_declared(
+        &self,
+        known_labels: &BTreeSet<&str>,
+        declared_labels: &BTreeMap<String, String>,
+    ) -> Vec<PolicyError> {
+        declared_labels
+            .keys()
+            .filter(|label_name| !known_labels.contains(label_name.as_str()))
+            .map(|label_name| PolicyError::UnknownLabel {
+                policy_name: self.name.clone(),
+                label_name: label_name.clone(),
+                suggestion: closest_name(label_name, known_labels).map(str::to_string),
+            })
+            .collect()
+    }
+
+    /// A stored immutable label has to be kept with the same value. Setting it
+    /// for the first time is allowed.
+    fn check_immutable(
+        &self,
+        label_name: &str,
+        existing_labels: &BTreeMap<String, String>,
+        new_labels: &BTreeMap<String, String>,
+    ) -> Result<(), PolicyError> {
+        match existing_labels.get(label_name) {
+            Some(value) if new_labels.get(label_name) != Some(value) => {
+                Err(PolicyError::ImmutableLabel {
+                    policy_name: self.name.clone(),
+                    label_name: label_name.to_owned(),
+                    value: value.clone(),
+                })
+            }
+            _ => Ok(()),
+        }
     }
 }
 
+/// Orders required and optional labels, each flagged with whether it's optional, so
+/// a label comes after the labels its `when` or default template reads. Otherwise
+/// labels keep the order they were declared in, required labels first, so a default
+/// applied by an earlier label can make a later label's condition match.
+fn evaluation_order<'a>(
+    policy_name: &str,
+    required_labels: &'a [RequiredLabel],
+    optional_labels: &'a [RequiredLabel],
+) -> Result<Vec<(&'a RequiredLabel, bool)>, PolicyError> {
+    let mut remaining: Vec<(&RequiredLabel, bool)> = required_labels
+        .iter()
+        .map(|label| (label, false))
+        .chain(optional_labels.iter().map(|label| (label, true)))
+        .collect();
+    if remaining
+        .iter()
+        .all(|(label, _)| label.dependencies().next().is_none())
+    {
+        return Ok(remaining);
+    }
+
+    let mut pending: BTreeSet<&str> = remaining
+        .iter()
+        .map(|(label, _)| label.name.as_str())
+        .collect();
+    let mut ordered = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+        let ready = remaining
+            .iter()
+            .position(|(label, _)| label.dependencies().all(|key| !pending.contains(key)));
+        let (label, optional) = match ready {
+            Some(index) => remaining.remove(index),
+            None => {
+                let names: Vec<String> = remaining
+                    .iter()
+                    .map(|(label, _)| format!("`{}`", label.name))
+                    .collect();
+                // Cycles through `when` alone keep their own error, templates are
+                // only blamed when one of them is involved.
+                let templates = remaining
+                    .iter()
+                    .filter_map(|(label, _)| label.compiled_default.as_ref())
+                    .any(|template| template.label_keys().next().is_some());
+                let label_name = remaining[0].0.name.clone();
+                let policy_name = policy_name.to_owned();
+                return Err(match (templates, names.len()) {
+                    (false, 1) => PolicyError::InvalidCondition {
+                        policy_name,
+                        label_name,
+                        reason: "the condition depends on the label itself".to_owned(),
+                    },
+                    (false, _) => PolicyError::InvalidCondition {
+                        policy_name,
+                        label_name,
+                        reason: format!(
+                            "the conditions of {} depend on each other",
+                            names.join(", ")
+                        ),
+                    },
+                    (true, 1) => PolicyError::InvalidDefault {
+                        policy_name,
+                        label_name,
+                        reason: "the default depends on the label itself".to_owned(),
+                    },
+                    (true, _) => PolicyError::InvalidDefault {
+                        policy_name,
+                        label_name,
+                        reason: format!(
+                            "the defaults and conditions of {} depend on each other",
+                            names.join(", ")
+                        ),
+                    },
+                });
+            }
+        };
+        pending.remove(label.name.as_str());
+        ordered.push((label, optional));
+    }
+
+    Ok(ordered)
+}
+
 #[test]
 fn realized_pattern_will_match() {
     let policy = RealizedPolicy::test_new("example/.*", Vec::new());
@@ -390,15 +1777,621 @@ fn realized_pattern_will_match() {
     assert!(!policy.policy_matches_repo("another-example/foo"));
 }
 
+#[test]
+fn immutable_labels_can_not_change() {
+    let policy = RealizedPolicy::test_new_different_labels(
+        "example/.*",
+        vec![],
+        vec![RequiredLabel::new("git_sha", vec![], None).immutable()],
+    )
+    .with_immutable_labels(vec!["image"]);
+
+    let labels = |values: Vec<(&str, &str)>| -> BTreeMap<String, String> {
+        values
+            .into_iter()
+            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+            .collect()
+    };
+    let existing = labels(vec![("git_sha", "abc"), ("image", "clu:1")]);
+    let update = LabelChange::Update {
+        existing_labels: &existing,
+    };
+
+    assert!(policy
+        .process_version_labels(
+            &TemplateContext::default(),
+            &mut labels(vec![("git_sha", "abc"), ("image", "clu:1"), ("other", "x")]),
+            update
+        )
+        .is_ok());
+    assert_eq!(
+        policy
+            .process_version_labels(&TemplateContext::default(), &mut labels(vec![("git_sha", "def"), ("image", "clu:1")]), update)
+            .unwrap_err()
+            .to_string(),
+        "Policy `test` does not allow immutable label `git_sha` to be changed or removed once set, it is `abc`."
+    );
+    assert_eq!(
+        policy
+            .process_version_labels(&TemplateContext::default(), &mut labels(vec![("git_sha", "abc")]), update)
+            .unwrap_err()
+            .to_string(),
+        "Policy `test` does not allow immutable label `image` to be changed or removed once set, it is `clu:1`."
+    );
+
+    // Labels that were never stored can be set, and an admin override may change them.
+    let existing = labels(vec![("git_sha", "abc")]);
+    assert!(policy
+        .process_version_labels(
+            &TemplateContext::default(),
+            &mut labels(vec![("git_sha", "abc"), ("image", "clu:2")]),
+            LabelChange::Update {
+                existing_labels: &existing
+            }
+        )
+        .is_ok());
+    assert!(policy
+        .process_version_labels(
+            &TemplateContext::default(),
+            &mut labels(vec![("git_sha", "def")]),
+            LabelChange::Override {
+                existing_labels: &existing
+            }
+        )
+        .is_ok());
+}
+
+#[test]
+fn optional_immutable_labels_can_not_be_removed() {
+    let policy = RealizedPolicy::test_new("example/.*", vec![]).with_optional_labels(
+        vec![],
+        vec![RequiredLabel::new("signed_by", vec![], None).immutable()],
+    );
+
+    let labels = |values: Vec<(&str, &str)>| -> BTreeMap<String, String> {
+        values
+            .into_iter()
+            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+            .collect()
+    };
+    let existing = labels(vec![("signed_by", "release-bot")]);
+
+    assert_eq!(
+        policy
+            .process_version_labels(
+                &TemplateContext::default(),
+                &mut labels(vec![("other", "x")]),
+                LabelChange::Update {
+                    existing_labels: &existing
+                }
+            )
+            .unwrap_err()
+            .to_string(),
+        "Policy `test` does not allow immutable label `signed_by` to be changed or removed once set, it is `release-bot`."
+    );
+    // Until it's set, the label can still be left out.
+    assert!(policy
+        .process_version_labels(
+            &TemplateContext::default(),
+            &mut labels(vec![("other", "x")]),
+            LabelChange::Update {
+                existing_labels: &labels(vec![])
+            }
+        )
+        .is_ok());
+}
+
+#[test]
+fn checks_do_not_fill_in_defaults() {
+    let container = RealizedPolicyContainer {
+        policies: vec![RealizedPolicy::test_new(
+            "example/.*",
+            vec![RequiredLabel::new(
+                "release_state",
+                vec!["pre-release", "released"],
+                Some("released"),
+            )],
+        )
+        .with_optional_labels(
+            vec![RequiredLabel::new("tier", vec![], Some("standard"))],
+            vec![],
+        )],
+        ..Default::default()
+    };
+
+    // A create fills in the default, so leaving the label out is fine.
+    let mut labels = BTreeMap::new();
+    assert!(container
+        .execute_repo_policies("example", "clu", &mut labels, LabelChange::Create)
+        .is_ok());
+    assert_eq!(labels["release_state"], "released");
+
+    // A stored repo without it never got the default, and breaks the policy.
+    assert_eq!(
+        container
+            .check_repo_labels("example", "clu", &BTreeMap::new())
+            .unwrap_err()
+            .to_string(),
+        "Policy `test` required that label `release_state` be set, however it was not stored. Its default is only filled in when the labels are written."
+    );
+    assert!(container
+        .check_repo_labels(
+            "example",
+            "clu",
+            &BTreeMap::from([("release_state".to_owned(), "released".to_owned())])
+        )
+        .is_ok());
+}
+
 impl TryFrom<PolicyDefinition> for RealizedPolicy {
     type Error = PolicyError;
 
     fn try_from(policy: PolicyDefinition) -> Result<Self, Self::Error> {
-        RealizedPolicy::new(
-            &policy.name,
-            &policy.repository_pattern,
-            policy.required_repo_labels.clone(),
-            policy.required_version_labels.clone(),
-        )
+        RealizedPolicy::new(policy)
     }
 }
+
+/// Finds the name closest to `name` by edit distance, if any is close enough to
+/// likely be what was meant.
+fn closest_name<'a>(name: &str, candidates: &BTreeSet<&'a str>) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(name, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between two strings, counted in chars.
+fn edit_distance(left: &str, right: &str) -> usize {
+    let right: Vec<char> = right.chars().collect();
+    let mut previous: Vec<usize> = (0..=right.len()).collect();
+
+    for (i, left_char) in left.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, right_char) in right.iter().enumerate() {
+            let substitution = previous[j] + usize::from(left_char != *right_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[right.len()]
+}
+
+#[test]
+fn test_closest_name() {
+    let known = BTreeSet::from_iter(["release_state", "git_hash", "owners"]);
+    assert_eq!(edit_distance("releae_state", "release_state"), 1);
+    assert_eq!(closest_name("releae_state", &known), Some("release_state"));
+    assert_eq!(closest_name("owner", &known), Some("owners"));
+    assert_eq!(closest_name("build_number", &known), None);
+}
+
+#[test]
+fn closed_policy_rejects_unknown_labels() {
+    let policy = RealizedPolicy::test_new_different_labels(
+        "example/.*",
+        vec![],
+        vec![RequiredLabel::new(
+            "release_state",
+            vec!["built", "deployed"],
+            Some("built"),
+        )],
+    )
+    .with_optional_labels(
+        vec![],
+        vec![RequiredLabel::new("build_number", vec![], None).with_type(LabelType::Integer)],
+    )
+    .closed();
+
+    let labels = |values: Vec<(&str, &str)>| -> BTreeMap<String, String> {
+        values
+            .into_iter()
+            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+            .collect()
+    };
+
+    assert!(policy
+        .process_version_labels(
+            &TemplateContext::default(),
+            &mut labels(vec![]),
+            LabelChange::Create
+        )
+        .is_ok());
+    assert!(policy
+        .process_version_labels(
+            &TemplateContext::default(),
+            &mut labels(vec![("build_number", "42")]),
+            LabelChange::Create
+        )
+        .is_ok());
+    assert_eq!(
+        policy
+            .process_version_labels(
+                &TemplateContext::default(),
+                &mut labels(vec![("build_number", "forty-two")]),
+                LabelChange::Create
+            )
+            .unwrap_err()
+            .to_string(),
+        "Policy `test` required that label `build_number` be a integer, however `forty-two` was not."
+    );
+    assert_eq!(
+        policy
+            .process_version_labels(
+                &TemplateContext::default(),
+                &mut labels(vec![("releae_state", "deployed")]),
+                LabelChange::Create
+            )
+            .unwrap_err()
+            .to_string(),
+        "Policy `test` does not declare the label `releae_state`. Did you mean `release_state`?"
+    );
+    assert_eq!(
+        policy
+            .process_repo_labels(
+                &TemplateContext::default(),
+                &mut labels(vec![("owners", "team-a")]),
+                LabelChange::Create
+            )
+            .unwrap_err()
+            .to_string(),
+        "Policy `test` does not declare the label `owners`."
+    );
+}
+
+#[test]
+fn version_names_follow_the_policy() {
+    let parsed: PolicyDefinitionContainer = toml::from_str(
+        r#"
+[[policy]]
+name = "python"
+repository_pattern = "example/py-.*"
+priority = 10
+version_scheme = "pep440"
+max_version_length = 40
+required_repo_labels = []
+required_version_labels = []
+
+[[policy]]
+name = "calendar"
+repository_pattern = "example/.*"
+version_scheme = { calver = "YYYY.0M.MICRO" }
+required_repo_labels = []
+required_version_labels = []
+"#,
+    )
+    .unwrap();
+    let container = RealizedPolicyContainer::try_from(parsed).unwrap();
+
+    assert!(container
+        .check_version_name("example", "py-lib", "1.0rc1")
+        .is_ok());
+    assert!(container
+        .check_version_name("example", "py-lib", "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0")
+        .is_ok());
+    assert!(container
+        .check_version_name("example", "service", "2023.04.1")
+        .is_ok());
+    assert_eq!(
+        container
+            .check_version_name("example", "service", "1.0.0")
+            .unwrap_err()
+            .to_string(),
+        "Policy `calendar` required that versions follow calver `YYYY.0M.MICRO`, however `1.0.0` does not."
+    );
+
+    // Repos no policy applies to keep the default limit.
+    assert!(container
+        .check_version_name("other", "repo", &"a".repeat(30))
+        .is_ok());
+    assert_eq!(
+        container
+            .check_version_name("other", "repo", &"a".repeat(31))
+            .unwrap_err()
+            .to_string(),
+        format!(
+            "Version `{}` is 31 characters long, more than the limit of 30.",
+            "a".repeat(31)
+        )
+    );
+
+    let scheme = container.version_scheme("example", "service").unwrap();
+    assert_eq!(
+        scheme.compare("2023.04.10", "2023.04.9"),
+        Some(std::cmp::Ordering::Greater)
+    );
+    assert!(container.version_scheme("other", "repo").is_none());
+}
+
+#[test]
+fn conditional_labels_follow_evaluation_order() {
+    let deployed = || LabelSelector::new(vec![("release_state", "deployed")]);
+    let policy = RealizedPolicy::test_new_different_labels(
+        "example/.*",
+        vec![
+            // Declared before `tier`, but evaluated after its default is applied.
+            RequiredLabel::new("oncall", vec![], None).when(
+                LabelSelector::default().with_expression("tier", SelectorOperator::In, vec!["1"]),
+            ),
+            RequiredLabel::new("tier", vec!["1", "2", "3"], Some("1")),
+        ],
+        vec![
+            RequiredLabel::new("release_state", vec!["built", "deployed"], Some("built")),
+            RequiredLabel::new("deployed_at", vec![], None)
+                .with_type(LabelType::Rfc3339Timestamp)
+                .when(deployed()),
+            RequiredLabel::new("deployed_by", vec![], None).when(deployed()),
+        ],
+    );
+
+    let labels = |values: Vec<(&str, &str)>| -> BTreeMap<String, String> {
+        values
+            .into_iter()
+            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+            .collect()
+    };
+
+    let mut built = labels(vec![]);
+    policy
+        .process_version_labels(&TemplateContext::default(), &mut built, LabelChange::Create)
+        .unwrap();
+    assert_eq!(built, labels(vec![("release_state", "built")]));
+    assert_eq!(
+        policy
+            .process_version_labels(
+                &TemplateContext::default(),
+                &mut labels(vec![("release_state", "deployed"), ("deployed_by", "flynn")]),
+                LabelChange::Create
+            )
+            .unwrap_err()
+            .to_string(),
+        "Policy `test` required that label `deployed_at` be set when `release_state` is `deployed`, however it was not and no default was specified."
+    );
+    assert!(policy
+        .process_version_labels(
+            &TemplateContext::default(),
+            &mut labels(vec![
+                ("release_state", "deployed"),
+                ("deployed_at", "2026-10-19T12:00:00Z"),
+                ("deployed_by", "flynn"),
+            ]),
+            LabelChange::Create
+        )
+        .is_ok());
+    // A label whose condition doesn't match is still checked when it's set.
+    assert!(policy
+        .process_version_labels(
+            &TemplateContext::default(),
+            &mut labels(vec![("deployed_at", "yesterday")]),
+            LabelChange::Create
+        )
+        .is_err());
+
+    assert_eq!(
+        policy
+            .process_repo_labels(&TemplateContext::default(), &mut labels(vec![]), LabelChange::Create)
+            .unwrap_err()
+            .to_string(),
+        "Policy `test` required that label `oncall` be set when `tier` is one of `1`, however it was not and no default was specified."
+    );
+    assert!(policy
+        .process_repo_labels(
+            &TemplateContext::default(),
+            &mut labels(vec![("tier", "2")]),
+            LabelChange::Create
+        )
+        .is_ok());
+}
+
+#[test]
+fn templated_defaults_read_names_and_labels() {
+    let input = r#"
+[[policy]]
+name = "service"
+repository_pattern = "example/.*"
+required_repo_labels = [
+  {name = "image_name", default_value = "registry.example.com/{org}/{repo}"},
+]
+required_version_labels = [
+  {name = "image", default_value = "{labels.image_name}:{version}"},
+  {name = "image_name", default_value = "{repo.labels.image_name}"},
+  {name = "owners", default_value = "{repo.labels.owners | \"unowned\"}"},
+  {name = "release_channel", one_of = ["beta", "stable"], default_value = "{version.pre_release ? \"beta\" : \"stable\"}"},
+]
+"#;
+    let parsed: PolicyDefinitionContainer = toml::from_str(input).unwrap();
+    let container = RealizedPolicyContainer::try_from(parsed).unwrap();
+
+    let mut repo_labels = BTreeMap::new();
+    container
+        .execute_repo_policies("example", "clu", &mut repo_labels, LabelChange::Create)
+        .unwrap();
+    assert_eq!(
+        repo_labels.get("image_name").unwrap(),
+        "registry.example.com/example/clu"
+    );
+
+    let version_labels = |version: &str, labels: Vec<(&str, &str)>| {
+        let mut labels: BTreeMap<String, String> = labels
+            .into_iter()
+            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+            .collect();
+        container
+            .execute_version_policies(
+                "example",
+                "clu",
+                version,
+                &repo_labels,
+                &mut labels,
+                LabelChange::Create,
+            )
+            .map(|_| labels)
+    };
+
+    // `image` is declared first, but reads `image_name` after its default is applied.
+    let labels = version_labels("1.0.0-rc.1", vec![]).unwrap();
+    assert_eq!(
+        labels.get("image").unwrap(),
+        "registry.example.com/example/clu:1.0.0-rc.1"
+    );
+    assert_eq!(labels.get("owners").unwrap(), "unowned");
+    assert_eq!(labels.get("release_channel").unwrap(), "beta");
+
+    let labels = version_labels("1.0.0", vec![("image_name", "clu")]).unwrap();
+    assert_eq!(labels.get("image").unwrap(), "clu:1.0.0");
+    assert_eq!(labels.get("release_channel").unwrap(), "stable");
+}
+
+#[test]
+fn invalid_templates_fail_to_load() {
+    let load = |repo_labels: &str, version_labels: &str| {
+        let document = format!(
+            "[[policy]]\nname = \"service\"\nrepository_pattern = \".*\"\nrequired_repo_labels = [{}]\nrequired_version_labels = [{}]\n",
+            repo_labels, version_labels
+        );
+        let container: PolicyDefinitionContainer = toml::from_str(&document).unwrap();
+        RealizedPolicyContainer::try_from(container)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    };
+
+    assert_eq!(
+        load(r#"{name = "image", default_value = "{owner}"}"#, ""),
+        Err("Policy `service` has an invalid default_value on label `image`: `owner` is not a known variable, use `org`, `repo`, `version`, `version.pre_release`, `labels.<name>` or `repo.labels.<name>`".to_owned())
+    );
+    assert_eq!(
+        load(r#"{name = "image", default_value = "{org}:{version}"}"#, ""),
+        Err("Policy `service` has an invalid default_value on label `image`: repo labels can only use `org`, `repo` and `labels.<name>`".to_owned())
+    );
+    assert!(load("", r#"{name = "image", default_value = "{org}:{version}"}"#).is_ok());
+    assert_eq!(
+        load("", r#"{name = "a", default_value = "{labels.a}"}"#),
+        Err("Policy `service` has an invalid default_value on label `a`: the default depends on the label itself".to_owned())
+    );
+    assert_eq!(
+        load(
+            "",
+            r#"{name = "a", default_value = "{labels.b}"}, {name = "b", when = { match_labels = { a = "x" } }}"#
+        ),
+        Err("Policy `service` has an invalid default_value on label `a`: the defaults and conditions of `a`, `b` depend on each other".to_owned())
+    );
+}
+
+#[test]
+fn invalid_conditions_fail_to_load() {
+    let load = |labels: &str| {
+        let document = format!(
+            "[[policy]]\nname = \"service\"\nrepository_pattern = \".*\"\nrequired_repo_labels = [{}]\nrequired_version_labels = []\n",
+            labels
+        );
+        let container: PolicyDefinitionContainer = toml::from_str(&document).unwrap();
+        RealizedPolicyContainer::try_from(container).map(|_| ())
+    };
+
+    assert!(load(
+        r#"{name = "oncall", when = { match_labels = { tier = "1" } }}, {name = "tier"}"#
+    )
+    .is_ok());
+    assert_eq!(
+        load(r#"{name = "a", when = { match_expressions = [{ key = "b", operator = "exists" }] }}, {name = "b", when = { match_labels = { a = "x" } }}"#)
+            .unwrap_err()
+            .to_string(),
+        "Policy `service` has an invalid `when` on label `a`: the conditions of `a`, `b` depend on each other"
+    );
+    assert_eq!(
+        load(r#"{name = "a", when = { match_labels = { a = "x" } }}"#)
+            .unwrap_err()
+            .to_string(),
+        "Policy `service` has an invalid `when` on label `a`: the condition depends on the label itself"
+    );
+    assert_eq!(
+        load(r#"{name = "a", when = { match_expressions = [{ key = "b", operator = "in" }] }}"#)
+            .unwrap_err()
+            .to_string(),
+        "Policy `service` has an invalid `when` on label `a`: the `in` expression on `b` needs at least one value"
+    );
+}
+
+#[test]
+fn every_violation_is_reported() {
+    let input = r#"
+evaluation = "all_matching"
+
+[[policy]]
+name = "service"
+repository_pattern = "example/.*"
+required_repo_labels = []
+additional_version_labels = false
+required_version_labels = [
+  {name = "owner"},
+  {name = "release_state", one_of = ["built", "deployed"]},
+  {name = "build_number", type = "integer"},
+]
+
+[[policy]]
+name = "audited"
+repository_pattern = "example/.*"
+required_repo_labels = []
+required_version_labels = [
+  {name = "audited_by"},
+]
+"#;
+    let parsed: PolicyDefinitionContainer = toml::from_str(input).unwrap();
+    let container = RealizedPolicyContainer::try_from(parsed).unwrap();
+
+    let mut labels: BTreeMap<String, String> = vec![
+        ("release_state", "released"),
+        ("build_number", "forty-two"),
+        ("ownr", "team-a"),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_owned(), v.to_owned()))
+    .collect();
+    let error = container
+        .execute_version_policies(
+            "example",
+            "clu",
+            "1.0.0",
+            &BTreeMap::new(),
+            &mut labels,
+            LabelChange::Create,
+        )
+        .unwrap_err();
+
+    let violations: Vec<_> = error
+        .violations()
+        .iter()
+        .map(|e| (e.policy_name(), e.label_name(), e.kind(), e.value()))
+        .collect();
+    assert_eq!(
+        violations,
+        vec![
+            (Some("service"), Some("ownr"), "unknown_label", None),
+            (Some("service"), Some("owner"), "label_not_defined", None),
+            (
+                Some("service"),
+                Some("release_state"),
+                "label_not_in_set",
+                Some("released")
+            ),
+            (
+                Some("service"),
+                Some("build_number"),
+                "label_type_mismatch",
+                Some("forty-two")
+            ),
+            (
+                Some("audited"),
+                Some("audited_by"),
+                "label_not_defined",
+                None
+            ),
+        ]
+    );
+    assert!(error.to_string().starts_with(
+        "Policy `service` does not declare the label `ownr`. Did you mean `owner`? Policy `service` required"
+    ));
+}
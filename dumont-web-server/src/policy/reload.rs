@@ -0,0 +1,214 @@
+//! Holds the policies the server enforces, and replaces them at runtime when the
+//! active policy version changes.
+
+use super::{PolicyDefinitionContainer, PolicyError, RealizedPolicyContainer};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::info;
+use utoipa::ToSchema;
+
+/// A realized policy document, and the SHA-256 of the document it was loaded from.
+#[derive(Debug)]
+pub struct LoadedPolicy {
+    pub container: RealizedPolicyContainer,
+    pub hash: String,
+}
+
+impl LoadedPolicy {
+    /// Parses and validates the whole document, so a broken document never
+    /// replaces a working one.
+    pub fn parse(document: &str) -> Result<Self, PolicyError> {
+        let definitions: PolicyDefinitionContainer = toml::from_str(document)?;
+        Ok(Self {
+            container: RealizedPolicyContainer::try_from(definitions)?,
+            hash: policy_hash(document),
+        })
+    }
+}
+
+/// Hex encoded SHA-256 of a policy document. Running without a document reports
+/// the hash of an empty one.
+pub fn policy_hash(document: &str) -> String {
+    format!("{:x}", Sha256::digest(document.as_bytes()))
+}
+
+/// Result of a reload.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct PolicyReload {
+    pub hash: String,
+    /// False when the document hasn't changed since it was last loaded.
+    pub changed: bool,
+}
+
+/// The policies currently enforced. Requests take the current `LoadedPolicy` and use
+/// it until they finish, so a reload never changes the policies part way through one.
+#[derive(Debug)]
+pub struct PolicyHandle {
+    document: Option<PathBuf>,
+    /// Hash of the policy document, as it's stored in a policy version, when it was
+    /// last imported.
+    imported_hash: Mutex<Option<String>>,
+    current: RwLock<Arc<LoadedPolicy>>,
+}
+
+impl PolicyHandle {
+    /// Loads the policy document at `document`, or enforces no policies without one.
+    pub fn load(document: Option<PathBuf>) -> Result<Self, PolicyError> {
+        let loaded = match &document {
+            Some(path) => LoadedPolicy::parse(&std::fs::read_to_string(path)?)?,
+            None => LoadedPolicy {
+                container: Default::default(),
+                hash: policy_hash(""),
+            },
+        };
+        crate::api::metrics::set_policy_hash(&loaded.hash);
+
+        Ok(Self {
+            document,
+            imported_hash: Mutex::new(None),
+            current: RwLock::new(Arc::new(loaded)),
+        })
+    }
+
+    pub fn has_document(&self) -> bool {
+        self.document.is_some()
+    }
+
+    pub fn current(&self) -> Arc<LoadedPolicy> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Reads the policy document the server was started with, without realizing it.
+    pub fn read_document(&self) -> Result<PolicyDefinitionContainer, PolicyError> {
+        Ok(toml::from_str(&self.read()?)?)
+    }
+
+    /// The hash of the policy document when it was last imported, none until then.
+    pub fn imported_hash(&self) -> Option<String> {
+        self.imported_hash.lock().unwrap().clone()
+    }
+
+    pub fn set_imported_hash(&self, hash: String) {
+        *self.imported_hash.lock().unwrap() = Some(hash);
+    }
+
+    /// Replaces the current policies with `definitions` if they are valid, when they
+    /// aren't the current policies are kept. Nothing is realized when `hash` is
+    /// already loaded.
+    pub fn replace<F>(&self, hash: String, definitions: F) -> Result<PolicyReload, PolicyError>
+    where
+        F: FnOnce() -> Result<PolicyDefinitionContainer, PolicyError>,
+    {
+        let reload = self.try_replace(hash, definitions)?;
+        if reload.changed {
+            info!(hash = %reload.hash, "Policy document reloaded");
+        }
+
+        Ok(reload)
+    }
+
+    fn read(&self) -> Result<String, PolicyError> {
+        let path = self
+            .document
+            .as_ref()
+            .ok_or(PolicyError::NoPolicyDocument)?;
+        Ok(std::fs::read_to_string(path)?)
+    }
+
+    fn try_replace<F>(&self, hash: String, definitions: F) -> Result<PolicyReload, PolicyError>
+    where
+        F: FnOnce() -> Result<PolicyDefinitionContainer, PolicyError>,
+    {
+        if hash == self.current().hash {
+            return Ok(PolicyReload {
+                hash,
+                changed: false,
+            });
+        }
+
+        let loaded = LoadedPolicy {
+            container: RealizedPolicyContainer::try_from(definitions()?)?,
+            hash: hash.clone(),
+        };
+        crate::api::metrics::set_policy_hash(&loaded.hash);
+        *self.current.write().unwrap() = Arc::new(loaded);
+
+        Ok(PolicyReload {
+            hash,
+            changed: true,
+        })
+    }
+}
+
+#[cfg(test)]
+impl From<RealizedPolicyContainer> for PolicyHandle {
+    fn from(container: RealizedPolicyContainer) -> Self {
+        Self {
+            document: None,
+            imported_hash: Mutex::new(None),
+            current: RwLock::new(Arc::new(LoadedPolicy {
+                container,
+                hash: policy_hash(""),
+            })),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Default for PolicyHandle {
+    fn default() -> Self {
+        RealizedPolicyContainer::default().into()
+    }
+}
+
+#[test]
+fn replace_keeps_only_valid_documents() {
+    use std::io::Write;
+
+    let path = std::env::temp_dir().join(format!("dumont-policy-{}.toml", std::process::id()));
+    let mut file = std::fs::File::create(&path).unwrap();
+    file.write_all(
+        br#"
+[[policy]]
+name = "library"
+repository_pattern = ".*"
+required_repo_labels = [{name = "owners"}]
+required_version_labels = []
+"#,
+    )
+    .unwrap();
+    let handle = PolicyHandle::load(Some(path.clone())).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let first_hash = handle.current().hash.clone();
+    assert_eq!(handle.current().container.policies.len(), 1);
+
+    let reload = handle
+        .replace(first_hash.clone(), || unreachable!("Loaded already"))
+        .unwrap();
+    assert_eq!(reload.hash, first_hash);
+    assert!(!reload.changed);
+
+    let parse = |document: &'static str| move || Ok(toml::from_str(document)?);
+    let invalid = r#"
+[[policy]]
+name = "library"
+repository_pattern = ".*"
+required_repo_labels = [{name = "owners", pattern = "team-[a-z"}]
+required_version_labels = []
+"#;
+    assert!(matches!(
+        handle
+            .replace(policy_hash(invalid), parse(invalid))
+            .unwrap_err(),
+        PolicyError::RegexError { .. }
+    ));
+    assert_eq!(handle.current().hash, first_hash);
+
+    let reload = handle.replace(policy_hash(""), parse("")).unwrap();
+    assert!(reload.changed);
+    assert_eq!(reload.hash, policy_hash(""));
+    assert!(handle.current().container.policies.is_empty());
+}
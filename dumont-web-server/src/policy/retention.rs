@@ -0,0 +1,213 @@
+//! Retention rules, deciding which versions garbage collection deletes.
+
+use super::{LabelSelector, PolicyError, VersionMatcher};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// Expires the versions selected by `when` that are neither one of the newest
+/// `keep_newest` selected versions, nor younger than `keep_days`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionRule {
+    /// The versions the rule applies to, every version when empty.
+    #[serde(default)]
+    when: LabelSelector,
+    #[serde(default)]
+    keep_newest: Option<usize>,
+    #[serde(default)]
+    keep_days: Option<u32>,
+}
+
+/// A stored version, as seen by retention rules.
+#[derive(Debug)]
+pub struct RetentionCandidate<'a> {
+    pub version: &'a str,
+    pub labels: &'a BTreeMap<String, String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl RetentionRule {
+    #[cfg(test)]
+    pub fn new(when: LabelSelector, keep_newest: Option<usize>, keep_days: Option<u32>) -> Self {
+        RetentionRule {
+            when,
+            keep_newest,
+            keep_days,
+        }
+    }
+
+    /// A rule without `keep_newest` or `keep_days` would expire every version it
+    /// selects, which is never what was meant.
+    pub fn validate(&self, policy_name: &str) -> Result<(), PolicyError> {
+        let reason = if self.keep_newest.is_none() && self.keep_days.is_none() {
+            Some("a rule needs `keep_newest`, `keep_days`, or both".to_owned())
+        } else {
+            self.when.validate().err()
+        };
+
+        match reason {
+            Some(reason) => Err(PolicyError::InvalidRetention {
+                policy_name: policy_name.to_owned(),
+                reason,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Returns the versions to delete, as indexes into `candidates` with the name of the
+/// policy whose rule expired them.
+///
+/// Versions are ranked newest first by `scheme`, then by creation time. A version only
+/// expires when every rule that selects it expires it, and versions no rule selects
+/// are always kept.
+pub fn expired_versions<'a>(
+    rules: &[(&'a str, &RetentionRule)],
+    candidates: &[RetentionCandidate],
+    scheme: Option<&VersionMatcher>,
+    now: DateTime<Utc>,
+) -> Vec<(usize, &'a str)> {
+    let mut newest_first: Vec<usize> = (0..candidates.len()).collect();
+    newest_first.sort_by(|&left, &right| {
+        let (left, right) = (&candidates[left], &candidates[right]);
+        // Versions that don't follow the scheme rank below the ones that do.
+        let by_scheme = match scheme {
+            Some(scheme) => match (scheme.matches(left.version), scheme.matches(right.version)) {
+                (true, true) => scheme
+                    .compare(left.version, right.version)
+                    .unwrap_or(Ordering::Equal),
+                (left, right) => left.cmp(&right),
+            },
+            None => Ordering::Equal,
+        };
+        by_scheme
+            .then(left.created_at.cmp(&right.created_at))
+            .reverse()
+    });
+
+    let mut kept = vec![false; candidates.len()];
+    let mut expired_by: Vec<Option<&str>> = vec![None; candidates.len()];
+    for (policy_name, rule) in rules {
+        let selected = newest_first
+            .iter()
+            .filter(|&&index| rule.when.matches(candidates[index].labels));
+        for (rank, &index) in selected.enumerate() {
+            let recent = rule
+                .keep_days
+                .map(|days| now - candidates[index].created_at < Duration::days(days.into()))
+                .unwrap_or(false);
+            let newest = rule
+                .keep_newest
+                .map(|keep_newest| rank < keep_newest)
+                .unwrap_or(false);
+            if recent || newest {
+                kept[index] = true;
+            } else if expired_by[index].is_none() {
+                expired_by[index] = Some(policy_name);
+            }
+        }
+    }
+
+    expired_by
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| !kept[*index])
+        .filter_map(|(index, policy_name)| policy_name.map(|policy_name| (index, policy_name)))
+        .collect()
+}
+
+#[test]
+fn retention_keeps_newest_and_recent_versions() {
+    use super::{SelectorOperator, VersionScheme};
+
+    let now = Utc::now();
+    let pre_release: BTreeMap<String, String> =
+        BTreeMap::from([("release_state".to_owned(), "pre-release".to_owned())]);
+    let released: BTreeMap<String, String> =
+        BTreeMap::from([("release_state".to_owned(), "released".to_owned())]);
+
+    // 1.0.0-rc.0 to 1.0.0-rc.9, one a day, with rc.9 created today.
+    let names: Vec<String> = (0..10).map(|i| format!("1.0.0-rc.{}", i)).collect();
+    let mut candidates: Vec<RetentionCandidate> = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| RetentionCandidate {
+            version: name,
+            labels: &pre_release,
+            created_at: now - Duration::days(9 - i as i64),
+        })
+        .collect();
+    candidates.push(RetentionCandidate {
+        version: "0.9.0",
+        labels: &released,
+        created_at: now - Duration::days(100),
+    });
+
+    let selector = || LabelSelector::new(vec![("release_state", "pre-release")]);
+    let expired = |rules: &[(&'static str, &RetentionRule)]| -> Vec<&str> {
+        let mut versions: Vec<&str> = expired_versions(rules, &candidates, None, now)
+            .into_iter()
+            .map(|(index, _)| candidates[index].version)
+            .collect();
+        versions.sort();
+        versions
+    };
+
+    let newest = RetentionRule::new(selector(), Some(3), None);
+    assert_eq!(
+        expired(&[("ci", &newest)]),
+        vec![
+            "1.0.0-rc.0",
+            "1.0.0-rc.1",
+            "1.0.0-rc.2",
+            "1.0.0-rc.3",
+            "1.0.0-rc.4",
+            "1.0.0-rc.5",
+            "1.0.0-rc.6"
+        ]
+    );
+
+    let newest_or_recent = RetentionRule::new(selector(), Some(3), Some(5));
+    assert_eq!(
+        expired(&[("ci", &newest_or_recent)]),
+        vec![
+            "1.0.0-rc.0",
+            "1.0.0-rc.1",
+            "1.0.0-rc.2",
+            "1.0.0-rc.3",
+            "1.0.0-rc.4"
+        ]
+    );
+
+    // A version selected by two rules is kept when either keeps it.
+    let keep_more = RetentionRule::new(
+        LabelSelector::default().with_expression("release_state", SelectorOperator::Exists, vec![]),
+        Some(9),
+        None,
+    );
+    assert_eq!(
+        expired(&[("ci", &newest), ("all", &keep_more)]),
+        vec!["0.9.0", "1.0.0-rc.0"]
+    );
+
+    // Ranked by the version scheme rather than creation time.
+    candidates[0].created_at = now;
+    let scheme = VersionScheme::Semver.compile("ci").unwrap();
+    let by_scheme: Vec<&str> =
+        expired_versions(&[("ci", &newest)], &candidates, Some(&scheme), now)
+            .into_iter()
+            .map(|(index, policy_name)| {
+                assert_eq!(policy_name, "ci");
+                candidates[index].version
+            })
+            .collect();
+    assert!(by_scheme.contains(&"1.0.0-rc.0"));
+    assert!(!by_scheme.contains(&"1.0.0-rc.9"));
+
+    assert!(RetentionRule::new(selector(), None, None)
+        .validate("ci")
+        .is_err());
+}
@@ -0,0 +1,238 @@
+//! Label selectors, used by `when` to make a label's rules depend on the other labels
+//! in the same set.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use utoipa::ToSchema;
+
+/// Selects label sets, in the style of a Kubernetes label selector. A set is selected
+/// when it has every label in `match_labels` and matches every expression.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LabelSelector {
+    /// Labels that have to be set to exactly these values.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[schema(value_type = Object)]
+    match_labels: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    match_expressions: Vec<LabelSelectorRequirement>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LabelSelectorRequirement {
+    key: String,
+    operator: SelectorOperator,
+    /// The values `in` and `not_in` compare against. Must be empty for the other operators.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    values: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectorOperator {
+    /// The label is set to one of the values.
+    In,
+    /// The label is not set, or set to none of the values.
+    NotIn,
+    /// The label is set to any value.
+    Exists,
+    /// The label is not set.
+    DoesNotExist,
+}
+
+impl LabelSelector {
+    #[cfg(test)]
+    pub fn new(match_labels: Vec<(&str, &str)>) -> Self {
+        LabelSelector {
+            match_labels: match_labels
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value.to_owned()))
+                .collect(),
+            match_expressions: Vec::new(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_expression(
+        mut self,
+        key: &str,
+        operator: SelectorOperator,
+        values: Vec<&str>,
+    ) -> Self {
+        self.match_expressions.push(LabelSelectorRequirement {
+            key: key.to_owned(),
+            operator,
+            values: values.into_iter().map(str::to_string).collect(),
+        });
+        self
+    }
+
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.match_labels
+            .iter()
+            .all(|(key, value)| labels.get(key) == Some(value))
+            && self
+                .match_expressions
+                .iter()
+                .all(|requirement| requirement.matches(labels))
+    }
+
+    /// The labels the selector looks at.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.match_labels
+            .keys()
+            .chain(
+                self.match_expressions
+                    .iter()
+                    .map(|requirement| &requirement.key),
+            )
+            .map(String::as_str)
+    }
+
+    /// Checks each expression has values exactly when its operator uses them.
+    pub fn validate(&self) -> Result<(), String> {
+        for requirement in &self.match_expressions {
+            match (requirement.operator, requirement.values.is_empty()) {
+                (SelectorOperator::In | SelectorOperator::NotIn, true) => {
+                    return Err(format!(
+                        "the `{}` expression on `{}` needs at least one value",
+                        requirement.operator, requirement.key
+                    ))
+                }
+                (SelectorOperator::Exists | SelectorOperator::DoesNotExist, false) => {
+                    return Err(format!(
+                        "the `{}` expression on `{}` does not take values",
+                        requirement.operator, requirement.key
+                    ))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl LabelSelectorRequirement {
+    fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        let value = labels.get(&self.key);
+        match self.operator {
+            SelectorOperator::In => matches!(value, Some(value) if self.values.contains(value)),
+            SelectorOperator::NotIn => !matches!(value, Some(value) if self.values.contains(value)),
+            SelectorOperator::Exists => value.is_some(),
+            SelectorOperator::DoesNotExist => value.is_none(),
+        }
+    }
+}
+
+impl fmt::Display for SelectorOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SelectorOperator::In => "in",
+            SelectorOperator::NotIn => "not_in",
+            SelectorOperator::Exists => "exists",
+            SelectorOperator::DoesNotExist => "does_not_exist",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Describes the selected label sets, e.g. "`release_state` is `deployed`".
+impl fmt::Display for LabelSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let quoted = |values: &[String]| {
+            values
+                .iter()
+                .map(|value| format!("`{}`", value))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let clauses: Vec<String> = self
+            .match_labels
+            .iter()
+            .map(|(key, value)| format!("`{}` is `{}`", key, value))
+            .chain(self.match_expressions.iter().map(|requirement| {
+                let key = &requirement.key;
+                match requirement.operator {
+                    SelectorOperator::In => {
+                        format!("`{}` is one of {}", key, quoted(&requirement.values))
+                    }
+                    SelectorOperator::NotIn => {
+                        format!("`{}` is not one of {}", key, quoted(&requirement.values))
+                    }
+                    SelectorOperator::Exists => format!("`{}` is set", key),
+                    SelectorOperator::DoesNotExist => format!("`{}` is not set", key),
+                }
+            }))
+            .collect();
+
+        if clauses.is_empty() {
+            write!(f, "any labels are set")
+        } else {
+            write!(f, "{}", clauses.join(" and "))
+        }
+    }
+}
+
+#[test]
+fn selector_matches_labels() {
+    let labels: BTreeMap<String, String> = vec![("release_state", "deployed"), ("tier", "1")]
+        .into_iter()
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .collect();
+
+    assert!(LabelSelector::default().matches(&labels));
+    assert!(LabelSelector::new(vec![("release_state", "deployed")]).matches(&labels));
+    assert!(!LabelSelector::new(vec![("release_state", "built")]).matches(&labels));
+    assert!(
+        !LabelSelector::new(vec![("release_state", "deployed"), ("tier", "2")]).matches(&labels)
+    );
+
+    let expression =
+        |operator, values| LabelSelector::default().with_expression("tier", operator, values);
+    assert!(expression(SelectorOperator::In, vec!["1", "2"]).matches(&labels));
+    assert!(!expression(SelectorOperator::In, vec!["2"]).matches(&labels));
+    assert!(expression(SelectorOperator::NotIn, vec!["2"]).matches(&labels));
+    assert!(!expression(SelectorOperator::NotIn, vec!["1"]).matches(&labels));
+    assert!(expression(SelectorOperator::Exists, vec![]).matches(&labels));
+    assert!(!expression(SelectorOperator::DoesNotExist, vec![]).matches(&labels));
+
+    let missing =
+        LabelSelector::default().with_expression("oncall", SelectorOperator::NotIn, vec!["me"]);
+    assert!(missing.matches(&labels));
+    let missing =
+        LabelSelector::default().with_expression("oncall", SelectorOperator::In, vec!["me"]);
+    assert!(!missing.matches(&labels));
+}
+
+#[test]
+fn selector_validation_and_display() {
+    let selector = LabelSelector::new(vec![("release_state", "deployed")])
+        .with_expression("tier", SelectorOperator::In, vec!["1", "2"])
+        .with_expression("oncall", SelectorOperator::DoesNotExist, vec![]);
+    assert_eq!(selector.validate(), Ok(()));
+    assert_eq!(
+        selector.to_string(),
+        "`release_state` is `deployed` and `tier` is one of `1`, `2` and `oncall` is not set"
+    );
+    assert_eq!(
+        selector.keys().collect::<Vec<_>>(),
+        vec!["release_state", "tier", "oncall"]
+    );
+
+    assert_eq!(
+        LabelSelector::default()
+            .with_expression("tier", SelectorOperator::In, vec![])
+            .validate(),
+        Err("the `in` expression on `tier` needs at least one value".to_owned())
+    );
+    assert_eq!(
+        LabelSelector::default()
+            .with_expression("tier", SelectorOperator::Exists, vec!["1"])
+            .validate(),
+        Err("the `exists` expression on `tier` does not take values".to_owned())
+    );
+}
@@ -0,0 +1,415 @@
+//! Templates for `default_value`, filled in from the names of the repo or version
+//! being checked and the labels around it.
+//!
+//! Text outside braces is copied as is, `{{` and `}}` stand for literal braces. A
+//! placeholder holds alternatives separated by `|`, and is replaced by the first one
+//! that isn't empty. An alternative is a variable or a quoted string. A placeholder
+//! can also pick between two choices with `condition ? "then" : "else"`, taking the
+//! first when the condition isn't empty.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The names and labels a default template can refer to, besides the labels being
+/// checked.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TemplateContext<'a> {
+    pub org: &'a str,
+    pub repo: &'a str,
+    /// Only set when the labels of a version are checked.
+    pub version: Option<&'a str>,
+    /// The labels of the version's repo, only set when the labels of a version are checked.
+    pub repo_labels: Option<&'a BTreeMap<String, String>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Placeholder(Expression),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expression {
+    Choice(Vec<Value>),
+    Conditional {
+        condition: Vec<Value>,
+        then: Vec<Value>,
+        otherwise: Vec<Value>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Value {
+    Literal(String),
+    Variable(Variable),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Variable {
+    Org,
+    Repo,
+    Version,
+    /// The pre-release part of a semver version, e.g. `rc.1` for `1.0.0-rc.1`.
+    PreRelease,
+    Label(String),
+    RepoLabel(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Or,
+    Question,
+    Colon,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '}' => {
+                    return Err("`}` has no matching `{`, use `}}` for a literal brace".to_owned())
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    let mut quoted = false;
+                    loop {
+                        match chars.next() {
+                            Some('}') if !quoted => break,
+                            Some(c) => {
+                                if c == '"' {
+                                    quoted = !quoted;
+                                }
+                                placeholder.push(c);
+                            }
+                            None => {
+                                return Err("`{` has no matching `}`, use `{{` for a literal brace"
+                                    .to_owned())
+                            }
+                        }
+                    }
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Placeholder(Expression::parse(&placeholder)?));
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Ok(Template { parts })
+    }
+
+    pub fn render(&self, context: &TemplateContext, labels: &BTreeMap<String, String>) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Placeholder(expression) => expression.render(context, labels),
+            })
+            .collect()
+    }
+
+    /// The labels of the same repo or version the template reads, which have to be
+    /// evaluated before it.
+    pub fn label_keys(&self) -> impl Iterator<Item = &str> {
+        self.variables().filter_map(|variable| match variable {
+            Variable::Label(key) => Some(key.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Whether the template reads the version name or the repo's labels, which
+    /// only exist when a version is checked.
+    pub fn needs_version(&self) -> bool {
+        self.variables().any(|variable| {
+            matches!(
+                variable,
+                Variable::Version | Variable::PreRelease | Variable::RepoLabel(_)
+            )
+        })
+    }
+
+    fn variables(&self) -> impl Iterator<Item = &Variable> {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                Part::Text(_) => None,
+                Part::Placeholder(expression) => Some(expression.values()),
+            })
+            .flatten()
+            .filter_map(|value| match value {
+                Value::Variable(variable) => Some(variable),
+                Value::Literal(_) => None,
+            })
+    }
+}
+
+impl Expression {
+    fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut sections = tokens.split(|token| *token == Token::Question);
+        let condition = parse_choice(sections.next().unwrap_or_default())?;
+        let branches = match sections.next() {
+            Some(branches) => branches,
+            None => return Ok(Expression::Choice(condition)),
+        };
+        if sections.next().is_some() {
+            return Err(format!("`{{{}}}` has more than one `?`", source));
+        }
+
+        let mut branches = branches.split(|token| *token == Token::Colon);
+        match (branches.next(), branches.next(), branches.next()) {
+            (Some(then), Some(otherwise), None) => Ok(Expression::Conditional {
+                condition,
+                then: parse_choice(then)?,
+                otherwise: parse_choice(otherwise)?,
+            }),
+            _ => Err(format!(
+                "`{{{}}}` needs exactly one `:` after the `?`",
+                source
+            )),
+        }
+    }
+
+    fn render(&self, context: &TemplateContext, labels: &BTreeMap<String, String>) -> String {
+        let first = |values: &[Value]| {
+            values
+                .iter()
+                .map(|value| value.render(context, labels))
+                .find(|value| !value.is_empty())
+                .unwrap_or_default()
+        };
+        match self {
+            Expression::Choice(values) => first(values),
+            Expression::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => match first(condition).is_empty() {
+                false => first(then),
+                true => first(otherwise),
+            },
+        }
+    }
+
+    fn values(&self) -> impl Iterator<Item = &Value> {
+        let (first, second, third): (&[Value], &[Value], &[Value]) = match self {
+            Expression::Choice(values) => (values, &[], &[]),
+            Expression::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => (condition, then, otherwise),
+        };
+        first.iter().chain(second).chain(third)
+    }
+}
+
+impl Value {
+    fn render(&self, context: &TemplateContext, labels: &BTreeMap<String, String>) -> String {
+        match self {
+            Value::Literal(text) => text.clone(),
+            Value::Variable(Variable::Org) => context.org.to_owned(),
+            Value::Variable(Variable::Repo) => context.repo.to_owned(),
+            Value::Variable(Variable::Version) => context.version.unwrap_or_default().to_owned(),
+            Value::Variable(Variable::PreRelease) => context
+                .version
+                .and_then(|version| semver::Version::parse(version).ok())
+                .map(|version| version.pre.to_string())
+                .unwrap_or_default(),
+            Value::Variable(Variable::Label(key)) => labels.get(key).cloned().unwrap_or_default(),
+            Value::Variable(Variable::RepoLabel(key)) => context
+                .repo_labels
+                .and_then(|repo_labels| repo_labels.get(key))
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Quoted(text) => write!(f, "\"{}\"", text),
+            Token::Or => write!(f, "|"),
+            Token::Question => write!(f, "?"),
+            Token::Colon => write!(f, ":"),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '|' => tokens.push(Token::Or),
+            '?' => tokens.push(Token::Question),
+            ':' => tokens.push(Token::Colon),
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => text.push(c),
+                        None => return Err(format!("`{{{}}}` has an unterminated string", source)),
+                    }
+                }
+                tokens.push(Token::Quoted(text));
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '|' | '?' | ':' | '"') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Parses `value | value | ...`.
+fn parse_choice(tokens: &[Token]) -> Result<Vec<Value>, String> {
+    let mut values = Vec::new();
+    for alternative in tokens.split(|token| *token == Token::Or) {
+        let value = match alternative {
+            [Token::Quoted(text)] => Value::Literal(text.clone()),
+            [Token::Word(word)] => Value::Variable(parse_variable(word)?),
+            [] => return Err("a placeholder is missing a value".to_owned()),
+            tokens => {
+                let tokens: Vec<String> = tokens.iter().map(Token::to_string).collect();
+                return Err(format!(
+                    "`{}` is not a single variable or quoted string",
+                    tokens.join(" ")
+                ));
+            }
+        };
+        values.push(value);
+    }
+
+    Ok(values)
+}
+
+fn parse_variable(word: &str) -> Result<Variable, String> {
+    let variable = match word {
+        "org" => Variable::Org,
+        "repo" => Variable::Repo,
+        "version" => Variable::Version,
+        "version.pre_release" => Variable::PreRelease,
+        _ => {
+            if let Some(key) = word.strip_prefix("repo.labels.") {
+                Variable::RepoLabel(key.to_owned())
+            } else if let Some(key) = word.strip_prefix("labels.") {
+                Variable::Label(key.to_owned())
+            } else {
+                return Err(format!("`{}` is not a known variable, use `org`, `repo`, `version`, `version.pre_release`, `labels.<name>` or `repo.labels.<name>`", word));
+            }
+        }
+    };
+    match &variable {
+        Variable::Label(key) | Variable::RepoLabel(key) if key.is_empty() => {
+            Err(format!("`{}` is missing the label name", word))
+        }
+        _ => Ok(variable),
+    }
+}
+
+#[test]
+fn template_renders_names_and_labels() {
+    let repo_labels = BTreeMap::from([("owners".to_owned(), "team-a".to_owned())]);
+    let context = TemplateContext {
+        org: "example",
+        repo: "clu",
+        version: Some("1.0.0-rc.1"),
+        repo_labels: Some(&repo_labels),
+    };
+    let labels = BTreeMap::from([("git_sha".to_owned(), "abc123".to_owned())]);
+    let render = |source: &str| Template::parse(source).unwrap().render(&context, &labels);
+
+    assert_eq!(render("released"), "released");
+    assert_eq!(
+        render("registry.example.com/{org}/{repo}:{version}"),
+        "registry.example.com/example/clu:1.0.0-rc.1"
+    );
+    assert_eq!(render("{repo.labels.owners}"), "team-a");
+    assert_eq!(render("{ labels.git_sha }"), "abc123");
+    assert_eq!(render("{labels.owners | repo.labels.owners}"), "team-a");
+    assert_eq!(render("{labels.owners | \"nobody\"}"), "nobody");
+    assert_eq!(render("{labels.owners}"), "");
+    assert_eq!(render("{version.pre_release}"), "rc.1");
+    assert_eq!(
+        render("{version.pre_release ? \"pre-release\" : \"stable\"}"),
+        "pre-release"
+    );
+    assert_eq!(render("{{literal}}"), "{literal}");
+    assert_eq!(render("{\"a|b ? c\"}"), "a|b ? c");
+
+    let release = Template::parse("{version.pre_release ? \"pre-release\" : \"stable\"}").unwrap();
+    let context = TemplateContext {
+        version: Some("1.0.0"),
+        ..context
+    };
+    assert_eq!(release.render(&context, &labels), "stable");
+    let context = TemplateContext {
+        version: Some("2024.10-rc"),
+        ..context
+    };
+    assert_eq!(release.render(&context, &labels), "stable");
+}
+
+#[test]
+fn template_reports_what_it_reads() {
+    let template = Template::parse("{labels.a | labels.b}-{repo}").unwrap();
+    assert_eq!(template.label_keys().collect::<Vec<_>>(), vec!["a", "b"]);
+    assert!(!template.needs_version());
+
+    assert!(Template::parse("{version}").unwrap().needs_version());
+    assert!(Template::parse("{labels.a ? repo.labels.b : \"\"}")
+        .unwrap()
+        .needs_version());
+}
+
+#[test]
+fn invalid_templates_fail_to_parse() {
+    for (source, error) in [
+        ("{org", "`{` has no matching `}`, use `{{` for a literal brace"),
+        ("org}", "`}` has no matching `{`, use `}}` for a literal brace"),
+        ("{}", "a placeholder is missing a value"),
+        ("{owner}", "`owner` is not a known variable, use `org`, `repo`, `version`, `version.pre_release`, `labels.<name>` or `repo.labels.<name>`"),
+        ("{labels.}", "`labels.` is missing the label name"),
+        ("{org repo}", "`org repo` is not a single variable or quoted string"),
+        ("{org ? repo}", "`{org ? repo}` needs exactly one `:` after the `?`"),
+        ("{org ? repo : org ? repo : org}", "`{org ? repo : org ? repo : org}` has more than one `?`"),
+    ] {
+        assert_eq!(Template::parse(source), Err(error.to_owned()), "{}", source);
+    }
+}
@@ -0,0 +1,389 @@
+//! Naming rules for versions. A `VersionScheme` is compiled into a `VersionMatcher`,
+//! which checks version names and orders them, so anything sorting versions agrees
+//! with the policy that accepted them.
+
+use super::PolicyError;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+use utoipa::ToSchema;
+
+/// How versions of a repo are named.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionScheme {
+    /// Semantic versioning, e.g. `1.6.9-rc.1`.
+    Semver,
+    /// Calendar versioning in the given format, e.g. `YYYY.0M.MICRO`.
+    Calver(String),
+    /// Python's PEP 440, e.g. `1.6.9rc1`.
+    Pep440,
+    /// A regex the whole version has to match.
+    Pattern(String),
+}
+
+impl fmt::Display for VersionScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionScheme::Semver => write!(f, "semver"),
+            VersionScheme::Calver(format) => write!(f, "calver `{}`", format),
+            VersionScheme::Pep440 => write!(f, "PEP 440"),
+            VersionScheme::Pattern(pattern) => write!(f, "the pattern `{}`", pattern),
+        }
+    }
+}
+
+impl VersionScheme {
+    pub fn compile(&self, policy_name: &str) -> Result<VersionMatcher, PolicyError> {
+        let regex =
+            match self {
+                VersionScheme::Semver | VersionScheme::Pep440 => None,
+                VersionScheme::Calver(format) => Some(calver_regex(format).map_err(|reason| {
+                    PolicyError::InvalidVersionScheme {
+                        policy_name: policy_name.to_owned(),
+                        reason,
+                    }
+                })?),
+                VersionScheme::Pattern(pattern) => Some(Regex::new(&format!("^(?:{})$", pattern))?),
+            };
+
+        Ok(VersionMatcher {
+            scheme: self.clone(),
+            regex,
+        })
+    }
+}
+
+/// A compiled `VersionScheme`.
+#[derive(Clone, Debug)]
+pub struct VersionMatcher {
+    scheme: VersionScheme,
+    regex: Option<Regex>,
+}
+
+impl Serialize for VersionMatcher {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.scheme.serialize(serializer)
+    }
+}
+
+impl VersionMatcher {
+    pub fn scheme(&self) -> &VersionScheme {
+        &self.scheme
+    }
+
+    pub fn matches(&self, version: &str) -> bool {
+        self.key(version).is_some()
+    }
+
+    /// Orders two versions by the scheme's precedence. `None` when either doesn't
+    /// follow the scheme.
+    pub fn compare(&self, left: &str, right: &str) -> Option<Ordering> {
+        match (&self.scheme, self.key(left)?, self.key(right)?) {
+            (VersionScheme::Semver, _, _) => {
+                let left = semver::Version::parse(left).ok()?;
+                let right = semver::Version::parse(right).ok()?;
+                Some(left.cmp_precedence(&right))
+            }
+            (_, left, right) => Some(left.cmp(&right)),
+        }
+    }
+
+    /// A key that sorts like the version. Semver is compared by the `semver` crate,
+    /// so its key is empty.
+    fn key(&self, version: &str) -> Option<Vec<i64>> {
+        match (&self.scheme, &self.regex) {
+            (VersionScheme::Semver, _) => semver::Version::parse(version).ok().map(|_| Vec::new()),
+            (VersionScheme::Pep440, _) => pep440_key(version),
+            (VersionScheme::Calver(_), Some(regex)) => {
+                let captures = regex.captures(version)?;
+                captures
+                    .iter()
+                    .skip(1)
+                    .flatten()
+                    .map(|part| part.as_str().parse().ok())
+                    .collect()
+            }
+            (VersionScheme::Pattern(_), Some(regex)) => {
+                regex.is_match(version).then(|| natural_key(version))
+            }
+            (_, None) => None,
+        }
+    }
+}
+
+/// Calver tokens, longest first so `YYYY` isn't read as two `YY`.
+const CALVER_TOKENS: &[(&str, &str)] = &[
+    ("YYYY", r"[1-9][0-9]{3}"),
+    ("MAJOR", r"0|[1-9][0-9]*"),
+    ("MINOR", r"0|[1-9][0-9]*"),
+    ("MICRO", r"0|[1-9][0-9]*"),
+    ("YY", r"0|[1-9][0-9]{0,2}"),
+    ("0Y", r"[0-9]{2,3}"),
+    ("MM", r"[1-9]|1[0-2]"),
+    ("0M", r"0[1-9]|1[0-2]"),
+    ("WW", r"[1-9]|[1-4][0-9]|5[0-3]"),
+    ("0W", r"0[1-9]|[1-4][0-9]|5[0-3]"),
+    ("DD", r"[1-9]|[12][0-9]|3[01]"),
+    ("0D", r"0[1-9]|[12][0-9]|3[01]"),
+];
+
+/// Builds a regex capturing each token of a calver format. Anything between the
+/// tokens has to appear literally.
+fn calver_regex(format: &str) -> Result<Regex, String> {
+    let mut pattern = String::from("^");
+    let mut rest = format;
+    let mut tokens = 0;
+    while let Some(next) = rest.chars().next() {
+        if let Some((token, token_pattern)) = CALVER_TOKENS
+            .iter()
+            .find(|(token, _)| rest.starts_with(token))
+        {
+            pattern.push_str(&format!("({})", token_pattern));
+            rest = &rest[token.len()..];
+            tokens += 1;
+        } else if next.is_ascii_alphanumeric() {
+            return Err(format!(
+                "`{}` is not a calver format, `{}` is not one of {}",
+                format,
+                rest,
+                CALVER_TOKENS
+                    .iter()
+                    .map(|(token, _)| *token)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        } else {
+            pattern.push_str(&regex::escape(&next.to_string()));
+            rest = &rest[next.len_utf8()..];
+        }
+    }
+    if tokens == 0 {
+        return Err(format!(
+            "`{}` is not a calver format, it has no tokens",
+            format
+        ));
+    }
+    pattern.push('$');
+
+    Regex::new(&pattern).map_err(|e| e.to_string())
+}
+
+lazy_static! {
+    /// From the appendix of PEP 440.
+    static ref PEP440: Regex = Regex::new(
+        r"(?xi)^
+        v?
+        (?:(?P<epoch>[0-9]+)!)?
+        (?P<release>[0-9]+(?:\.[0-9]+)*)
+        (?P<pre>
+            [-_\.]?
+            (?P<pre_l>alpha|a|beta|b|preview|pre|c|rc)
+            [-_\.]?
+            (?P<pre_n>[0-9]+)?
+        )?
+        (?P<post>
+            (?:-(?P<post_n1>[0-9]+))
+            |
+            (?:
+                [-_\.]?
+                (?P<post_l>post|rev|r)
+                [-_\.]?
+                (?P<post_n2>[0-9]+)?
+            )
+        )?
+        (?P<dev>
+            [-_\.]?
+            dev
+            [-_\.]?
+            (?P<dev_n>[0-9]+)?
+        )?
+        (?:\+(?P<local>[a-z0-9]+(?:[-_\.][a-z0-9]+)*))?
+        $"
+    )
+    .unwrap();
+}
+
+/// Sort key following PEP 440: epoch, release, then pre, post and dev releases.
+/// Local versions are accepted but not ordered.
+fn pep440_key(version: &str) -> Option<Vec<i64>> {
+    let captures = PEP440.captures(version)?;
+    let number = |name: &str| -> Option<i64> {
+        captures
+            .name(name)
+            .map(|value| value.as_str().parse().ok())
+            .unwrap_or(Some(0))
+    };
+
+    let mut release: Vec<i64> = captures["release"]
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    while release.len() > 1 && release.last() == Some(&0) {
+        release.pop();
+    }
+
+    let has_pre = captures.name("pre").is_some();
+    let has_post = captures.name("post").is_some();
+    let has_dev = captures.name("dev").is_some();
+
+    // A dev release of a final version sorts before its pre releases.
+    let pre = if has_pre {
+        let phase = match captures["pre_l"].to_lowercase().as_str() {
+            "a" | "alpha" => 1,
+            "b" | "beta" => 2,
+            _ => 3,
+        };
+        [1, phase, number("pre_n")?]
+    } else if has_dev && !has_post {
+        [0, 0, 0]
+    } else {
+        [2, 0, 0]
+    };
+    let post = if has_post {
+        captures
+            .name("post_n1")
+            .or_else(|| captures.name("post_n2"))
+            .map(|value| value.as_str().parse().ok())
+            .unwrap_or(Some(0))?
+    } else {
+        -1
+    };
+    let dev = if has_dev { number("dev_n")? } else { i64::MAX };
+
+    // Release segments end with -1, so `1` sorts before `1.1`.
+    let mut key = vec![number("epoch")?];
+    key.extend(release);
+    key.push(-1);
+    key.extend(pre);
+    key.push(post);
+    key.push(dev);
+    Some(key)
+}
+
+/// Orders runs of digits by value and everything else by character, so `v10`
+/// sorts after `v9`.
+fn natural_key(version: &str) -> Vec<i64> {
+    let mut key = Vec::new();
+    let mut digits = String::new();
+    for c in version.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        if !digits.is_empty() {
+            key.push(digits.parse().unwrap_or(i64::MAX));
+            digits.clear();
+        }
+        // Offset characters past any number, so `1a` and `10` differ.
+        key.push(-(c as i64) - 1);
+    }
+    if !digits.is_empty() {
+        key.push(digits.parse().unwrap_or(i64::MAX));
+    }
+    key
+}
+
+#[cfg(test)]
+fn matcher(scheme: VersionScheme) -> VersionMatcher {
+    scheme.compile("test").unwrap()
+}
+
+#[test]
+fn validate_semver() {
+    let semver = matcher(VersionScheme::Semver);
+    assert!(semver.matches("1.6.9"));
+    assert!(semver.matches("1.6.9-rc.1+build.5"));
+    assert!(!semver.matches("1.6"));
+    assert!(!semver.matches("v1.6.9"));
+
+    assert_eq!(semver.compare("1.6.9-rc.1", "1.6.9"), Some(Ordering::Less));
+    assert_eq!(semver.compare("1.10.0", "1.9.0"), Some(Ordering::Greater));
+    assert_eq!(semver.compare("1.0.0+a", "1.0.0+b"), Some(Ordering::Equal));
+    assert_eq!(semver.compare("1.0.0", "latest"), None);
+}
+
+#[test]
+fn validate_calver() {
+    let calver = matcher(VersionScheme::Calver("YYYY.0M.MICRO".to_owned()));
+    assert!(calver.matches("2023.04.0"));
+    assert!(calver.matches("2023.12.15"));
+    assert!(!calver.matches("2023.4.0"));
+    assert!(!calver.matches("2023.13.0"));
+    assert!(!calver.matches("2023-04-0"));
+
+    assert_eq!(
+        calver.compare("2023.04.10", "2023.04.9"),
+        Some(Ordering::Greater)
+    );
+    assert_eq!(
+        calver.compare("2022.12.1", "2023.01.0"),
+        Some(Ordering::Less)
+    );
+
+    let error = VersionScheme::Calver("YYYY.Q".to_owned())
+        .compile("test")
+        .unwrap_err();
+    assert!(error.to_string().starts_with(
+        "Policy `test` has an invalid version_scheme: `YYYY.Q` is not a calver format, `Q` is not one of"
+    ));
+    assert!(VersionScheme::Calver("..".to_owned())
+        .compile("test")
+        .is_err());
+}
+
+#[test]
+fn validate_pep440() {
+    let pep440 = matcher(VersionScheme::Pep440);
+    for version in [
+        "1.0",
+        "1!2.0",
+        "1.0a1",
+        "1.0.post1",
+        "1.0.dev3",
+        "1.0rc1+local.7",
+    ] {
+        assert!(pep440.matches(version), "{}", version);
+    }
+    assert!(!pep440.matches("1.0-beta-gamma"));
+    assert!(!pep440.matches("one"));
+
+    let ordered = [
+        "1.0.dev1",
+        "1.0a1",
+        "1.0a2.dev1",
+        "1.0a2",
+        "1.0b1",
+        "1.0rc1",
+        "1.0",
+        "1.0.post1.dev1",
+        "1.0.post1",
+        "1.1",
+        "1!0.1",
+    ];
+    for pair in ordered.windows(2) {
+        assert_eq!(
+            pep440.compare(pair[0], pair[1]),
+            Some(Ordering::Less),
+            "{} < {}",
+            pair[0],
+            pair[1]
+        );
+    }
+    assert_eq!(pep440.compare("1.0", "1.0.0"), Some(Ordering::Equal));
+}
+
+#[test]
+fn validate_pattern() {
+    let pattern = matcher(VersionScheme::Pattern("v[0-9]+".to_owned()));
+    assert!(pattern.matches("v9"));
+    assert!(!pattern.matches("v9-hotfix"));
+    assert_eq!(pattern.compare("v10", "v9"), Some(Ordering::Greater));
+
+    assert!(matches!(
+        VersionScheme::Pattern("v[0-9".to_owned()).compile("test"),
+        Err(PolicyError::RegexError { .. })
+    ));
+}
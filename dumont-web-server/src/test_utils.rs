@@ -1,59 +1,147 @@
 use crate::database::prelude::*;
+use crate::database::{
+    CacheOptions, CachedStorage, Storage, POSTGRES_MIGRATIONS, SQLITE_MIGRATIONS,
+};
 use async_trait::async_trait;
 pub use sea_orm::{entity::*, query::*, Database, DatabaseConnection, DbBackend, Schema};
 use std::sync::Arc;
 
-pub async fn make_backend() -> crate::Backend {
-    let db = setup_schema().await.unwrap();
-    let db_backend = BackendDatabase {
-        db,
-        date_time_provider: DateTimeProvider::RealDateTime,
-    };
+/// The `dumont.io/created-at` label of everything created through `make_backend`.
+pub const TEST_CREATED_AT: &str = "2026-01-01T00:00:00Z";
+
+pub fn test_date_time_provider() -> DateTimeProvider {
+    let now = chrono::DateTime::parse_from_rfc3339(TEST_CREATED_AT).unwrap();
+    DateTimeProvider::FixedDateTime(now.into())
+}
 
+pub async fn make_backend() -> crate::Backend {
     Arc::new(crate::backend::DefaultBackend {
-        database: db_backend,
+        database: make_storage().await,
         policy_container: Default::default(),
     })
 }
 
+/// A fresh database behind the cache, like the server's.
+pub async fn make_storage() -> CachedStorage {
+    let db_backend = BackendDatabase {
+        db: setup_schema().await.unwrap(),
+        date_time_provider: test_date_time_provider(),
+        read_replica: None,
+        id_cache: Default::default(),
+    }
+    .with_id_cache(CacheOptions::default());
+
+    CachedStorage::new(db_backend, CacheOptions::default())
+}
+
+/// Set to a Postgres URL to run the tests against Postgres rather than an in-memory
+/// SQLite database. Every test creates a database of its own next to the one in the URL,
+/// and drops it when it's over, so the user needs the `CREATEDB` privilege.
+pub const TEST_DATABASE_URL: &str = "TEST_DATABASE_URL";
+
 pub async fn setup_schema() -> DbResult<DatabaseConnection> {
-    use crate::database::prelude::*;
-    let db = Database::connect("sqlite::memory:").await?;
-
-    // Setup Schema helper
-    let schema = Schema::new(DbBackend::Sqlite);
-
-    // Derive from Entity
-    db.execute(
-        db.get_database_backend()
-            .build(&schema.create_table_from_entity(Organization)),
-    )
-    .await?;
-
-    db.execute(
-        db.get_database_backend()
-            .build(&schema.create_table_from_entity(Repository)),
-    )
-    .await?;
-
-    db.execute(
-        db.get_database_backend()
-            .build(&schema.create_table_from_entity(RepositoryLabel)),
-    )
-    .await?;
-
-    db.execute(
-        db.get_database_backend()
-            .build(&schema.create_table_from_entity(RepositoryRevision)),
-    )
-    .await?;
-
-    db.execute(
-        db.get_database_backend()
-            .build(&schema.create_table_from_entity(RepositoryRevisionLabel)),
-    )
-    .await?;
-    Ok(db)
+    match std::env::var(TEST_DATABASE_URL) {
+        Ok(url) => setup_postgres_schema(&url).await,
+        Err(_) => setup_sqlite_schema().await,
+    }
+}
+
+pub async fn setup_sqlite_schema() -> DbResult<DatabaseConnection> {
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+    use std::str::FromStr;
+
+    // Each connection to `sqlite::memory:` opens a database of its own, so keep exactly
+    // one connection open for the life of the test.
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(SqliteConnectOptions::from_str("sqlite::memory:")?)
+        .await?;
+    SQLITE_MIGRATIONS.run(&pool).await?;
+
+    Ok(sea_orm::SqlxSqliteConnector::from_sqlx_sqlite_pool(pool))
+}
+
+async fn setup_postgres_schema(connection_url: &str) -> DbResult<DatabaseConnection> {
+    use sqlx::{Connection, Executor};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT_DATABASE: AtomicUsize = AtomicUsize::new(0);
+
+    // Tests run in parallel, so each gets a database of its own. The name only has to be
+    // unique among the tests running right now, a left over one is dropped first.
+    let database_name = format!(
+        "dumont_test_{}_{}",
+        std::process::id(),
+        NEXT_DATABASE.fetch_add(1, Ordering::SeqCst)
+    );
+    let mut connection = sqlx::PgConnection::connect(connection_url).await?;
+    connection
+        .execute(format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", database_name).as_str())
+        .await?;
+    connection
+        .execute(format!("CREATE DATABASE {}", database_name).as_str())
+        .await?;
+    connection.close().await?;
+
+    // Every test runs on a runtime of its own, which drops its tasks when the test returns
+    // or panics, and with them this one.
+    let drop_database = DropDatabase {
+        connection_url: connection_url.to_owned(),
+        database_name: database_name.clone(),
+    };
+    tokio::spawn(async move {
+        let _drop_database = drop_database;
+        std::future::pending::<()>().await
+    });
+
+    let mut test_url = url::Url::parse(connection_url).expect("TEST_DATABASE_URL to be a URL");
+    test_url.set_path(&database_name);
+    let pool = sqlx::postgres::PgPoolOptions::new()
+        .max_connections(5)
+        .connect(test_url.as_str())
+        .await?;
+    POSTGRES_MIGRATIONS.run(&pool).await?;
+
+    Ok(sea_orm::SqlxPostgresConnector::from_sqlx_postgres_pool(
+        pool,
+    ))
+}
+
+/// Drops a test's Postgres database, and the connections still open to it.
+struct DropDatabase {
+    connection_url: String,
+    database_name: String,
+}
+
+impl Drop for DropDatabase {
+    fn drop(&mut self) {
+        use sqlx::{Connection, Executor};
+
+        let connection_url = self.connection_url.clone();
+        let statement = format!(
+            "DROP DATABASE IF EXISTS {} WITH (FORCE)",
+            self.database_name
+        );
+        // The test's runtime is shutting down, so the database is dropped from another one.
+        let dropped = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            runtime.block_on(async {
+                let mut connection = sqlx::PgConnection::connect(&connection_url).await?;
+                connection.execute(statement.as_str()).await?;
+                connection.close().await?;
+                Ok::<_, anyhow::Error>(())
+            })
+        })
+        .join();
+
+        if let Ok(Err(e)) = dropped {
+            eprintln!("Unable to drop {}: {}", self.database_name, e);
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -120,7 +208,7 @@ impl TestBackend for crate::Backend {
 }
 
 #[async_trait]
-impl TestBackend for BackendDatabase {
+impl<S: Storage> TestBackend for S {
     async fn create_test_repo(&self, org: &str, repo: &str) -> DbResult<()> {
         self.create_test_repo_with_params(org, repo, CreateRepoParam::default())
             .await
@@ -220,6 +308,8 @@ pub fn assert_response(
 pub fn assert_error_response(
     response: http::Response<bytes::Bytes>,
     status: http::StatusCode,
+    error_code: &str,
+    error_details: json::JsonValue,
     message: &str,
 ) {
     use json::object;
@@ -232,11 +322,17 @@ pub fn assert_error_response(
         }
         Ok(body) => body,
     };
+    let mut expected_status = object! {
+        "code": response.status().as_u16(),
+        "error": [message],
+        "error_code": error_code,
+    };
+    if !error_details.is_empty() {
+        expected_status["error_details"] = error_details;
+    }
     assert_eq!(
         json::stringify(body),
-        json::stringify(object! {
-            "status": { "code": response.status().as_u16(), "error": [message] },
-        })
+        json::stringify(object! { "status": expected_status })
     );
     assert_eq!(response.status(), status);
 }